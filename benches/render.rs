@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use raster::{RenderOptions, render_with_options};
+
+// EDF trees written to a temporary directory, the shapes of site trees:
+// chains of bases, bases shared by several parents and long mount lists.
+
+fn tree_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("raster-bench-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write(dir: &Path, name: &str, content: &str) {
+    std::fs::write(dir.join(format!("{name}.toml")), content).unwrap();
+}
+
+fn mounts(prefix: &str, n: usize) -> String {
+    let m: Vec<String> = (0..n).map(|i| format!("\"/tmp:/{prefix}/{i}\"")).collect();
+    format!("mounts = [{}]\n", m.join(", "))
+}
+
+// site <- l1 <- ... <- l7, each level adding env entries and mounts.
+fn deep_chain() -> PathBuf {
+    let dir = tree_dir("chain");
+    write(&dir, "site", &format!("image = \"ubuntu:24.04\"\n{}", mounts("site", 10)));
+    for l in 1..8 {
+        let content = format!(
+            "base_environment = \"{}\"\n{}[env]\nLEVEL{l} = \"{l}\"\n",
+            if l == 1 { String::from("site") } else { format!("l{}", l - 1) },
+            mounts(&format!("l{l}"), 10),
+        );
+        write(&dir, &format!("l{l}"), &content);
+    }
+    dir
+}
+
+// app has 10 bases, each of them based on site.
+fn shared_base() -> PathBuf {
+    let dir = tree_dir("shared");
+    let env: Vec<String> = (0..50).map(|i| format!("SITE{i} = \"{i}\"")).collect();
+    write(&dir, "site", &format!("image = \"ubuntu:24.04\"\n[env]\n{}\n", env.join("\n")));
+    let mut bases = vec![];
+    for b in 0..10 {
+        write(&dir, &format!("b{b}"), &format!("base_environment = \"site\"\n{}", mounts(&format!("b{b}"), 5)));
+        bases.push(format!("\"b{b}\""));
+    }
+    write(&dir, "app", &format!("base_environment = [{}]\n", bases.join(", ")));
+    dir
+}
+
+fn many_mounts() -> PathBuf {
+    let dir = tree_dir("mounts");
+    write(&dir, "site", "image = \"ubuntu:24.04\"\n");
+    write(&dir, "app", &format!("base_environment = \"site\"\n{}", mounts("data", 300)));
+    dir
+}
+
+fn options(dir: &Path) -> RenderOptions {
+    RenderOptions::new()
+        .search_paths(vec![dir.display().to_string()])
+        .env(HashMap::new())
+}
+
+fn bench_render(c: &mut Criterion) {
+    for (name, dir, edf) in [
+        ("deep_chain", deep_chain(), "l7"),
+        ("shared_base", shared_base(), "app"),
+        ("many_mounts", many_mounts(), "app"),
+    ] {
+        let opts = options(&dir);
+        render_with_options(String::from(edf), &opts).unwrap();
+        c.bench_function(name, |b| b.iter(|| render_with_options(String::from(edf), &opts).unwrap()));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
+
+criterion_group!(benches, bench_render);
+criterion_main!(benches);
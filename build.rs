@@ -0,0 +1,20 @@
+// Generate include/raster.h, the C header of the ffi feature.
+fn main() {
+    #[cfg(feature = "ffi")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        let dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        let config = cbindgen::Config {
+            language: cbindgen::Language::C,
+            include_guard: Some(String::from("RASTER_H")),
+            autogen_warning: Some(String::from("/* Generated by build.rs from src/ffi.rs, do not edit. */")),
+            ..Default::default()
+        };
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src(format!("{dir}/src/ffi.rs"))
+            .generate()
+            .expect("can't generate the C header")
+            .write_to_file(format!("{dir}/include/raster.h"));
+    }
+}
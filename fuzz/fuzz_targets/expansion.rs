@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use raster::fuzz::ExpansionInput;
+
+fuzz_target!(|input: ExpansionInput| {
+    raster::fuzz::fuzz_expansion(input);
+});
@@ -0,0 +1,9 @@
+#![no_main]
+
+use std::collections::HashMap;
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (&str, HashMap<String, String>)| {
+    raster::fuzz::fuzz_mount(input.0, input.1);
+});
@@ -0,0 +1,15 @@
+#![no_main]
+
+use std::collections::HashMap;
+
+use libfuzzer_sys::arbitrary::{Arbitrary, Unstructured};
+use libfuzzer_sys::fuzz_target;
+use raster::RawEDF;
+
+// RawEDF isn't Debug, it is built here rather than by fuzz_target!.
+fuzz_target!(|data: &[u8]| {
+    let mut u = Unstructured::new(data);
+    if let (Ok(raw), Ok(env)) = (RawEDF::arbitrary(&mut u), HashMap::<String, String>::arbitrary(&mut u)) {
+        raster::fuzz::fuzz_raw_edf(raw, env);
+    }
+});
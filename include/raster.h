@@ -0,0 +1,52 @@
+#ifndef RASTER_H
+#define RASTER_H
+
+/* Generated by build.rs from src/ffi.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Render an EDF with the site configuration, as JSON. Returns NULL on
+ * failure.
+ *
+ * # Safety
+ * path must be NULL or a NUL-terminated string.
+ */
+char *raster_render(const char *path);
+
+/**
+ * Validate an EDF against the schema. Returns 0 when valid, -1 otherwise.
+ *
+ * # Safety
+ * path must be NULL or a NUL-terminated string.
+ */
+int raster_validate(const char *path);
+
+/**
+ * Code of the last error of the thread, -1 when the last call succeeded.
+ */
+int raster_last_error_code(void);
+
+/**
+ * Message of the last error of the thread, NULL when the last call
+ * succeeded.
+ */
+char *raster_last_error_message(void);
+
+/**
+ * Last error of the thread as JSON, NULL when the last call succeeded.
+ */
+char *raster_last_error_json(void);
+
+/**
+ * Free a string returned by the library.
+ *
+ * # Safety
+ * s must be NULL or a string returned by the library, not freed yet.
+ */
+void raster_string_free(char *s);
+
+#endif  /* RASTER_H */
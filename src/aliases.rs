@@ -0,0 +1,123 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+// Former name of an EDF key, accepted until the release removing it.
+// Dotted names address keys of nested tables, e.g. "parallax.imagestore".
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyAlias {
+    pub old: &'static str,
+    pub new: &'static str,
+    pub removed_in: &'static str,
+}
+
+// Renamed EDF keys. Files using the old names keep working, with a
+// deprecation warning in their ValidationReport.
+pub const EDF_KEY_ALIASES: &[KeyAlias] = &[];
+
+// An old key name found in a file.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Deprecation {
+    pub old: String,
+    pub new: String,
+    pub removed_in: String,
+}
+
+impl Deprecation {
+    pub fn msg(&self) -> String {
+        format!(
+            "key \"{}\" is deprecated and will be removed in {}, use \"{}\" instead",
+            self.old, self.removed_in, self.new
+        )
+    }
+}
+
+// Move the values of old keys to their new name. When both names are set
+// the new one wins, the old key is dropped either way.
+pub(crate) fn resolve_aliases(doc: &mut Value, aliases: &[KeyAlias]) -> Vec<Deprecation> {
+    let mut res = vec![];
+    for a in aliases {
+        let value = match take_path(doc, a.old) {
+            Some(v) => v,
+            None => continue,
+        };
+        if get_path(doc, a.new).is_none() {
+            set_path(doc, a.new, value);
+        }
+        res.push(Deprecation {
+            old: String::from(a.old),
+            new: String::from(a.new),
+            removed_in: String::from(a.removed_in),
+        });
+    }
+    res
+}
+
+fn get_path<'a>(doc: &'a Value, path: &str) -> Option<&'a Value> {
+    let mut cur = doc;
+    for k in path.split('.') {
+        cur = cur.as_object()?.get(k)?;
+    }
+    Some(cur)
+}
+
+fn take_path(doc: &mut Value, path: &str) -> Option<Value> {
+    let (parent, key) = match path.rsplit_once('.') {
+        Some((p, k)) => (p.split('.').collect::<Vec<_>>(), k),
+        None => (vec![], path),
+    };
+    let mut cur = doc;
+    for k in parent {
+        cur = cur.as_object_mut()?.get_mut(k)?;
+    }
+    cur.as_object_mut()?.remove(key)
+}
+
+fn set_path(doc: &mut Value, path: &str, value: Value) {
+    let keys: Vec<&str> = path.split('.').collect();
+    let mut cur = doc;
+    for k in keys[..keys.len() - 1].iter() {
+        let t = match cur.as_object_mut() {
+            Some(t) => t,
+            None => return,
+        };
+        cur = t.entry(k.to_string()).or_insert(Value::Object(Map::new()));
+    }
+    if let Some(t) = cur.as_object_mut() {
+        t.insert(keys[keys.len() - 1].to_string(), value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aliases_resolve() {
+        let aliases = [
+            KeyAlias {
+                old: "parallax_imagestore",
+                new: "parallax.imagestore",
+                removed_in: "2.0",
+            },
+            KeyAlias {
+                old: "workingdir",
+                new: "workdir",
+                removed_in: "2.0",
+            },
+        ];
+
+        let mut doc: Value =
+            serde_json::from_str(r#"{"parallax_imagestore": "/scratch/is", "workingdir": "/a", "workdir": "/b"}"#)
+                .unwrap();
+        let deprecations = resolve_aliases(&mut doc, &aliases);
+
+        assert!(deprecations.len() == 2);
+        assert!(deprecations[0].msg().contains("\"parallax.imagestore\""));
+        assert!(doc["parallax"]["imagestore"] == "/scratch/is");
+        assert!(doc["workdir"] == "/b");
+        assert!(doc.get("workingdir").is_none() && doc.get("parallax_imagestore").is_none());
+
+        let mut doc: Value = serde_json::from_str(r#"{"image": "ubuntu"}"#).unwrap();
+        assert!(resolve_aliases(&mut doc, &aliases).is_empty());
+    }
+}
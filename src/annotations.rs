@@ -0,0 +1,332 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::error::{SarusError, SarusErrorKind, SarusResult};
+
+// Prefix of the annotations setting Sarus options.
+pub const SARUS_ANNOTATION_PREFIX: &str = "com.sarus.";
+
+// An annotation whose value can't be parsed, it's left unset in the view.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MalformedAnnotation {
+    pub key: String,
+    pub value: String,
+    pub msg: String,
+}
+
+// Typed view of the com.sarus.* annotations of an EDF. Entries are None
+// when the annotation is missing or malformed, booleans are "true" or
+// "false" and a leading ~ of paths is expanded to the home directory.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SarusAnnotations {
+    pub hooks_parallax_imagestore_create: Option<bool>,
+    pub parallax_imagestore: Option<String>,
+    pub parallax_imagestore_keepalive: Option<bool>,
+    pub parallax_mount_program: Option<String>,
+    pub parallax_mp_logfile: Option<String>,
+    pub parallax_mp_squashfuse_path: Option<String>,
+    pub parallax_path: Option<String>,
+    pub perfmon: Option<bool>,
+    pub podman_module: Option<String>,
+    pub podman_path: Option<String>,
+    pub podman_tmp_path: Option<String>,
+    pub runtime_path: Option<String>,
+    pub skybox_enabled: Option<bool>,
+    pub tracking_enabled: Option<bool>,
+    pub tracking_tool: Option<String>,
+    pub malformed: Vec<MalformedAnnotation>,
+}
+
+impl SarusAnnotations {
+    pub fn parse(annotations: &BTreeMap<String, String>) -> SarusAnnotations {
+        let mut a = SarusAnnotations::default();
+        for key in annotations.keys() {
+            let name = match key.strip_prefix(SARUS_ANNOTATION_PREFIX) {
+                Some(n) => n,
+                None => continue,
+            };
+            let value = &annotations[key];
+            match name {
+                "hooks.parallax_imagestore_create" => a.hooks_parallax_imagestore_create = a.boolean(key, value),
+                "parallax_imagestore" => a.parallax_imagestore = Some(path(value)),
+                "parallax_imagestore_keepalive" => a.parallax_imagestore_keepalive = a.boolean(key, value),
+                "parallax_mount_program" => a.parallax_mount_program = Some(path(value)),
+                "parallax_mp_logfile" => a.parallax_mp_logfile = Some(path(value)),
+                "parallax_mp_squashfuse_path" => a.parallax_mp_squashfuse_path = Some(path(value)),
+                "parallax_path" => a.parallax_path = Some(path(value)),
+                "perfmon" => a.perfmon = a.boolean(key, value),
+                "podman_module" => a.podman_module = Some(value.clone()),
+                "podman_path" => a.podman_path = Some(path(value)),
+                "podman_tmp_path" => a.podman_tmp_path = Some(path(value)),
+                "runtime_path" => a.runtime_path = Some(path(value)),
+                "skybox_enabled" => a.skybox_enabled = a.boolean(key, value),
+                "tracking_enabled" => a.tracking_enabled = a.boolean(key, value),
+                "tracking_tool" => a.tracking_tool = Some(value.clone()),
+                _ => {}
+            }
+        }
+        a
+    }
+
+    fn boolean(&mut self, key: &str, value: &str) -> Option<bool> {
+        match value {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => {
+                self.malformed.push(MalformedAnnotation {
+                    key: String::from(key),
+                    value: String::from(value),
+                    msg: format!("annotation {key} expects true or false, got \"{value}\""),
+                });
+                None
+            }
+        }
+    }
+}
+
+fn path(value: &str) -> String {
+    shellexpand::tilde(value).into_owned()
+}
+
+// Settings of the com.sarus.* annotations, without the prefix.
+pub(crate) const SARUS_SETTINGS: [&str; 15] = [
+    "hooks.parallax_imagestore_create",
+    "parallax_imagestore",
+    "parallax_imagestore_keepalive",
+    "parallax_mount_program",
+    "parallax_mp_logfile",
+    "parallax_mp_squashfuse_path",
+    "parallax_path",
+    "perfmon",
+    "podman_module",
+    "podman_path",
+    "podman_tmp_path",
+    "runtime_path",
+    "skybox_enabled",
+    "tracking_enabled",
+    "tracking_tool",
+];
+
+// Prefix of the annotations read by the OCI hooks.
+pub const HOOK_ANNOTATION_PREFIX: &str = "com.hooks.";
+
+// com.sarus.* annotations taking true or false.
+const SARUS_BOOLEAN_ANNOTATIONS: [&str; 5] = [
+    "hooks.parallax_imagestore_create",
+    "parallax_imagestore_keepalive",
+    "perfmon",
+    "skybox_enabled",
+    "tracking_enabled",
+];
+
+// A step rewriting or checking the annotations of rendered EDFs, run once
+// the templates, MPI and GPUs are applied.
+pub trait AnnotationProcessor: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn process(&self, annotations: &mut BTreeMap<String, String>) -> SarusResult<()>;
+}
+
+// Processors run in turn on the annotations of rendered EDFs, the built-in
+// ones by default.
+#[derive(Clone)]
+pub struct AnnotationProcessors {
+    processors: Vec<Arc<dyn AnnotationProcessor>>,
+}
+
+impl Default for AnnotationProcessors {
+    fn default() -> Self {
+        AnnotationProcessors::builtin()
+    }
+}
+
+impl std::fmt::Debug for AnnotationProcessors {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
+
+impl AnnotationProcessors {
+    // No processing at all.
+    pub fn empty() -> AnnotationProcessors {
+        AnnotationProcessors { processors: vec![] }
+    }
+
+    // The conventions of the CSCS hooks: shorthands expanded, then booleans
+    // normalized, then the values of the known annotations checked.
+    pub fn builtin() -> AnnotationProcessors {
+        let mut p = AnnotationProcessors::empty();
+        p.register(HookShorthand);
+        p.register(BooleanNormalizer);
+        p.register(HookValueCheck);
+        p
+    }
+
+    pub fn register<P: AnnotationProcessor + 'static>(&mut self, processor: P) {
+        self.processors.push(Arc::new(processor));
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.processors.iter().map(|p| p.name()).collect()
+    }
+
+    pub fn process(&self, annotations: &mut BTreeMap<String, String>) -> SarusResult<()> {
+        for p in self.processors.iter() {
+            p.process(annotations)?;
+        }
+        Ok(())
+    }
+}
+
+fn invalid_annotation(key: &str, msg: String) -> SarusError {
+    SarusError {
+        kind: SarusErrorKind::InvalidAnnotation { key: String::from(key) },
+        file_path: None,
+        msg,
+        location: None,
+    }
+}
+
+fn is_boolean_annotation(key: &str) -> bool {
+    match key.strip_prefix(SARUS_ANNOTATION_PREFIX) {
+        Some(n) => SARUS_BOOLEAN_ANNOTATIONS.contains(&n),
+        None => key.starts_with(HOOK_ANNOTATION_PREFIX) && key.ends_with(".enabled"),
+    }
+}
+
+// com.hooks.NAME = "true" stands for com.hooks.NAME.enabled = "true", the
+// full form winning when both are set.
+pub struct HookShorthand;
+
+impl AnnotationProcessor for HookShorthand {
+    fn name(&self) -> &'static str {
+        "hook-shorthand"
+    }
+
+    fn process(&self, annotations: &mut BTreeMap<String, String>) -> SarusResult<()> {
+        let shorthands: Vec<String> = annotations
+            .keys()
+            .filter(|k| k.strip_prefix(HOOK_ANNOTATION_PREFIX).is_some_and(|n| !n.is_empty() && !n.contains('.')))
+            .cloned()
+            .collect();
+        for k in shorthands {
+            let v = annotations.remove(&k).unwrap_or_default();
+            annotations.entry(format!("{k}.enabled")).or_insert(v);
+        }
+        Ok(())
+    }
+}
+
+// Booleans written as yes/no, on/off, 1/0 or in capitals become true and
+// false.
+pub struct BooleanNormalizer;
+
+impl AnnotationProcessor for BooleanNormalizer {
+    fn name(&self) -> &'static str {
+        "boolean"
+    }
+
+    fn process(&self, annotations: &mut BTreeMap<String, String>) -> SarusResult<()> {
+        for (k, v) in annotations.iter_mut() {
+            if !is_boolean_annotation(k) {
+                continue;
+            }
+            match v.to_lowercase().as_str() {
+                "true" | "yes" | "on" | "1" => *v = String::from("true"),
+                "false" | "no" | "off" | "0" => *v = String::from("false"),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+// Values of the annotations known to the hooks: booleans, the GPU vendor
+// and the port of the SSH hook.
+pub struct HookValueCheck;
+
+impl AnnotationProcessor for HookValueCheck {
+    fn name(&self) -> &'static str {
+        "hook-values"
+    }
+
+    fn process(&self, annotations: &mut BTreeMap<String, String>) -> SarusResult<()> {
+        for (k, v) in annotations.iter() {
+            if is_boolean_annotation(k) && v != "true" && v != "false" {
+                return Err(invalid_annotation(k, format!("annotation {k} expects true or false, got \"{v}\"")));
+            }
+            match k.as_str() {
+                "com.hooks.gpu.vendor" if v != "nvidia" && v != "amd" => {
+                    return Err(invalid_annotation(k, format!("annotation {k} expects nvidia or amd, got \"{v}\"")));
+                }
+                "com.hooks.ssh.port" if v.parse::<u16>().is_err() => {
+                    return Err(invalid_annotation(k, format!("annotation {k} expects a port number, got \"{v}\"")));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sarus_annotations_parse() {
+        let h = BTreeMap::from([
+            (String::from("com.sarus.perfmon"), String::from("true")),
+            (String::from("com.sarus.skybox_enabled"), String::from("yes")),
+            (String::from("com.sarus.podman_tmp_path"), String::from("~/tmp")),
+            (String::from("com.sarus.hooks.parallax_imagestore_create"), String::from("false")),
+            (String::from("com.hooks.ssh.enabled"), String::from("true")),
+        ]);
+        let a = SarusAnnotations::parse(&h);
+
+        assert!(a.perfmon == Some(true));
+        assert!(a.skybox_enabled.is_none());
+        assert!(a.hooks_parallax_imagestore_create == Some(false));
+        assert!(!a.podman_tmp_path.unwrap().starts_with('~'));
+        assert!(a.malformed.len() == 1 && a.malformed[0].key == "com.sarus.skybox_enabled");
+    }
+
+    #[test]
+    fn annotation_processors() {
+        let mut h = BTreeMap::from([
+            (String::from("com.hooks.ssh"), String::from("yes")),
+            (String::from("com.hooks.cxi"), String::from("on")),
+            (String::from("com.hooks.cxi.enabled"), String::from("False")),
+            (String::from("com.sarus.perfmon"), String::from("1")),
+            (String::from("com.example.flag"), String::from("yes")),
+        ]);
+        let p = AnnotationProcessors::builtin();
+        p.process(&mut h).unwrap();
+        assert!(h["com.hooks.ssh.enabled"] == "true" && !h.contains_key("com.hooks.ssh"));
+        assert!(h["com.hooks.cxi.enabled"] == "false");
+        assert!(h["com.sarus.perfmon"] == "true" && h["com.example.flag"] == "yes");
+
+        for (k, v) in [("com.hooks.gpu.enabled", "maybe"), ("com.hooks.gpu.vendor", "intel"), ("com.hooks.ssh.port", "ssh")] {
+            let mut h = BTreeMap::from([(String::from(k), String::from(v))]);
+            let err = p.process(&mut h).err().unwrap();
+            assert!(err.kind == SarusErrorKind::InvalidAnnotation { key: String::from(k) });
+        }
+
+        struct Site;
+        impl AnnotationProcessor for Site {
+            fn name(&self) -> &'static str {
+                "site"
+            }
+            fn process(&self, annotations: &mut BTreeMap<String, String>) -> SarusResult<()> {
+                annotations.insert(String::from("ch.cscs.site"), String::from("alps"));
+                Ok(())
+            }
+        }
+        let mut p = AnnotationProcessors::empty();
+        p.register(Site);
+        let mut h = BTreeMap::from([(String::from("com.hooks.ssh"), String::from("yes"))]);
+        p.process(&mut h).unwrap();
+        assert!(p.names() == vec!["site"] && h.len() == 2 && h["com.hooks.ssh"] == "yes");
+    }
+}
@@ -0,0 +1,390 @@
+use std::process::ExitCode;
+
+use raster::error::{SarusError, SarusResult};
+use raster::mount::MountEntry;
+use raster::{
+    CONFIG_PATH, EDF, LintReport, MigrateOptions, RawEDF, RenderOptions, SkeletonKind, ValidationReport, diff, explain,
+    get_search_paths, get_site_render_options, lint_to_sarif, lint_with_options, list_environments,
+    migrate_with_options, render_with_options, render_with_overrides, validate_config_dir, validate_with_report,
+    validation_to_sarif,
+};
+
+const USAGE: &str = "usage: raster <command> [options] <edf>...
+
+commands:
+  render <edf>          render an EDF, as TOML or JSON
+  validate <edf>...     validate EDF files against the schema
+  validate-config [dir] validate the config files, of /etc/sarus-suite by default
+  lint <edf>...         check EDF files against the lint rules
+  diff <edf> <edf>      compare two rendered EDFs
+  explain <edf>         list the steps taken to render an EDF
+  list                  list the environments of the search paths
+  init <kind>           print a starter EDF: plain, gpu-mpi, squashfs or dev
+  migrate <edf>...      list the changes moving EDFs to the current layout
+  completion bash       print the bash completion of raster
+
+options:
+  --json                machine-readable output
+  --format <format>     output as text, json or, for validate and lint, sarif
+  --strict              reject unknown keys when rendering
+  --search-path <dir>   EDF search path, can be repeated
+  --reveal-secrets      don't redact sensitive env values
+  --image <image>       render: override the image of the EDF
+  --mount <mount>       render: add a mount on top of the EDF, can be repeated
+  --verify-mounts       render: check that the mount sources exist
+  --write               migrate: write the migrated EDFs back
+  --table-mounts        migrate: write mounts as tables
+
+exit status:
+  0                     success
+  1                     invalid files, lint errors or differing EDFs
+  2                     wrong usage
+  10 + code             failure with the given error code";
+
+// Exit status of a failure is offset by the error code, so that scripts can
+// tell error kinds apart while 0 and 1 keep their usual meaning.
+const EXIT_ERROR_BASE: u64 = 10;
+
+struct Args {
+    command: String,
+    json: bool,
+    sarif: bool,
+    strict: bool,
+    reveal_secrets: bool,
+    search_paths: Vec<String>,
+    image: Option<String>,
+    mounts: Vec<String>,
+    verify_mounts: bool,
+    write: bool,
+    table_mounts: bool,
+    files: Vec<String>,
+}
+
+fn parse_args(argv: Vec<String>) -> Result<Args, String> {
+    let mut it = argv.into_iter();
+    let command = match it.next() {
+        Some(c) => c,
+        None => return Err(String::from("missing command")),
+    };
+
+    let mut args = Args {
+        command,
+        json: false,
+        sarif: false,
+        strict: false,
+        reveal_secrets: false,
+        search_paths: vec![],
+        image: None,
+        mounts: vec![],
+        verify_mounts: false,
+        write: false,
+        table_mounts: false,
+        files: vec![],
+    };
+    while let Some(a) = it.next() {
+        match a.as_str() {
+            "--json" => args.json = true,
+            "--strict" => args.strict = true,
+            "--reveal-secrets" => args.reveal_secrets = true,
+            "--verify-mounts" => args.verify_mounts = true,
+            "--write" => args.write = true,
+            "--table-mounts" => args.table_mounts = true,
+            "--format" => match it.next().as_deref() {
+                Some("text") => {}
+                Some("json") => args.json = true,
+                Some("sarif") => args.sarif = true,
+                _ => return Err(String::from("--format expects text, json or sarif")),
+            },
+            "--search-path" => match it.next() {
+                Some(p) => args.search_paths.push(p),
+                None => return Err(String::from("--search-path expects a directory")),
+            },
+            "--image" => match it.next() {
+                Some(i) => args.image = Some(i),
+                None => return Err(String::from("--image expects an image")),
+            },
+            "--mount" => match it.next() {
+                Some(m) => args.mounts.push(m),
+                None => return Err(String::from("--mount expects a mount")),
+            },
+            o if o.starts_with("--") => return Err(format!("unknown option {o}")),
+            _ => args.files.push(a),
+        }
+    }
+
+    let expected = match args.command.as_str() {
+        "render" | "explain" => args.files.len() == 1,
+        "validate" | "lint" | "migrate" => !args.files.is_empty(),
+        "diff" => args.files.len() == 2,
+        "list" => args.files.is_empty(),
+        "validate-config" => args.files.len() <= 1,
+        "init" | "completion" => args.files.len() == 1,
+        c => return Err(format!("unknown command {c}")),
+    };
+    if !expected {
+        return Err(format!("wrong number of files for {}", args.command));
+    }
+    if args.sarif && !["validate", "validate-config", "lint"].contains(&args.command.as_str()) {
+        return Err(format!("{} has no sarif output", args.command));
+    }
+    Ok(args)
+}
+
+fn render_options(args: &Args) -> SarusResult<RenderOptions> {
+    let mut opts = get_site_render_options()?
+        .strict(args.strict)
+        .reveal_secrets(args.reveal_secrets)
+        .verify_mounts(args.verify_mounts);
+    if !args.search_paths.is_empty() {
+        opts.search_paths = Some(args.search_paths.clone());
+    }
+    Ok(opts)
+}
+
+fn to_json<T: serde::Serialize>(v: &T) -> String {
+    serde_json::to_string_pretty(v).unwrap_or_default()
+}
+
+fn print_edf(edf: &EDF, json: bool) -> SarusResult<()> {
+    match json {
+        true => println!("{}", edf.to_json()?),
+        false => print!("{}", edf.to_toml_string()?),
+    }
+    Ok(())
+}
+
+fn run_render(args: &Args) -> SarusResult<bool> {
+    let opts = render_options(args)?;
+    let edf = match args.image.is_some() || !args.mounts.is_empty() {
+        true => {
+            let overrides = RawEDF {
+                image: args.image.clone(),
+                mounts: Some(args.mounts.iter().cloned().map(MountEntry::Spec).collect()),
+                ..Default::default()
+            };
+            render_with_overrides(args.files[0].clone(), overrides, &opts)?
+        }
+        false => render_with_options(args.files[0].clone(), &opts)?,
+    };
+    for w in edf.warnings.iter() {
+        eprintln!("warning: {w}");
+    }
+    print_edf(&edf, args.json)?;
+    Ok(true)
+}
+
+fn run_validate(args: &Args) -> SarusResult<bool> {
+    let mut reports: Vec<ValidationReport> = vec![];
+    for f in args.files.iter() {
+        reports.push(validate_with_report(f.clone())?);
+    }
+    print_validation(args, &reports)
+}
+
+fn run_validate_config(args: &Args) -> SarusResult<bool> {
+    let dir = match args.files.first() {
+        Some(d) => d.as_str(),
+        None => CONFIG_PATH,
+    };
+    let reports = validate_config_dir(std::path::Path::new(dir))?;
+    print_validation(args, &reports)
+}
+
+fn print_validation(args: &Args, reports: &[ValidationReport]) -> SarusResult<bool> {
+    if args.sarif {
+        println!("{}", validation_to_sarif(reports)?);
+    } else if args.json {
+        println!("{}", to_json(&reports));
+    } else {
+        for r in reports.iter() {
+            let status = if r.is_valid() { "ok" } else { "invalid" };
+            println!("{}: {status}", r.file_path);
+            for d in r.errors.iter() {
+                println!("  error[{}]: {}", d.id, d.msg);
+            }
+            for d in r.warnings.iter() {
+                println!("  warning[{}]: {}", d.id, d.msg);
+            }
+        }
+    }
+    Ok(reports.iter().all(|r| r.is_valid()))
+}
+
+fn run_lint(args: &Args) -> SarusResult<bool> {
+    let opts = render_options(args)?;
+    let mut reports: Vec<LintReport> = vec![];
+    for f in args.files.iter() {
+        reports.push(lint_with_options(f.clone(), &opts)?);
+    }
+
+    if args.sarif {
+        println!("{}", lint_to_sarif(&reports)?);
+    } else if args.json {
+        println!("{}", to_json(&reports));
+    } else {
+        for r in reports.iter() {
+            for f in r.findings.iter() {
+                let sev = format!("{:?}", f.severity).to_lowercase();
+                println!("{}: {sev}[{}]: {}", r.file_path, f.rule, f.msg);
+            }
+        }
+    }
+    Ok(reports.iter().all(|r| !r.has_errors()))
+}
+
+fn run_diff(args: &Args) -> SarusResult<bool> {
+    let opts = render_options(args)?;
+    let a = render_with_options(args.files[0].clone(), &opts)?;
+    let b = render_with_options(args.files[1].clone(), &opts)?;
+
+    let d = diff(&a, &b);
+    match args.json {
+        true => println!("{}", d.to_json()?),
+        false if !d.is_empty() => println!("{d}"),
+        false => {}
+    }
+    Ok(d.is_empty())
+}
+
+fn run_explain(args: &Args) -> SarusResult<bool> {
+    let opts = render_options(args)?;
+    let trace = explain(args.files[0].clone(), &opts);
+    match args.json {
+        true => println!("{}", trace.to_json()?),
+        false => println!("{trace}"),
+    }
+    Ok(!trace.has_failures())
+}
+
+fn run_list(args: &Args) -> SarusResult<bool> {
+    let sp = match args.search_paths.is_empty() {
+        true => get_search_paths(),
+        false => args.search_paths.clone(),
+    };
+    let envs = list_environments(&sp);
+    match args.json {
+        true => println!("{}", to_json(&envs)),
+        false => {
+            for e in envs.iter() {
+                let image = e.image.clone().unwrap_or_default();
+                let description = e.description.clone().unwrap_or_default();
+                println!("{}\t{image}\t{description}", e.name);
+            }
+        }
+    }
+    Ok(true)
+}
+
+fn run_migrate(args: &Args) -> SarusResult<bool> {
+    let opts = MigrateOptions {
+        table_mounts: args.table_mounts,
+    };
+    let mut changes = vec![];
+    for f in args.files.iter() {
+        let m = migrate_with_options(f, &opts)?;
+        if args.write && m.is_changed() {
+            m.document.write()?;
+        }
+        changes.push((f.clone(), m.changes));
+    }
+
+    if args.json {
+        println!("{}", to_json(&changes));
+    } else {
+        for (f, cs) in changes.iter() {
+            for c in cs.iter() {
+                println!("{f}: {}", c.msg);
+            }
+        }
+    }
+    Ok(true)
+}
+
+fn run_init(args: &Args) -> SarusResult<bool> {
+    match SkeletonKind::from_name(&args.files[0]) {
+        Some(k) => {
+            print!("{}", EDF::skeleton(k));
+            Ok(true)
+        }
+        None => {
+            let kinds: Vec<&str> = SkeletonKind::ALL.iter().map(|k| k.name()).collect();
+            eprintln!("raster: unknown kind {}, expected one of {}", args.files[0], kinds.join(", "));
+            Ok(false)
+        }
+    }
+}
+
+const BASH_COMPLETION: &str = r#"_raster() {
+    local cur prev
+    cur="${COMP_WORDS[COMP_CWORD]}"
+    prev="${COMP_WORDS[COMP_CWORD-1]}"
+    if [ "$COMP_CWORD" -eq 1 ]; then
+        COMPREPLY=($(compgen -W "render validate validate-config lint diff explain list init migrate completion" -- "$cur"))
+        return
+    fi
+    case "$prev" in
+        --search-path) COMPREPLY=($(compgen -d -- "$cur")); return ;;
+        --format) COMPREPLY=($(compgen -W "text json sarif" -- "$cur")); return ;;
+        --image|--mount) return ;;
+    esac
+    case "${COMP_WORDS[1]}" in
+        init) COMPREPLY=($(compgen -W "KINDS" -- "$cur")); return ;;
+        completion) COMPREPLY=($(compgen -W "bash" -- "$cur")); return ;;
+    esac
+    if [[ "$cur" == --* ]]; then
+        COMPREPLY=($(compgen -W "--json --format --strict --search-path --reveal-secrets --image --mount --verify-mounts --write --table-mounts" -- "$cur"))
+    else
+        COMPREPLY=($(compgen -f -X '!*.@(toml|yaml|yml)' -- "$cur") $(compgen -d -- "$cur"))
+    fi
+}
+complete -o filenames -F _raster raster
+"#;
+
+fn run_completion(args: &Args) -> SarusResult<bool> {
+    if args.files[0] != "bash" {
+        eprintln!("raster: no completion for {}, only bash is supported", args.files[0]);
+        return Ok(false);
+    }
+    let kinds: Vec<&str> = SkeletonKind::ALL.iter().map(|k| k.name()).collect();
+    print!("{}", BASH_COMPLETION.replace("KINDS", &kinds.join(" ")));
+    Ok(true)
+}
+
+fn report_error(e: &SarusError, json: bool) -> ExitCode {
+    match json {
+        true => eprintln!("{}", e.to_json().unwrap_or_default()),
+        false => eprintln!("{e}"),
+    }
+    let status = EXIT_ERROR_BASE + e.code();
+    ExitCode::from(u8::try_from(status).unwrap_or(u8::MAX))
+}
+
+fn main() -> ExitCode {
+    let argv: Vec<String> = std::env::args().skip(1).collect();
+    let args = match parse_args(argv) {
+        Ok(a) => a,
+        Err(e) => {
+            eprintln!("raster: {e}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let res = match args.command.as_str() {
+        "render" => run_render(&args),
+        "validate" => run_validate(&args),
+        "validate-config" => run_validate_config(&args),
+        "lint" => run_lint(&args),
+        "explain" => run_explain(&args),
+        "list" => run_list(&args),
+        "init" => run_init(&args),
+        "migrate" => run_migrate(&args),
+        "completion" => run_completion(&args),
+        _ => run_diff(&args),
+    };
+
+    match res {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => report_error(&e, args.json),
+    }
+}
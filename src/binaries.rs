@@ -0,0 +1,121 @@
+use std::path::{Path, PathBuf};
+
+use crate::Config;
+use crate::error::{SarusError, SarusErrorKind, SarusResult};
+
+// Programs of the site config run when launching containers, checked by the
+// preflight of RenderOptions::check_binaries. Empty paths are not checked.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BinaryPaths {
+    pub podman_path: String,
+    pub parallax_path: String,
+    pub parallax_mount_program: String,
+    pub runtime_path: String,
+}
+
+impl BinaryPaths {
+    pub fn from_config(config: &Config) -> Self {
+        BinaryPaths {
+            podman_path: config.podman_path.clone(),
+            parallax_path: config.parallax_path.clone(),
+            parallax_mount_program: config.parallax_mount_program.clone(),
+            runtime_path: config.runtime_path.clone(),
+        }
+    }
+
+    fn entries(&self) -> [(&'static str, &str); 4] {
+        [
+            ("podman_path", &self.podman_path),
+            ("parallax_path", &self.parallax_path),
+            ("parallax_mount_program", &self.parallax_mount_program),
+            ("runtime_path", &self.runtime_path),
+        ]
+    }
+}
+
+#[cfg(unix)]
+fn is_executable_file(p: &Path) -> bool {
+    use is_executable::IsExecutable;
+    p.is_file() && p.is_executable()
+}
+
+#[cfg(not(unix))]
+fn is_executable_file(p: &Path) -> bool {
+    p.is_file()
+}
+
+// The executable a program name runs, searched in the directories of
+// path_var when it has no slash.
+pub fn resolve_binary(program: &str, path_var: &str) -> Option<PathBuf> {
+    if program.contains('/') {
+        let p = PathBuf::from(program);
+        return is_executable_file(&p).then_some(p);
+    }
+    std::env::split_paths(path_var)
+        .map(|d| d.join(program))
+        .find(|p| is_executable_file(p))
+}
+
+// Check that every binary resolves to an executable file, the failing ones
+// being reported at once. path_var is the PATH the binaries are searched
+// in.
+pub fn check_binaries(binaries: &BinaryPaths, path_var: &str) -> SarusResult<()> {
+    let mut names = vec![];
+    let mut problems = vec![];
+    for (name, program) in binaries.entries() {
+        if program.is_empty() || resolve_binary(program, path_var).is_some() {
+            continue;
+        }
+        names.push(String::from(name));
+        problems.push(format!("{name} \"{program}\""));
+    }
+
+    if names.is_empty() {
+        return Ok(());
+    }
+    Err(SarusError {
+        kind: SarusErrorKind::BinariesNotFound { names },
+        file_path: None,
+        msg: format!("not executable or not found: {}", problems.join(", ")),
+        location: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RenderOptions, render_from_str_with_options};
+
+    #[test]
+    fn binaries_preflight() {
+        let path_var = "/nonexistent:/bin:/usr/bin";
+        assert!(resolve_binary("sh", path_var).is_some());
+        assert!(resolve_binary("/bin/sh", "").is_some());
+        assert!(resolve_binary("/etc/passwd", "").is_none());
+        assert!(resolve_binary("sh", "/nonexistent").is_none());
+
+        let binaries = BinaryPaths {
+            podman_path: String::from("sh"),
+            parallax_path: String::from("/nonexistent/parallax"),
+            parallax_mount_program: String::new(),
+            runtime_path: String::from("no-such-runtime"),
+        };
+        let err = check_binaries(&binaries, path_var).err().unwrap();
+        let expected = vec![String::from("parallax_path"), String::from("runtime_path")];
+        assert!(err.kind == SarusErrorKind::BinariesNotFound { names: expected });
+        assert!(err.msg.contains("runtime_path \"no-such-runtime\""));
+
+        let env = std::collections::HashMap::from([(String::from("PATH"), String::from(path_var))]);
+        let opts = RenderOptions::new().env(env).check_binaries(binaries);
+        let content = String::from("image = \"ubuntu\"\n");
+        let err = render_from_str_with_options(content.clone(), "socket", &opts).err().unwrap();
+        assert!(err.code() == 60);
+
+        let ok = BinaryPaths {
+            podman_path: String::from("sh"),
+            ..Default::default()
+        };
+        let opts = opts.check_binaries(ok);
+        assert!(render_from_str_with_options(content, "socket", &opts).is_ok());
+    }
+}
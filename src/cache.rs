@@ -0,0 +1,314 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::hash::{DefaultHasher, Hasher};
+use std::io::{Read, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt};
+use std::path::PathBuf;
+
+use regex::Regex;
+
+use crate::common::ExpansionBackend;
+use crate::error::SarusResult;
+use crate::git::is_git_ref;
+use crate::options::RenderOptions;
+use crate::provenance::Provenance;
+use crate::remote::is_remote;
+use crate::template::builtin_template_vars;
+use crate::{EDF, RawEDF, RenderWalk, edf_from_raw_with_hooks, finish_edf, get_search_paths, render_inner_loop, resolve_env_path};
+
+// Bumped when the format of the entries changes.
+const CACHE_FORMAT: u32 = 1;
+
+// On-disk cache of rendered EDFs, for callers rendering the same EDF over
+// and over, e.g. once per task of a job. An entry holds the merged files of
+// the EDF and is valid as long as its files, the way its names resolve and
+// the variables they reference are unchanged. The site steps of the render
+// (registries, templates, hooks, policy, ...) run on every call.
+//
+// Entries hold unredacted env values, they are private to the user. Only
+// the ones the user owns and nobody else can read or write are used, and
+// keys are per user, so that a shared directory serves no forged entry.
+#[derive(Clone, Debug)]
+pub struct RenderCache {
+    pub dir: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    format: u32,
+    // Names looked up, with the file they resolved to.
+    lookups: Vec<(String, String)>,
+    // Files read, with the hash of their content.
+    files: Vec<(String, u64)>,
+    // Variables referenced by the files, with the hash of their values.
+    vars: Vec<String>,
+    vars_hash: u64,
+    raw: RawEDF,
+}
+
+fn hash_str(h: &mut DefaultHasher, s: &str) {
+    h.write(s.as_bytes());
+    h.write_u8(0xff);
+}
+
+fn hash_content(content: &str) -> u64 {
+    let mut h = DefaultHasher::new();
+    hash_str(&mut h, content);
+    h.finish()
+}
+
+// Names of the $VAR and ${VAR} references of a file.
+fn referenced_vars(content: &str, vars: &mut BTreeSet<String>) {
+    let re = Regex::new(r"\$\{?([A-Za-z_][A-Za-z0-9_]*)").unwrap();
+    for c in re.captures_iter(content) {
+        vars.insert(c[1].to_string());
+    }
+}
+
+fn vars_hash(vars: &[String], options: &RenderOptions, templates: bool) -> u64 {
+    let mut h = DefaultHasher::new();
+    for v in vars {
+        let value = match &options.env {
+            Some(e) => e.get(v).cloned(),
+            None => std::env::var(v).ok(),
+        };
+        hash_str(&mut h, v);
+        match value {
+            Some(x) => hash_str(&mut h, &x),
+            None => h.write_u8(0),
+        }
+    }
+    if templates {
+        let builtins: BTreeMap<String, String> = builtin_template_vars().into_iter().collect();
+        for (k, v) in builtins {
+            hash_str(&mut h, &k);
+            hash_str(&mut h, &v);
+        }
+    }
+    h.finish()
+}
+
+impl RenderCache {
+    pub fn new(dir: &str) -> RenderCache {
+        RenderCache { dir: PathBuf::from(dir) }
+    }
+
+    // Render through the cache. EDFs fetched from remote locations and
+    // renders using the bash backend, whose output may change between two
+    // calls, are never cached.
+    pub fn render(&self, path: String, options: &RenderOptions) -> SarusResult<EDF> {
+        let sp = match &options.search_paths {
+            Some(s) => s.clone(),
+            None => get_search_paths(),
+        };
+        let entry_path = self.dir.join(format!("{:016x}.json", self.key(&path, &sp, options)));
+
+        let raw = match self.lookup(&entry_path, &sp, options) {
+            Some(r) => r,
+            None => {
+                let mut prov = Some(Provenance::default());
+                let raw = render_inner_loop(path.clone(), &sp, &mut RenderWalk::default(), &mut prov, options, false)?;
+                if options.expansion_backend == ExpansionBackend::Native
+                    && let Some(p) = prov
+                {
+                    self.store(&entry_path, &path, p, &raw, options);
+                }
+                raw
+            }
+        };
+
+        let e = edf_from_raw_with_hooks(raw, options)?;
+        finish_edf(e, options)
+    }
+
+    // Remove every entry of the cache.
+    pub fn clear(&self) -> std::io::Result<()> {
+        match std::fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    // Entries are per EDF and per set of options affecting the merge of
+    // its files.
+    fn key(&self, path: &str, sp: &[String], options: &RenderOptions) -> u64 {
+        let mut h = DefaultHasher::new();
+        h.write_u32(CACHE_FORMAT);
+        h.write_u32(nix::unistd::geteuid().as_raw());
+        hash_str(&mut h, path);
+        for s in sp {
+            hash_str(&mut h, s);
+        }
+        h.write_u8(options.strict as u8);
+        h.write_u8(options.skip_validation as u8);
+        h.write_u64(options.max_levels);
+        let template_vars: BTreeMap<_, _> = options.template_vars.iter().collect();
+        for (k, v) in template_vars {
+            hash_str(&mut h, k);
+            hash_str(&mut h, v);
+        }
+        for p in options.expansion_allowed_vars.iter().chain(options.expansion_denied_vars.iter()) {
+            hash_str(&mut h, p);
+        }
+        h.write_u8(options.merge_policy as u8);
+        let fields: BTreeMap<_, _> = options.expansion_fields.iter().collect();
+        for (k, v) in fields {
+            hash_str(&mut h, k);
+            h.write_u8(*v as u8);
+        }
+        h.finish()
+    }
+
+    fn lookup(&self, entry_path: &PathBuf, sp: &Vec<String>, options: &RenderOptions) -> Option<RawEDF> {
+        let mut f = std::fs::File::open(entry_path).ok()?;
+        let meta = f.metadata().ok()?;
+        if !meta.is_file() || meta.uid() != nix::unistd::geteuid().as_raw() || meta.mode() & 0o7777 != 0o600 {
+            return None;
+        }
+        let mut content = String::new();
+        f.read_to_string(&mut content).ok()?;
+        let entry: CacheEntry = serde_json::from_str(&content).ok()?;
+        if entry.format != CACHE_FORMAT {
+            return None;
+        }
+
+        let xopts = options.expand_options();
+        for (name, file) in entry.lookups.iter() {
+            let now = resolve_env_path(name.clone(), sp, &options.env, &xopts, &options.load_limits).ok()?;
+            if &now != file {
+                return None;
+            }
+        }
+        let mut templates = false;
+        for (file, hash) in entry.files.iter() {
+            let content = std::fs::read_to_string(file).ok()?;
+            if hash_content(&content) != *hash {
+                return None;
+            }
+            templates |= content.contains("%{");
+        }
+        if vars_hash(&entry.vars, options, templates) != entry.vars_hash {
+            return None;
+        }
+        Some(entry.raw)
+    }
+
+    // Write an entry, failures only cost a cache miss next time.
+    fn store(&self, entry_path: &PathBuf, path: &str, prov: Provenance, raw: &RawEDF, options: &RenderOptions) {
+        if prov.files.iter().any(|f| is_remote(f) || is_git_ref(f)) {
+            return;
+        }
+
+        let mut vars = BTreeSet::new();
+        referenced_vars(path, &mut vars);
+        let mut files = vec![];
+        let mut templates = false;
+        for f in prov.files {
+            let content = match std::fs::read_to_string(&f) {
+                Ok(c) => c,
+                Err(_) => return,
+            };
+            referenced_vars(&content, &mut vars);
+            templates |= content.contains("%{");
+            files.push((f, hash_content(&content)));
+        }
+        let vars: Vec<String> = vars.into_iter().collect();
+
+        let entry = CacheEntry {
+            format: CACHE_FORMAT,
+            lookups: prov.lookups,
+            files,
+            vars_hash: vars_hash(&vars, options, templates),
+            vars,
+            raw: raw.clone(),
+        };
+        let json = match serde_json::to_string(&entry) {
+            Ok(j) => j,
+            Err(_) => return,
+        };
+
+        if std::fs::DirBuilder::new().recursive(true).mode(0o700).create(&self.dir).is_err() {
+            return;
+        }
+        let tmp = entry_path.with_extension(format!("tmp.{}", std::process::id()));
+        let written = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&tmp)
+            .and_then(|mut f| f.write_all(json.as_bytes()));
+        match written {
+            Ok(_) => {
+                let _ = std::fs::rename(&tmp, entry_path);
+            }
+            Err(_) => {
+                let _ = std::fs::remove_file(&tmp);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn render_cache() {
+        let tmp = std::env::temp_dir().join(format!("raster-cache-{}", std::process::id()));
+        let edfs = tmp.join("edf");
+        std::fs::create_dir_all(&edfs).unwrap();
+        std::fs::write(edfs.join("base.toml"), "image = \"ubuntu:24.04\"\n").unwrap();
+        std::fs::write(
+            edfs.join("top.toml"),
+            "base_environment = \"base\"\nworkdir = \"$SCRATCH\"\n",
+        )
+        .unwrap();
+
+        let cache = RenderCache::new(&tmp.join("cache").display().to_string());
+        let env = |scratch: &str| Some(HashMap::from([(String::from("SCRATCH"), String::from(scratch))]));
+        let mut opts = RenderOptions::new().search_paths(vec![edfs.display().to_string()]);
+        opts.env = env("/scratch/a");
+
+        let edf = cache.render(String::from("top"), &opts).unwrap();
+        assert!(edf.image == "ubuntu:24.04" && edf.workdir == "/scratch/a");
+        assert!(std::fs::read_dir(&cache.dir).unwrap().count() == 1);
+
+        // Valid as long as nothing changed
+        let sp = opts.search_paths.clone().unwrap();
+        let key = cache.key("top", &sp, &opts);
+        let entry_path = cache.dir.join(format!("{key:016x}.json"));
+        assert!(cache.lookup(&entry_path, &sp, &opts).is_some());
+
+        // A referenced variable changed
+        opts.env = env("/scratch/b");
+        assert!(cache.lookup(&entry_path, &sp, &opts).is_none());
+        let edf = cache.render(String::from("top"), &opts).unwrap();
+        assert!(edf.workdir == "/scratch/b");
+
+        // A base file changed
+        std::fs::write(edfs.join("base.toml"), "image = \"ubuntu:26.04\"\n").unwrap();
+        assert!(cache.lookup(&entry_path, &sp, &opts).is_none());
+        let edf = cache.render(String::from("top"), &opts).unwrap();
+        assert!(edf.image == "ubuntu:26.04");
+
+        // Entries others may have written are not used
+        use std::os::unix::fs::PermissionsExt;
+        assert!(cache.lookup(&entry_path, &sp, &opts).is_some());
+        std::fs::set_permissions(&entry_path, std::fs::Permissions::from_mode(0o622)).unwrap();
+        assert!(cache.lookup(&entry_path, &sp, &opts).is_none());
+        std::fs::set_permissions(&entry_path, std::fs::Permissions::from_mode(0o600)).unwrap();
+
+        // A file shadowing the base in an earlier search path
+        let shadow = tmp.join("shadow");
+        std::fs::create_dir_all(&shadow).unwrap();
+        std::fs::write(shadow.join("base.toml"), "image = \"alpine\"\n").unwrap();
+        let sp2 = vec![shadow.display().to_string(), edfs.display().to_string()];
+        assert!(cache.lookup(&entry_path, &sp2, &opts).is_none());
+
+        cache.clear().unwrap();
+        assert!(!cache.dir.exists());
+        std::fs::remove_dir_all(tmp).unwrap();
+    }
+}
@@ -1,15 +1,259 @@
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::process::Command;
+use std::sync::{Arc, LazyLock, Mutex};
 
-use crate::error::{SarusError, SarusResult};
+use crate::error::{SarusError, SarusErrorKind, SarusResult};
+use crate::mount::MountCollision;
+use crate::secrets::glob_match;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ExpansionBackend {
+    #[default]
+    Native, // Expand variables in-process, without spawning any program.
+    Bash,   // Evaluate strings in a restricted bash shell.
+}
+
+// How the devices and mounts of merged EDFs are deduplicated. Entries keep
+// the order of the files they come from, mounts are set up in that order.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MergePolicy {
+    #[default]
+    PreserveOrderDedupe, // Drop repeated entries, the first one stays.
+    LastWinsByTarget,    // Same, and a mount replaces the earlier ones on its target.
+    KeepDuplicates,      // Keep every entry.
+}
+
+// Settings applied to every variable expansion of a render.
+#[derive(Clone, Debug, Default)]
+pub struct ExpandOptions {
+    pub backend: ExpansionBackend,
+    // Records the variables referenced by the expansions, when set.
+    pub audit: Option<ExpansionAudit>,
+    // Glob patterns of the variables which may be referenced, any when
+    // empty, and of the ones which may not.
+    pub allowed_vars: Vec<String>,
+    pub denied_vars: Vec<String>,
+    // Whether the variables of an EDF field are expanded, by field name.
+    // Fields not listed are.
+    pub fields: HashMap<String, bool>,
+    pub merge_policy: MergePolicy,
+    pub mount_collision: MountCollision,
+    // Collects the failures of independent fields, which are then left
+    // as is, instead of failing on the first one.
+    pub errors: Option<ErrorSink>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ErrorSink {
+    errors: Arc<Mutex<Vec<SarusError>>>,
+}
+
+impl ErrorSink {
+    pub fn take(&self) -> Vec<SarusError> {
+        match self.errors.lock() {
+            Ok(mut e) => std::mem::take(&mut *e),
+            Err(_) => vec![],
+        }
+    }
+}
+
+impl ExpandOptions {
+    // The result of processing a field, or the fallback when failures are
+    // collected.
+    pub(crate) fn collect<T>(&self, r: SarusResult<T>, fallback: T) -> SarusResult<T> {
+        match (r, &self.errors) {
+            (Err(e), Some(sink)) => {
+                if let Ok(mut v) = sink.errors.lock() {
+                    v.push(e);
+                }
+                Ok(fallback)
+            }
+            (r, _) => r,
+        }
+    }
+}
+
+// Drop repeated entries of a vector unless the policy keeps them.
+pub(crate) fn dedup_entries<T: PartialEq>(v: &mut Vec<T>, policy: MergePolicy) {
+    if policy == MergePolicy::KeepDuplicates {
+        return;
+    }
+    let mut i = 0;
+    while i < v.len() {
+        if v[..i].contains(&v[i]) {
+            v.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+}
+
+impl ExpandOptions {
+    pub fn expands(&self, field: &str) -> bool {
+        self.fields.get(field).copied().unwrap_or(true)
+    }
+
+    // Whether the site lets EDFs read the variable.
+    pub(crate) fn var_allowed(&self, var: &str) -> bool {
+        let allowed = self.allowed_vars.is_empty() || self.allowed_vars.iter().any(|p| glob_match(p, var));
+        allowed && !self.denied_vars.iter().any(|p| glob_match(p, var))
+    }
+}
+
+// Names of the variables a string reads. With a user env they are found
+// walking the string as expand_vars_nested does, so that escapes and
+// defaults are taken the way the expansion takes them. The expansion from
+// the process environment doesn't honour escapes.
+fn referenced_vars(input: &str, escapes: bool) -> Vec<String> {
+    if !escapes {
+        let input = LITERAL_RE.replace_all(input, "$1");
+        return RAW_VAR_RE.captures_iter(&input).map(|c| c[1].to_string()).collect();
+    }
+    let input = LITERAL_RE.replace_all(input, "\\$$$1");
+    let chars: Vec<char> = input.chars().collect();
+    let mut vars = vec![];
+    scan_vars(&chars, &mut vars);
+    vars
+}
+
+// Every operator of a braced reference is scanned, not only defaults, the
+// bash backend expanding the ones native doesn't know.
+fn scan_vars(chars: &[char], vars: &mut Vec<String>) {
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\\' && i + 1 < chars.len() && ['$', '`', '"', '\\'].contains(&chars[i + 1]) {
+            i += 2;
+        } else if c == '$' && i + 1 < chars.len() && chars[i + 1] == '{' {
+            let end = find_closing_brace(chars, i + 2).unwrap_or(chars.len());
+            let expr = &chars[i + 2..end];
+            let name_len = expr.iter().take_while(|c| is_name_char(**c)).count();
+            if name_len > 0 {
+                vars.push(expr[..name_len].iter().collect());
+            }
+            scan_vars(&expr[name_len..], vars);
+            i = end + 1;
+        } else if c == '$' && i + 1 < chars.len() && is_name_start(chars[i + 1]) {
+            let mut end = i + 1;
+            while end < chars.len() && is_name_char(chars[end]) {
+                end += 1;
+            }
+            vars.push(chars[i + 1..end].iter().collect());
+            i = end;
+        } else {
+            i += 1;
+        }
+    }
+}
+
+// Compiled once, strings are expanded for every field of every file.
+static RAW_VAR_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\$\{?([A-Za-z_][A-Za-z0-9_]*)").unwrap());
+static BANNED_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"([^\\]|^)(\$\(|`|;|")"#).unwrap());
+
+// $$VAR and $${VAR} stand for the literal $VAR and ${VAR}, e.g. for values
+// meant to be expanded inside the container.
+static LITERAL_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\$\$([A-Za-z_{])").unwrap());
+
+fn check_var_access(input: &str, env: &Option<HashMap<String, String>>, opts: &ExpandOptions) -> SarusResult<()> {
+    if opts.allowed_vars.is_empty() && opts.denied_vars.is_empty() {
+        return Ok(());
+    }
+    for var in referenced_vars(input, env.is_some()) {
+        if !opts.var_allowed(&var) {
+            return Err(SarusError {
+                kind: SarusErrorKind::ExpansionDenied { var: var.clone() },
+                file_path: None,
+                msg: format!("cannot expand variable {var}, denied by the site configuration"),
+                location: None,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VarSource {
+    UserEnv,    // The env passed by the caller.
+    ProcessEnv, // The environment of the process.
+}
+
+// A variable referenced by an expansion, value is None when unset.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ExpansionRecord {
+    pub var: String,
+    pub source: VarSource,
+    pub value: Option<String>,
+}
+
+// Variables referenced by the expansions sharing it, e.g. all the ones of
+// a render. Clones record into the same list.
+#[derive(Clone, Debug, Default)]
+pub struct ExpansionAudit {
+    records: Arc<Mutex<Vec<ExpansionRecord>>>,
+}
+
+impl ExpansionAudit {
+    fn record(&self, input: &str, env: &Option<HashMap<String, String>>) {
+        let mut records = match self.records.lock() {
+            Ok(r) => r,
+            Err(_) => return,
+        };
+        for var in referenced_vars(input, env.is_some()) {
+            let (source, value) = match env {
+                Some(h) => (VarSource::UserEnv, h.get(&var).cloned()),
+                None => (VarSource::ProcessEnv, std::env::var(&var).ok()),
+            };
+            if !records.iter().any(|r| r.var == var && r.source == source) {
+                records.push(ExpansionRecord { var, source, value });
+            }
+        }
+    }
+
+    // The variables referenced so far, sorted by name.
+    pub fn records(&self) -> Vec<ExpansionRecord> {
+        let mut res = match self.records.lock() {
+            Ok(r) => r.clone(),
+            Err(_) => vec![],
+        };
+        res.sort_by(|a, b| a.var.cmp(&b.var));
+        res
+    }
+}
 
 pub fn expand_vars_string(
     input: String,
     env: &Option<HashMap<String, String>>,
 ) -> SarusResult<String> {
+    expand_vars_string_with_options(input, env, &ExpandOptions::default())
+}
+
+pub fn expand_vars_string_with_backend(
+    input: String,
+    env: &Option<HashMap<String, String>>,
+    backend: ExpansionBackend,
+) -> SarusResult<String> {
+    let opts = ExpandOptions {
+        backend,
+        ..Default::default()
+    };
+    expand_vars_string_with_options(input, env, &opts)
+}
+
+pub fn expand_vars_string_with_options(
+    input: String,
+    env: &Option<HashMap<String, String>>,
+    opts: &ExpandOptions,
+) -> SarusResult<String> {
+    if let Some(a) = &opts.audit {
+        a.record(&input, env);
+    }
+    check_var_access(&input, env, opts)?;
     match env {
-        Some(h) => expand_vars_string_with_env(input, &h),
+        Some(h) => expand_vars_string_with_env(input, h, opts.backend),
         None => expand_vars_string_without_env(input),
     }
 }
@@ -17,17 +261,30 @@ pub fn expand_vars_string(
 fn expand_vars_string_with_env(
     input: String,
     env: &HashMap<String, String>,
+    backend: ExpansionBackend,
 ) -> SarusResult<String> {
     // Ban any strings that will attempt to execute something upon evaluation.
-    let re_banned = Regex::new(r#"([^\\]|^)(\$\(|`|;|")"#).unwrap();
-    if re_banned.is_match(&input) {
+    if BANNED_RE.is_match(&input) {
         return Err(SarusError {
-            code: 18,
+            kind: SarusErrorKind::ExpansionFailed { input: input.clone() },
             file_path: None,
             msg: String::from(format!("cannot expand string {input}, invalid string")),
+            location: None,
         });
     }
 
+    // Both backends take \$VAR for a literal.
+    let input = LITERAL_RE.replace_all(&input, "\\$$$1").to_string();
+    match backend {
+        ExpansionBackend::Native => expand_vars_native(&input, env),
+        ExpansionBackend::Bash => expand_vars_bash(input, env),
+    }
+}
+
+fn expand_vars_bash(
+    input: String,
+    env: &HashMap<String, String>,
+) -> SarusResult<String> {
     // Evaluate 'input' in a restricted shell.
     // This will block any redirection attempts, among other things.
     let output = Command::new("bash")
@@ -42,9 +299,10 @@ fn expand_vars_string_with_env(
         Ok(o) => o.stdout,
         Err(e) => {
             return Err(SarusError {
-                code: 18,
+                kind: SarusErrorKind::ExpansionFailed { input: input.clone() },
                 file_path: None,
                 msg: String::from(format!("cannot expand string {input}, {e}")),
+                location: None,
             });
         }
     };
@@ -53,9 +311,10 @@ fn expand_vars_string_with_env(
         Ok(o) => o,
         Err(e) => {
             return Err(SarusError {
-                code: 19,
+                kind: SarusErrorKind::ExpansionOutput { input: input.clone() },
                 file_path: None,
                 msg: String::from(format!("cannot expand string {input}, {e}")),
+                location: None,
             });
         }
     };
@@ -63,17 +322,270 @@ fn expand_vars_string_with_env(
     Ok(String::from(out))
 }
 
+// Defaults nested deeper are rejected, the recursion being bounded whatever
+// the input.
+const MAX_EXPANSION_DEPTH: usize = 32;
+
+// Expand a string the way bash expands a double-quoted word, supporting
+// $VAR, ${VAR}, ${VAR:-default} and ${VAR:offset[:length]}.
+fn expand_vars_native(input: &str, env: &HashMap<String, String>) -> SarusResult<String> {
+    expand_vars_nested(input, env, 0)
+}
+
+fn expand_vars_nested(input: &str, env: &HashMap<String, String>, depth: usize) -> SarusResult<String> {
+    if depth > MAX_EXPANSION_DEPTH {
+        return Err(SarusError {
+            kind: SarusErrorKind::ExpansionFailed { input: input.to_string() },
+            file_path: None,
+            msg: format!("cannot expand string, defaults nested deeper than {MAX_EXPANSION_DEPTH}"),
+            location: None,
+        });
+    }
+    let chars: Vec<char> = input.chars().collect();
+    let mut out = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\\' && i + 1 < chars.len() && ['$', '`', '"', '\\'].contains(&chars[i + 1]) {
+            out.push(chars[i + 1]);
+            i += 2;
+        } else if c == '$' && i + 1 < chars.len() && chars[i + 1] == '{' {
+            let end = match find_closing_brace(&chars, i + 2) {
+                Some(e) => e,
+                None => {
+                    return Err(SarusError {
+                        kind: SarusErrorKind::ExpansionFailed { input: input.to_string() },
+                        file_path: None,
+                        msg: format!("cannot expand string {input}, missing closing brace"),
+                        location: None,
+                    });
+                }
+            };
+            let expr: String = chars[i + 2..end].iter().collect();
+            out.push_str(&expand_braced(&expr, input, env, depth)?);
+            i = end + 1;
+        } else if c == '$' && i + 1 < chars.len() && is_name_start(chars[i + 1]) {
+            let mut end = i + 1;
+            while end < chars.len() && is_name_char(chars[end]) {
+                end += 1;
+            }
+            let name: String = chars[i + 1..end].iter().collect();
+            out.push_str(&lookup_var(&name, env)?);
+            i = end;
+        } else {
+            out.push(c);
+            i += 1;
+        }
+    }
+
+    Ok(out)
+}
+
+fn expand_braced(expr: &str, input: &str, env: &HashMap<String, String>, depth: usize) -> SarusResult<String> {
+    let name_len = expr.chars().take_while(|c| is_name_char(*c)).count();
+    let (name, op) = expr.split_at(name_len);
+
+    if name.is_empty() || !is_name_start(name.chars().next().unwrap()) {
+        return Err(SarusError {
+            kind: SarusErrorKind::ExpansionFailed { input: input.to_string() },
+            file_path: None,
+            msg: format!("cannot expand string {input}, bad substitution ${{{expr}}}"),
+            location: None,
+        });
+    }
+
+    if op.is_empty() {
+        return lookup_var(name, env);
+    }
+
+    if let Some(default) = op.strip_prefix(":-") {
+        return match env.get(name) {
+            Some(v) if !v.is_empty() => Ok(v.clone()),
+            _ => expand_vars_nested(default, env, depth + 1),
+        };
+    }
+
+    if let Some(range) = op.strip_prefix(':') {
+        let value: Vec<char> = lookup_var(name, env)?.chars().collect();
+        let parse = |s: &str| -> SarusResult<i64> {
+            match s.trim().parse::<i64>() {
+                Ok(n) => Ok(n),
+                Err(_) => Err(SarusError {
+                    kind: SarusErrorKind::ExpansionFailed { input: input.to_string() },
+                    file_path: None,
+                    msg: format!("cannot expand string {input}, bad substring ${{{expr}}}"),
+                    location: None,
+                }),
+            }
+        };
+        let len = value.len() as i64;
+        let (off, count) = match range.split_once(':') {
+            Some((o, l)) => (parse(o)?, Some(parse(l)?)),
+            None => (parse(range)?, None),
+        };
+        // Saturating, offsets and lengths come from users.
+        let start = if off < 0 { len.saturating_add(off).max(0) } else { off.min(len) };
+        let end = match count {
+            None => len,
+            Some(n) if n < 0 => len.saturating_add(n),
+            Some(n) => start.saturating_add(n).min(len),
+        };
+        if end < start {
+            return Err(SarusError {
+                kind: SarusErrorKind::ExpansionFailed { input: input.to_string() },
+                file_path: None,
+                msg: format!("cannot expand string {input}, substring expression < 0"),
+                location: None,
+            });
+        }
+        return Ok(value[start as usize..end as usize].iter().collect());
+    }
+
+    Err(SarusError {
+        kind: SarusErrorKind::ExpansionFailed { input: input.to_string() },
+        file_path: None,
+        msg: format!("cannot expand string {input}, unsupported substitution ${{{expr}}}"),
+        location: None,
+    })
+}
+
+fn lookup_var(name: &str, env: &HashMap<String, String>) -> SarusResult<String> {
+    match env.get(name) {
+        Some(v) => Ok(v.clone()),
+        None => Err(SarusError {
+            kind: SarusErrorKind::UndefinedVariable { var: name.to_string() },
+            file_path: None,
+            msg: format!("cannot expand variable {name}, unbound variable"),
+            location: None,
+        }),
+    }
+}
+
+fn find_closing_brace(chars: &[char], from: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (j, c) in chars.iter().enumerate().skip(from) {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some(j),
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn is_name_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+// Whether s is a valid environment variable name.
+pub(crate) fn is_var_name(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if is_name_start(c) => chars.all(is_name_char),
+        _ => false,
+    }
+}
+
+// Strip one level of matching single or double quotes.
+pub(crate) fn unquote(s: &str) -> &str {
+    for q in ['"', '\''] {
+        if s.len() >= 2 && s.starts_with(q) && s.ends_with(q) {
+            return &s[1..s.len() - 1];
+        }
+    }
+    s
+}
+
+// Expand a leading ~ or ~/ to the home of the user, taken from HOME, and
+// ~name or ~name/ to the home of another user, which needs the "passwd"
+// feature.
+pub fn expand_tilde(input: &str, env: &Option<HashMap<String, String>>) -> SarusResult<String> {
+    let rest = match input.strip_prefix('~') {
+        Some(r) => r,
+        None => return Ok(String::from(input)),
+    };
+    let (name, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, ""),
+    };
+
+    let home = match name.is_empty() {
+        true => {
+            let home = match env {
+                Some(h) => h.get("HOME").cloned(),
+                None => std::env::var("HOME").ok(),
+            };
+            match home {
+                Some(h) => h,
+                None => {
+                    return Err(SarusError {
+                        kind: SarusErrorKind::UndefinedVariable { var: String::from("HOME") },
+                        file_path: None,
+                        msg: format!("cannot expand {input}, HOME is not set"),
+                        location: None,
+                    });
+                }
+            }
+        }
+        false => user_home(name)?,
+    };
+    Ok(format!("{}{path}", home.trim_end_matches('/')))
+}
+
+#[cfg(all(unix, feature = "passwd"))]
+fn user_home(name: &str) -> SarusResult<String> {
+    match nix::unistd::User::from_name(name) {
+        Ok(Some(u)) => Ok(u.dir.display().to_string()),
+        _ => Err(SarusError {
+            kind: SarusErrorKind::UnknownUser { name: String::from(name) },
+            file_path: None,
+            msg: format!("cannot expand ~{name}, no such user"),
+            location: None,
+        }),
+    }
+}
+
+#[cfg(not(all(unix, feature = "passwd")))]
+fn user_home(name: &str) -> SarusResult<String> {
+    Err(SarusError {
+        kind: SarusErrorKind::UnknownUser { name: String::from(name) },
+        file_path: None,
+        msg: format!("cannot expand ~{name}, the homes of other users need the \"passwd\" feature"),
+        location: None,
+    })
+}
+
 fn expand_vars_string_without_env(s: String) -> SarusResult<String> {
+    // shellexpand doesn't know about escapes, expand around the literals.
+    let re = &*LITERAL_RE;
+    if re.is_match(&s) {
+        let mut out = String::new();
+        let mut last = 0;
+        for m in re.find_iter(&s) {
+            out.push_str(&expand_vars_string_without_env(s[last..m.start()].to_string())?);
+            out.push('$');
+            last = m.start() + 2;
+        }
+        out.push_str(&expand_vars_string_without_env(s[last..].to_string())?);
+        return Ok(out);
+    }
     match shellexpand::env(&s) {
         Ok(ok) => return Ok(ok.to_string()),
         Err(e) => {
             return Err(SarusError {
-                code: 17,
+                kind: SarusErrorKind::UndefinedVariable { var: e.var_name.clone() },
                 file_path: None,
                 msg: String::from(format!(
                     "cannot expand variable {}, {}",
                     e.var_name, e.cause
                 )),
+                location: None,
             });
         }
     };
@@ -82,10 +594,20 @@ fn expand_vars_string_without_env(s: String) -> SarusResult<String> {
 pub fn expand_vars_hashmap(
     h: HashMap<String, String>,
     env: &Option<HashMap<String, String>>,
+) -> SarusResult<HashMap<String, String>> {
+    expand_vars_hashmap_with_options(h, env, &ExpandOptions::default())
+}
+
+pub fn expand_vars_hashmap_with_options(
+    h: HashMap<String, String>,
+    env: &Option<HashMap<String, String>>,
+    opts: &ExpandOptions,
 ) -> SarusResult<HashMap<String, String>> {
     let mut newh = h.clone();
-    for (k, v) in h {
-        let ev = expand_vars_string(v.clone(), env)?;
+    let mut entries: Vec<(String, String)> = h.into_iter().collect();
+    entries.sort();
+    for (k, v) in entries {
+        let ev = opts.collect(expand_vars_string_with_options(v.clone(), env, opts), v.clone())?;
         if ev != v {
             newh.insert(k, ev);
         }
@@ -96,10 +618,18 @@ pub fn expand_vars_hashmap(
 pub fn expand_vars_vec(
     v: Vec<String>,
     env: &Option<HashMap<String, String>>,
+) -> SarusResult<Vec<String>> {
+    expand_vars_vec_with_options(v, env, &ExpandOptions::default())
+}
+
+pub fn expand_vars_vec_with_options(
+    v: Vec<String>,
+    env: &Option<HashMap<String, String>>,
+    opts: &ExpandOptions,
 ) -> SarusResult<Vec<String>> {
     let mut newv = vec![];
     for s in v {
-        newv.push(expand_vars_string(s, env)?);
+        newv.push(opts.collect(expand_vars_string_with_options(s.clone(), env, opts), s)?);
     }
     return Ok(newv);
 }
@@ -108,10 +638,14 @@ pub fn expand_vars_vec(
 mod tests {
     use super::*;
 
-    fn check_expand_vars_string(input: &str, expected: &str) -> bool {
+    fn check_expand_vars_string_backend(
+        input: &str,
+        expected: &str,
+        backend: ExpansionBackend,
+    ) -> bool {
         let mut env = HashMap::new();
         env.insert("XXX".to_string(), "111".to_string());
-        match expand_vars_string_with_env(input.to_string(), &env) {
+        match expand_vars_string_with_env(input.to_string(), &env, backend) {
             Ok(s) => {
                 println!("{}", s);
                 return s == expected;
@@ -120,6 +654,13 @@ mod tests {
         }
     }
 
+    fn check_expand_vars_string(input: &str, expected: &str) -> bool {
+        let native = check_expand_vars_string_backend(input, expected, ExpansionBackend::Native);
+        let bash = check_expand_vars_string_backend(input, expected, ExpansionBackend::Bash);
+        assert!(native == bash, "backends disagree on {input}");
+        native
+    }
+
     #[test]
     fn expand_vars_normal_strs() {
         assert!(check_expand_vars_string(
@@ -174,4 +715,88 @@ mod tests {
         assert!(!check_expand_vars_string(r#"; xxx--xxx"#, ""));
         assert!(!check_expand_vars_string(r#"" >/tmp/file"#, ""));
     }
+
+    #[test]
+    fn expand_vars_substrings() {
+        assert!(check_expand_vars_string(r#"${XXX:0:2}"#, r#"11"#));
+        assert!(check_expand_vars_string(r#"${XXX: -1}"#, r#"1"#));
+        assert!(check_expand_vars_string(r#"${XXX:1:-1}"#, r#"1"#));
+        assert!(check_expand_vars_string(r#"${YYY:-$XXX}-xxx"#, r#"111-xxx"#));
+        assert!(check_expand_vars_string(r#"$XXX$XXX"#, r#"111111"#));
+        assert!(check_expand_vars_string(r#"\\$XXX"#, r#"\111"#));
+    }
+
+    #[test]
+    fn expand_vars_adversarial() {
+        let native = |input: &str| check_expand_vars_string_backend(input, "1", ExpansionBackend::Native);
+        assert!(native(r#"${XXX:2:9223372036854775807}"#));
+        assert!(native(r#"${XXX: -9223372036854775808:1}"#));
+        assert!(!native(r#"${XXX:0:-9223372036854775808}"#));
+
+        let nested = |n: usize| format!("{}1{}", "${YYY:-".repeat(n), "}".repeat(n));
+        assert!(native(&nested(MAX_EXPANSION_DEPTH)));
+        assert!(!native(&nested(100000)));
+    }
+
+    #[test]
+    fn expand_vars_allowed_denied() {
+        let env = Some(HashMap::from([
+            (String::from("SCRATCH"), String::from("/scratch")),
+            (String::from("SSH_AUTH_SOCK"), String::from("/tmp/agent")),
+        ]));
+        let opts = ExpandOptions {
+            denied_vars: vec![String::from("SSH_*")],
+            ..Default::default()
+        };
+        let ok = expand_vars_string_with_options(String::from("${SCRATCH}/run"), &env, &opts);
+        assert!(ok.unwrap() == "/scratch/run");
+        let err = expand_vars_string_with_options(String::from("x:${SSH_AUTH_SOCK}"), &env, &opts);
+        assert!(err.err().unwrap().kind == SarusErrorKind::ExpansionDenied { var: String::from("SSH_AUTH_SOCK") });
+        // Escaped references are literals
+        assert!(expand_vars_string_with_options(String::from("\\$SSH_AUTH_SOCK"), &env, &opts).is_ok());
+        // An escaped backslash is not, nor are defaults
+        for s in ["\\\\$SSH_AUTH_SOCK", "${X:-$SSH_AUTH_SOCK}", "${SCRATCH:-${SSH_AGENT_PID}}"] {
+            let err = expand_vars_string_with_options(String::from(s), &env, &opts);
+            assert!(err.err().unwrap().code() == 52);
+        }
+        let audit = ExpansionAudit::default();
+        let opts = ExpandOptions {
+            audit: Some(audit.clone()),
+            ..Default::default()
+        };
+        expand_vars_string_with_options(String::from("\\\\$SCRATCH \\$HOME"), &env, &opts).unwrap();
+        assert!(audit.records().iter().map(|r| r.var.as_str()).collect::<Vec<_>>() == ["SCRATCH"]);
+
+        let opts = ExpandOptions {
+            allowed_vars: vec![String::from("SCRATCH")],
+            ..Default::default()
+        };
+        assert!(expand_vars_string_with_options(String::from("$SCRATCH"), &env, &opts).is_ok());
+        let err = expand_vars_string_with_options(String::from("${HOME:-/}"), &None, &opts);
+        assert!(err.err().unwrap().code() == 52);
+    }
+
+    #[test]
+    fn expand_vars_literal() {
+        assert!(check_expand_vars_string(r#"$$HOME/bin:$XXX"#, r#"$HOME/bin:111"#));
+        assert!(check_expand_vars_string(r#"$${XXX}-${XXX}"#, r#"${XXX}-111"#));
+        assert!(check_expand_vars_string(r#"$$YYY"#, r#"$YYY"#));
+
+        let opts = ExpandOptions {
+            denied_vars: vec![String::from("HOME")],
+            ..Default::default()
+        };
+        let env = Some(HashMap::new());
+        let s = expand_vars_string_with_options(String::from("$$HOME"), &env, &opts).unwrap();
+        assert!(s == "$HOME");
+        let s = expand_vars_string_with_options(String::from("$${HOME}/x"), &None, &opts).unwrap();
+        assert!(s == "${HOME}/x");
+    }
+
+    #[cfg(all(unix, feature = "passwd"))]
+    #[test]
+    fn expand_tilde_user() {
+        assert!(expand_tilde("~root/data", &None).unwrap() == "/root/data");
+        assert!(expand_tilde("~nosuchuser-raster", &None).err().unwrap().code() == 53);
+    }
 }
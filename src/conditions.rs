@@ -0,0 +1,198 @@
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::common::{ExpandOptions, is_var_name, unquote};
+use crate::error::{SarusError, SarusErrorKind, SarusResult};
+
+// Tables which can hold conditioned sub-tables, e.g.
+// [env.when."$CLUSTER == daint"]
+const CONDITIONAL_TABLES: [&str; 2] = ["annotations", "env"];
+
+// Lists whose entries can be conditioned, e.g.
+// mounts = [{ spec = "/a:/b", when = "$CLUSTER != daint" }]
+const CONDITIONAL_LISTS: [&str; 2] = ["devices", "mounts"];
+
+// Evaluate the conditions of an EDF document, keeping only the entries
+// whose condition holds. Variables are looked up in env, or in the process
+// environment when env is None, those the site denies to expansion can't be
+// tested either.
+pub(crate) fn resolve_conditions(
+    doc: &mut Value,
+    env: &Option<HashMap<String, String>>,
+    xopts: &ExpandOptions,
+    file_path: &str,
+) -> SarusResult<()> {
+    let table = match doc.as_object_mut() {
+        Some(t) => t,
+        None => return Ok(()),
+    };
+
+    for key in CONDITIONAL_TABLES.iter() {
+        let t = match table.get_mut(*key).and_then(|t| t.as_object_mut()) {
+            Some(t) => t,
+            None => continue,
+        };
+        let when = match t.remove("when") {
+            Some(Value::Object(w)) => w,
+            Some(other) => {
+                t.insert(String::from("when"), other);
+                continue;
+            }
+            None => continue,
+        };
+        for (cond, entries) in when {
+            if !evaluate(&cond, env, xopts, file_path)? {
+                continue;
+            }
+            if let Value::Object(e) = entries {
+                t.extend(e);
+            }
+        }
+    }
+
+    for key in CONDITIONAL_LISTS.iter() {
+        let a = match table.get_mut(*key).and_then(|a| a.as_array_mut()) {
+            Some(a) => a,
+            None => continue,
+        };
+        let mut kept = vec![];
+        for item in a.drain(..) {
+            let cond = match item.get("when").and_then(|w| w.as_str()) {
+                Some(c) => c.to_string(),
+                None => {
+                    kept.push(item);
+                    continue;
+                }
+            };
+            if !evaluate(&cond, env, xopts, file_path)? {
+                continue;
+            }
+            match item.get("spec") {
+                Some(s) => kept.push(s.clone()),
+                None => {
+                    // Tables, e.g. mount tables, carry the condition inline
+                    let mut item = item;
+                    if let Some(t) = item.as_object_mut() {
+                        t.remove("when");
+                    }
+                    kept.push(item);
+                }
+            }
+        }
+        *a = kept;
+    }
+
+    Ok(())
+}
+
+// Conditions are "$VAR == value", "$VAR != value", "$VAR" (set and not
+// empty) or "!$VAR". Values can be quoted, undefined variables are empty.
+pub(crate) fn evaluate(
+    condition: &str,
+    env: &Option<HashMap<String, String>>,
+    xopts: &ExpandOptions,
+    file_path: &str,
+) -> SarusResult<bool> {
+    let invalid = || SarusError {
+        kind: SarusErrorKind::InvalidCondition {
+            condition: condition.to_string(),
+        },
+        file_path: Some(String::from(file_path)),
+        msg: format!("invalid condition \"{condition}\", expected \"$VAR == value\", \"$VAR != value\", \"$VAR\" or \"!$VAR\""),
+        location: None,
+    };
+
+    let c = condition.trim();
+    let (lhs, op, rhs) = if let Some((l, r)) = c.split_once("==") {
+        (l.trim(), "==", r.trim())
+    } else if let Some((l, r)) = c.split_once("!=") {
+        (l.trim(), "!=", r.trim())
+    } else if let Some(l) = c.strip_prefix('!') {
+        (l.trim(), "!", "")
+    } else {
+        (c, "", "")
+    };
+
+    let name = match variable_name(lhs) {
+        Some(n) => n,
+        None => return Err(invalid()),
+    };
+    if !xopts.var_allowed(name) {
+        return Err(SarusError {
+            kind: SarusErrorKind::ExpansionDenied { var: String::from(name) },
+            file_path: Some(String::from(file_path)),
+            msg: format!("cannot test variable {name} in condition \"{condition}\", denied by the site configuration"),
+            location: None,
+        });
+    }
+    let value = lookup(name, env);
+
+    match op {
+        "==" => Ok(value == unquote(rhs)),
+        "!=" => Ok(value != unquote(rhs)),
+        "!" => Ok(value.is_empty()),
+        _ => Ok(!value.is_empty()),
+    }
+}
+
+fn variable_name(s: &str) -> Option<&str> {
+    let name = match s.strip_prefix("${") {
+        Some(n) => n.strip_suffix('}')?,
+        None => s.strip_prefix('$')?,
+    };
+
+    match is_var_name(name) {
+        true => Some(name),
+        false => None,
+    }
+}
+
+fn lookup(name: &str, env: &Option<HashMap<String, String>>) -> String {
+    match env {
+        Some(h) => h.get(name).cloned().unwrap_or_default(),
+        None => std::env::var(name).unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env() -> Option<HashMap<String, String>> {
+        let mut h = HashMap::new();
+        h.insert(String::from("CLUSTER"), String::from("daint"));
+        h.insert(String::from("EMPTY"), String::from(""));
+        Some(h)
+    }
+
+    #[test]
+    fn condition_evaluate() {
+        let e = env();
+        let x = ExpandOptions::default();
+        assert!(evaluate("$CLUSTER == daint", &e, &x, "").unwrap());
+        assert!(evaluate("${CLUSTER}=='daint'", &e, &x, "").unwrap());
+        assert!(!evaluate("$CLUSTER != \"daint\"", &e, &x, "").unwrap());
+        assert!(evaluate("$CLUSTER", &e, &x, "").unwrap());
+        assert!(evaluate("!$EMPTY", &e, &x, "").unwrap());
+        assert!(evaluate("!$UNDEFINED", &e, &x, "").unwrap());
+        assert!(evaluate("$UNDEFINED == ''", &e, &x, "").unwrap());
+
+        for c in ["CLUSTER == daint", "$1 == a", "${CLUSTER == daint", ""] {
+            let err = evaluate(c, &e, &x, "a.toml").err().unwrap();
+            assert!(err.kind == SarusErrorKind::InvalidCondition { condition: c.to_string() });
+        }
+
+        let x = ExpandOptions {
+            denied_vars: vec![String::from("SSH_*")],
+            ..Default::default()
+        };
+        let err = evaluate("$SSH_AUTH_SOCK", &e, &x, "a.toml").err().unwrap();
+        assert!(err.kind == SarusErrorKind::ExpansionDenied { var: String::from("SSH_AUTH_SOCK") });
+        assert!(evaluate("$CLUSTER == daint", &e, &x, "").unwrap());
+        let x = ExpandOptions {
+            allowed_vars: vec![String::from("CLUSTER")],
+            ..Default::default()
+        };
+        assert!(evaluate("!$EMPTY", &e, &x, "").is_err());
+    }
+}
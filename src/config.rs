@@ -1,15 +1,50 @@
-use crate::common::expand_vars_string;
+use crate::annotations::{SARUS_ANNOTATION_PREFIX, SarusAnnotations};
+use crate::common::{expand_tilde, expand_vars_string};
+use crate::error::SarusErrorKind;
+use crate::hooks::HookDefinition;
+use crate::mpi::MpiTemplate;
+use crate::provenance::{document_location, location_of};
+use crate::secrets::glob_match;
+use crate::validation::{Diagnostic, ValidationReport};
+use crate::validator::Schema;
 use crate::{EDF, SarusError, SarusResult, check_file_path_extension, validate_file};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
-const CONFIG_PATH: &str = "/etc/sarus-suite";
+pub const CONFIG_PATH: &str = "/etc/sarus-suite";
+
+// Drop-in directories read below the config path, from lowest to highest
+// precedence: each layer overrides values set by the previous ones.
+const CONFIG_DROPIN_LAYERS: [&str; 4] = ["conf.d/vendor", "conf.d", "conf.d/site", "conf.d/cluster"];
 
 #[derive(Serialize, Deserialize, Clone, Default)]
 pub struct RawConfig {
     edf_system_search_path: Option<String>,
+    default_registry: Option<String>,
+    default_engine: Option<String>,
+    default_volume_driver: Option<String>,
+    edf_policy_path: Option<String>,
+    edf_max_size: Option<u32>,
+    edf_reject_world_writable: Option<bool>,
+    edf_contain_symlinks: Option<bool>,
+    edf_contain_names: Option<bool>,
+    edf_system_owner_check: Option<bool>,
+    edf_system_owner_uid: Option<u32>,
+    edf_system_group_writable: Option<bool>,
+    enroot_path: Option<String>,
+    expansion_allowed_vars: Option<Vec<String>>,
+    expansion_denied_vars: Option<Vec<String>>,
+    expansion_fields: Option<HashMap<String, bool>>,
+    git_cache_dir: Option<String>,
     hooks: Option<RawConfigHooks>,
+    locked_settings: Option<Vec<String>>,
+    locked_settings_action: Option<String>,
+    parallax: Option<ParallaxSettings>,
+    podman: Option<PodmanSettings>,
+    mpi_templates: Option<HashMap<String, MpiTemplate>>,
+    oci_hooks: Option<HashMap<String, HookDefinition>>,
     parallax_imagestore: Option<String>,
     parallax_imagestore_keepalive: Option<bool>,
     parallax_mount_program: Option<String>,
@@ -22,10 +57,71 @@ pub struct RawConfig {
     podman_module: Option<String>,
     podman_path: Option<String>,
     podman_tmp_path: Option<String>,
+    registry_aliases: Option<HashMap<String, String>>,
+    registry_auth_file: Option<String>,
+    remote_max_size: Option<u32>,
+    remote_timeout: Option<u32>,
+    render_cache_dir: Option<String>,
+    require_image_digest: Option<bool>,
     runtime_path: Option<String>,
+    sensitive_env_patterns: Option<Vec<String>>,
     skybox_enabled: Option<bool>,
+    template_variables: Option<HashMap<String, String>>,
     tracking_enabled: Option<bool>,
     tracking_tool: Option<String>,
+    user_settings: Option<Vec<String>>,
+    writable_overlay_path: Option<String>,
+    writable_overlay_size: Option<String>,
+    // [match."PATTERN"] sections, applied on nodes whose hostname matches
+    // the glob pattern, and [partition.NAME] sections, applied in jobs of
+    // the partition. Sections inside sections are not followed.
+    #[serde(rename = "match")]
+    match_sections: Option<HashMap<String, RawConfig>>,
+    partition: Option<HashMap<String, RawConfig>>,
+}
+
+// What the [match] and [partition] sections of the config files are
+// matched against.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConfigSelector {
+    pub hostname: Option<String>,
+    pub partition: Option<String>,
+}
+
+impl ConfigSelector {
+    // The node the process runs on and the Slurm partition of its job.
+    pub fn current() -> ConfigSelector {
+        ConfigSelector {
+            hostname: std::fs::read_to_string("/proc/sys/kernel/hostname")
+                .ok()
+                .map(|h| h.trim().to_string()),
+            partition: std::env::var("SLURM_JOB_PARTITION").ok(),
+        }
+    }
+}
+
+// Nested [parallax] table, the parallax_* keys without their prefix. Unset
+// entries leave the flat keys as they are.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ParallaxSettings {
+    pub imagestore: Option<String>,
+    pub imagestore_keepalive: Option<bool>,
+    pub mount_program: Option<String>,
+    pub path: Option<String>,
+    pub mp_uid: Option<u32>,
+    pub mp_gid: Option<u32>,
+    pub mp_logfile: Option<String>,
+    pub mp_squashfuse_path: Option<String>,
+}
+
+// Nested [podman] table, the podman_* keys without their prefix.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PodmanSettings {
+    pub module: Option<String>,
+    pub path: Option<String>,
+    pub tmp_path: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Default)]
@@ -37,8 +133,48 @@ pub struct RawConfigHooks {
 pub struct Config {
     #[serde(default = "get_default_edf_system_search_path")]
     pub edf_system_search_path: String,
+    #[serde(default = "get_default_default_registry")]
+    pub default_registry: String,
+    #[serde(default = "get_default_default_engine")]
+    pub default_engine: String,
+    #[serde(default = "get_default_default_volume_driver")]
+    pub default_volume_driver: String,
+    #[serde(default = "get_default_edf_policy_path")]
+    pub edf_policy_path: String,
+    #[serde(default = "get_default_edf_max_size")]
+    pub edf_max_size: u32,
+    #[serde(default = "get_default_edf_reject_world_writable")]
+    pub edf_reject_world_writable: bool,
+    #[serde(default = "get_default_edf_contain_symlinks")]
+    pub edf_contain_symlinks: bool,
+    #[serde(default = "get_default_edf_contain_names")]
+    pub edf_contain_names: bool,
+    #[serde(default = "get_default_edf_system_owner_check")]
+    pub edf_system_owner_check: bool,
+    #[serde(default = "get_default_edf_system_owner_uid")]
+    pub edf_system_owner_uid: u32,
+    #[serde(default = "get_default_edf_system_group_writable")]
+    pub edf_system_group_writable: bool,
+    #[serde(default = "get_default_enroot_path")]
+    pub enroot_path: String,
+    #[serde(default = "get_default_expansion_allowed_vars")]
+    pub expansion_allowed_vars: Vec<String>,
+    #[serde(default = "get_default_expansion_denied_vars")]
+    pub expansion_denied_vars: Vec<String>,
+    #[serde(default = "get_default_expansion_fields")]
+    pub expansion_fields: HashMap<String, bool>,
+    #[serde(default = "get_default_git_cache_dir")]
+    pub git_cache_dir: String,
     #[serde(default = "get_default_hooks")]
     pub hooks: ConfigHooks,
+    #[serde(default = "get_default_locked_settings")]
+    pub locked_settings: Vec<String>,
+    #[serde(default = "get_default_locked_settings_action")]
+    pub locked_settings_action: String,
+    #[serde(default = "get_default_mpi_templates")]
+    pub mpi_templates: HashMap<String, MpiTemplate>,
+    #[serde(default = "get_default_oci_hooks")]
+    pub oci_hooks: HashMap<String, HookDefinition>,
     #[serde(default = "get_default_parallax_imagestore")]
     pub parallax_imagestore: String,
     #[serde(default = "get_default_parallax_imagestore_keepalive")]
@@ -63,14 +199,36 @@ pub struct Config {
     pub podman_path: String,
     #[serde(default = "get_default_podman_tmp_path")]
     pub podman_tmp_path: String,
+    #[serde(default = "get_default_registry_aliases")]
+    pub registry_aliases: HashMap<String, String>,
+    #[serde(default = "get_default_registry_auth_file")]
+    pub registry_auth_file: String,
+    #[serde(default = "get_default_remote_max_size")]
+    pub remote_max_size: u32,
+    #[serde(default = "get_default_remote_timeout")]
+    pub remote_timeout: u32,
+    #[serde(default = "get_default_render_cache_dir")]
+    pub render_cache_dir: String,
+    #[serde(default = "get_default_require_image_digest")]
+    pub require_image_digest: bool,
     #[serde(default = "get_default_runtime_path")]
     pub runtime_path: String,
+    #[serde(default = "get_default_sensitive_env_patterns")]
+    pub sensitive_env_patterns: Vec<String>,
     #[serde(default = "get_default_skybox_enabled")]
     pub skybox_enabled: bool,
+    #[serde(default = "get_default_template_variables")]
+    pub template_variables: HashMap<String, String>,
     #[serde(default = "get_default_tracking_enabled")]
     pub tracking_enabled: bool,
     #[serde(default = "get_default_tracking_tool")]
     pub tracking_tool: String,
+    #[serde(default = "get_default_user_settings")]
+    pub user_settings: Vec<String>,
+    #[serde(default = "get_default_writable_overlay_path")]
+    pub writable_overlay_path: String,
+    #[serde(default = "get_default_writable_overlay_size")]
+    pub writable_overlay_size: String,
 }
 
 #[derive(Serialize, Deserialize, Clone, Default)]
@@ -90,6 +248,70 @@ fn get_default_edf_system_search_path() -> String {
     return String::from("/etc/edf");
 }
 
+fn get_default_default_registry() -> String {
+    return String::from("");
+}
+
+fn get_default_default_engine() -> String {
+    return String::from("podman");
+}
+
+fn get_default_default_volume_driver() -> String {
+    return String::from("local");
+}
+
+fn get_default_edf_policy_path() -> String {
+    return String::from("");
+}
+
+fn get_default_edf_max_size() -> u32 {
+    return 1048576;
+}
+
+fn get_default_edf_reject_world_writable() -> bool {
+    return true;
+}
+
+fn get_default_edf_contain_symlinks() -> bool {
+    return false;
+}
+
+fn get_default_edf_contain_names() -> bool {
+    return true;
+}
+
+fn get_default_edf_system_owner_check() -> bool {
+    return true;
+}
+
+fn get_default_edf_system_owner_uid() -> u32 {
+    return 0;
+}
+
+fn get_default_edf_system_group_writable() -> bool {
+    return false;
+}
+
+fn get_default_enroot_path() -> String {
+    return String::from("enroot");
+}
+
+fn get_default_expansion_allowed_vars() -> Vec<String> {
+    return vec![];
+}
+
+fn get_default_expansion_denied_vars() -> Vec<String> {
+    return vec![String::from("LD_AUDIT"), String::from("LD_PRELOAD"), String::from("SSH_AUTH_SOCK")];
+}
+
+fn get_default_expansion_fields() -> HashMap<String, bool> {
+    return HashMap::new();
+}
+
+fn get_default_git_cache_dir() -> String {
+    return String::from("");
+}
+
 fn get_default_parallax_imagestore() -> String {
     return String::from("");
 }
@@ -106,16 +328,37 @@ fn get_default_parallax_path() -> String {
     return String::from("parallax");
 }
 
+// No users without unix, e.g. on wasm32
+#[cfg(unix)]
+fn current_uid() -> u32 {
+    nix::unistd::geteuid().as_raw()
+}
+
+#[cfg(unix)]
+fn current_gid() -> u32 {
+    nix::unistd::getegid().as_raw()
+}
+
+#[cfg(not(unix))]
+fn current_uid() -> u32 {
+    0
+}
+
+#[cfg(not(unix))]
+fn current_gid() -> u32 {
+    0
+}
+
 fn get_default_parallax_mp_uid() -> u32 {
-    return nix::unistd::geteuid().as_raw();
+    return current_uid();
 }
 
 fn get_default_parallax_mp_gid() -> u32 {
-    return nix::unistd::getegid().as_raw();
+    return current_gid();
 }
 
 fn get_default_parallax_mp_logfile() -> String {
-    let uid = nix::unistd::geteuid().as_raw();
+    let uid = current_uid();
     return format!("/tmp/parallax-{}/mount_program.log", uid);
 }
 
@@ -139,14 +382,46 @@ fn get_default_podman_tmp_path() -> String {
     return String::from("/dev/shm");
 }
 
+fn get_default_registry_aliases() -> HashMap<String, String> {
+    return HashMap::new();
+}
+
+fn get_default_registry_auth_file() -> String {
+    return String::from("");
+}
+
+fn get_default_remote_max_size() -> u32 {
+    return 1048576;
+}
+
+fn get_default_remote_timeout() -> u32 {
+    return 30;
+}
+
+fn get_default_render_cache_dir() -> String {
+    return String::from("");
+}
+
+fn get_default_require_image_digest() -> bool {
+    return false;
+}
+
 fn get_default_runtime_path() -> String {
     return String::from("crun");
 }
 
+fn get_default_sensitive_env_patterns() -> Vec<String> {
+    return vec![String::from("*_TOKEN"), String::from("*_PASSWORD"), String::from("*_SECRET")];
+}
+
 fn get_default_skybox_enabled() -> bool {
     return false;
 }
 
+fn get_default_template_variables() -> HashMap<String, String> {
+    return HashMap::new();
+}
+
 fn get_default_tracking_enabled() -> bool {
     return false;
 }
@@ -155,6 +430,18 @@ fn get_default_tracking_tool() -> String {
     return String::from("");
 }
 
+fn get_default_user_settings() -> Vec<String> {
+    return vec![];
+}
+
+fn get_default_writable_overlay_path() -> String {
+    return String::from("");
+}
+
+fn get_default_writable_overlay_size() -> String {
+    return String::from("");
+}
+
 fn get_default_hook_parallax_imagestore_create() -> String {
     return String::from("");
 }
@@ -165,6 +452,70 @@ fn get_default_hooks() -> ConfigHooks {
     }
 }
 
+fn get_default_locked_settings() -> Vec<String> {
+    return vec![];
+}
+
+fn get_default_locked_settings_action() -> String {
+    return String::from("reject");
+}
+
+fn get_default_mpi_templates() -> HashMap<String, MpiTemplate> {
+    return HashMap::new();
+}
+
+fn get_default_oci_hooks() -> HashMap<String, HookDefinition> {
+    return HashMap::new();
+}
+
+impl RawConfig {
+    // Move the entries of the [parallax] and [podman] tables to the flat
+    // keys, a table entry wins over the flat key of the same file.
+    fn flatten_tables(&mut self) {
+        if let Some(p) = self.parallax.take() {
+            self.parallax_imagestore = p.imagestore.or(self.parallax_imagestore.take());
+            self.parallax_imagestore_keepalive = p.imagestore_keepalive.or(self.parallax_imagestore_keepalive);
+            self.parallax_mount_program = p.mount_program.or(self.parallax_mount_program.take());
+            self.parallax_path = p.path.or(self.parallax_path.take());
+            self.parallax_mp_uid = p.mp_uid.or(self.parallax_mp_uid);
+            self.parallax_mp_gid = p.mp_gid.or(self.parallax_mp_gid);
+            self.parallax_mp_logfile = p.mp_logfile.or(self.parallax_mp_logfile.take());
+            self.parallax_mp_squashfuse_path = p.mp_squashfuse_path.or(self.parallax_mp_squashfuse_path.take());
+        }
+        if let Some(p) = self.podman.take() {
+            self.podman_module = p.module.or(self.podman_module.take());
+            self.podman_path = p.path.or(self.podman_path.take());
+            self.podman_tmp_path = p.tmp_path.or(self.podman_tmp_path.take());
+        }
+    }
+
+    // Apply the sections matching the selector on top of the settings of
+    // the file, the [match] ones in the order of their pattern, then the
+    // [partition] one.
+    fn apply_sections(&mut self, selector: &ConfigSelector) {
+        let mut sections = vec![];
+        if let Some(m) = self.match_sections.take()
+            && let Some(h) = &selector.hostname
+        {
+            let mut m: Vec<(String, RawConfig)> = m.into_iter().filter(|(p, _)| glob_match(p, h)).collect();
+            m.sort_by(|a, b| a.0.cmp(&b.0));
+            sections.extend(m.into_iter().map(|(_, s)| s));
+        }
+        if let Some(mut p) = self.partition.take()
+            && let Some(name) = &selector.partition
+            && let Some(s) = p.remove(name)
+        {
+            sections.push(s);
+        }
+        for mut s in sections {
+            s.match_sections = None;
+            s.partition = None;
+            s.flatten_tables();
+            self.extend(s);
+        }
+    }
+}
+
 impl From<RawConfig> for Config {
     fn from(r: RawConfig) -> Self {
         Config {
@@ -172,10 +523,90 @@ impl From<RawConfig> for Config {
                 Some(s) => s,
                 None => get_default_edf_system_search_path(),
             },
+            default_registry: match r.default_registry {
+                Some(s) => s,
+                None => get_default_default_registry(),
+            },
+            default_engine: match r.default_engine {
+                Some(s) => s,
+                None => get_default_default_engine(),
+            },
+            default_volume_driver: match r.default_volume_driver {
+                Some(s) => s,
+                None => get_default_default_volume_driver(),
+            },
+            edf_policy_path: match r.edf_policy_path {
+                Some(s) => s,
+                None => get_default_edf_policy_path(),
+            },
+            edf_max_size: match r.edf_max_size {
+                Some(s) => s,
+                None => get_default_edf_max_size(),
+            },
+            edf_reject_world_writable: match r.edf_reject_world_writable {
+                Some(s) => s,
+                None => get_default_edf_reject_world_writable(),
+            },
+            edf_contain_symlinks: match r.edf_contain_symlinks {
+                Some(s) => s,
+                None => get_default_edf_contain_symlinks(),
+            },
+            edf_contain_names: match r.edf_contain_names {
+                Some(s) => s,
+                None => get_default_edf_contain_names(),
+            },
+            edf_system_owner_check: match r.edf_system_owner_check {
+                Some(s) => s,
+                None => get_default_edf_system_owner_check(),
+            },
+            edf_system_owner_uid: match r.edf_system_owner_uid {
+                Some(s) => s,
+                None => get_default_edf_system_owner_uid(),
+            },
+            edf_system_group_writable: match r.edf_system_group_writable {
+                Some(s) => s,
+                None => get_default_edf_system_group_writable(),
+            },
+            enroot_path: match r.enroot_path {
+                Some(s) => s,
+                None => get_default_enroot_path(),
+            },
+            expansion_allowed_vars: match r.expansion_allowed_vars {
+                Some(s) => s,
+                None => get_default_expansion_allowed_vars(),
+            },
+            expansion_denied_vars: match r.expansion_denied_vars {
+                Some(s) => s,
+                None => get_default_expansion_denied_vars(),
+            },
+            expansion_fields: match r.expansion_fields {
+                Some(s) => s,
+                None => get_default_expansion_fields(),
+            },
+            git_cache_dir: match r.git_cache_dir {
+                Some(s) => s,
+                None => get_default_git_cache_dir(),
+            },
             hooks: match r.hooks {
                 Some(s) => ConfigHooks::from(s),
                 None => get_default_hooks(),
             },
+            locked_settings: match r.locked_settings {
+                Some(s) => s,
+                None => get_default_locked_settings(),
+            },
+            locked_settings_action: match r.locked_settings_action {
+                Some(s) => s,
+                None => get_default_locked_settings_action(),
+            },
+            mpi_templates: match r.mpi_templates {
+                Some(s) => s,
+                None => get_default_mpi_templates(),
+            },
+            oci_hooks: match r.oci_hooks {
+                Some(s) => s,
+                None => get_default_oci_hooks(),
+            },
             parallax_imagestore: match r.parallax_imagestore {
                 Some(s) => s,
                 None => get_default_parallax_imagestore(),
@@ -224,14 +655,46 @@ impl From<RawConfig> for Config {
                 Some(s) => s,
                 None => get_default_podman_tmp_path(),
             },
+            registry_aliases: match r.registry_aliases {
+                Some(s) => s,
+                None => get_default_registry_aliases(),
+            },
+            registry_auth_file: match r.registry_auth_file {
+                Some(s) => s,
+                None => get_default_registry_auth_file(),
+            },
+            remote_max_size: match r.remote_max_size {
+                Some(s) => s,
+                None => get_default_remote_max_size(),
+            },
+            remote_timeout: match r.remote_timeout {
+                Some(s) => s,
+                None => get_default_remote_timeout(),
+            },
+            render_cache_dir: match r.render_cache_dir {
+                Some(s) => s,
+                None => get_default_render_cache_dir(),
+            },
+            require_image_digest: match r.require_image_digest {
+                Some(s) => s,
+                None => get_default_require_image_digest(),
+            },
             runtime_path: match r.runtime_path {
                 Some(s) => s,
                 None => get_default_runtime_path(),
             },
+            sensitive_env_patterns: match r.sensitive_env_patterns {
+                Some(s) => s,
+                None => get_default_sensitive_env_patterns(),
+            },
             skybox_enabled: match r.skybox_enabled {
                 Some(s) => s,
                 None => get_default_skybox_enabled(),
             },
+            template_variables: match r.template_variables {
+                Some(s) => s,
+                None => get_default_template_variables(),
+            },
             tracking_enabled: match r.tracking_enabled {
                 Some(s) => s,
                 None => get_default_tracking_enabled(),
@@ -240,6 +703,18 @@ impl From<RawConfig> for Config {
                 Some(s) => s,
                 None => get_default_tracking_tool(),
             },
+            user_settings: match r.user_settings {
+                Some(s) => s,
+                None => get_default_user_settings(),
+            },
+            writable_overlay_path: match r.writable_overlay_path {
+                Some(s) => s,
+                None => get_default_writable_overlay_path(),
+            },
+            writable_overlay_size: match r.writable_overlay_size {
+                Some(s) => s,
+                None => get_default_writable_overlay_size(),
+            },
         }
     }
 }
@@ -250,9 +725,69 @@ impl RawConfig {
         if i.edf_system_search_path.is_some() {
             self.edf_system_search_path = i.edf_system_search_path;
         }
+        if i.default_registry.is_some() {
+            self.default_registry = i.default_registry;
+        }
+        if i.default_engine.is_some() {
+            self.default_engine = i.default_engine;
+        }
+        if i.default_volume_driver.is_some() {
+            self.default_volume_driver = i.default_volume_driver;
+        }
+        if i.edf_policy_path.is_some() {
+            self.edf_policy_path = i.edf_policy_path;
+        }
+        if i.edf_max_size.is_some() {
+            self.edf_max_size = i.edf_max_size;
+        }
+        if i.edf_reject_world_writable.is_some() {
+            self.edf_reject_world_writable = i.edf_reject_world_writable;
+        }
+        if i.edf_contain_symlinks.is_some() {
+            self.edf_contain_symlinks = i.edf_contain_symlinks;
+        }
+        if i.edf_contain_names.is_some() {
+            self.edf_contain_names = i.edf_contain_names;
+        }
+        if i.edf_system_owner_check.is_some() {
+            self.edf_system_owner_check = i.edf_system_owner_check;
+        }
+        if i.edf_system_owner_uid.is_some() {
+            self.edf_system_owner_uid = i.edf_system_owner_uid;
+        }
+        if i.edf_system_group_writable.is_some() {
+            self.edf_system_group_writable = i.edf_system_group_writable;
+        }
+        if i.enroot_path.is_some() {
+            self.enroot_path = i.enroot_path;
+        }
+        if i.expansion_allowed_vars.is_some() {
+            self.expansion_allowed_vars = i.expansion_allowed_vars;
+        }
+        if i.expansion_denied_vars.is_some() {
+            self.expansion_denied_vars = i.expansion_denied_vars;
+        }
+        if i.expansion_fields.is_some() {
+            self.expansion_fields = i.expansion_fields;
+        }
+        if i.git_cache_dir.is_some() {
+            self.git_cache_dir = i.git_cache_dir;
+        }
         if i.hooks.is_some() {
             self.hooks = i.hooks;
         }
+        if i.locked_settings.is_some() {
+            self.locked_settings = i.locked_settings;
+        }
+        if i.locked_settings_action.is_some() {
+            self.locked_settings_action = i.locked_settings_action;
+        }
+        if i.mpi_templates.is_some() {
+            self.mpi_templates = i.mpi_templates;
+        }
+        if i.oci_hooks.is_some() {
+            self.oci_hooks = i.oci_hooks;
+        }
         if i.parallax_imagestore.is_some() {
             self.parallax_imagestore = i.parallax_imagestore;
         }
@@ -289,18 +824,51 @@ impl RawConfig {
         if i.podman_tmp_path.is_some() {
             self.podman_tmp_path = i.podman_tmp_path;
         }
+        if i.registry_aliases.is_some() {
+            self.registry_aliases = i.registry_aliases;
+        }
+        if i.registry_auth_file.is_some() {
+            self.registry_auth_file = i.registry_auth_file;
+        }
+        if i.remote_max_size.is_some() {
+            self.remote_max_size = i.remote_max_size;
+        }
+        if i.remote_timeout.is_some() {
+            self.remote_timeout = i.remote_timeout;
+        }
+        if i.render_cache_dir.is_some() {
+            self.render_cache_dir = i.render_cache_dir;
+        }
+        if i.require_image_digest.is_some() {
+            self.require_image_digest = i.require_image_digest;
+        }
         if i.runtime_path.is_some() {
             self.runtime_path = i.runtime_path;
         }
+        if i.sensitive_env_patterns.is_some() {
+            self.sensitive_env_patterns = i.sensitive_env_patterns;
+        }
         if i.skybox_enabled.is_some() {
             self.skybox_enabled = i.skybox_enabled;
         }
+        if i.template_variables.is_some() {
+            self.template_variables = i.template_variables;
+        }
         if i.tracking_enabled.is_some() {
             self.tracking_enabled = i.tracking_enabled;
         }
         if i.tracking_tool.is_some() {
             self.tracking_tool = i.tracking_tool;
         }
+        if i.user_settings.is_some() {
+            self.user_settings = i.user_settings;
+        }
+        if i.writable_overlay_path.is_some() {
+            self.writable_overlay_path = i.writable_overlay_path;
+        }
+        if i.writable_overlay_size.is_some() {
+            self.writable_overlay_size = i.writable_overlay_size;
+        }
     }
 }
 
@@ -315,17 +883,24 @@ impl From<RawConfigHooks> for ConfigHooks {
     }
 }
 
+static CONFIG_SCHEMA: OnceLock<SarusResult<Schema>> = OnceLock::new();
+
 fn validate_configfile(path: String) -> SarusResult<()> {
-    // Embedding schema file
-    let schema_content = include_str!("schema/config.json");
+    // Embedding schema file, compiled once
+    let schema = CONFIG_SCHEMA.get_or_init(|| Schema::compile(include_str!("schema/config.json")));
+    let schema = match schema {
+        Ok(s) => s,
+        Err(e) => return Err(e.clone()),
+    };
 
-    check_file_path_extension(&path, "conf")?;
+    check_file_path_extension(&path, &["conf"])?;
 
-    validate_file(path, schema_content)
+    validate_file(path, schema)
 }
 
 fn load_raw_config_from_file(
     filepath: String,
+    selector: &ConfigSelector,
     force_expand: VarExpand,
     env_option: &Option<HashMap<String, String>>,
 ) -> SarusResult<RawConfig> {
@@ -340,9 +915,10 @@ fn load_raw_config_from_file(
         Ok(c) => c,
         Err(e) => {
             return Err(SarusError {
-                code: 2,
+                kind: SarusErrorKind::FileRead,
                 file_path: Some(String::from(path_str)),
                 msg: String::from(format!("{}", e)),
+                location: None,
             });
         }
     };
@@ -351,14 +927,17 @@ fn load_raw_config_from_file(
         Ok(v) => v,
         Err(e) => {
             return Err(SarusError {
-                code: 3,
+                kind: SarusErrorKind::TomlParse,
                 file_path: Some(String::from(path_str)),
                 msg: String::from(format!("{}", e)),
+                location: None,
             });
         }
     };
 
     let mut r: RawConfig = toml_value;
+    r.flatten_tables();
+    r.apply_sections(selector);
 
     //let mut r: RawConfig = toml_read(path_str)?;
 
@@ -379,7 +958,11 @@ fn expand_raw_config_fields(
         VarExpand::Must => true,
     };
 
+    expand_raw_option_string(&mut r.default_registry, force, e)?;
+    expand_raw_option_string(&mut r.edf_policy_path, force, e)?;
     expand_raw_option_string(&mut r.edf_system_search_path, force, e)?;
+    expand_raw_option_string(&mut r.enroot_path, force, e)?;
+    expand_raw_option_string(&mut r.git_cache_dir, force, e)?;
     expand_raw_option_string(&mut r.parallax_imagestore, force, e)?;
     expand_raw_option_string(&mut r.parallax_mount_program, force, e)?;
     expand_raw_option_string(&mut r.parallax_path, force, e)?;
@@ -388,8 +971,11 @@ fn expand_raw_config_fields(
     expand_raw_option_string(&mut r.podman_module, force, e)?;
     expand_raw_option_string(&mut r.podman_path, force, e)?;
     expand_raw_option_string(&mut r.podman_tmp_path, force, e)?;
+    expand_raw_option_string(&mut r.registry_auth_file, force, e)?;
+    expand_raw_option_string(&mut r.render_cache_dir, force, e)?;
     expand_raw_option_string(&mut r.runtime_path, force, e)?;
     expand_raw_option_string(&mut r.tracking_tool, force, e)?;
+    expand_raw_option_string(&mut r.writable_overlay_path, force, e)?;
     Ok(())
 }
 
@@ -400,7 +986,8 @@ fn expand_raw_option_string(
 ) -> SarusResult<()> {
     if optstr.is_some() {
         let original = optstr.clone().unwrap();
-        let updated = match expand_vars_string(original.clone(), env_option) {
+        let expanded = expand_vars_string(original.clone(), env_option).and_then(|s| expand_tilde(&s, env_option));
+        let updated = match expanded {
             Ok(s) => s,
             Err(e) => {
                 if force {
@@ -420,46 +1007,195 @@ pub fn load_config() -> SarusResult<Config> {
     load_config_path(None, VarExpand::Must, &None)
 }
 
+// The configuration of the site, None when it has none. Settings of the
+// site may restrict renders, any other failure to load them is an error
+// rather than nothing to apply.
+pub fn load_site_config() -> SarusResult<Option<Config>> {
+    load_site_config_path(Path::new(CONFIG_PATH))
+}
+
+fn load_site_config_path(config_path: &Path) -> SarusResult<Option<Config>> {
+    if !config_path.exists() {
+        return Ok(None);
+    }
+    load_config_path(Some(config_path.to_path_buf()), VarExpand::Must, &None).map(Some)
+}
+
 pub fn load_config_path(
     config_option: Option<PathBuf>,
     force_expand: VarExpand,
     env_option: &Option<HashMap<String, String>>,
+) -> SarusResult<Config> {
+    load_config_path_with_selector(config_option, &ConfigSelector::current(), force_expand, env_option)
+}
+
+// Same as load_config_path(), the [match] and [partition] sections being
+// matched against the selector instead of the current node and job.
+pub fn load_config_path_with_selector(
+    config_option: Option<PathBuf>,
+    selector: &ConfigSelector,
+    force_expand: VarExpand,
+    env_option: &Option<HashMap<String, String>>,
 ) -> SarusResult<Config> {
     let config_path = match config_option {
         Some(path) => path,
         None => PathBuf::from(CONFIG_PATH),
     };
 
-    let r = load_raw_config_from_dir(&config_path, force_expand, env_option)?;
+    let user_dir = user_config_dir();
+    let mut r = load_raw_config_from_layers(&config_path, user_dir.as_deref(), selector, force_expand, env_option, None)?;
+    apply_env_overrides(&mut r, std::env::vars())?;
     let c = Config::from(r);
     Ok(c)
 }
 
-fn load_raw_config_from_dir(
-    config_path: &Path,
-    force_expand: VarExpand,
-    env_option: &Option<HashMap<String, String>>,
-) -> SarusResult<RawConfig> {
-    let empty = RawConfig::default();
+// Settings guarding renders against their users, never taken from the
+// environment of the render.
+const ENV_PROTECTED_SETTINGS: [&str; 13] = [
+    "edf_policy_path",
+    "locked_settings_action",
+    "require_image_digest",
+    "edf_max_size",
+    "edf_reject_world_writable",
+    "edf_contain_symlinks",
+    "edf_system_owner_check",
+    "edf_system_owner_uid",
+    "edf_system_group_writable",
+    "edf_contain_names",
+    "render_cache_dir",
+    "remote_max_size",
+    "remote_timeout",
+];
+
+// Override config values with SARUS_<FIELD> variables, e.g. SARUS_PODMAN_PATH
+// or SARUS_HOOKS_PARALLAX_IMAGESTORE_CREATE for the hooks table. Locked
+// settings are overridden no more than by annotations, the variables fail
+// the load unless locked_settings_action is "ignore".
+fn apply_env_overrides<I>(r: &mut RawConfig, vars: I) -> SarusResult<()>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let locked = r.locked_settings.clone().unwrap_or_else(get_default_locked_settings);
+    let action = r.locked_settings_action.clone().unwrap_or_else(get_default_locked_settings_action);
+    for (k, v) in vars {
+        let field = match k.strip_prefix("SARUS_") {
+            Some(f) => f.to_lowercase(),
+            None => continue,
+        };
+        if ENV_PROTECTED_SETTINGS.contains(&field.as_str()) {
+            continue;
+        }
+        if locked.contains(&field) {
+            if action == "ignore" {
+                continue;
+            }
+            let violations = vec![format!("{k} overrides a locked setting")];
+            return Err(SarusError {
+                kind: SarusErrorKind::PolicyViolation { violations: violations.clone() },
+                file_path: None,
+                msg: format!("environment violates the site configuration:\n{}", violations.join("\n")),
+                location: None,
+            });
+        }
+
+        match field.as_str() {
+            "edf_system_search_path" => r.edf_system_search_path = Some(v),
+            "default_registry" => r.default_registry = Some(v),
+            "default_engine" => r.default_engine = Some(v),
+            "default_volume_driver" => r.default_volume_driver = Some(v),
+            "enroot_path" => r.enroot_path = Some(v),
+            "git_cache_dir" => r.git_cache_dir = Some(v),
+            "hooks_parallax_imagestore_create" => {
+                let mut hooks = r.hooks.clone().unwrap_or_default();
+                hooks.parallax_imagestore_create = Some(v);
+                r.hooks = Some(hooks);
+            }
+            "parallax_imagestore" => r.parallax_imagestore = Some(v),
+            "parallax_imagestore_keepalive" => {
+                r.parallax_imagestore_keepalive = Some(env_override_bool(&k, &v)?)
+            }
+            "parallax_mount_program" => r.parallax_mount_program = Some(v),
+            "parallax_path" => r.parallax_path = Some(v),
+            "parallax_mp_uid" => r.parallax_mp_uid = Some(env_override_u32(&k, &v)?),
+            "parallax_mp_gid" => r.parallax_mp_gid = Some(env_override_u32(&k, &v)?),
+            "parallax_mp_logfile" => r.parallax_mp_logfile = Some(v),
+            "parallax_mp_squashfuse_path" => r.parallax_mp_squashfuse_path = Some(v),
+            "perfmon" => r.perfmon = Some(env_override_bool(&k, &v)?),
+            "podman_module" => r.podman_module = Some(v),
+            "podman_path" => r.podman_path = Some(v),
+            "podman_tmp_path" => r.podman_tmp_path = Some(v),
+            "registry_auth_file" => r.registry_auth_file = Some(v),
+            "runtime_path" => r.runtime_path = Some(v),
+            "skybox_enabled" => r.skybox_enabled = Some(env_override_bool(&k, &v)?),
+            "tracking_enabled" => r.tracking_enabled = Some(env_override_bool(&k, &v)?),
+            "tracking_tool" => r.tracking_tool = Some(v),
+            "writable_overlay_path" => r.writable_overlay_path = Some(v),
+            "writable_overlay_size" => r.writable_overlay_size = Some(v),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn env_override_bool(var: &str, value: &str) -> SarusResult<bool> {
+    match value {
+        "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        _ => Err(SarusError {
+            kind: SarusErrorKind::InvalidConfigOverride { var: var.to_string() },
+            file_path: None,
+            msg: format!("{var}=\"{value}\" is not a boolean (true, false, 1, 0)"),
+            location: None,
+        }),
+    }
+}
+
+fn env_override_u32(var: &str, value: &str) -> SarusResult<u32> {
+    match value.parse::<u32>() {
+        Ok(n) => Ok(n),
+        Err(e) => Err(SarusError {
+            kind: SarusErrorKind::InvalidConfigOverride { var: var.to_string() },
+            file_path: None,
+            msg: format!("{var}=\"{value}\" is not a valid id, {e}"),
+            location: None,
+        }),
+    }
+}
+
+// The config path, then every existing drop-in layer in
+// CONFIG_DROPIN_LAYERS order.
+fn config_dirs(config_path: &Path) -> Vec<PathBuf> {
+    let mut dirs = vec![config_path.to_path_buf()];
+    for layer in CONFIG_DROPIN_LAYERS.iter() {
+        let layer_path = config_path.join(layer);
+        if layer_path.is_dir() {
+            dirs.push(layer_path);
+        }
+    }
+    dirs
+}
 
+// The *.conf files of a directory, sorted by name.
+fn config_files(config_path: &Path) -> SarusResult<Vec<String>> {
     let readdir = match std::fs::read_dir(config_path) {
         Ok(ok) => ok,
         Err(emsg) => {
             return Err(SarusError {
-                code: 23,
+                kind: SarusErrorKind::ConfigDirRead,
                 file_path: Some(config_path.to_string_lossy().to_string()),
                 msg: String::from(format!("Cannot find config files, {}", emsg)),
+                location: None,
             });
         }
     };
 
-    let mut rcfg = empty;
     let mut entries = readdir
         .filter_map(Result::ok)
         .collect::<Vec<std::fs::DirEntry>>();
 
     entries.sort_by_key(|dir| dir.path());
 
+    let mut files = vec![];
     for e in entries {
         let file_name = match e.file_name().into_string() {
             Ok(s) => s,
@@ -476,115 +1212,549 @@ fn load_raw_config_from_dir(
         }
 
         if file_name.ends_with(".conf") {
-            let cur_rcfg = load_raw_config_from_file(file_path, force_expand, env_option)?;
-            rcfg.extend(cur_rcfg);
+            files.push(file_path);
         }
     }
-    Ok(rcfg)
+    Ok(files)
 }
 
-pub fn update_config_by_user(config: &mut Config, edf: EDF) -> SarusResult<()> {
-    let parallax_imagestore_create = edf.annotations.get("com.sarus.hooks.parallax_imagestore_create");
-    if parallax_imagestore_create.is_some() {
-        match parallax_imagestore_create.unwrap().as_str() {
-            "false" => { config.hooks.parallax_imagestore_create = String::from("") },
-            _ => {},
-        }
+// Directory of the config of the user, $XDG_CONFIG_HOME/sarus-suite or
+// ~/.config/sarus-suite.
+pub fn user_config_dir() -> Option<PathBuf> {
+    match std::env::var("XDG_CONFIG_HOME") {
+        Ok(d) if !d.is_empty() => Some(PathBuf::from(d).join("sarus-suite")),
+        _ => std::env::var("HOME").ok().map(|h| PathBuf::from(h).join(".config/sarus-suite")),
     }
+}
 
-    let parallax_imagestore = edf.annotations.get("com.sarus.parallax_imagestore");
-    if parallax_imagestore.is_some() {
-        config.parallax_imagestore = parallax_imagestore.unwrap().to_string();
+// The *.conf files of the user config directory, when the site config
+// allows users to set some settings and the directory exists.
+fn user_config_files(user_dir: Option<&Path>, allowed: &[String]) -> SarusResult<Vec<String>> {
+    match user_dir {
+        Some(d) if !allowed.is_empty() && d.is_dir() => config_files(d),
+        _ => Ok(vec![]),
     }
+}
 
-    let parallax_imagestore_keepalive = edf.annotations.get("com.sarus.parallax_imagestore_keepalive");
-    if parallax_imagestore_keepalive.is_some() {
-        config.parallax_imagestore_keepalive = match parallax_imagestore_keepalive.unwrap().as_str() {
-            "true" => true,
-            "false" => false,
-            _ => config.parallax_imagestore_keepalive,
-        };
+// A user config file may only set the keys listed in user_settings.
+fn check_user_settings(r: &RawConfig, allowed: &[String], file_path: &str) -> SarusResult<()> {
+    let values = config_values_raw(r);
+    let denied: Vec<String> = values
+        .iter()
+        .filter(|(k, v)| !v.is_null() && !allowed.contains(k))
+        .map(|(k, _)| format!("{k} can't be set in a user config"))
+        .collect();
+    if denied.is_empty() {
+        return Ok(());
     }
+    Err(SarusError {
+        kind: SarusErrorKind::PolicyViolation { violations: denied.clone() },
+        file_path: Some(String::from(file_path)),
+        msg: format!("{file_path} violates the site configuration:\n{}", denied.join("\n")),
+        location: None,
+    })
+}
 
-    let parallax_mount_program = edf.annotations.get("com.sarus.parallax_mount_program");
-    if parallax_mount_program.is_some() {
-        config.parallax_mount_program = parallax_mount_program.unwrap().to_string();
+fn config_values_raw(r: &RawConfig) -> serde_json::Map<String, serde_json::Value> {
+    match serde_json::to_value(r) {
+        Ok(serde_json::Value::Object(m)) => m,
+        _ => serde_json::Map::new(),
     }
+}
 
-    let parallax_mp_logfile = edf.annotations.get("com.sarus.parallax_mp_logfile");
-    if parallax_mp_logfile.is_some() {
-        config.parallax_mp_logfile = parallax_mp_logfile.unwrap().to_string();
+// Load <config_path>/*.conf, then the *.conf files of every existing
+// drop-in layer in CONFIG_DROPIN_LAYERS order, then the ones of the user
+// config directory. Files failing to load are skipped and their error
+// collected when skipped is set, they fail the load otherwise.
+fn load_raw_config_from_layers(
+    config_path: &Path,
+    user_dir: Option<&Path>,
+    selector: &ConfigSelector,
+    force_expand: VarExpand,
+    env_option: &Option<HashMap<String, String>>,
+    mut skipped: Option<&mut Vec<SarusError>>,
+) -> SarusResult<RawConfig> {
+    let mut rcfg = RawConfig::default();
+    for dir in config_dirs(config_path) {
+        for f in config_files(&dir)? {
+            match load_raw_config_from_file(f, selector, force_expand, env_option) {
+                Ok(cur_rcfg) => rcfg.extend(cur_rcfg),
+                Err(e) => match skipped.as_mut() {
+                    Some(s) => s.push(e),
+                    None => return Err(e),
+                },
+            }
+        }
     }
 
-    let parallax_mp_squashfuse_path = edf.annotations.get("com.sarus.parallax_mp_squashfuse_path");
-    if parallax_mp_squashfuse_path.is_some() {
-        config.parallax_mp_squashfuse_path = parallax_mp_squashfuse_path.unwrap().to_string();
+    let allowed = rcfg.user_settings.clone().unwrap_or_default();
+    for f in user_config_files(user_dir, &allowed)? {
+        let res = load_raw_config_from_file(f.clone(), selector, force_expand, env_option)
+            .and_then(|r| check_user_settings(&r, &allowed, &f).map(|_| r));
+        match res {
+            Ok(cur_rcfg) => rcfg.extend(cur_rcfg),
+            Err(e) => match skipped.as_mut() {
+                Some(s) => s.push(e),
+                None => return Err(e),
+            },
+        }
     }
+    Ok(rcfg)
+}
 
-    let parallax_path = edf.annotations.get("com.sarus.parallax_path");
-    if parallax_path.is_some() {
-        config.parallax_path = parallax_path.unwrap().to_string();
+// Load the configuration like load_config_path(), skipping the files which
+// fail validation or can't be read instead of failing, e.g. to keep a
+// service running on a typo. The errors of the skipped files are returned
+// with the configuration.
+pub fn load_config_path_lenient(
+    config_option: Option<PathBuf>,
+    force_expand: VarExpand,
+    env_option: &Option<HashMap<String, String>>,
+) -> SarusResult<(Config, Vec<SarusError>)> {
+    let config_path = match config_option {
+        Some(path) => path,
+        None => PathBuf::from(CONFIG_PATH),
+    };
+
+    let mut skipped = vec![];
+    let selector = ConfigSelector::current();
+    let user_dir = user_config_dir();
+    let mut r = load_raw_config_from_layers(
+        &config_path,
+        user_dir.as_deref(),
+        &selector,
+        force_expand,
+        env_option,
+        Some(&mut skipped),
+    )?;
+    apply_env_overrides(&mut r, std::env::vars())?;
+    Ok((Config::from(r), skipped))
+}
+
+// Where a config value was set.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", content = "name", rename_all = "snake_case")]
+pub enum ConfigOrigin {
+    // A .conf file, by path.
+    File(String),
+    // A SARUS_* variable, by name.
+    Env(String),
+    // A com.sarus.* annotation of an EDF, by key.
+    Annotation(String),
+}
+
+impl std::fmt::Display for ConfigOrigin {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConfigOrigin::File(p) => write!(f, "{p}"),
+            ConfigOrigin::Env(v) => write!(f, "env {v}"),
+            ConfigOrigin::Annotation(k) => write!(f, "annotation {k}"),
+        }
     }
+}
 
-    let perfmon = edf.annotations.get("com.sarus.perfmon");
-    if perfmon.is_some() {
-        config.perfmon = match perfmon.unwrap().as_str() {
-            "true" => true,
-            "false" => false,
-            _ => config.perfmon,
-        };
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConfigSetting {
+    pub origin: ConfigOrigin,
+    pub value: serde_json::Value,
+}
+
+// The settings of every config field in the order they were applied, the
+// last one giving the final value. Fields missing have their default.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ConfigProvenance {
+    pub fields: BTreeMap<String, Vec<ConfigSetting>>,
+}
+
+impl ConfigProvenance {
+    // Where the final value of the field comes from, None for defaults.
+    pub fn origin(&self, field: &str) -> Option<&ConfigOrigin> {
+        self.fields.get(field).and_then(|s| s.last()).map(|s| &s.origin)
     }
 
-    let podman_module = edf.annotations.get("com.sarus.podman_module");
-    if podman_module.is_some() {
-        config.podman_module = podman_module.unwrap().to_string();
+    fn push(&mut self, field: &str, origin: &ConfigOrigin, value: serde_json::Value) {
+        self.fields.entry(String::from(field)).or_default().push(ConfigSetting {
+            origin: origin.clone(),
+            value,
+        });
     }
 
-    let podman_path = edf.annotations.get("com.sarus.podman_path");
-    if podman_path.is_some() {
-        config.podman_path = podman_path.unwrap().to_string();
+    // Record the fields set by a raw config.
+    fn record(&mut self, r: &RawConfig, origin: ConfigOrigin) {
+        for (k, v) in config_values_raw(r) {
+            if !v.is_null() {
+                self.push(&k, &origin, v);
+            }
+        }
     }
 
-    let podman_tmp_path = edf.annotations.get("com.sarus.podman_tmp_path");
-    if podman_tmp_path.is_some() {
-        config.podman_tmp_path = podman_tmp_path.unwrap().to_string();
+    // One line per field set, "field = value (origin)", followed by the
+    // values it overrides.
+    pub fn report(&self) -> String {
+        let mut lines = vec![];
+        for (field, settings) in self.fields.iter() {
+            for (i, s) in settings.iter().rev().enumerate() {
+                match i {
+                    0 => lines.push(format!("{field} = {} ({})", s.value, s.origin)),
+                    _ => lines.push(format!("  overrides {} ({})", s.value, s.origin)),
+                }
+            }
+        }
+        lines.join("\n")
     }
+}
 
-    let runtime_path = edf.annotations.get("com.sarus.runtime_path");
-    if runtime_path.is_some() {
-        config.runtime_path = runtime_path.unwrap().to_string();
+pub fn load_config_with_provenance() -> SarusResult<(Config, ConfigProvenance)> {
+    load_config_path_with_provenance(None, VarExpand::Must, &None)
+}
+
+// Load the configuration like load_config_path(), recording which file or
+// SARUS_* variable set every field.
+pub fn load_config_path_with_provenance(
+    config_option: Option<PathBuf>,
+    force_expand: VarExpand,
+    env_option: &Option<HashMap<String, String>>,
+) -> SarusResult<(Config, ConfigProvenance)> {
+    let config_path = match config_option {
+        Some(path) => path,
+        None => PathBuf::from(CONFIG_PATH),
+    };
+    let user_dir = user_config_dir();
+    load_config_with_provenance_from(
+        &config_path,
+        user_dir.as_deref(),
+        force_expand,
+        env_option,
+        std::env::vars().collect(),
+    )
+}
+
+fn load_config_with_provenance_from(
+    config_path: &Path,
+    user_dir: Option<&Path>,
+    force_expand: VarExpand,
+    env_option: &Option<HashMap<String, String>>,
+    mut vars: Vec<(String, String)>,
+) -> SarusResult<(Config, ConfigProvenance)> {
+    let mut prov = ConfigProvenance::default();
+    let selector = ConfigSelector::current();
+    let mut r = RawConfig::default();
+    for dir in config_dirs(config_path) {
+        for f in config_files(&dir)? {
+            let cur_rcfg = load_raw_config_from_file(f.clone(), &selector, force_expand, env_option)?;
+            prov.record(&cur_rcfg, ConfigOrigin::File(f));
+            r.extend(cur_rcfg);
+        }
+    }
+    let allowed = r.user_settings.clone().unwrap_or_default();
+    for f in user_config_files(user_dir, &allowed)? {
+        let cur_rcfg = load_raw_config_from_file(f.clone(), &selector, force_expand, env_option)?;
+        check_user_settings(&cur_rcfg, &allowed, &f)?;
+        prov.record(&cur_rcfg, ConfigOrigin::File(f));
+        r.extend(cur_rcfg);
     }
 
-    let skybox_enabled = edf.annotations.get("com.sarus.skybox_enabled");
-    if skybox_enabled.is_some() {
-        config.skybox_enabled = match skybox_enabled.unwrap().as_str() {
-            "true" => true,
-            "false" => false,
-            _ => config.skybox_enabled,
-        };
+    vars.sort();
+    for (k, v) in vars.iter() {
+        let mut cur = RawConfig::default();
+        apply_env_overrides(&mut cur, [(k.clone(), v.clone())])?;
+        prov.record(&cur, ConfigOrigin::Env(k.clone()));
     }
+    apply_env_overrides(&mut r, vars)?;
+    Ok((Config::from(r), prov))
+}
 
-    let tracking_enabled = edf.annotations.get("com.sarus.tracking_enabled");
-    if tracking_enabled.is_some() {
-        config.tracking_enabled = match tracking_enabled.unwrap().as_str() {
-            "true" => true,
-            "false" => false,
-            _ => config.tracking_enabled,
+// Same as update_config_by_user_with_warnings(), recording the fields set
+// by the annotations of the EDF in the provenance.
+pub fn update_config_by_user_with_provenance(
+    config: &mut Config,
+    edf: EDF,
+    prov: &mut ConfigProvenance,
+) -> SarusResult<Vec<String>> {
+    let applied = applied_annotations(config, &edf);
+    let warnings = update_config_by_user_with_warnings(config, edf)?;
+
+    let values = config_values(config);
+    for (k, field) in applied {
+        if let Some(v) = values.get(field) {
+            prov.push(field, &ConfigOrigin::Annotation(k), v.clone());
+        }
+    }
+    Ok(warnings)
+}
+
+// Config fields which a com.sarus.* annotation of the same name overrides.
+const ANNOTATION_FIELDS: [&str; 14] = [
+    "parallax_imagestore",
+    "parallax_imagestore_keepalive",
+    "parallax_mount_program",
+    "parallax_mp_logfile",
+    "parallax_mp_squashfuse_path",
+    "parallax_path",
+    "perfmon",
+    "podman_module",
+    "podman_path",
+    "podman_tmp_path",
+    "runtime_path",
+    "skybox_enabled",
+    "tracking_enabled",
+    "tracking_tool",
+];
+
+// The com.sarus.* annotations of an EDF which update_config_by_user() applies,
+// with the config field each one sets.
+fn applied_annotations(config: &Config, edf: &EDF) -> Vec<(String, &'static str)> {
+    let malformed: Vec<String> = SarusAnnotations::parse(&edf.annotations)
+        .malformed
+        .into_iter()
+        .map(|m| m.key)
+        .collect();
+    let mut res = vec![];
+    for k in edf.annotations.keys() {
+        let name = match k.strip_prefix(SARUS_ANNOTATION_PREFIX) {
+            Some(n) => n,
+            None => continue,
         };
+        if malformed.contains(k) || config.locked_settings.iter().any(|l| l == name) {
+            continue;
+        }
+        let field = match name {
+            "hooks.parallax_imagestore_create" => "hooks",
+            n => match ANNOTATION_FIELDS.iter().find(|f| **f == n) {
+                Some(f) => *f,
+                None => continue,
+            },
+        };
+        res.push((k.clone(), field));
+    }
+    res
+}
+
+fn config_values(config: &Config) -> serde_json::Map<String, serde_json::Value> {
+    match serde_json::to_value(config) {
+        Ok(serde_json::Value::Object(m)) => m,
+        _ => serde_json::Map::new(),
     }
+}
 
-    let tracking_tool = edf.annotations.get("com.sarus.tracking_tool");
-    if tracking_tool.is_some() {
-        config.tracking_tool = tracking_tool.unwrap().to_string();
+// A config field changed by an annotation of an EDF.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OverrideRecord {
+    pub annotation: String,
+    pub field: String,
+    pub old: serde_json::Value,
+    pub new: serde_json::Value,
+}
+
+impl Config {
+    // The config of a run of the EDF, with the overrides its annotations
+    // made, for the runtime to log them.
+    pub fn effective(&self, edf: EDF) -> SarusResult<(Config, Vec<OverrideRecord>)> {
+        let mut config = self.clone();
+        let applied = applied_annotations(&config, &edf);
+        update_config_by_user_with_warnings(&mut config, edf)?;
+
+        let old = config_values(self);
+        let new = config_values(&config);
+        let records = applied
+            .into_iter()
+            .map(|(annotation, field)| OverrideRecord {
+                annotation,
+                field: String::from(field),
+                old: old.get(field).cloned().unwrap_or_default(),
+                new: new.get(field).cloned().unwrap_or_default(),
+            })
+            .collect();
+        Ok((config, records))
+    }
+}
+
+fn unknown_config_keys(table: &serde_json::Value, known: &serde_json::Map<String, serde_json::Value>) -> Vec<String> {
+    match table.as_object() {
+        Some(t) => t.keys().filter(|k| !known.contains_key(*k)).cloned().collect(),
+        None => vec![],
+    }
+}
+
+// Validate the config files of a config path and of its drop-in layers,
+// one report per file in load order. Keys unknown to the schema, e.g.
+// typos, are ignored by the load and reported as warnings.
+pub fn validate_config_dir(config_path: &Path) -> SarusResult<Vec<ValidationReport>> {
+    let schema = CONFIG_SCHEMA.get_or_init(|| Schema::compile(include_str!("schema/config.json")));
+    let schema = match schema {
+        Ok(s) => s,
+        Err(e) => return Err(e.clone()),
+    };
+    // Every key of the RawConfig, whose unset fields serialize as null
+    let known = match serde_json::to_value(RawConfig::default()) {
+        Ok(serde_json::Value::Object(m)) => m,
+        _ => serde_json::Map::new(),
+    };
+
+    let mut reports = vec![];
+    for dir in config_dirs(config_path) {
+        for f in config_files(&dir)? {
+            let mut report = ValidationReport {
+                file_path: f.clone(),
+                ..Default::default()
+            };
+            let content = match std::fs::read_to_string(&f) {
+                Ok(c) => c,
+                Err(e) => {
+                    report.error("file-read", None, format!("{e}"));
+                    reports.push(report);
+                    continue;
+                }
+            };
+            let doc: serde_json::Value = match toml::from_str(&content) {
+                Ok(d) => d,
+                Err(e) => {
+                    report.errors.push(Diagnostic {
+                        id: String::from("toml-parse"),
+                        key: None,
+                        msg: e.message().to_string(),
+                        location: e.span().map(|r| location_of(&content, r.start)),
+                    });
+                    reports.push(report);
+                    continue;
+                }
+            };
+
+            for (pointer, e) in schema.errors_with_paths(&doc) {
+                report.errors.push(Diagnostic {
+                    id: String::from("schema"),
+                    key: None,
+                    msg: e,
+                    location: document_location(&content, &f, &pointer),
+                });
+            }
+            if report.errors.is_empty()
+                && let Err(e) = toml::from_str::<RawConfig>(&content)
+            {
+                report.errors.push(Diagnostic {
+                    id: String::from("invalid-value"),
+                    key: None,
+                    msg: e.message().to_string(),
+                    location: e.span().map(|r| location_of(&content, r.start)),
+                });
+            }
+            for key in unknown_config_keys(&doc, &known) {
+                report.warning("unknown-key", Some(&key), format!("unknown key \"{key}\" is ignored"));
+            }
+            for section in ["match", "partition"] {
+                let sections = match doc.get(section).and_then(|s| s.as_object()) {
+                    Some(s) => s,
+                    None => continue,
+                };
+                for (name, table) in sections {
+                    for key in unknown_config_keys(table, &known) {
+                        let key = format!("{section}.{name}.{key}");
+                        report.warning("unknown-key", Some(&key), format!("unknown key \"{key}\" is ignored"));
+                    }
+                }
+            }
+            report.locate(&content);
+            reports.push(report);
+        }
     }
+    Ok(reports)
+}
+
+pub fn update_config_by_user(config: &mut Config, edf: EDF) -> SarusResult<()> {
+    update_config_by_user_with_warnings(config, edf)?;
     Ok(())
 }
 
+// Same as update_config_by_user(), returning warnings about the annotations
+// which were ignored because they are malformed or override a locked setting. Such
+// annotations fail the update unless locked_settings_action is "ignore".
+pub fn update_config_by_user_with_warnings(config: &mut Config, edf: EDF) -> SarusResult<Vec<String>> {
+    let mut edf = edf;
+    let mut locked: Vec<String> = edf
+        .annotations
+        .keys()
+        .filter(|k| match k.strip_prefix(SARUS_ANNOTATION_PREFIX) {
+            Some(s) => config.locked_settings.iter().any(|l| l == s),
+            None => false,
+        })
+        .cloned()
+        .collect();
+    locked.sort();
+
+    let mut warnings = vec![];
+    if !locked.is_empty() {
+        if config.locked_settings_action != "ignore" {
+            let violations: Vec<String> = locked.iter().map(|k| format!("{k} overrides a locked setting")).collect();
+            return Err(SarusError {
+                kind: SarusErrorKind::PolicyViolation { violations: violations.clone() },
+                file_path: None,
+                msg: format!("EDF violates the site configuration:\n{}", violations.join("\n")),
+                location: None,
+            });
+        }
+        for k in locked {
+            edf.annotations.remove(&k);
+            warnings.push(format!("annotation {k} is ignored, it overrides a locked setting"));
+        }
+    }
+
+    let a = SarusAnnotations::parse(&edf.annotations);
+    if a.hooks_parallax_imagestore_create == Some(false) {
+        config.hooks.parallax_imagestore_create = String::from("");
+    }
+    if let Some(v) = a.parallax_imagestore {
+        config.parallax_imagestore = v;
+    }
+    if let Some(v) = a.parallax_imagestore_keepalive {
+        config.parallax_imagestore_keepalive = v;
+    }
+    if let Some(v) = a.parallax_mount_program {
+        config.parallax_mount_program = v;
+    }
+    if let Some(v) = a.parallax_mp_logfile {
+        config.parallax_mp_logfile = v;
+    }
+    if let Some(v) = a.parallax_mp_squashfuse_path {
+        config.parallax_mp_squashfuse_path = v;
+    }
+    if let Some(v) = a.parallax_path {
+        config.parallax_path = v;
+    }
+    if let Some(v) = a.perfmon {
+        config.perfmon = v;
+    }
+    if let Some(v) = a.podman_module {
+        config.podman_module = v;
+    }
+    if let Some(v) = a.podman_path {
+        config.podman_path = v;
+    }
+    if let Some(v) = a.podman_tmp_path {
+        config.podman_tmp_path = v;
+    }
+    if let Some(v) = a.runtime_path {
+        config.runtime_path = v;
+    }
+    if let Some(v) = a.skybox_enabled {
+        config.skybox_enabled = v;
+    }
+    if let Some(v) = a.tracking_enabled {
+        config.tracking_enabled = v;
+    }
+    if let Some(v) = a.tracking_tool {
+        config.tracking_tool = v;
+    }
+
+    // Malformed values leave the configuration as it is
+    warnings.extend(a.malformed.into_iter().map(|m| m.msg));
+    Ok(warnings)
+}
+
 pub fn remove_sarus_annotations(edf: &mut EDF) -> SarusResult<()> {
     let loop_edf = edf.clone();
 
     for key in loop_edf.annotations.keys() {
-        if key.starts_with("com.sarus.") {
+        if key.starts_with(SARUS_ANNOTATION_PREFIX) {
             edf.annotations.remove(key);
         }
     }
@@ -626,6 +1796,269 @@ mod tests {
         assert!(cfg.skybox_enabled == true);
         assert!(cfg.tracking_enabled == false);
         assert!(cfg.tracking_tool == "");
+        let cray = cfg.mpi_templates.get("cray").unwrap();
+        assert!(cray.mounts == vec!["/opt/cray:/opt/cray:ro"]);
+        assert!(cray.env.get("MPICH_GPU_SUPPORT_ENABLED").unwrap() == "1");
+    }
+
+    #[test]
+    fn load_config_dropin_layers() {
+        let cfg = get_rendered_config("config-dropin").unwrap();
+
+        assert!(cfg.edf_system_search_path == "/etc/edf_base");
+        assert!(cfg.podman_path == "podman_confd");
+        assert!(cfg.parallax_path == "parallax_site");
+        assert!(cfg.runtime_path == "crun_cluster");
+        assert!(cfg.podman_module == "hpc_vendor");
+    }
+
+    #[test]
+    fn load_config_sections() {
+        let cfg_path = PathBuf::from(format!("{}/test/config-sections", env!("CARGO_MANIFEST_DIR")));
+        let load = |hostname: &str, partition: Option<&str>| {
+            let selector = ConfigSelector {
+                hostname: Some(String::from(hostname)),
+                partition: partition.map(String::from),
+            };
+            load_config_path_with_selector(Some(cfg_path.clone()), &selector, VarExpand::Must, &None).unwrap()
+        };
+
+        let cfg = load("login01", None);
+        assert!(cfg.podman_path == "/usr/bin/podman" && cfg.runtime_path == "crun");
+        let cfg = load("nid001234", None);
+        assert!(cfg.podman_path == "/opt/podman/bin/podman" && cfg.runtime_path == "crun");
+        assert!(cfg.podman_tmp_path == "/dev/shm/podman");
+        let cfg = load("nid001234", Some("gpu"));
+        assert!(cfg.podman_path == "/opt/podman/bin/podman" && cfg.runtime_path == "crun-gpu");
+        assert!(cfg.default_engine == "enroot");
+        let cfg = load("nid001234", Some("debug"));
+        assert!(cfg.runtime_path == "crun" && cfg.default_engine == "podman");
+    }
+
+    #[test]
+    fn load_config_invalid_files() {
+        let cfg_path = PathBuf::from(format!("{}/test/config-invalid", env!("CARGO_MANIFEST_DIR")));
+        let err = load_config_path(Some(cfg_path.clone()), VarExpand::Must, &None).err().unwrap();
+        assert!(err.file_path.unwrap().ends_with("10-broken.conf"));
+
+        let (cfg, skipped) = load_config_path_lenient(Some(cfg_path.clone()), VarExpand::Must, &None).unwrap();
+        assert!(cfg.podman_path == "/usr/bin/podman" && cfg.runtime_path == get_default_runtime_path());
+        assert!(skipped.len() == 1 && skipped[0].file_path.as_ref().unwrap().ends_with("10-broken.conf"));
+
+        let reports = validate_config_dir(&cfg_path).unwrap();
+        assert!(reports.len() == 2 && reports[0].is_valid() && !reports[1].is_valid());
+        let keys: Vec<&str> = reports[0].warnings.iter().map(|w| w.key.as_deref().unwrap()).collect();
+        assert!(keys == vec!["podman_pth", "partition.gpu.runtim_path"]);
+        assert!(reports[0].warnings[0].location.unwrap().line == 2);
+        assert!(reports[1].errors[0].id == "invalid-value" && reports[1].errors[0].location.unwrap().line == 2);
+        assert!(reports[1].warnings.is_empty());
+    }
+
+    #[test]
+    fn load_config_provenance() {
+        let cfg_path = PathBuf::from(format!("{}/test/config-dropin", env!("CARGO_MANIFEST_DIR")));
+        let vars = vec![(String::from("SARUS_PODMAN_PATH"), String::from("/env/podman"))];
+        let (mut cfg, mut prov) = load_config_with_provenance_from(&cfg_path, None, VarExpand::Must, &None, vars).unwrap();
+
+        assert!(cfg.podman_path == "/env/podman");
+        assert!(prov.origin("podman_path") == Some(&ConfigOrigin::Env(String::from("SARUS_PODMAN_PATH"))));
+        let layers = &prov.fields["podman_path"];
+        assert!(matches!(&layers[0].origin, ConfigOrigin::File(f) if f.ends_with("/00-base.conf")));
+        assert!(matches!(&layers[layers.len() - 2].origin, ConfigOrigin::File(f) if f.ends_with("10-dropin.conf")));
+        assert!(matches!(prov.origin("runtime_path"), Some(ConfigOrigin::File(f)) if f.ends_with("10-cluster.conf")));
+        assert!(prov.origin("enroot_path").is_none());
+        assert!(prov.report().contains("podman_path = \"/env/podman\" (env SARUS_PODMAN_PATH)\n  overrides"));
+
+        let edf = crate::get_edf_from_string(String::from(
+            "image = \"ubuntu\"\n[annotations]\n\"com.sarus.runtime_path\" = \"/usr/bin/runc\"\n",
+        ))
+        .unwrap();
+        update_config_by_user_with_provenance(&mut cfg, edf, &mut prov).unwrap();
+        assert!(prov.origin("runtime_path") == Some(&ConfigOrigin::Annotation(String::from("com.sarus.runtime_path"))));
+        assert!(prov.fields["runtime_path"].last().unwrap().value == "/usr/bin/runc");
+    }
+
+    #[test]
+    fn load_config_user_settings() {
+        let dir = PathBuf::from(format!("{}/test/config-user", env!("CARGO_MANIFEST_DIR")));
+        let selector = ConfigSelector::default();
+        let load = |user_dir: &str, skipped: Option<&mut Vec<SarusError>>| {
+            let user_dir = dir.join(user_dir);
+            load_raw_config_from_layers(&dir.join("site"), Some(&user_dir), &selector, VarExpand::Must, &None, skipped)
+                .map(Config::from)
+        };
+
+        let cfg = load("home", None).unwrap();
+        assert!(cfg.podman_tmp_path == "/dev/shm/podman" && cfg.podman_path == "/usr/bin/podman");
+        assert!(load("missing", None).unwrap().podman_tmp_path == "/tmp/podman");
+
+        let err = load("home-denied", None).err().unwrap();
+        assert!(err.code() == 38 && err.msg.contains("podman_path can't be set"));
+        assert!(!err.msg.contains("podman_tmp_path"));
+        let mut skipped = vec![];
+        let cfg = load("home-denied", Some(&mut skipped)).unwrap();
+        assert!(cfg.podman_path == "/usr/bin/podman" && skipped.len() == 1);
+
+        // Ignored when the site allows no user settings
+        let cfg = load_raw_config_from_layers(
+            &PathBuf::from(format!("{}/test/config-dropin", env!("CARGO_MANIFEST_DIR"))),
+            Some(&dir.join("home-denied")),
+            &selector,
+            VarExpand::Must,
+            &None,
+            None,
+        )
+        .map(Config::from)
+        .unwrap();
+        assert!(cfg.podman_path != "/home/u/podman");
+    }
+
+    #[test]
+    fn load_config_tables() {
+        let cfg = get_rendered_config("config-tables").unwrap();
+
+        assert!(cfg.parallax_path == "parallax_table");
+        assert!(cfg.parallax_mp_uid == 1000);
+        assert!(cfg.podman_module == "hpc_flat");
+        assert!(cfg.podman_tmp_path == "/tmp/podman_table");
+        assert!(cfg.runtime_path == "crun_tables");
+    }
+
+    #[test]
+    fn merge_config_locked_settings() {
+        let edf = crate::get_edf_from_string(String::from(
+            r#"
+            image = "ubuntu"
+            [annotations]
+            "com.sarus.podman_path" = "/home/u/podman"
+            "com.sarus.perfmon" = "true"
+            "#,
+        ))
+        .unwrap();
+
+        let mut cfg = Config {
+            podman_path: String::from("/usr/bin/podman"),
+            locked_settings: vec![String::from("podman_path")],
+            locked_settings_action: String::from("reject"),
+            ..Default::default()
+        };
+        let err = update_config_by_user(&mut cfg, edf.clone()).err().unwrap();
+        assert!(err.code() == 38);
+        assert!(cfg.podman_path == "/usr/bin/podman");
+
+        cfg.locked_settings_action = String::from("ignore");
+        let warnings = update_config_by_user_with_warnings(&mut cfg, edf).unwrap();
+        assert!(warnings.len() == 1 && warnings[0].contains("com.sarus.podman_path"));
+        assert!(cfg.podman_path == "/usr/bin/podman");
+        assert!(cfg.perfmon);
+    }
+
+    #[test]
+    fn config_effective() {
+        let edf = crate::get_edf_from_string(String::from(
+            "image = \"ubuntu\"\n[annotations]\n\"com.sarus.podman_path\" = \"/home/u/podman\"\n\
+             \"com.sarus.perfmon\" = \"true\"\n\"com.sarus.locked_settings\" = \"\"\n\
+             \"com.sarus.tracking_enabled\" = \"maybe\"\n",
+        ))
+        .unwrap();
+        let cfg = Config {
+            podman_path: String::from("/usr/bin/podman"),
+            locked_settings: vec![String::from("perfmon")],
+            locked_settings_action: String::from("ignore"),
+            ..Default::default()
+        };
+        let (eff, records) = cfg.effective(edf).unwrap();
+        assert!(eff.podman_path == "/home/u/podman" && cfg.podman_path == "/usr/bin/podman");
+        assert!(!eff.perfmon);
+        assert!(records.len() == 1);
+        assert!(records[0].annotation == "com.sarus.podman_path" && records[0].field == "podman_path");
+        assert!(records[0].old == "/usr/bin/podman" && records[0].new == "/home/u/podman");
+        let json = serde_json::to_string(&records).unwrap();
+        assert!(json.contains("\"old\":\"/usr/bin/podman\""));
+    }
+
+    #[test]
+    fn load_config_env_overrides() {
+        let mut r = RawConfig {
+            podman_path: Some(String::from("podman_file")),
+            runtime_path: Some(String::from("crun_file")),
+            ..Default::default()
+        };
+        let vars = vec![
+            (String::from("SARUS_PODMAN_PATH"), String::from("podman_env")),
+            (String::from("SARUS_PERFMON"), String::from("true")),
+            (String::from("SARUS_PARALLAX_MP_UID"), String::from("1234")),
+            (String::from("SARUS_HOOKS_PARALLAX_IMAGESTORE_CREATE"), String::from("/bin/true")),
+            (String::from("PODMAN_PATH"), String::from("ignored")),
+        ];
+        apply_env_overrides(&mut r, vars).unwrap();
+
+        let cfg = Config::from(r);
+        assert!(cfg.podman_path == "podman_env");
+        assert!(cfg.runtime_path == "crun_file");
+        assert!(cfg.perfmon);
+        assert!(cfg.parallax_mp_uid == 1234);
+        assert!(cfg.hooks.parallax_imagestore_create == "/bin/true");
+
+        let mut r = RawConfig::default();
+        let vars = vec![(String::from("SARUS_SKYBOX_ENABLED"), String::from("maybe"))];
+        assert!(apply_env_overrides(&mut r, vars).is_err());
+
+        let mut r = RawConfig {
+            podman_path: Some(String::from("/usr/bin/podman")),
+            locked_settings: Some(vec![String::from("podman_path"), String::from("runtime_path")]),
+            ..Default::default()
+        };
+        let vars = vec![(String::from("SARUS_RUNTIME_PATH"), String::from("/home/u/crun"))];
+        assert!(apply_env_overrides(&mut r, vars).err().unwrap().code() == 38);
+        r.locked_settings_action = Some(String::from("ignore"));
+        let vars = vec![
+            (String::from("SARUS_PODMAN_PATH"), String::from("/home/u/podman")),
+            (String::from("SARUS_RUNTIME_PATH"), String::from("/home/u/crun")),
+            (String::from("SARUS_PERFMON"), String::from("true")),
+        ];
+        apply_env_overrides(&mut r, vars).unwrap();
+        assert!(r.podman_path.as_deref() == Some("/usr/bin/podman") && r.runtime_path.is_none());
+        assert!(r.perfmon == Some(true));
+
+        let mut r = RawConfig::default();
+        let vars: Vec<(String, String)> = ENV_PROTECTED_SETTINGS
+            .iter()
+            .map(|s| (format!("SARUS_{}", s.to_uppercase()), String::from("1")))
+            .collect();
+        apply_env_overrides(&mut r, vars).unwrap();
+        assert!(r.edf_policy_path.is_none() && r.locked_settings_action.is_none());
+        assert!(r.require_image_digest.is_none());
+        assert!(r.edf_max_size.is_none() && r.edf_reject_world_writable.is_none() && r.edf_contain_symlinks.is_none());
+        assert!(r.edf_system_owner_check.is_none() && r.edf_system_owner_uid.is_none()
+            && r.edf_system_group_writable.is_none());
+        assert!(r.edf_contain_names.is_none());
+        assert!(r.render_cache_dir.is_none());
+        assert!(r.remote_max_size.is_none() && r.remote_timeout.is_none());
+    }
+
+    #[test]
+    fn load_site_config_failures() {
+        let tmp = std::env::temp_dir().join(format!("raster-site-config-{}", std::process::id()));
+        assert!(load_site_config_path(&tmp).unwrap().is_none());
+
+        std::fs::create_dir_all(&tmp).unwrap();
+        std::fs::write(tmp.join("00-base.conf"), "perfmon = \"maybe\"\n").unwrap();
+        assert!(load_site_config_path(&tmp).is_err());
+        std::fs::write(tmp.join("00-base.conf"), "perfmon = true\n").unwrap();
+        assert!(load_site_config_path(&tmp).unwrap().unwrap().perfmon);
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn load_config_tilde() {
+        let cfg_path = std::env::current_dir().unwrap().join("test/config-tilde");
+        let env = Some(HashMap::from([(String::from("HOME"), String::from("/users/u"))]));
+        let cfg = load_config_path(Some(cfg_path), VarExpand::Must, &env).unwrap();
+
+        assert!(cfg.podman_tmp_path == "/users/u/podman");
+        assert!(cfg.parallax_imagestore == "/users/u");
+        assert!(cfg.parallax_path == "/opt/~parallax");
     }
 
     #[test]
@@ -0,0 +1,228 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use regex::Regex;
+
+use crate::error::{SarusError, SarusErrorKind, SarusResult};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Device {
+    Path(PathDevice), // HOST_PATH[:CONTAINER_PATH][:PERMISSIONS]
+    Cdi(CdiDevice),   // VENDOR/CLASS=NAME, e.g. nvidia.com/gpu=all
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PathDevice {
+    pub host_path: String,
+    pub container_path: Option<String>,
+    pub permissions: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CdiDevice {
+    pub vendor: String,
+    pub class: String,
+    pub name: String,
+}
+
+impl Serialize for Device {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_device_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Device {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Device::try_new(s).map_err(serde::de::Error::custom)
+    }
+}
+
+impl PathDevice {
+    fn parse(input: &str) -> Result<PathDevice, String> {
+        let parts: Vec<&str> = input.split(':').collect();
+        if parts.len() > 3 {
+            return Err(String::from("too many ':' separated fields"));
+        }
+
+        let host_path = parts[0].to_string();
+        if !host_path.starts_with('/') {
+            return Err(format!("device path \"{host_path}\" is not absolute"));
+        }
+
+        let mut container_path = None;
+        let mut permissions = None;
+        for p in parts[1..].iter() {
+            if p.starts_with('/') && container_path.is_none() && permissions.is_none() {
+                container_path = Some(p.to_string());
+            } else if is_permissions(p) && permissions.is_none() {
+                permissions = Some(p.to_string());
+            } else {
+                return Err(format!("invalid device field \"{p}\""));
+            }
+        }
+
+        Ok(PathDevice {
+            host_path,
+            container_path,
+            permissions,
+        })
+    }
+}
+
+impl CdiDevice {
+    fn parse(input: &str) -> Result<CdiDevice, String> {
+        let re = Regex::new(
+            r"^([A-Za-z0-9][A-Za-z0-9_.-]*)/([A-Za-z0-9][A-Za-z0-9_.-]*)=([A-Za-z0-9][A-Za-z0-9_.:-]*)$",
+        )
+        .unwrap();
+
+        let caps = match re.captures(input) {
+            Some(c) => c,
+            None => return Err(String::from("expected a CDI name like vendor.com/class=name")),
+        };
+        let vendor = caps[1].to_string();
+        if !vendor.contains('.') {
+            return Err(format!("CDI vendor \"{vendor}\" is not a domain name"));
+        }
+
+        Ok(CdiDevice {
+            vendor,
+            class: caps[2].to_string(),
+            name: caps[3].to_string(),
+        })
+    }
+
+    // Fully qualified CDI name, e.g. nvidia.com/gpu=all
+    pub fn qualified_name(&self) -> String {
+        format!("{}/{}={}", self.vendor, self.class, self.name)
+    }
+}
+
+impl Device {
+    pub fn try_new(input: String) -> SarusResult<Device> {
+        let r = if is_cdi_name(&input) {
+            CdiDevice::parse(&input).map(Device::Cdi)
+        } else {
+            PathDevice::parse(&input).map(Device::Path)
+        };
+
+        match r {
+            Ok(d) => Ok(d),
+            Err(e) => Err(SarusError {
+                kind: SarusErrorKind::InvalidDevice { device: input.clone() },
+                file_path: None,
+                msg: format!("invalid device \"{input}\": {e}"),
+                location: None,
+            }),
+        }
+    }
+
+    pub fn is_cdi(&self) -> bool {
+        matches!(self, Device::Cdi(_))
+    }
+
+    // Device as written in an EDF.
+    pub fn to_device_string(&self) -> String {
+        match self {
+            Device::Path(p) => {
+                let mut parts = vec![p.host_path.clone()];
+                if let Some(c) = &p.container_path {
+                    parts.push(c.clone());
+                }
+                if let Some(m) = &p.permissions {
+                    parts.push(m.clone());
+                }
+                parts.join(":")
+            }
+            Device::Cdi(c) => c.qualified_name(),
+        }
+    }
+
+    // Container engine argument, "--device=/dev/fuse" or "--device=nvidia.com/gpu=all".
+    pub fn to_arg(&self) -> String {
+        match self {
+            Device::Path(_) => format!("--device={}", self.to_device_string()),
+            Device::Cdi(c) => format!("--device={}", c.qualified_name()),
+        }
+    }
+}
+
+pub fn devices_from_strings(input: Vec<String>) -> SarusResult<Vec<Device>> {
+    let mut res = vec![];
+    for i in input {
+        res.push(Device::try_new(i)?);
+    }
+    Ok(res)
+}
+
+// CDI names carry a kind (vendor/class) before '=', paths start with '/'.
+fn is_cdi_name(input: &str) -> bool {
+    match input.split_once('=') {
+        Some((kind, _)) => !input.starts_with('/') && kind.contains('/'),
+        None => false,
+    }
+}
+
+fn is_permissions(p: &str) -> bool {
+    !p.is_empty() && p.len() <= 3 && p.chars().all(|c| "rwm".contains(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_device(input: &str) -> SarusResult<Device> {
+        Device::try_new(input.to_string())
+    }
+
+    #[test]
+    fn device_kinds() {
+        let d = new_device("/dev/fuse").unwrap();
+        assert!(!d.is_cdi());
+        assert!(d.to_arg() == "--device=/dev/fuse");
+
+        let d = new_device("/dev/nvidia0:/dev/gpu0:rw").unwrap();
+        let p = PathDevice {
+            host_path: "/dev/nvidia0".to_string(),
+            container_path: Some("/dev/gpu0".to_string()),
+            permissions: Some("rw".to_string()),
+        };
+        assert!(d == Device::Path(p));
+        assert!(d.to_device_string() == "/dev/nvidia0:/dev/gpu0:rw");
+
+        let d = new_device("/dev/fuse:rwm").unwrap();
+        assert!(d.to_device_string() == "/dev/fuse:rwm");
+
+        let d = new_device("nvidia.com/gpu=all").unwrap();
+        let c = CdiDevice {
+            vendor: "nvidia.com".to_string(),
+            class: "gpu".to_string(),
+            name: "all".to_string(),
+        };
+        assert!(d == Device::Cdi(c));
+        assert!(d.to_arg() == "--device=nvidia.com/gpu=all");
+    }
+
+    #[test]
+    fn device_invalid() {
+        for i in [
+            "dev1",
+            "/dev/fuse:rwx",
+            "/dev/fuse:/a:/b",
+            "/dev/fuse:rw:/a",
+            "/a:/b:rw:m",
+            "nvidia/gpu=all",
+            "nvidia.com/gpu=",
+            "nvidia.com/=0",
+        ] {
+            let err = new_device(i).err().unwrap();
+            assert!(err.kind == SarusErrorKind::InvalidDevice { device: i.to_string() });
+        }
+    }
+}
@@ -0,0 +1,320 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::EDF;
+use crate::limits::Limits;
+use crate::error::SarusResult;
+use crate::secrets::{REDACTED, is_sensitive};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Change<T> {
+    pub from: T,
+    pub to: T,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MapDiff {
+    pub added: BTreeMap<String, String>,
+    pub removed: BTreeMap<String, String>,
+    pub changed: BTreeMap<String, Change<String>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ListDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+// Field by field difference between two rendered EDFs, going from the
+// first one to the second one. Fields left untouched are None or empty.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct EdfDiff {
+    pub image: Option<Change<String>>,
+    pub auth_file: Option<Change<Option<String>>>,
+    pub workdir: Option<Change<String>>,
+    pub engine: Option<Change<Option<String>>>,
+    pub entrypoint: Option<Change<bool>>,
+    pub gpu: Option<Change<bool>>,
+    pub mpi: Option<Change<Option<String>>>,
+    pub user: Option<Change<Option<String>>>,
+    pub group: Option<Change<Option<String>>>,
+    pub userns: Option<Change<Option<String>>>,
+    pub limits: Option<Change<Limits>>,
+    pub network: Option<Change<Option<String>>>,
+    pub pull_policy: Option<Change<Option<String>>>,
+    pub writable: Option<Change<bool>>,
+    pub writable_overlay_path: Option<Change<Option<String>>>,
+    pub writable_overlay_size: Option<Change<Option<String>>>,
+    pub env: MapDiff,
+    pub annotations: MapDiff,
+    pub labels: MapDiff,
+    pub mounts: ListDiff,
+    pub devices: ListDiff,
+    pub hooks: ListDiff,
+}
+
+impl MapDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    fn lines(&self, field: &str, lines: &mut Vec<String>) {
+        for (k, v) in self.removed.iter() {
+            lines.push(format!("- {field}.{k} = {v:?}"));
+        }
+        for (k, v) in self.added.iter() {
+            lines.push(format!("+ {field}.{k} = {v:?}"));
+        }
+        for (k, c) in self.changed.iter() {
+            lines.push(format!("~ {field}.{k}: {:?} -> {:?}", c.from, c.to));
+        }
+    }
+}
+
+impl ListDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+
+    fn lines(&self, field: &str, lines: &mut Vec<String>) {
+        for v in self.removed.iter() {
+            lines.push(format!("- {field}: {v}"));
+        }
+        for v in self.added.iter() {
+            lines.push(format!("+ {field}: {v}"));
+        }
+    }
+}
+
+impl EdfDiff {
+    pub fn is_empty(&self) -> bool {
+        self.image.is_none()
+            && self.auth_file.is_none()
+            && self.workdir.is_none()
+            && self.engine.is_none()
+            && self.entrypoint.is_none()
+            && self.gpu.is_none()
+            && self.mpi.is_none()
+            && self.user.is_none()
+            && self.group.is_none()
+            && self.userns.is_none()
+            && self.limits.is_none()
+            && self.network.is_none()
+            && self.pull_policy.is_none()
+            && self.writable.is_none()
+            && self.writable_overlay_path.is_none()
+            && self.writable_overlay_size.is_none()
+            && self.env.is_empty()
+            && self.annotations.is_empty()
+            && self.labels.is_empty()
+            && self.mounts.is_empty()
+            && self.devices.is_empty()
+            && self.hooks.is_empty()
+    }
+
+    // One line per difference, "-" removed, "+" added and "~" changed.
+    pub fn report(&self) -> String {
+        let mut lines = vec![];
+        if let Some(c) = &self.image {
+            lines.push(format!("~ image: {:?} -> {:?}", c.from, c.to));
+        }
+        if let Some(c) = &self.auth_file {
+            lines.push(format!("~ auth_file: {:?} -> {:?}", c.from, c.to));
+        }
+        if let Some(c) = &self.workdir {
+            lines.push(format!("~ workdir: {:?} -> {:?}", c.from, c.to));
+        }
+        if let Some(c) = &self.engine {
+            lines.push(format!("~ engine: {:?} -> {:?}", c.from, c.to));
+        }
+        if let Some(c) = &self.entrypoint {
+            lines.push(format!("~ entrypoint: {} -> {}", c.from, c.to));
+        }
+        if let Some(c) = &self.gpu {
+            lines.push(format!("~ gpu: {} -> {}", c.from, c.to));
+        }
+        if let Some(c) = &self.mpi {
+            lines.push(format!("~ mpi: {:?} -> {:?}", c.from, c.to));
+        }
+        if let Some(c) = &self.user {
+            lines.push(format!("~ user: {:?} -> {:?}", c.from, c.to));
+        }
+        if let Some(c) = &self.group {
+            lines.push(format!("~ group: {:?} -> {:?}", c.from, c.to));
+        }
+        if let Some(c) = &self.userns {
+            lines.push(format!("~ userns: {:?} -> {:?}", c.from, c.to));
+        }
+        if let Some(c) = &self.limits {
+            lines.push(format!("~ limits: {:?} -> {:?}", c.from, c.to));
+        }
+        if let Some(c) = &self.network {
+            lines.push(format!("~ network: {:?} -> {:?}", c.from, c.to));
+        }
+        if let Some(c) = &self.pull_policy {
+            lines.push(format!("~ pull_policy: {:?} -> {:?}", c.from, c.to));
+        }
+        if let Some(c) = &self.writable {
+            lines.push(format!("~ writable: {} -> {}", c.from, c.to));
+        }
+        if let Some(c) = &self.writable_overlay_path {
+            lines.push(format!("~ writable_overlay_path: {:?} -> {:?}", c.from, c.to));
+        }
+        if let Some(c) = &self.writable_overlay_size {
+            lines.push(format!("~ writable_overlay_size: {:?} -> {:?}", c.from, c.to));
+        }
+        self.env.lines("env", &mut lines);
+        self.annotations.lines("annotations", &mut lines);
+        self.labels.lines("labels", &mut lines);
+        self.mounts.lines("mounts", &mut lines);
+        self.devices.lines("devices", &mut lines);
+        self.hooks.lines("hooks", &mut lines);
+        lines.join("\n")
+    }
+
+    pub fn to_json(&self) -> SarusResult<String> {
+        crate::to_canonical_json(self)
+    }
+}
+
+impl std::fmt::Display for EdfDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.report())
+    }
+}
+
+fn change<T: PartialEq + Clone>(a: &T, b: &T) -> Option<Change<T>> {
+    match a == b {
+        true => None,
+        false => Some(Change {
+            from: a.clone(),
+            to: b.clone(),
+        }),
+    }
+}
+
+// Values of sensitive variables are compared, but never shown.
+fn map_diff(a: &BTreeMap<String, String>, b: &BTreeMap<String, String>, sensitive: &[String]) -> MapDiff {
+    let shown = |k: &str, v: &String| match is_sensitive(k, sensitive) {
+        true => String::from(REDACTED),
+        false => v.clone(),
+    };
+
+    let mut d = MapDiff::default();
+    for (k, v) in a.iter() {
+        match b.get(k) {
+            None => {
+                d.removed.insert(k.clone(), shown(k, v));
+            }
+            Some(w) if w != v => {
+                let c = Change {
+                    from: shown(k, v),
+                    to: shown(k, w),
+                };
+                d.changed.insert(k.clone(), c);
+            }
+            Some(_) => {}
+        }
+    }
+    for (k, w) in b.iter() {
+        if !a.contains_key(k) {
+            d.added.insert(k.clone(), shown(k, w));
+        }
+    }
+    d
+}
+
+fn list_diff(a: &[String], b: &[String]) -> ListDiff {
+    ListDiff {
+        added: b.iter().filter(|v| !a.contains(v)).cloned().collect(),
+        removed: a.iter().filter(|v| !b.contains(v)).cloned().collect(),
+    }
+}
+
+pub fn diff(a: &EDF, b: &EDF) -> EdfDiff {
+    let mut sensitive = a.sensitive_env.clone();
+    sensitive.extend(b.sensitive_env.iter().cloned());
+
+    let a_mounts: Vec<String> = a.mounts.iter().map(|m| m.to_volume_string()).collect();
+    let b_mounts: Vec<String> = b.mounts.iter().map(|m| m.to_volume_string()).collect();
+    let a_hooks: Vec<String> = a.hooks.iter().map(|h| h.name.clone()).collect();
+    let b_hooks: Vec<String> = b.hooks.iter().map(|h| h.name.clone()).collect();
+
+    EdfDiff {
+        image: change(&a.image, &b.image),
+        auth_file: change(&a.auth_file, &b.auth_file),
+        workdir: change(&a.workdir, &b.workdir),
+        engine: change(&a.engine, &b.engine),
+        entrypoint: change(&a.entrypoint, &b.entrypoint),
+        gpu: change(&a.gpu, &b.gpu),
+        mpi: change(&a.mpi, &b.mpi),
+        user: change(&a.user, &b.user),
+        group: change(&a.group, &b.group),
+        userns: change(&a.userns, &b.userns),
+        limits: change(&a.limits, &b.limits),
+        network: change(&a.network, &b.network),
+        pull_policy: change(&a.pull_policy, &b.pull_policy),
+        writable: change(&a.writable, &b.writable),
+        writable_overlay_path: change(&a.writable_overlay_path, &b.writable_overlay_path),
+        writable_overlay_size: change(&a.writable_overlay_size, &b.writable_overlay_size),
+        env: map_diff(&a.env, &b.env, &sensitive),
+        annotations: map_diff(&a.annotations, &b.annotations, &[]),
+        labels: map_diff(&a.labels, &b.labels, &[]),
+        mounts: list_diff(&a_mounts, &b_mounts),
+        devices: list_diff(&a.devices, &b.devices),
+        hooks: list_diff(&a_hooks, &b_hooks),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_edf_from_string;
+
+    #[test]
+    fn diff_edfs() {
+        let a = get_edf_from_string(String::from(
+            r#"
+            image = "ubuntu:24.04"
+            mounts = ["/capstor/scratch:/scratch", "/users:/users"]
+            [env]
+            A = "1"
+            B = "2"
+            API_TOKEN = "old"
+            "#,
+        ))
+        .unwrap();
+        let mut b = get_edf_from_string(String::from(
+            r#"
+            image = "ubuntu:24.04"
+            writable = false
+            mounts = ["/capstor/scratch:/scratch:ro", "/users:/users"]
+            devices = ["/dev/fuse"]
+            [env]
+            A = "1"
+            B = "3"
+            C = "4"
+            API_TOKEN = "new"
+            "#,
+        ))
+        .unwrap();
+        b.sensitive_env = vec![String::from("*_TOKEN")];
+
+        assert!(diff(&a, &a).is_empty());
+
+        let d = diff(&a, &b);
+        assert!(d.image.is_none());
+        assert!(d.writable == Some(Change { from: true, to: false }));
+        assert!(d.env.added.get("C").unwrap() == "4");
+        assert!(d.env.changed.get("B").unwrap().to == "3");
+        assert!(d.env.changed.get("API_TOKEN").unwrap().to == REDACTED);
+        assert!(d.mounts.removed == vec![String::from("/capstor/scratch:/scratch")]);
+        assert!(d.devices.added == vec![String::from("/dev/fuse")]);
+
+        let report = d.report();
+        assert!(report.contains("~ writable: true -> false"));
+        assert!(report.contains("+ mounts: /capstor/scratch:/scratch:ro"));
+        assert!(!report.contains("new"));
+        assert!(d.to_json().unwrap().contains("\"added\""));
+    }
+}
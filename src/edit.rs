@@ -0,0 +1,260 @@
+use toml_edit::{Array, DocumentMut, Item, Value, value};
+
+use crate::aliases::{EDF_KEY_ALIASES, resolve_aliases};
+use crate::common::ExpandOptions;
+use crate::conditions::resolve_conditions;
+use crate::error::{SarusError, SarusErrorKind, SarusResult};
+use crate::provenance::location_of;
+use crate::{RawEDF, document_parse, is_yaml_path, load, raw_from_document};
+
+// An EDF file opened for programmatic changes, e.g. adding a mount or
+// bumping the image tag, written back with its comments and formatting
+// untouched. Only TOML files can be edited.
+#[derive(Clone, Debug)]
+pub struct EdfDocument {
+    pub file_path: String,
+    pub(crate) doc: DocumentMut,
+}
+
+impl EdfDocument {
+    pub fn read(path: &str) -> SarusResult<EdfDocument> {
+        let content = match load(path) {
+            Ok(c) => c,
+            Err(e) => {
+                return Err(SarusError {
+                    kind: SarusErrorKind::FileRead,
+                    file_path: Some(String::from(path)),
+                    msg: format!("{}", e),
+                    location: None,
+                });
+            }
+        };
+        EdfDocument::parse(&content, path)
+    }
+
+    // Content received in memory, origin_hint being the path it's written
+    // back to.
+    pub fn parse(content: &str, origin_hint: &str) -> SarusResult<EdfDocument> {
+        if is_yaml_path(origin_hint) {
+            return Err(SarusError {
+                kind: SarusErrorKind::WrongExtension { expected: String::from("toml") },
+                file_path: Some(String::from(origin_hint)),
+                msg: format!("can't edit {origin_hint}, only TOML EDFs can be edited"),
+                location: None,
+            });
+        }
+        match content.parse::<DocumentMut>() {
+            Ok(doc) => Ok(EdfDocument {
+                file_path: String::from(origin_hint),
+                doc,
+            }),
+            Err(e) => Err(SarusError {
+                kind: SarusErrorKind::TomlParse,
+                file_path: Some(String::from(origin_hint)),
+                msg: e.message().to_string(),
+                location: e.span().map(|r| location_of(content, r.start)),
+            }),
+        }
+    }
+
+    // The EDF as currently edited, parsed like the files of a render.
+    pub fn raw(&self) -> SarusResult<RawEDF> {
+        let mut doc: serde_json::Value = document_parse(&self.to_string(), &self.file_path)?;
+        resolve_aliases(&mut doc, EDF_KEY_ALIASES);
+        resolve_conditions(&mut doc, &None, &ExpandOptions::default(), &self.file_path)?;
+        raw_from_document(doc, &self.file_path)
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.doc.get(key).and_then(|i| i.as_str())
+    }
+
+    // Set a top-level field, keeping the comments around it.
+    pub fn set_str(&mut self, key: &str, v: &str) {
+        self.set(key, Value::from(v));
+    }
+
+    pub fn set_bool(&mut self, key: &str, v: bool) {
+        self.set(key, Value::from(v));
+    }
+
+    fn set(&mut self, key: &str, v: Value) {
+        match self.doc.get_mut(key).and_then(|i| i.as_value_mut()) {
+            Some(old) => {
+                let decor = old.decor().clone();
+                *old = v;
+                *old.decor_mut() = decor;
+            }
+            None => self.doc[key] = Item::Value(v),
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) -> bool {
+        self.doc.remove(key).is_some()
+    }
+
+    // Replace the tag of the image, dropping its digest. False when the
+    // EDF has no image.
+    pub fn set_image_tag(&mut self, tag: &str) -> bool {
+        let image = match self.get_str("image") {
+            Some(i) => i,
+            None => return false,
+        };
+        let name = image.split('@').next().unwrap_or(image);
+        let repository = match name.rfind(':') {
+            Some(i) if !name[i..].contains('/') => &name[..i],
+            _ => name,
+        };
+        let image = format!("{repository}:{tag}");
+        self.set_str("image", &image);
+        true
+    }
+
+    // Append a mount, on its own line when the others are. False when the
+    // EDF already has it.
+    pub fn add_mount(&mut self, mount: &str) -> bool {
+        if self.doc.get("mounts").is_none() {
+            self.doc["mounts"] = value(Array::new());
+        }
+        let mounts = match self.doc["mounts"].as_array_mut() {
+            Some(m) => m,
+            None => return false,
+        };
+        if mounts.iter().any(|m| m.as_str() == Some(mount)) {
+            return false;
+        }
+        push_line(mounts, Value::from(mount));
+        true
+    }
+
+    pub fn remove_mount(&mut self, mount: &str) -> bool {
+        let mounts = match self.doc.get_mut("mounts").and_then(|i| i.as_array_mut()) {
+            Some(m) => m,
+            None => return false,
+        };
+        let before = mounts.len();
+        mounts.retain(|m| m.as_str() != Some(mount));
+        mounts.len() != before
+    }
+
+    pub fn set_env(&mut self, name: &str, v: &str) {
+        self.doc["env"][name] = value(v);
+    }
+
+    // Write the EDF back to its file.
+    pub fn write(&self) -> SarusResult<()> {
+        match std::fs::write(&self.file_path, self.to_string()) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(SarusError {
+                kind: SarusErrorKind::FileWrite,
+                file_path: Some(self.file_path.clone()),
+                msg: format!("cannot write {}: {e}", self.file_path),
+                location: None,
+            }),
+        }
+    }
+}
+
+fn decor_str(d: Option<&toml_edit::RawString>) -> String {
+    d.and_then(|r| r.as_str()).unwrap_or("").to_string()
+}
+
+// Push a value after the last one of an array, with its indentation when
+// the items are one per line. Comments closing the line of the last item
+// stay there.
+fn push_line(a: &mut Array, mut v: Value) {
+    let (prefix, suffix) = match a.iter().last() {
+        Some(l) => (decor_str(l.decor().prefix()), decor_str(l.decor().suffix())),
+        None => {
+            a.push_formatted(v);
+            return;
+        }
+    };
+    if !prefix.contains('\n') {
+        v.decor_mut().set_prefix(" ");
+        a.push_formatted(v);
+        return;
+    }
+    let indent = prefix.rsplit('\n').next().unwrap_or("");
+
+    // What follows the last item up to the closing bracket, after the
+    // trailing comma when there's one
+    let comma = a.trailing_comma();
+    let rest = match comma {
+        true => decor_str(Some(a.trailing())),
+        false => suffix,
+    };
+    let (line_end, before_bracket) = match rest.rfind('\n') {
+        Some(i) => (&rest[..=i], &rest[i + 1..]),
+        None => ("\n", rest.as_str()),
+    };
+    v.decor_mut().set_prefix(format!("{line_end}{indent}"));
+    if comma {
+        a.set_trailing(format!("\n{before_bracket}"));
+    } else {
+        v.decor_mut().set_suffix(format!("\n{before_bracket}"));
+        if let Some(l) = a.iter_mut().last() {
+            l.decor_mut().set_suffix("");
+        }
+    }
+    a.push_formatted(v);
+}
+
+impl std::fmt::Display for EdfDocument {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.doc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_preserves_comments() {
+        let content = "# PyTorch from NGC\n\
+                       image = \"nvcr.io/nvidia/pytorch:24.01-py3\" # bumped monthly\n\
+                       \n\
+                       mounts = [\n    \"/capstor/scratch:/scratch\", # scratch\n]\n\
+                       \n\
+                       [env]\n\
+                       # threads\n\
+                       OMP_NUM_THREADS = \"8\"\n";
+        let mut d = EdfDocument::parse(content, "pytorch.toml").unwrap();
+        assert!(d.to_string() == content);
+
+        assert!(d.set_image_tag("24.02-py3"));
+        assert!(d.add_mount("/iopsstor/data:/data"));
+        assert!(!d.add_mount("/iopsstor/data:/data"));
+        d.set_env("NCCL_DEBUG", "INFO");
+        d.set_bool("entrypoint", false);
+
+        let s = d.to_string();
+        assert!(s.starts_with("# PyTorch from NGC\nimage = \"nvcr.io/nvidia/pytorch:24.02-py3\" # bumped monthly\n"));
+        assert!(s.contains("\"/capstor/scratch:/scratch\", # scratch\n    \"/iopsstor/data:/data\",\n]\n"));
+        assert!(s.contains("# threads\nOMP_NUM_THREADS = \"8\"\n"));
+
+        let r = d.raw().unwrap();
+        assert!(r.image.as_deref() == Some("nvcr.io/nvidia/pytorch:24.02-py3"));
+        assert!(r.mounts.unwrap().len() == 2);
+        assert!(r.env.unwrap().get("NCCL_DEBUG").map(|v| v.as_str()) == Some("INFO"));
+        assert!(r.entrypoint == Some(false));
+
+        assert!(d.remove_mount("/iopsstor/data:/data"));
+        assert!(!d.remove_mount("/iopsstor/data:/data"));
+
+        let mut d = EdfDocument::parse("mounts = [\n  \"/a:/a\"\n]\n", "a.toml").unwrap();
+        d.add_mount("/b:/b");
+        assert!(d.to_string() == "mounts = [\n  \"/a:/a\",\n  \"/b:/b\"\n]\n");
+        let mut d = EdfDocument::parse("mounts = [\"/a:/a\"]\n", "a.toml").unwrap();
+        d.add_mount("/b:/b");
+        assert!(d.to_string() == "mounts = [\"/a:/a\", \"/b:/b\"]\n");
+
+        let mut d = EdfDocument::parse("image = \"localhost:5000/app@sha256:abc\"\n", "a.toml").unwrap();
+        assert!(d.set_image_tag("v2") && d.get_str("image") == Some("localhost:5000/app:v2"));
+
+        assert!(EdfDocument::parse("image: ubuntu\n", "a.yaml").is_err());
+        let err = EdfDocument::parse("image = \n", "a.toml").err().unwrap();
+        assert!(err.code() == 3 && err.location.unwrap().line == 1);
+    }
+}
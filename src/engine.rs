@@ -0,0 +1,127 @@
+// Translation of rendered EDFs into container engine invocations.
+pub mod enroot;
+pub mod podman;
+
+use crate::error::{SarusError, SarusErrorKind, SarusResult};
+use crate::mount::MountKind;
+use crate::{Config, EDF};
+
+pub use enroot::{Enroot, EnrootFstab};
+pub use podman::Podman;
+
+// Names accepted by the engine field of EDFs and default_engine.
+pub const ENGINES: [&str; 2] = ["podman", "enroot"];
+
+// EDF features an engine can carry out.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Capabilities {
+    pub annotations: bool,
+    pub archive_images: bool,
+    pub cdi_devices: bool,
+    pub labels: bool,
+    pub limits: bool,
+    pub named_volumes: bool,
+    pub networks: bool,
+    pub read_only_root: bool,
+    pub tmpfs_mounts: bool,
+    pub user_mapping: bool,
+    pub workdir: bool,
+}
+
+pub trait Engine {
+    fn name(&self) -> &'static str;
+
+    fn capabilities(&self) -> Capabilities;
+
+    // Program and arguments launching a container for the EDF.
+    fn command(&self, edf: &EDF, config: &Config) -> Vec<String>;
+
+    // Features used by the EDF which the engine can't carry out, and which
+    // command() leaves out.
+    fn unsupported(&self, edf: &EDF) -> Vec<&'static str> {
+        let c = self.capabilities();
+        let mut res = vec![];
+        if !c.annotations && !edf.annotations.is_empty() {
+            res.push("annotations");
+        }
+        if !c.archive_images && edf.image_source().is_archive() {
+            res.push("archive_images");
+        }
+        if !c.cdi_devices && edf.devices.iter().any(|d| d.contains('=')) {
+            res.push("cdi_devices");
+        }
+        if !c.labels && !edf.labels.is_empty() {
+            res.push("labels");
+        }
+        if !c.limits && !edf.limits.is_empty() {
+            res.push("limits");
+        }
+        if !c.named_volumes && edf.mounts.iter().any(|m| matches!(m.kind(), MountKind::Volume(_))) {
+            res.push("named_volumes");
+        }
+        // Containers share the network of the host without network support
+        if !c.networks && edf.network.as_deref().is_some_and(|n| n != "host") {
+            res.push("networks");
+        }
+        if !c.read_only_root && !edf.writable {
+            res.push("read_only_root");
+        }
+        if !c.tmpfs_mounts && edf.mounts.iter().any(|m| matches!(m.kind(), MountKind::Tmpfs(_))) {
+            res.push("tmpfs_mounts");
+        }
+        if !c.user_mapping && (edf.user.is_some() || edf.group.is_some() || edf.userns.is_some()) {
+            res.push("user_mapping");
+        }
+        if !c.workdir && !edf.workdir.is_empty() {
+            res.push("workdir");
+        }
+        res
+    }
+}
+
+pub fn engine_by_name(name: &str) -> SarusResult<Box<dyn Engine>> {
+    match name {
+        "podman" => Ok(Box::new(Podman)),
+        "enroot" => Ok(Box::new(Enroot)),
+        _ => Err(SarusError {
+            kind: SarusErrorKind::UnknownEngine { name: String::from(name) },
+            file_path: None,
+            msg: format!("unknown engine \"{name}\", expected one of {ENGINES:?}"),
+            location: None,
+        }),
+    }
+}
+
+// Engine set by the EDF, the configured default one otherwise.
+pub fn select_engine(edf: &EDF, config: &Config) -> SarusResult<Box<dyn Engine>> {
+    match &edf.engine {
+        Some(e) => engine_by_name(e),
+        None => engine_by_name(&config.default_engine),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_edf_from_string;
+
+    #[test]
+    fn engine_select() {
+        let config = Config {
+            default_engine: String::from("podman"),
+            ..Default::default()
+        };
+        let edf = get_edf_from_string(String::from("image = \"alpine\"\nworkdir = \"/work\"\n")).unwrap();
+        assert!(select_engine(&edf, &config).unwrap().name() == "podman");
+
+        let mut edf = edf;
+        edf.engine = Some(String::from("enroot"));
+        let e = select_engine(&edf, &config).unwrap();
+        assert!(e.name() == "enroot");
+        assert!(e.unsupported(&edf) == vec!["workdir"]);
+
+        edf.engine = Some(String::from("docker"));
+        let err = select_engine(&edf, &config).err().unwrap();
+        assert!(err.kind == SarusErrorKind::UnknownEngine { name: String::from("docker") });
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::BTreeMap;
+
+use crate::engine::{Capabilities, Engine};
+use crate::error::{SarusError, SarusErrorKind, SarusResult};
+use crate::mount::{MountKind, SarusMount, SarusMounts};
+use crate::{Config, EDF};
+
+// Bind mount flags passed on to enroot, which has no SELinux relabeling.
+const ENROOT_BIND_FLAGS: [&str; 14] = [
+    "ro", "rw", "private", "rprivate", "shared", "rshared", "slave", "rslave", "unbindable", "runbindable",
+    "nosuid", "nodev", "noexec", "suid",
+];
+
+pub struct Enroot;
+
+impl Engine for Enroot {
+    fn name(&self) -> &'static str {
+        "enroot"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            tmpfs_mounts: true,
+            read_only_root: true,
+            ..Default::default()
+        }
+    }
+
+    fn command(&self, edf: &EDF, config: &Config) -> Vec<String> {
+        let mut cmd = vec![config.enroot_path.clone()];
+        cmd.extend(edf.to_enroot_args());
+        cmd
+    }
+}
+
+impl EDF {
+    // Arguments following the enroot binary, starting the image. Mounts are
+    // given as fstab entries, their sources and targets already escaped.
+    pub fn to_enroot_args(&self) -> Vec<String> {
+        let mut args = vec![String::from("start")];
+
+        if self.writable {
+            args.push(String::from("--rw"));
+        }
+
+        let env: BTreeMap<_, _> = self.env.iter().collect();
+        for (k, v) in env {
+            args.push(format!("--env={k}={v}"));
+        }
+
+        for entry in self.mounts.iter().filter_map(enroot_fstab_entry) {
+            args.push(format!("--mount={entry}"));
+        }
+
+        args.push(self.image.clone());
+        args
+    }
+}
+
+// Mounts in the fstab format read by enroot from the mounts of its config,
+// one entry per line. Sources and targets are escaped when rendering.
+pub trait EnrootFstab {
+    fn to_enroot_fstab(&self) -> String;
+
+    fn write_enroot_fstab(&self, path: &str) -> SarusResult<()> {
+        match std::fs::write(path, self.to_enroot_fstab()) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(SarusError {
+                kind: SarusErrorKind::FileWrite,
+                file_path: Some(String::from(path)),
+                msg: format!("cannot write enroot fstab: {e}"),
+                location: None,
+            }),
+        }
+    }
+}
+
+impl EnrootFstab for SarusMounts {
+    fn to_enroot_fstab(&self) -> String {
+        let mut out = String::new();
+        for entry in self.iter().filter_map(enroot_fstab_entry) {
+            out.push_str(&entry);
+            out.push('\n');
+        }
+        out
+    }
+}
+
+// Squashfs, detach and volume mounts are carried out by the runtime hooks
+// or not supported by enroot, they have no entry.
+fn enroot_fstab_entry(m: &SarusMount) -> Option<String> {
+    match m.kind() {
+        MountKind::Bind(o) => {
+            let mut opts = vec![String::from("x-create=auto"), String::from("rbind")];
+            opts.extend(o.flags.iter().filter(|f| ENROOT_BIND_FLAGS.contains(&f.as_str())).cloned());
+            Some(format!("{} {} none {}", m.source(), m.target(), opts.join(",")))
+        }
+        MountKind::Tmpfs(_) => match m.flags().is_empty() {
+            true => Some(format!("tmpfs {} tmpfs x-create=dir", m.target())),
+            false => Some(format!("tmpfs {} tmpfs x-create=dir,{}", m.target(), m.flags())),
+        },
+        MountKind::Squashfs(_) | MountKind::Detach | MountKind::Volume(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_edf_from_string;
+
+    #[test]
+    fn enroot_args() {
+        let edf = get_edf_from_string(String::from(
+            r#"
+            image = "/scratch/ubuntu.sqsh"
+            mounts = ["/scratch:/scratch:ro", "tmpfs:/tmp:size=1g", "vol-data:/data"]
+            [env]
+            B = "2"
+            A = "1"
+            "#,
+        ))
+        .unwrap();
+
+        let expected = vec![
+            "start",
+            "--rw",
+            "--env=A=1",
+            "--env=B=2",
+            "--mount=/scratch /scratch none x-create=auto,rbind,ro",
+            "--mount=tmpfs /tmp tmpfs x-create=dir,size=1g",
+            "/scratch/ubuntu.sqsh",
+        ];
+        assert!(edf.to_enroot_args() == expected);
+    }
+
+    #[test]
+    fn enroot_fstab() {
+        let edf = get_edf_from_string(String::from(
+            r#"
+            image = "ubuntu"
+            mounts = ["/my data:/data:ro,Z,nosuid", "tmpfs:/tmp", "umount:/opt"]
+            "#,
+        ))
+        .unwrap();
+
+        let fstab = edf.mounts.to_enroot_fstab();
+        assert!(fstab == "/my\\040data /data none x-create=auto,rbind,ro,nosuid\ntmpfs /tmp tmpfs x-create=dir\n");
+
+        let path = std::env::temp_dir().join(format!("raster-fstab-{}", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        edf.mounts.write_enroot_fstab(&path).unwrap();
+        assert!(std::fs::read_to_string(&path).unwrap() == fstab);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::BTreeMap;
+
+use crate::device::Device;
+use crate::engine::{Capabilities, Engine};
+use crate::mount::{MountKind, unescape_mount};
+use crate::{Config, EDF};
+
+pub struct Podman;
+
+impl Engine for Podman {
+    fn name(&self) -> &'static str {
+        "podman"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            annotations: true,
+            archive_images: true,
+            cdi_devices: true,
+            labels: true,
+            limits: true,
+            named_volumes: true,
+            networks: true,
+            read_only_root: true,
+            tmpfs_mounts: true,
+            user_mapping: true,
+            workdir: true,
+        }
+    }
+
+    fn command(&self, edf: &EDF, config: &Config) -> Vec<String> {
+        let mut cmd = vec![config.podman_path.clone()];
+        cmd.extend(edf.to_podman_args(config));
+        cmd
+    }
+}
+
+impl EDF {
+    // Arguments following the podman binary, up to and including the image.
+    // Options are emitted in a fixed order, maps sorted by key, so that the
+    // same EDF always yields the same command line.
+    pub fn to_podman_args(&self, config: &Config) -> Vec<String> {
+        let mut args = vec![];
+
+        if !config.podman_module.is_empty() {
+            args.push(format!("--module={}", config.podman_module));
+        }
+        args.push(String::from("run"));
+
+        if let Some(p) = &self.pull_policy {
+            args.push(format!("--pull={p}"));
+        }
+        match &self.auth_file {
+            Some(a) => args.push(format!("--authfile={a}")),
+            None if !config.registry_auth_file.is_empty() => {
+                args.push(format!("--authfile={}", config.registry_auth_file))
+            }
+            None => {}
+        }
+        if !self.entrypoint {
+            args.push(String::from("--entrypoint="));
+        }
+        if !self.writable {
+            args.push(String::from("--read-only"));
+        }
+        if !self.workdir.is_empty() {
+            args.push(format!("--workdir={}", self.workdir));
+        }
+        if let Some(u) = &self.user {
+            match &self.group {
+                Some(g) => args.push(format!("--user={u}:{g}")),
+                None => args.push(format!("--user={u}")),
+            }
+        }
+        if let Some(n) = &self.userns {
+            args.push(format!("--userns={n}"));
+        }
+        if let Some(n) = &self.network {
+            args.push(format!("--network={n}"));
+        }
+        if let Some(s) = &self.limits.shm_size {
+            args.push(format!("--shm-size={s}"));
+        }
+        if let Some(p) = self.limits.pids_limit {
+            args.push(format!("--pids-limit={p}"));
+        }
+        for (name, u) in self.limits.ulimits() {
+            args.push(format!("--ulimit={name}={u}:{u}"));
+        }
+
+        let env: BTreeMap<_, _> = self.env.iter().collect();
+        for (k, v) in env {
+            args.push(format!("--env={k}={v}"));
+        }
+
+        let annotations: BTreeMap<_, _> = self.annotations.iter().collect();
+        for (k, v) in annotations {
+            args.push(format!("--annotation={k}={v}"));
+        }
+        for (k, v) in self.labels.iter() {
+            args.push(format!("--label={k}={v}"));
+        }
+
+        for m in self.mounts.iter() {
+            match m.kind() {
+                MountKind::Bind(_) | MountKind::Volume(_) => args.push(m.to_podman_mount_arg()),
+                MountKind::Tmpfs(_) => {
+                    let target = unescape_mount(m.target());
+                    if m.flags().is_empty() {
+                        args.push(format!("--tmpfs={target}"));
+                    } else {
+                        args.push(format!("--tmpfs={target}:{}", m.flags()));
+                    }
+                }
+                // Squashfs and detach mounts are carried out by the runtime
+                // hooks, podman has no option for them.
+                MountKind::Squashfs(_) | MountKind::Detach => {}
+            }
+        }
+
+        // podman takes both device paths and CDI names through --device,
+        // devices of rendered EDFs are valid already
+        for d in self.devices.iter() {
+            match Device::try_new(d.clone()) {
+                Ok(d) => args.push(d.to_arg()),
+                Err(_) => args.push(format!("--device={d}")),
+            }
+        }
+
+        args.push(self.image.clone());
+        args
+    }
+
+    // Arguments creating the named volumes mounted by the EDF with the
+    // configured driver, one command per volume. Existing volumes are kept.
+    pub fn to_podman_volume_args(&self, config: &Config) -> Vec<Vec<String>> {
+        let mut res = vec![];
+        for m in self.mounts.iter() {
+            let name = match m.kind() {
+                MountKind::Volume(o) => &o.name,
+                _ => continue,
+            };
+
+            let mut args = vec![];
+            if !config.podman_module.is_empty() {
+                args.push(format!("--module={}", config.podman_module));
+            }
+            args.push(String::from("volume"));
+            args.push(String::from("create"));
+            args.push(String::from("--ignore"));
+            if !config.default_volume_driver.is_empty() {
+                args.push(format!("--driver={}", config.default_volume_driver));
+            }
+            args.push(name.clone());
+            res.push(args);
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::get_edf_from_string;
+    use crate::validate_str_with_report;
+    use crate::Config;
+
+    #[test]
+    fn podman_args() {
+        let edf = get_edf_from_string(String::from(
+            r#"
+            image = "ubuntu:24.04"
+            entrypoint = false
+            writable = false
+            workdir = "/work"
+            devices = ["/dev/fuse", "nvidia.com/gpu=all"]
+            mounts = ["/scratch:/scratch:ro", "tmpfs:/tmp:size=1g", "umount:/opt"]
+            [env]
+            B = "2"
+            A = "1"
+            [annotations]
+            com.hooks.ssh.enabled = "true"
+            "#,
+        ))
+        .unwrap();
+
+        let config = Config {
+            podman_module: String::from("hpc"),
+            ..Default::default()
+        };
+
+        let args = edf.to_podman_args(&config);
+        let expected = vec![
+            "--module=hpc",
+            "run",
+            "--entrypoint=",
+            "--read-only",
+            "--workdir=/work",
+            "--env=A=1",
+            "--env=B=2",
+            "--annotation=com.hooks.ssh.enabled=true",
+            "--volume=/scratch:/scratch:ro",
+            "--tmpfs=/tmp:size=1g",
+            "--device=/dev/fuse",
+            "--device=nvidia.com/gpu=all",
+            "ubuntu:24.04",
+        ];
+        assert!(args == expected);
+
+        let edf = get_edf_from_string(String::from(
+            "image = \"alpine\"\nmounts = [\"vol-mydata:/data:ro\", \"/scratch:/scratch\"]\n",
+        ))
+        .unwrap();
+        let config = Config {
+            default_volume_driver: String::from("local"),
+            ..Default::default()
+        };
+        let args = edf.to_podman_args(&config);
+        assert!(args[1] == "--volume=vol-mydata:/data:ro");
+        let create = edf.to_podman_volume_args(&config);
+        assert!(create == vec![vec!["volume", "create", "--ignore", "--driver=local", "vol-mydata"]]);
+
+        let edf = get_edf_from_string(String::from(
+            "image = \"alpine\"\nmounts = [\"/scratch/my data:/data/my data:ro\", \"tmpfs:/my tmp\"]\n",
+        ))
+        .unwrap();
+        let args = edf.to_podman_args(&Config::default());
+        assert!(args[1] == "--volume=/scratch/my data:/data/my data:ro" && args[2] == "--tmpfs=/my tmp");
+
+        let edf = get_edf_from_string(String::from("image = \"alpine\"\nentrypoint = true\n")).unwrap();
+        let args = edf.to_podman_args(&Config::default());
+        assert!(args == vec!["run", "alpine"]);
+
+        let edf = get_edf_from_string(String::from("image = \"alpine\"\npull_policy = \"never\"\n")).unwrap();
+        let args = edf.to_podman_args(&Config::default());
+        assert!(args == vec!["run", "--pull=never", "alpine"]);
+
+        let edf = get_edf_from_string(String::from(
+            "image = \"alpine\"\nuser = \"1000\"\ngroup = \"100\"\nuserns = \"keep-id:uid=1000,gid=100\"\n",
+        ))
+        .unwrap();
+        let args = edf.to_podman_args(&Config::default());
+        assert!(args == vec!["run", "--user=1000:100", "--userns=keep-id:uid=1000,gid=100", "alpine"]);
+
+        let edf = get_edf_from_string(String::from(
+            "image = \"alpine\"\n[limits]\nshm_size = \"8G\"\npids_limit = 4096\nmemlock = \"unlimited\"\nstack = 8388608\n",
+        ))
+        .unwrap();
+        let args = edf.to_podman_args(&Config::default());
+        let expected = vec![
+            "run",
+            "--shm-size=8G",
+            "--pids-limit=4096",
+            "--ulimit=memlock=-1:-1",
+            "--ulimit=stack=8388608:8388608",
+            "alpine",
+        ];
+        assert!(args == expected);
+        let report = validate_str_with_report("image = \"alpine\"\npull_policy = \"newer\"\n", "a.toml").unwrap();
+        assert!(!report.is_valid());
+    }
+}
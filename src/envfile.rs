@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::common::{is_var_name, unquote};
+use crate::error::{SarusError, SarusErrorKind, SarusResult};
+use crate::join_search_path;
+use crate::load::{LoadLimits, load_edf, search_root};
+
+fn invalid_env_file(name: &str, edf_path: &str, msg: String) -> SarusError {
+    SarusError {
+        kind: SarusErrorKind::InvalidEnvFile { path: String::from(name) },
+        file_path: Some(String::from(edf_path)),
+        msg,
+        location: None,
+    }
+}
+
+// Path of an env file named by the EDF at edf_path, relative to the
+// directory of the EDF, and the directory it must stay in: the search path
+// the EDF was found in, or the directory of the EDF. EDFs which aren't
+// local files, e.g. fetched or rendered from a string, can't name any.
+pub(crate) fn env_file_path(name: &str, edf_path: &str, sp: &[String]) -> SarusResult<(String, String)> {
+    if !Path::new(edf_path).is_file() {
+        return Err(invalid_env_file(
+            name,
+            edf_path,
+            format!("env_file \"{name}\" is only allowed in EDF files of the filesystem"),
+        ));
+    }
+    if Path::new(name).is_absolute() {
+        return Err(invalid_env_file(
+            name,
+            edf_path,
+            format!("env_file \"{name}\" must be relative to the directory of the EDF"),
+        ));
+    }
+
+    let dir = match Path::new(edf_path).parent() {
+        Some(d) if !d.as_os_str().is_empty() => d,
+        _ => Path::new("."),
+    };
+    let (root, rel) = match search_root(edf_path, sp) {
+        Some(r) => (r.clone(), dir.strip_prefix(r).unwrap_or(Path::new("")).join(name)),
+        None => (dir.display().to_string(), Path::new(name).to_path_buf()),
+    };
+    match join_search_path(&root, &rel.to_string_lossy()) {
+        Some(p) => Ok((p, root)),
+        None => Err(invalid_env_file(name, edf_path, format!("env_file \"{name}\" leaves {root}"))),
+    }
+}
+
+// Read a file of KEY=VALUE lines, within the limits of EDF files and root
+// being the directory holding it. Empty lines and lines starting with '#'
+// are skipped, an "export " prefix is allowed and values can be quoted.
+pub(crate) fn env_file_read(file_path: &str, root: &str, limits: &LoadLimits) -> SarusResult<HashMap<String, String>> {
+    let content = load_edf(file_path, std::slice::from_ref(&String::from(root)), limits)?;
+
+    let mut env = HashMap::new();
+    // Lines aren't quoted in errors, they could be of any file
+    for (i, l) in content.lines().enumerate() {
+        let line = l.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+
+        let (k, v) = match line.split_once('=') {
+            Some((k, v)) if is_var_name(k.trim()) => (k.trim(), v.trim()),
+            _ => {
+                return Err(SarusError {
+                    kind: SarusErrorKind::EnvFileParse { line: i + 1 },
+                    file_path: Some(String::from(file_path)),
+                    msg: format!("line {}: expected KEY=VALUE", i + 1),
+                    location: None,
+                });
+            }
+        };
+        env.insert(k.to_string(), unquote(v).to_string());
+    }
+    Ok(env)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn env_file() {
+        let limits = LoadLimits::default();
+        let env = env_file_read("test/toml/job.env", "test/toml", &limits).unwrap();
+        assert!(env.get("OMP_NUM_THREADS").unwrap() == "8");
+        assert!(env.get("GREETING").unwrap() == "hello world");
+        assert!(env.get("EXPORTED").unwrap() == "yes");
+        assert!(env.get("EMPTY").unwrap() == "");
+        assert!(env.len() == 5);
+
+        let err = env_file_read("test/toml/invalid.env", "test/toml", &limits).err().unwrap();
+        assert!(err.kind == SarusErrorKind::EnvFileParse { line: 2 });
+        assert!(!err.msg.contains("not a variable"));
+    }
+
+    #[test]
+    fn env_file_paths() {
+        let sp = vec![String::from("test")];
+        let edf = "test/toml/env-file.toml";
+        assert!(env_file_path("./job.env", edf, &sp).unwrap() == (String::from("test/toml/job.env"), String::from("test")));
+        assert!(env_file_path("../toml/job.env", edf, &sp).unwrap().0 == "test/toml/job.env");
+        assert!(env_file_path("job.env", edf, &[]).unwrap() == (String::from("test/toml/job.env"), String::from("test/toml")));
+
+        for (name, edf, sp) in [
+            ("/etc/shadow", edf, &sp),
+            ("../../etc/shadow", edf, &sp),
+            ("../job.env", edf, &vec![]),
+            ("job.env", "socket", &sp),
+            ("job.env", "https://example.com/edf/app.toml", &sp),
+        ] {
+            let err = env_file_path(name, edf, sp).err().unwrap();
+            assert!(err.kind == SarusErrorKind::InvalidEnvFile { path: String::from(name) }, "{name}");
+        }
+    }
+}
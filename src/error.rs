@@ -1,23 +1,263 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::error::Error;
 
 pub type SarusResult<T> = std::result::Result<T, SarusError>;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct SarusError {
-    pub code: u64,
+    pub kind: SarusErrorKind,
     pub file_path: Option<String>,
     pub msg: String,
+    // Where in the file the error is, when known.
+    #[serde(default)]
+    pub location: Option<Location>,
+}
+
+// Line and column of a position in a file, both starting at 1.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Location {
+    pub line: u32,
+    pub column: u32,
+}
+
+// Every kind maps to the numeric code historically carried by SarusError,
+// see SarusErrorKind::code().
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum SarusErrorKind {
+    SchemaParse,
+    SchemaInvalid,
+    FileRead,
+    TomlParse,
+    SchemaViolation { errors: Vec<String> },
+    MaxDepthExceeded { max: u64 },
+    EnvironmentNotFound { name: String, search_paths: Vec<String> },
+    MissingImage,
+    InvalidMount { mount: String },
+    MountPathTranslation { path: String },
+    MountPathEncoding { path: String },
+    InvalidMountSource { source: String },
+    InvalidMountTarget { target: String },
+    SquashfsStat { source: String },
+    SquashfsNotFile { source: String },
+    UndefinedVariable { var: String },
+    ExpansionFailed { input: String },
+    ExpansionOutput { input: String },
+    FileName,
+    FileExtension,
+    WrongExtension { expected: String },
+    ConfigDirRead,
+    Serialization,
+    StringParse,
+    UnknownHook { name: String },
+    HookNotFound { name: String, path: String },
+    HookNotExecutable { name: String, path: String },
+    HookFailed { command: String },
+    YamlParse,
+    InvalidMountOption { mount: String },
+    InvalidConfigOverride { var: String },
+    UnknownKey { key: String },
+    BaseEnvironmentCycle { chain: Vec<String> },
+    InvalidDevice { device: String },
+    UnsupportedEdfVersion { version: String },
+    InvalidCondition { condition: String },
+    PolicyViolation { violations: Vec<String> },
+    MissingImageDigest { image: String },
+    EnvFileParse { line: usize },
+    InvalidWritableOverlay { field: String },
+    SquashfsMagic { source: String },
+    GpuNotFound,
+    UnknownMpi { name: String },
+    UndefinedTemplateVariable { var: String },
+    UnknownEngine { name: String },
+    FileWrite,
+    RemoteFetch { url: String },
+    ChecksumMismatch { url: String },
+    GitFetch { url: String },
+    AsyncTask,
+    ExpansionDenied { var: String },
+    UnknownUser { name: String },
+    MountTargetCollision { target: String },
+    MountSourcesMissing { paths: Vec<String> },
+    InvalidUserMapping { field: String },
+    InvalidLimit { field: String },
+    InvalidNetwork { network: String },
+    InvalidAnnotation { key: String },
+    BinariesNotFound { names: Vec<String> },
+    ImagestoreUnusable { path: String },
+    InvalidImage { image: String },
+    InvalidAuthFile { path: String },
+    FileTooLarge { path: String, max_size: u64 },
+    FileWorldWritable { path: String },
+    FileOutOfSearchPath { path: String, search_path: String },
+    UntrustedSystemFile { path: String },
+    InvalidEnvironmentName { name: String },
+    InvalidEnvFile { path: String },
+    RenderPanicked { path: String },
+}
+
+impl SarusErrorKind {
+    pub fn code(&self) -> u64 {
+        match self {
+            SarusErrorKind::SchemaParse => 0,
+            SarusErrorKind::SchemaInvalid => 1,
+            SarusErrorKind::FileRead => 2,
+            SarusErrorKind::TomlParse => 3,
+            SarusErrorKind::SchemaViolation { .. } => 4,
+            SarusErrorKind::MaxDepthExceeded { .. } => 5,
+            SarusErrorKind::EnvironmentNotFound { .. } => 6,
+            SarusErrorKind::MissingImage => 7,
+            SarusErrorKind::InvalidMount { .. } => 8,
+            SarusErrorKind::MountPathTranslation { .. } => 9,
+            SarusErrorKind::MountPathEncoding { .. } => 11,
+            SarusErrorKind::InvalidMountSource { .. } => 12,
+            SarusErrorKind::InvalidMountTarget { .. } => 13,
+            SarusErrorKind::SquashfsStat { .. } => 14,
+            SarusErrorKind::SquashfsNotFile { .. } => 16,
+            SarusErrorKind::UndefinedVariable { .. } => 17,
+            SarusErrorKind::ExpansionFailed { .. } => 18,
+            SarusErrorKind::ExpansionOutput { .. } => 19,
+            SarusErrorKind::FileName => 20,
+            SarusErrorKind::FileExtension => 21,
+            SarusErrorKind::WrongExtension { .. } => 22,
+            SarusErrorKind::ConfigDirRead => 23,
+            SarusErrorKind::Serialization => 24,
+            SarusErrorKind::StringParse => 25,
+            SarusErrorKind::UnknownHook { .. } => 26,
+            SarusErrorKind::HookNotFound { .. } => 27,
+            SarusErrorKind::HookNotExecutable { .. } => 28,
+            SarusErrorKind::HookFailed { .. } => 29,
+            SarusErrorKind::YamlParse => 30,
+            SarusErrorKind::InvalidMountOption { .. } => 31,
+            SarusErrorKind::InvalidConfigOverride { .. } => 32,
+            SarusErrorKind::UnknownKey { .. } => 33,
+            SarusErrorKind::BaseEnvironmentCycle { .. } => 34,
+            SarusErrorKind::InvalidDevice { .. } => 35,
+            SarusErrorKind::UnsupportedEdfVersion { .. } => 36,
+            SarusErrorKind::InvalidCondition { .. } => 37,
+            SarusErrorKind::PolicyViolation { .. } => 38,
+            SarusErrorKind::MissingImageDigest { .. } => 39,
+            SarusErrorKind::EnvFileParse { .. } => 40,
+            SarusErrorKind::InvalidWritableOverlay { .. } => 41,
+            SarusErrorKind::SquashfsMagic { .. } => 42,
+            SarusErrorKind::GpuNotFound => 43,
+            SarusErrorKind::UnknownMpi { .. } => 44,
+            SarusErrorKind::UndefinedTemplateVariable { .. } => 45,
+            SarusErrorKind::UnknownEngine { .. } => 46,
+            SarusErrorKind::FileWrite => 47,
+            SarusErrorKind::RemoteFetch { .. } => 48,
+            SarusErrorKind::ChecksumMismatch { .. } => 49,
+            SarusErrorKind::GitFetch { .. } => 50,
+            SarusErrorKind::AsyncTask => 51,
+            SarusErrorKind::ExpansionDenied { .. } => 52,
+            SarusErrorKind::UnknownUser { .. } => 53,
+            SarusErrorKind::MountTargetCollision { .. } => 54,
+            SarusErrorKind::MountSourcesMissing { .. } => 55,
+            SarusErrorKind::InvalidUserMapping { .. } => 56,
+            SarusErrorKind::InvalidLimit { .. } => 57,
+            SarusErrorKind::InvalidNetwork { .. } => 58,
+            SarusErrorKind::InvalidAnnotation { .. } => 59,
+            SarusErrorKind::BinariesNotFound { .. } => 60,
+            SarusErrorKind::ImagestoreUnusable { .. } => 61,
+            SarusErrorKind::InvalidImage { .. } => 62,
+            SarusErrorKind::InvalidAuthFile { .. } => 63,
+            SarusErrorKind::FileTooLarge { .. } => 64,
+            SarusErrorKind::FileWorldWritable { .. } => 65,
+            SarusErrorKind::FileOutOfSearchPath { .. } => 66,
+            SarusErrorKind::UntrustedSystemFile { .. } => 67,
+            SarusErrorKind::InvalidEnvironmentName { .. } => 68,
+            SarusErrorKind::InvalidEnvFile { .. } => 69,
+            SarusErrorKind::RenderPanicked { .. } => 70,
+        }
+    }
+}
+
+impl SarusError {
+    pub fn code(&self) -> u64 {
+        self.kind.code()
+    }
+
+    // The error at a position of its file, when known.
+    pub(crate) fn at(mut self, location: Option<Location>) -> Self {
+        if location.is_some() {
+            self.location = location;
+        }
+        self
+    }
+
+    // Machine-readable form, e.g.
+    // {"code": 33, "file": "a.toml", "key": "image2", "kind": "UnknownKey", "message": "..."}
+    pub fn to_json(&self) -> SarusResult<String> {
+        let j = SarusErrorJson {
+            code: self.code(),
+            kind: &self.kind,
+            file: &self.file_path,
+            location: &self.location,
+            message: &self.msg,
+        };
+        crate::to_canonical_json(&j)
+    }
+}
+
+#[derive(Serialize)]
+struct SarusErrorJson<'a> {
+    code: u64,
+    #[serde(flatten)]
+    kind: &'a SarusErrorKind,
+    file: &'a Option<String>,
+    #[serde(flatten)]
+    location: &'a Option<Location>,
+    message: &'a str,
 }
 
 impl std::fmt::Display for SarusError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let fp = match &self.file_path {
-            Some(p) => format!(" on {p}"),
-            None => String::from(""),
+        let fp = match (&self.file_path, &self.location) {
+            (Some(p), Some(l)) => format!(" on {p}:{}:{}", l.line, l.column),
+            (Some(p), None) => format!(" on {p}"),
+            (None, _) => String::from(""),
         };
-        write!(f, "Error {:03}{}: {}", self.code, fp, self.msg)
+        write!(f, "Error {:03}{}: {}", self.code(), fp, self.msg)
     }
 }
 
 impl Error for SarusError {}
+
+// Every independent failure of a render, see render_with_options_errors().
+#[derive(Debug, Clone, Default)]
+pub struct SarusErrorList {
+    pub errors: Vec<SarusError>,
+}
+
+impl SarusErrorList {
+    // Machine-readable form, a JSON array of the errors.
+    pub fn to_json(&self) -> SarusResult<String> {
+        let errors: Vec<SarusErrorJson> = self
+            .errors
+            .iter()
+            .map(|e| SarusErrorJson {
+                code: e.code(),
+                kind: &e.kind,
+                file: &e.file_path,
+                location: &e.location,
+                message: &e.msg,
+            })
+            .collect();
+        crate::to_canonical_json(&errors)
+    }
+}
+
+impl From<SarusError> for SarusErrorList {
+    fn from(e: SarusError) -> Self {
+        SarusErrorList { errors: vec![e] }
+    }
+}
+
+impl std::fmt::Display for SarusErrorList {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let lines: Vec<String> = self.errors.iter().map(|e| e.to_string()).collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+impl Error for SarusErrorList {}
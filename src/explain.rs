@@ -0,0 +1,303 @@
+use regex::Regex;
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+use crate::aliases::{EDF_KEY_ALIASES, resolve_aliases};
+use crate::conditions::resolve_conditions;
+use crate::error::SarusResult;
+use crate::options::RenderOptions;
+use crate::provenance::Provenance;
+use crate::secrets::{REDACTED, is_sensitive};
+use crate::{
+    BaseEnvironment, canonical_path, document_parse, get_search_paths, load, raw_from_document,
+    resolve_env_path,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "step", rename_all = "snake_case")]
+pub enum TraceStep {
+    // An EDF name was resolved to a file, search_path is None for paths.
+    Resolved {
+        name: String,
+        file_path: String,
+        search_path: Option<String>,
+    },
+    // A file was loaded, at the given base_environment depth.
+    Loaded { file_path: String, depth: usize },
+    // A field set by a previous layer was set again.
+    Overridden {
+        field: String,
+        file_path: String,
+        previous: String,
+    },
+    // A variable referenced by a file, None when it's undefined.
+    Expanded {
+        variable: String,
+        value: Option<String>,
+        file_path: String,
+    },
+    // Resolution couldn't go further down this path.
+    Failed { file_path: Option<String>, msg: String },
+}
+
+// Steps taken to render an EDF, in order. Unlike rendering, failures are
+// recorded and the remaining files are still explained.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RenderTrace {
+    pub steps: Vec<TraceStep>,
+}
+
+impl RenderTrace {
+    pub fn has_failures(&self) -> bool {
+        self.steps.iter().any(|s| matches!(s, TraceStep::Failed { .. }))
+    }
+
+    pub fn report(&self) -> String {
+        let mut lines = vec![];
+        for s in self.steps.iter() {
+            let l = match s {
+                TraceStep::Resolved {
+                    name,
+                    file_path,
+                    search_path,
+                } => match search_path {
+                    Some(sp) => format!("resolved {name} to {file_path} in search path {sp}"),
+                    None => format!("resolved {name} to {file_path}"),
+                },
+                TraceStep::Loaded { file_path, depth } => {
+                    format!("{}loaded {file_path}", "  ".repeat(*depth))
+                }
+                TraceStep::Overridden {
+                    field,
+                    file_path,
+                    previous,
+                } => format!("{field} set by {previous} is overridden by {file_path}"),
+                TraceStep::Expanded {
+                    variable,
+                    value,
+                    file_path,
+                } => match value {
+                    Some(v) => format!("${variable} expands to {v:?} in {file_path}"),
+                    None => format!("${variable} is undefined in {file_path}"),
+                },
+                TraceStep::Failed { file_path, msg } => match file_path {
+                    Some(p) => format!("failed on {p}: {msg}"),
+                    None => format!("failed: {msg}"),
+                },
+            };
+            lines.push(l);
+        }
+        lines.join("\n")
+    }
+
+    pub fn to_json(&self) -> SarusResult<String> {
+        crate::to_canonical_json(self)
+    }
+}
+
+impl std::fmt::Display for RenderTrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.report())
+    }
+}
+
+struct Explainer<'a> {
+    opts: &'a RenderOptions,
+    sp: Vec<String>,
+    chain: Vec<String>,
+    setters: Provenance,
+    trace: RenderTrace,
+}
+
+pub fn explain(path: String, options: &RenderOptions) -> RenderTrace {
+    let sp = match &options.search_paths {
+        Some(s) => s.clone(),
+        None => get_search_paths(),
+    };
+    let mut e = Explainer {
+        opts: options,
+        sp,
+        chain: vec![],
+        setters: Provenance::default(),
+        trace: RenderTrace::default(),
+    };
+    e.visit(path);
+    e.trace
+}
+
+impl Explainer<'_> {
+    fn fail(&mut self, file_path: Option<&str>, msg: String) {
+        self.trace.steps.push(TraceStep::Failed {
+            file_path: file_path.map(String::from),
+            msg,
+        });
+    }
+
+    fn visit(&mut self, name: String) {
+        let env = &self.opts.env;
+        let xopts = self.opts.expand_options();
+
+        if self.chain.len() as u64 + 1 > self.opts.max_levels {
+            let max = self.opts.max_levels;
+            self.fail(None, format!("base_environment rendering has more than {max} levels"));
+            return;
+        }
+
+        let file_path = match resolve_env_path(name.clone(), &self.sp, env, &xopts, &self.opts.load_limits) {
+            Ok(p) => p,
+            Err(e) => return self.fail(None, e.msg),
+        };
+        // Paths are returned as given, names are looked up in the search paths
+        let search_path = match file_path == name {
+            true => None,
+            false => self.sp.iter().find(|s| file_path.starts_with(&format!("{s}/"))).cloned(),
+        };
+        self.trace.steps.push(TraceStep::Resolved {
+            name,
+            file_path: file_path.clone(),
+            search_path,
+        });
+
+        let canonical = canonical_path(&file_path);
+        if self.chain.iter().any(|p| canonical_path(p) == canonical) {
+            return self.fail(Some(&file_path), String::from("base_environment cycle"));
+        }
+
+        self.trace.steps.push(TraceStep::Loaded {
+            file_path: file_path.clone(),
+            depth: self.chain.len(),
+        });
+
+        let content = match load(&file_path) {
+            Ok(c) => c,
+            Err(e) => return self.fail(Some(&file_path), format!("{e}")),
+        };
+        let mut doc: serde_json::Value = match document_parse(&content, &file_path) {
+            Ok(d) => d,
+            Err(e) => return self.fail(Some(&file_path), e.msg),
+        };
+        resolve_aliases(&mut doc, EDF_KEY_ALIASES);
+        if let Err(e) = resolve_conditions(&mut doc, env, &self.opts.expand_options(), &file_path) {
+            return self.fail(Some(&file_path), e.msg);
+        }
+        let variables = referenced_variables(&doc);
+        let r = match raw_from_document(doc, &file_path) {
+            Ok(r) => r,
+            Err(e) => return self.fail(Some(&file_path), e.msg),
+        };
+
+        // Bases, then included fragments, are layered below the file
+        self.chain.push(file_path.clone());
+        for layer in [r.base_environment, r.include].into_iter().flatten() {
+            let names = match layer {
+                BaseEnvironment::TypeString(s) => vec![s],
+                BaseEnvironment::TypeVec(a) => a,
+            };
+            for n in names {
+                self.visit(n);
+            }
+        }
+        self.chain.pop();
+
+        let mut own = Provenance::default();
+        if own.record_content(&file_path, &content).is_ok() {
+            for (field, _) in own.entries.iter() {
+                // List entries are appended to, never overridden
+                if field.contains('[') {
+                    continue;
+                }
+                if let Some(prev) = self.setters.get(field) {
+                    let previous = prev.file_path.clone();
+                    self.trace.steps.push(TraceStep::Overridden {
+                        field: field.clone(),
+                        file_path: file_path.clone(),
+                        previous,
+                    });
+                }
+            }
+            self.setters.entries.extend(own.entries);
+        }
+
+        for variable in variables {
+            let mut value = match env {
+                Some(h) => h.get(&variable).cloned(),
+                None => std::env::var(&variable).ok(),
+            };
+            if value.is_some() && !self.opts.reveal_secrets && is_sensitive(&variable, &self.opts.sensitive_env) {
+                value = Some(String::from(REDACTED));
+            }
+            self.trace.steps.push(TraceStep::Expanded {
+                variable,
+                value,
+                file_path: file_path.clone(),
+            });
+        }
+    }
+}
+
+// Names of the variables referenced in the string values of a document.
+fn referenced_variables(doc: &serde_json::Value) -> BTreeSet<String> {
+    let re = Regex::new(r"\$\{?([A-Za-z_][A-Za-z0-9_]*)").unwrap();
+
+    let mut strings = vec![];
+    let mut stack = vec![doc];
+    while let Some(v) = stack.pop() {
+        match v {
+            serde_json::Value::String(s) => strings.push(s.as_str()),
+            serde_json::Value::Array(a) => stack.extend(a.iter()),
+            serde_json::Value::Object(o) => stack.extend(o.values()),
+            _ => {}
+        }
+    }
+
+    let mut res = BTreeSet::new();
+    for s in strings {
+        for c in re.captures_iter(s) {
+            res.insert(c[1].to_string());
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+    use std::collections::HashMap;
+    use std::env;
+
+    #[test]
+    #[serial]
+    fn explain_steps() {
+        let old_cwd = env::current_dir().unwrap();
+        env::set_current_dir("test/toml").unwrap();
+
+        let mut uenv = HashMap::new();
+        uenv.insert(String::from("SCRATCH"), String::from("/scratch"));
+        let opts = RenderOptions::new().env(uenv).search_paths(vec![String::from(".")]);
+        let trace = explain(String::from("include-top.toml"), &opts);
+        let invalid = explain(String::from("no-image.toml"), &opts);
+        let missing = explain(String::from("lint.toml"), &opts);
+
+        env::set_current_dir(old_cwd).unwrap();
+
+        assert!(!trace.has_failures());
+        let loaded: Vec<&TraceStep> = trace
+            .steps
+            .iter()
+            .filter(|s| matches!(s, TraceStep::Loaded { .. }))
+            .collect();
+        assert!(loaded.len() > 1);
+        assert!(matches!(loaded[0], TraceStep::Loaded { depth: 0, .. }));
+        assert!(trace.steps.iter().any(|s| matches!(s, TraceStep::Overridden { .. })));
+
+        // Files which can't be rendered are still explained
+        assert!(!invalid.has_failures());
+        assert!(invalid.steps.iter().any(|s| matches!(
+            s,
+            TraceStep::Expanded { variable, value: Some(v), .. } if variable == "SCRATCH" && v == "/scratch"
+        )));
+        assert!(missing.has_failures());
+        assert!(missing.report().contains("missing-base"));
+    }
+}
@@ -0,0 +1,154 @@
+// C interface of the renderer, built with the ffi feature. Strings returned
+// by the library are freed with raster_string_free. Failing calls set the
+// error of the calling thread, queried with raster_last_error_*.
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char, c_int};
+
+use crate::error::{SarusError, SarusErrorKind, SarusResult};
+use crate::{get_site_render_options, render_with_options, validate};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<SarusError>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(e: Option<SarusError>) {
+    LAST_ERROR.with(|l| *l.borrow_mut() = e);
+}
+
+fn c_path(path: *const c_char) -> SarusResult<String> {
+    if path.is_null() {
+        return Err(SarusError {
+            kind: SarusErrorKind::StringParse,
+            file_path: None,
+            msg: String::from("path is NULL"),
+            location: None,
+        });
+    }
+    // SAFETY: non-null, the caller passes a NUL-terminated string
+    let s = unsafe { CStr::from_ptr(path) };
+    match s.to_str() {
+        Ok(p) => Ok(String::from(p)),
+        Err(_) => Err(SarusError {
+            kind: SarusErrorKind::StringParse,
+            file_path: None,
+            msg: String::from("path is not valid UTF-8"),
+            location: None,
+        }),
+    }
+}
+
+fn c_string(s: String) -> *mut c_char {
+    match CString::new(s) {
+        Ok(c) => c.into_raw(),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+fn render_json(path: *const c_char) -> SarusResult<String> {
+    let path = c_path(path)?;
+    let opts = get_site_render_options()?;
+    render_with_options(path, &opts)?.to_json()
+}
+
+/// Render an EDF with the site configuration, as JSON. Returns NULL on
+/// failure.
+///
+/// # Safety
+/// path must be NULL or a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn raster_render(path: *const c_char) -> *mut c_char {
+    match render_json(path) {
+        Ok(j) => {
+            set_last_error(None);
+            c_string(j)
+        }
+        Err(e) => {
+            set_last_error(Some(e));
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Validate an EDF against the schema. Returns 0 when valid, -1 otherwise.
+///
+/// # Safety
+/// path must be NULL or a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn raster_validate(path: *const c_char) -> c_int {
+    match c_path(path).and_then(validate) {
+        Ok(_) => {
+            set_last_error(None);
+            0
+        }
+        Err(e) => {
+            set_last_error(Some(e));
+            -1
+        }
+    }
+}
+
+/// Code of the last error of the thread, -1 when the last call succeeded.
+#[unsafe(no_mangle)]
+pub extern "C" fn raster_last_error_code() -> c_int {
+    LAST_ERROR.with(|l| match &*l.borrow() {
+        Some(e) => c_int::try_from(e.code()).unwrap_or(c_int::MAX),
+        None => -1,
+    })
+}
+
+/// Message of the last error of the thread, NULL when the last call
+/// succeeded.
+#[unsafe(no_mangle)]
+pub extern "C" fn raster_last_error_message() -> *mut c_char {
+    LAST_ERROR.with(|l| match &*l.borrow() {
+        Some(e) => c_string(e.to_string()),
+        None => std::ptr::null_mut(),
+    })
+}
+
+/// Last error of the thread as JSON, NULL when the last call succeeded.
+#[unsafe(no_mangle)]
+pub extern "C" fn raster_last_error_json() -> *mut c_char {
+    LAST_ERROR.with(|l| match &*l.borrow() {
+        Some(e) => match e.to_json() {
+            Ok(j) => c_string(j),
+            Err(_) => std::ptr::null_mut(),
+        },
+        None => std::ptr::null_mut(),
+    })
+}
+
+/// Free a string returned by the library.
+///
+/// # Safety
+/// s must be NULL or a string returned by the library, not freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn raster_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        // SAFETY: allocated by CString::into_raw in c_string
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ffi_validate() {
+        let path = CString::new("test/toml/top-simple-1.toml").unwrap();
+        assert!(unsafe { raster_validate(path.as_ptr()) } == 0);
+        assert!(raster_last_error_code() == -1);
+        assert!(raster_last_error_message().is_null());
+
+        let path = CString::new("test/toml/not_found.toml").unwrap();
+        assert!(unsafe { raster_validate(path.as_ptr()) } == -1);
+        assert!(raster_last_error_code() == 2);
+        let msg = raster_last_error_message();
+        assert!(!msg.is_null());
+        unsafe { raster_string_free(msg) };
+
+        assert!(unsafe { raster_render(std::ptr::null()) }.is_null());
+        assert!(raster_last_error_code() == 25);
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use crate::common::ExpandOptions;
+use crate::limits::{Limits, Ulimit};
+use crate::mount::{MountEntry, MountTable, SarusMount};
+use crate::options::RenderOptions;
+use crate::{Annotations, RawEDF, edf_from_raw_with_hooks, finish_edf, render_from_str_with_options};
+
+// Entry points of the fuzz targets of fuzz/, e.g.
+// cargo fuzz run edf_str. EDFs are written by users and read by a
+// privileged launcher: whatever the input, parsing and rendering return an
+// error and never panic.
+
+// Renders never read the process environment nor the site search paths.
+fn fuzz_options(env: HashMap<String, String>) -> RenderOptions {
+    RenderOptions::new().search_paths(vec![]).env(env).max_levels(2)
+}
+
+// EDF files, TOML or YAML depending on the first byte.
+pub fn fuzz_edf_str(data: &[u8]) {
+    let (origin, content) = match data.split_first() {
+        Some((b, rest)) if b % 2 == 0 => ("fuzz.yaml", rest),
+        Some((_, rest)) => ("fuzz.toml", rest),
+        None => return,
+    };
+    if let Ok(s) = std::str::from_utf8(content) {
+        let _ = render_from_str_with_options(String::from(s), origin, &fuzz_options(HashMap::new()));
+    }
+}
+
+// EDFs past parsing, e.g. built by frontends.
+pub fn fuzz_raw_edf(raw: RawEDF, env: HashMap<String, String>) {
+    let opts = fuzz_options(env);
+    if let Ok(e) = edf_from_raw_with_hooks(raw, &opts) {
+        let _ = finish_edf(e, &opts);
+    }
+}
+
+pub fn fuzz_mount(spec: &str, env: HashMap<String, String>) {
+    let _ = SarusMount::try_new_with_options(String::from(spec), &Some(env), &ExpandOptions::default());
+}
+
+#[derive(Arbitrary, Debug)]
+pub struct ExpansionInput {
+    pub input: String,
+    pub env: HashMap<String, String>,
+    pub allowed_vars: Vec<String>,
+    pub denied_vars: Vec<String>,
+}
+
+// Native expansion only, the bash backend runs a program.
+pub fn fuzz_expansion(i: ExpansionInput) {
+    let opts = ExpandOptions {
+        allowed_vars: i.allowed_vars,
+        denied_vars: i.denied_vars,
+        ..Default::default()
+    };
+    let _ = crate::common::expand_vars_string_with_options(i.input, &Some(i.env), &opts);
+}
+
+fn arbitrary_ulimit(u: &mut Unstructured) -> Result<Option<Ulimit>> {
+    Ok(match u.int_in_range(0..=2)? {
+        0 => None,
+        1 => Some(Ulimit::Unlimited),
+        _ => Some(Ulimit::Bytes(u.arbitrary()?)),
+    })
+}
+
+fn arbitrary_mount(u: &mut Unstructured) -> Result<MountEntry> {
+    Ok(match u.arbitrary()? {
+        true => MountEntry::Spec(u.arbitrary()?),
+        false => MountEntry::Table(MountTable {
+            source: u.arbitrary()?,
+            target: u.arbitrary()?,
+            flags: u.arbitrary()?,
+            kind: u.arbitrary()?,
+        }),
+    })
+}
+
+// Base environments and includes are left out, they would be looked up in
+// the filesystem.
+impl<'a> Arbitrary<'a> for RawEDF {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let annotations: Option<HashMap<String, String>> = u.arbitrary()?;
+        let mounts = match u.arbitrary()? {
+            true => Some((0..u.int_in_range(0..=4)?).map(|_| arbitrary_mount(u)).collect::<Result<_>>()?),
+            false => None,
+        };
+        let limits = match u.arbitrary()? {
+            true => Some(Limits {
+                shm_size: u.arbitrary()?,
+                pids_limit: u.arbitrary()?,
+                memlock: arbitrary_ulimit(u)?,
+                stack: arbitrary_ulimit(u)?,
+            }),
+            false => None,
+        };
+        Ok(RawEDF {
+            annotations: annotations.map(Annotations::TypeHashMap),
+            auth_file: u.arbitrary()?,
+            devices: u.arbitrary()?,
+            edf_version: u.arbitrary()?,
+            engine: u.arbitrary()?,
+            entrypoint: u.arbitrary()?,
+            env: u.arbitrary()?,
+            env_passthrough: u.arbitrary()?,
+            gpu: u.arbitrary()?,
+            group: u.arbitrary()?,
+            hooks: u.arbitrary()?,
+            image: u.arbitrary()?,
+            labels: u.arbitrary()?,
+            limits,
+            mounts,
+            mpi: u.arbitrary()?,
+            network: u.arbitrary()?,
+            pull_policy: u.arbitrary()?,
+            user: u.arbitrary()?,
+            userns: u.arbitrary()?,
+            workdir: u.arbitrary()?,
+            writable: u.arbitrary()?,
+            writable_overlay_path: u.arbitrary()?,
+            writable_overlay_size: u.arbitrary()?,
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Bytes of a xorshift generator, the same on every run.
+    fn bytes(seed: u64, len: usize) -> Vec<u8> {
+        let mut x = seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+        (0..len)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                x as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fuzz_no_panics() {
+        let samples = [
+            "image = \"ubuntu\"\nmounts = [\"/a:/b:sqsh,offset=\"]\n",
+            "image = \"ubuntu\"\nmounts = [\"tmpfs:/t:size=\"]\nenv = { A = \"${A:-${B\" }\n",
+            "image: ubuntu\nlimits: {shm_size: 8G, memlock: unlimited}\n",
+            "image = \"oci-archive:\"\nuser = \":\"\n",
+        ];
+        for s in samples {
+            fuzz_edf_str(format!("\u{1}{s}").as_bytes());
+            fuzz_edf_str(format!("\u{2}{s}").as_bytes());
+        }
+        for spec in ["", ":", "::::", "tmpfs:", "a:b:sqsh,verity=", "$X:/b", "~:/b:ro,ro"] {
+            fuzz_mount(spec, HashMap::from([(String::from("X"), String::from(":"))]));
+        }
+
+        for seed in 0..500 {
+            let data = bytes(seed, 512);
+            fuzz_edf_str(&data);
+            let mut u = Unstructured::new(&data);
+            if let Ok(raw) = RawEDF::arbitrary(&mut u) {
+                fuzz_raw_edf(raw, HashMap::new());
+            }
+            let mut u = Unstructured::new(&data);
+            if let Ok(i) = ExpansionInput::arbitrary(&mut u) {
+                fuzz_expansion(i);
+            }
+            let mut u = Unstructured::new(&data);
+            if let Ok(spec) = <&str>::arbitrary(&mut u) {
+                fuzz_mount(spec, HashMap::new());
+            }
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use crate::error::{SarusError, SarusErrorKind, SarusResult};
+
+// EDF stored in a git repository, at a given ref:
+// git+ssh://git@example.org/edf/catalog.git#ref=v1.2:ml/pytorch.toml
+// The ref defaults to HEAD when the fragment is only a path.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GitRef {
+    // Repository URL, without the git+ prefix.
+    pub repo: String,
+    pub git_ref: String,
+    pub path: String,
+}
+
+pub fn is_git_ref(name: &str) -> bool {
+    name.starts_with("git+")
+}
+
+impl GitRef {
+    pub fn parse(name: &str) -> SarusResult<GitRef> {
+        let invalid = |msg: String| SarusError {
+            kind: SarusErrorKind::GitFetch { url: String::from(name) },
+            file_path: None,
+            msg,
+            location: None,
+        };
+
+        let rest = match name.strip_prefix("git+") {
+            Some(r) => r,
+            None => return Err(invalid(format!("{name} doesn't start with git+"))),
+        };
+        let (repo, fragment) = match rest.split_once('#') {
+            Some((r, f)) => (r, f),
+            None => return Err(invalid(format!("{name} lacks a #ref=<ref>:<path> fragment"))),
+        };
+        let (git_ref, path) = match fragment.strip_prefix("ref=") {
+            Some(f) => match f.split_once(':') {
+                Some((r, p)) => (r, p),
+                None => return Err(invalid(format!("{name} lacks a path after the ref"))),
+            },
+            None => ("HEAD", fragment),
+        };
+        if repo.is_empty() || git_ref.is_empty() || path.is_empty() || git_ref.starts_with('-') {
+            return Err(invalid(format!("invalid git reference {name}")));
+        }
+        Ok(GitRef {
+            repo: String::from(repo),
+            git_ref: String::from(git_ref),
+            path: String::from(path.trim_start_matches('/')),
+        })
+    }
+
+    // Path of the EDF, used for the extension check and for reporting.
+    pub fn origin(&self) -> String {
+        format!("git+{}#ref={}:{}", self.repo, self.git_ref, self.path)
+    }
+
+    // Directory of the mirror clone within the cache.
+    #[cfg(feature = "git")]
+    fn cache_name(&self) -> String {
+        self.repo
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+            .collect()
+    }
+}
+
+// Where repositories are cloned when the configuration leaves it empty.
+pub fn default_git_cache_dir() -> String {
+    match std::env::var("XDG_CACHE_HOME") {
+        Ok(d) if !d.is_empty() => format!("{d}/raster/git"),
+        _ => format!("{}/.cache/raster/git", std::env::var("HOME").unwrap_or_default()),
+    }
+}
+
+// Read the EDF at its ref, cloning the repository into the cache the first
+// time and fetching it afterwards.
+#[cfg(feature = "git")]
+pub(crate) fn fetch(r: &GitRef, cache_dir: &str) -> SarusResult<String> {
+    use std::process::Command;
+
+    let failed = |msg: String| SarusError {
+        kind: SarusErrorKind::GitFetch { url: r.origin() },
+        file_path: Some(r.origin()),
+        msg,
+        location: None,
+    };
+    let run = |cmd: &mut Command| -> SarusResult<Vec<u8>> {
+        match cmd.output() {
+            Ok(o) if o.status.success() => Ok(o.stdout),
+            Ok(o) => Err(failed(format!(
+                "git failed for {} - {}",
+                r.origin(),
+                String::from_utf8_lossy(&o.stderr).trim()
+            ))),
+            Err(e) => Err(failed(format!("can't run git - {e}"))),
+        }
+    };
+
+    if let Err(e) = std::fs::create_dir_all(cache_dir) {
+        return Err(failed(format!("can't create {cache_dir} - {e}")));
+    }
+    let dir = format!("{cache_dir}/{}", r.cache_name());
+    if std::path::Path::new(&dir).is_dir() {
+        run(Command::new("git").args(["-C", &dir, "fetch", "--quiet", "--tags", "--force", "origin"]))?;
+    } else {
+        run(Command::new("git").args(["clone", "--quiet", "--mirror", "--", &r.repo, &dir]))?;
+    }
+
+    let object = format!("{}:{}", r.git_ref, r.path);
+    let content = run(Command::new("git").args(["-C", &dir, "show", &object]))?;
+    match String::from_utf8(content) {
+        Ok(s) => Ok(s),
+        Err(_) => Err(failed(format!("{} is not valid UTF-8", r.origin()))),
+    }
+}
+
+#[cfg(not(feature = "git"))]
+pub(crate) fn fetch(r: &GitRef, _cache_dir: &str) -> SarusResult<String> {
+    Err(SarusError {
+        kind: SarusErrorKind::GitFetch { url: r.origin() },
+        file_path: Some(r.origin()),
+        msg: format!("can't fetch {}, git EDF references need the \"git\" feature", r.origin()),
+        location: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn git_ref_parse() {
+        let r = GitRef::parse("git+ssh://git@example.org/edf/catalog.git#ref=v1.2:ml/pytorch.toml").unwrap();
+        assert!(r.repo == "ssh://git@example.org/edf/catalog.git");
+        assert!(r.git_ref == "v1.2");
+        assert!(r.path == "ml/pytorch.toml");
+
+        let r = GitRef::parse("git+https://example.org/catalog.git#base.toml").unwrap();
+        assert!(r.git_ref == "HEAD" && r.path == "base.toml");
+
+        assert!(GitRef::parse("git+https://example.org/catalog.git").is_err());
+        assert!(GitRef::parse("git+https://example.org/catalog.git#ref=v1").is_err());
+        assert!(GitRef::parse("git+https://example.org/catalog.git#ref=--upload-pack=x:a.toml").is_err());
+    }
+
+    #[cfg(feature = "git")]
+    #[test]
+    fn git_fetch() {
+        use std::process::Command;
+
+        let tmp = std::env::temp_dir().join(format!("raster-git-{}", std::process::id()));
+        let repo = tmp.join("repo");
+        std::fs::create_dir_all(repo.join("ml")).unwrap();
+        std::fs::write(repo.join("ml/pytorch.toml"), "image = \"pytorch:24.01\"\n").unwrap();
+        let git = |args: &[&str]| {
+            let ok = Command::new("git")
+                .args(["-C", repo.to_str().unwrap(), "-c", "user.name=t", "-c", "user.email=t@t"])
+                .args(args)
+                .output()
+                .unwrap()
+                .status
+                .success();
+            assert!(ok);
+        };
+        git(&["init", "--quiet"]);
+        git(&["add", "."]);
+        git(&["commit", "--quiet", "-m", "init"]);
+        git(&["tag", "v1"]);
+
+        let cache = tmp.join("cache").display().to_string();
+        let r = GitRef::parse(&format!("git+file://{}#ref=v1:ml/pytorch.toml", repo.display())).unwrap();
+        assert!(fetch(&r, &cache).unwrap().contains("pytorch:24.01"));
+        // Served from the cache, fetched again
+        assert!(fetch(&r, &cache).is_ok());
+
+        let r = GitRef::parse(&format!("git+file://{}#ref=v1:missing.toml", repo.display())).unwrap();
+        assert!(fetch(&r, &cache).err().unwrap().code() == 50);
+        std::fs::remove_dir_all(tmp).unwrap();
+    }
+
+    #[cfg(not(feature = "git"))]
+    #[test]
+    fn git_disabled() {
+        let r = GitRef::parse("git+https://example.org/catalog.git#base.toml").unwrap();
+        assert!(fetch(&r, "/nonexistent").err().unwrap().code() == 50);
+    }
+}
@@ -0,0 +1,223 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use regex::Regex;
+use walkdir::WalkDir;
+
+use crate::EDF;
+use crate::error::{SarusError, SarusErrorKind, SarusResult};
+use crate::options::RenderOptions;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GpuVendor {
+    Nvidia,
+    Amd,
+}
+
+// Where GPUs are looked for, /dev and the CDI spec dirs by default.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GpuPaths {
+    pub dev_dir: String,
+    pub cdi_dirs: Vec<String>,
+}
+
+impl Default for GpuPaths {
+    fn default() -> Self {
+        GpuPaths {
+            dev_dir: String::from("/dev"),
+            cdi_dirs: vec![String::from("/etc/cdi"), String::from("/var/run/cdi")],
+        }
+    }
+}
+
+// GPUs found on the node, devices are CDI names when a CDI spec of the
+// vendor exists, device paths otherwise.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GpuProbe {
+    pub vendor: GpuVendor,
+    pub devices: Vec<String>,
+}
+
+impl GpuVendor {
+    fn name(&self) -> &'static str {
+        match self {
+            GpuVendor::Nvidia => "nvidia",
+            GpuVendor::Amd => "amd",
+        }
+    }
+
+    fn cdi_vendor(&self) -> &'static str {
+        match self {
+            GpuVendor::Nvidia => "nvidia.com",
+            GpuVendor::Amd => "amd.com",
+        }
+    }
+
+    // Variables selecting the visible GPUs, passed through to the container.
+    fn visible_devices_vars(&self) -> &'static [&'static str] {
+        match self {
+            GpuVendor::Nvidia => &["CUDA_VISIBLE_DEVICES"],
+            GpuVendor::Amd => &["ROCR_VISIBLE_DEVICES", "HIP_VISIBLE_DEVICES"],
+        }
+    }
+
+    // Device nodes of the vendor, relative to the dev dir.
+    fn is_device_node(&self, rel: &str) -> bool {
+        let re = match self {
+            GpuVendor::Nvidia => Regex::new(r"^nvidia([0-9]+|ctl|-uvm|-uvm-tools|-modeset)$").unwrap(),
+            GpuVendor::Amd => Regex::new(r"^(kfd|dri/renderD[0-9]+)$").unwrap(),
+        };
+        re.is_match(rel)
+    }
+}
+
+pub fn probe(paths: &GpuPaths) -> Option<GpuProbe> {
+    for vendor in [GpuVendor::Nvidia, GpuVendor::Amd] {
+        let cdi = cdi_devices(vendor, &paths.cdi_dirs);
+        if !cdi.is_empty() {
+            return Some(GpuProbe { vendor, devices: cdi });
+        }
+        let nodes = device_nodes(vendor, &paths.dev_dir);
+        if !nodes.is_empty() {
+            return Some(GpuProbe { vendor, devices: nodes });
+        }
+    }
+    None
+}
+
+fn device_nodes(vendor: GpuVendor, dev_dir: &str) -> Vec<String> {
+    let mut res = vec![];
+    for entry in WalkDir::new(dev_dir).max_depth(2).into_iter().flatten() {
+        let rel = match entry.path().strip_prefix(dev_dir) {
+            Ok(r) => r.to_string_lossy().to_string(),
+            Err(_) => continue,
+        };
+        if vendor.is_device_node(&rel) {
+            res.push(format!("/dev/{rel}"));
+        }
+    }
+    res.sort();
+    res
+}
+
+// CDI names of the GPUs described by the specs, "all" when a spec has it.
+fn cdi_devices(vendor: GpuVendor, cdi_dirs: &[String]) -> Vec<String> {
+    let mut res = vec![];
+    for dir in cdi_dirs {
+        let mut files: Vec<_> = match std::fs::read_dir(dir) {
+            Ok(d) => d.flatten().map(|e| e.path()).collect(),
+            Err(_) => continue,
+        };
+        files.sort();
+        for f in files {
+            let spec = match read_cdi_spec(&f) {
+                Some(s) => s,
+                None => continue,
+            };
+            let kind = spec.get("kind").and_then(|k| k.as_str()).unwrap_or_default();
+            if kind != format!("{}/gpu", vendor.cdi_vendor()) {
+                continue;
+            }
+            let names: Vec<&str> = match spec.get("devices").and_then(|d| d.as_array()) {
+                Some(d) => d.iter().filter_map(|d| d.get("name")?.as_str()).collect(),
+                None => vec![],
+            };
+            if names.contains(&"all") {
+                return vec![format!("{kind}=all")];
+            }
+            res.extend(names.iter().map(|n| format!("{kind}={n}")));
+        }
+    }
+    res
+}
+
+fn read_cdi_spec(path: &Path) -> Option<serde_json::Value> {
+    let ext = path.extension()?.to_str()?;
+    let content = std::fs::read_to_string(path).ok()?;
+    match ext {
+        "json" => serde_json::from_str(&content).ok(),
+        "yaml" | "yml" => serde_yaml::from_str(&content).ok(),
+        _ => None,
+    }
+}
+
+// Add the GPU devices, the visible devices variables of the host and the
+// annotations of the GPU hook to an EDF with gpu = true. Settings of the
+// EDF itself are kept.
+pub(crate) fn inject(e: &mut EDF, options: &RenderOptions) -> SarusResult<()> {
+    let p = match probe(&options.gpu_paths) {
+        Some(p) => p,
+        None => {
+            return Err(SarusError {
+                kind: SarusErrorKind::GpuNotFound,
+                file_path: None,
+                msg: format!("gpu = true, but no GPU was found in {}", options.gpu_paths.dev_dir),
+                location: None,
+            });
+        }
+    };
+
+    for d in p.devices.iter() {
+        if !e.devices.contains(d) {
+            e.devices.push(d.clone());
+        }
+    }
+
+    for var in p.vendor.visible_devices_vars() {
+        let value = match &options.env {
+            Some(h) => h.get(*var).cloned(),
+            None => std::env::var(var).ok(),
+        };
+        if let Some(v) = value
+            && !e.env.contains_key(*var)
+        {
+            e.env.insert(var.to_string(), v);
+        }
+    }
+
+    e.annotations
+        .entry(String::from("com.hooks.gpu.enabled"))
+        .or_insert(String::from("true"));
+    e.annotations
+        .entry(String::from("com.hooks.gpu.vendor"))
+        .or_insert(String::from(p.vendor.name()));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gpu_probe() {
+        let cdi = GpuPaths {
+            dev_dir: String::from("test/gpu/dev-nvidia"),
+            cdi_dirs: vec![String::from("test/gpu/cdi")],
+        };
+        let p = probe(&cdi).unwrap();
+        assert!(p.vendor == GpuVendor::Nvidia);
+        assert!(p.devices == vec![String::from("nvidia.com/gpu=all")]);
+
+        let nvidia = GpuPaths {
+            dev_dir: String::from("test/gpu/dev-nvidia"),
+            cdi_dirs: vec![],
+        };
+        let p = probe(&nvidia).unwrap();
+        assert!(p.devices == vec!["/dev/nvidia-uvm", "/dev/nvidia0", "/dev/nvidiactl"]);
+
+        let amd = GpuPaths {
+            dev_dir: String::from("test/gpu/dev-amd"),
+            cdi_dirs: vec![],
+        };
+        let p = probe(&amd).unwrap();
+        assert!(p.vendor == GpuVendor::Amd);
+        assert!(p.devices == vec!["/dev/dri/renderD128", "/dev/kfd"]);
+
+        let none = GpuPaths {
+            dev_dir: String::from("test/etc"),
+            cdi_dirs: vec![],
+        };
+        assert!(probe(&none).is_none());
+    }
+}
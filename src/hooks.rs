@@ -1,10 +1,77 @@
+use std::collections::HashMap;
 use std::path::Path;
 use std::process::{Command, Output};
+#[cfg(unix)]
 use is_executable::IsExecutable;
+use serde::{Deserialize, Serialize};
 
-use crate::error::{SarusError, SarusResult};
+use crate::error::{SarusError, SarusErrorKind, SarusResult};
 use crate::Config;
 
+// OCI hook of the site catalog, declared in the configuration:
+// [oci_hooks.ssh]
+// path = "/opt/sarus/hooks/ssh_hook"
+// stage = "createContainer"
+// args = ["ssh_hook", "start"]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct HookDefinition {
+    pub path: String,
+    pub stage: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: Vec<String>,
+    #[serde(default)]
+    pub timeout: Option<u32>,
+}
+
+// Hook requested by an EDF, resolved against the catalog, for the runtime
+// layer to install.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HookSpec {
+    pub name: String,
+    pub path: String,
+    pub stage: String,
+    pub args: Vec<String>,
+    pub env: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u32>,
+}
+
+// Resolve the hooks enabled in the [hooks] table of an EDF, sorted by name.
+pub(crate) fn resolve_hooks(
+    requested: &HashMap<String, bool>,
+    catalog: &HashMap<String, HookDefinition>,
+) -> SarusResult<Vec<HookSpec>> {
+    let mut names: Vec<&String> = requested.iter().filter(|(_, on)| **on).map(|(n, _)| n).collect();
+    names.sort();
+
+    let mut res = vec![];
+    for name in names {
+        let d = match catalog.get(name) {
+            Some(d) => d,
+            None => {
+                return Err(SarusError {
+                    kind: SarusErrorKind::UnknownHook { name: name.clone() },
+                    file_path: None,
+                    msg: format!("hook \"{name}\" is not in the hooks catalog of the site"),
+                    location: None,
+                });
+            }
+        };
+        res.push(HookSpec {
+            name: name.clone(),
+            path: d.path.clone(),
+            stage: d.stage.clone(),
+            args: d.args.clone(),
+            env: d.env.clone(),
+            timeout: d.timeout,
+        });
+    }
+    Ok(res)
+}
+
 pub struct ExecutedCommand {
     pub command: String,
     pub output: Output,
@@ -15,9 +82,10 @@ pub fn hook_run(config: &Config, name: &str, args: Vec<&str>) -> SarusResult<Opt
     let hook = match name {
         "parallax_imagestore_create" => &config.hooks.parallax_imagestore_create,
         _ => return Err(SarusError {
-                code: 26,
+                kind: SarusErrorKind::UnknownHook { name: name.to_string() },
                 file_path: None,
                 msg: format!("unknown hook name: \"{name}\""),
+                location: None,
         }),
     };
 
@@ -29,17 +97,20 @@ pub fn hook_run(config: &Config, name: &str, args: Vec<&str>) -> SarusResult<Opt
 
     if ! hook_path.exists() {
         return Err(SarusError {
-            code: 27,
+            kind: SarusErrorKind::HookNotFound { name: name.to_string(), path: hook.to_string() },
             file_path: None,
             msg: format!("config.hooks.{name} file \"{hook}\" doesn't exist"),
+            location: None,
         });
     }
 
+    #[cfg(unix)]
     if ! hook_path.is_executable() {
         return Err(SarusError {
-            code: 28,
+            kind: SarusErrorKind::HookNotExecutable { name: name.to_string(), path: hook.to_string() },
             file_path: None,
             msg: format!("config.hooks.{name} file \"{hook}\" isn't executable"),
+            location: None,
         });
     }
 
@@ -62,9 +133,10 @@ fn hook_run_path(path: &Path, args: &Vec<&str>) -> SarusResult<Output> {
                 .output() {
         Ok(output) => Ok(output),
         Err(err)    => return Err(SarusError {
-            code: 29,
+            kind: SarusErrorKind::HookFailed { command: format!("{} {}", path.display(), args.concat()) },
             file_path: None,
             msg: format!("Running command \"{} {}\" error: {err}", path.display(), args.concat()),
+            location: None,
         }),
     }
 }
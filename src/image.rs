@@ -0,0 +1,267 @@
+use std::path::{Path, PathBuf};
+
+use crate::EDF;
+use crate::error::{SarusError, SarusErrorKind, SarusResult};
+use crate::mount::SquashfsOptions;
+use crate::registry::{has_explicit_registry, image_registry};
+
+// Reference to an image of a registry, e.g. nvcr.io/nvidia/pytorch:24.01-py3.
+// Images without a registry come from docker.io.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageRef {
+    pub registry: String,
+    // The path of the image in the registry, e.g. nvidia/pytorch.
+    pub repository: String,
+    pub tag: Option<String>,
+    // The digest pinning the image, e.g. sha256:<hex>.
+    pub digest: Option<String>,
+}
+
+impl ImageRef {
+    // None for local squashfs files.
+    pub fn parse(image: &str) -> Option<ImageRef> {
+        let registry = image_registry(image)?;
+        let rest = match has_explicit_registry(image) {
+            true => image.split_once('/').map(|(_, r)| r).unwrap_or(image),
+            false => image,
+        };
+        let (name, digest) = match rest.split_once('@') {
+            Some((n, d)) => (n, Some(String::from(d))),
+            None => (rest, None),
+        };
+        let (repository, tag) = match name.rsplit_once(':') {
+            Some((r, t)) if !t.contains('/') => (r, Some(String::from(t))),
+            _ => (name, None),
+        };
+        Some(ImageRef {
+            registry,
+            repository: String::from(repository),
+            tag,
+            digest,
+        })
+    }
+}
+
+impl std::fmt::Display for ImageRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}/{}", self.registry, self.repository)?;
+        if let Some(t) = &self.tag {
+            write!(f, ":{t}")?;
+        }
+        if let Some(d) = &self.digest {
+            write!(f, "@{d}")?;
+        }
+        Ok(())
+    }
+}
+
+// Where the image of an EDF comes from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImageSource {
+    Registry(ImageRef),
+    // A local squashfs file, e.g. ./image.sqsh or /scratch/image.sqsh.
+    SquashFile(PathBuf),
+    // A tarball written by docker save or podman save, e.g.
+    // docker-archive:/scratch/image.tar, with the reference of the image to
+    // load when the archive holds several.
+    DockerArchive(PathBuf, Option<String>),
+    // An OCI image layout in a tarball, e.g. oci-archive:/scratch/image.tar.
+    OciArchive(PathBuf, Option<String>),
+    // An image of another transport of podman, e.g. dir:/scratch/app,
+    // rejected by render.
+    Unsupported(String),
+}
+
+// Transports of podman taking images from the host, the archives being the
+// only ones supported.
+const ARCHIVE_TRANSPORTS: [&str; 2] = ["docker-archive", "oci-archive"];
+const UNSUPPORTED_TRANSPORTS: [&str; 4] = ["containers-storage", "dir", "docker-daemon", "oci"];
+
+// The transport and the rest of an image given as TRANSPORT:..., e.g.
+// oci-archive:/scratch/image.tar. "oci:5000/app" is the registry oci on
+// port 5000.
+pub(crate) fn image_transport(image: &str) -> Option<(&str, &str)> {
+    let (transport, rest) = image.split_once(':')?;
+    let known = ARCHIVE_TRANSPORTS.contains(&transport) || UNSUPPORTED_TRANSPORTS.contains(&transport);
+    match known && !rest.starts_with(|c: char| c.is_ascii_digit()) {
+        true => Some((transport, rest)),
+        false => None,
+    }
+}
+
+pub fn image_source(image: &str) -> ImageSource {
+    if let Some((transport, rest)) = image_transport(image) {
+        if UNSUPPORTED_TRANSPORTS.contains(&transport) {
+            return ImageSource::Unsupported(String::from(image));
+        }
+        let (path, reference) = match rest.split_once(':') {
+            Some((p, r)) => (PathBuf::from(p), Some(String::from(r))),
+            None => (PathBuf::from(rest), None),
+        };
+        if transport == "oci-archive" {
+            return ImageSource::OciArchive(path, reference);
+        }
+        return ImageSource::DockerArchive(path, reference);
+    }
+    match ImageRef::parse(image) {
+        Some(r) => ImageSource::Registry(r),
+        None => ImageSource::SquashFile(PathBuf::from(image)),
+    }
+}
+
+impl ImageSource {
+    pub fn is_archive(&self) -> bool {
+        matches!(self, ImageSource::DockerArchive(..) | ImageSource::OciArchive(..))
+    }
+}
+
+impl EDF {
+    pub fn image_source(&self) -> ImageSource {
+        image_source(&self.image)
+    }
+}
+
+fn absolute_image_path(image: &str, path: &Path) -> SarusResult<PathBuf> {
+    match std::path::absolute(path) {
+        Ok(p) => Ok(p),
+        Err(_) => Err(SarusError {
+            kind: SarusErrorKind::MountPathTranslation { path: String::from(image) },
+            file_path: None,
+            msg: format!("cannot translate {image} in an absolute path"),
+            location: None,
+        }),
+    }
+}
+
+// Images on the host are checked and their path made absolute, images of
+// unsupported transports are rejected.
+pub(crate) fn apply_local_image(e: &mut EDF) -> SarusResult<()> {
+    match e.image_source() {
+        ImageSource::Registry(_) => Ok(()),
+        ImageSource::Unsupported(image) => Err(SarusError {
+            kind: SarusErrorKind::InvalidImage { image: image.clone() },
+            file_path: None,
+            msg: format!("image \"{image}\" uses an unsupported transport, expected one of {ARCHIVE_TRANSPORTS:?}"),
+            location: None,
+        }),
+        ImageSource::SquashFile(p) => apply_squashfs_image(e, &p),
+        ImageSource::DockerArchive(p, r) => apply_archive_image(e, "docker-archive", &p, r),
+        ImageSource::OciArchive(p, r) => apply_archive_image(e, "oci-archive", &p, r),
+    }
+}
+
+fn apply_archive_image(e: &mut EDF, transport: &str, path: &Path, reference: Option<String>) -> SarusResult<()> {
+    let path = absolute_image_path(&e.image, path)?;
+    if !path.is_file() {
+        return Err(SarusError {
+            kind: SarusErrorKind::InvalidImage { image: e.image.clone() },
+            file_path: None,
+            msg: format!("image archive {} doesn't exist or isn't a regular file", path.display()),
+            location: None,
+        });
+    }
+    e.image = match reference {
+        Some(r) => format!("{transport}:{}:{r}", path.display()),
+        None => format!("{transport}:{}", path.display()),
+    };
+    Ok(())
+}
+
+// Images in a squashfs file are checked like the sources of squashfs mounts
+// and made absolute. They are never pulled.
+fn apply_squashfs_image(e: &mut EDF, path: &Path) -> SarusResult<()> {
+    let path = absolute_image_path(&e.image, path)?;
+    let source = path.display().to_string();
+
+    let metadata = match std::fs::metadata(&path) {
+        Ok(m) => m,
+        Err(err) => {
+            return Err(SarusError {
+                kind: SarusErrorKind::SquashfsStat { source: source.clone() },
+                file_path: None,
+                msg: format!("could not stat squashfs image ({source}): {err}"),
+                location: None,
+            });
+        }
+    };
+    if !metadata.is_file() {
+        return Err(SarusError {
+            kind: SarusErrorKind::SquashfsNotFile { source: source.clone() },
+            file_path: None,
+            msg: format!("squashfs image ({source}) must be a regular file"),
+            location: None,
+        });
+    }
+    let opts = SquashfsOptions {
+        image: path,
+        ..Default::default()
+    };
+    if let Err(err) = opts.check_magic() {
+        return Err(SarusError {
+            kind: SarusErrorKind::SquashfsMagic { source: source.clone() },
+            file_path: None,
+            msg: format!("image ({source}) is not a squashfs image: {err}"),
+            location: None,
+        });
+    }
+
+    e.image = source;
+    if e.pull_policy.is_none() {
+        e.pull_policy = Some(String::from("never"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RenderOptions, render_from_str_with_options};
+
+    #[test]
+    fn image_sources() {
+        let r = ImageRef::parse("nvcr.io/nvidia/pytorch:24.01-py3").unwrap();
+        assert!(r.registry == "nvcr.io" && r.repository == "nvidia/pytorch" && r.tag.as_deref() == Some("24.01-py3"));
+        let r = ImageRef::parse("localhost:5000/app@sha256:abc").unwrap();
+        assert!(r.registry == "localhost:5000" && r.repository == "app" && r.tag.is_none());
+        assert!(r.digest.as_deref() == Some("sha256:abc"));
+        assert!(ImageRef::parse("ubuntu").unwrap().to_string() == "docker.io/ubuntu");
+        assert!(image_source("/scratch/ubuntu.sqsh") == ImageSource::SquashFile(PathBuf::from("/scratch/ubuntu.sqsh")));
+        assert!(image_source("ubuntu.sqsh") == ImageSource::SquashFile(PathBuf::from("ubuntu.sqsh")));
+
+        let opts = RenderOptions::new().skip_validation(true);
+        let content = String::from("image = \"./test/etc/image.sqsh\"\n");
+        let e = render_from_str_with_options(content, "socket", &opts).unwrap();
+        let expected = std::env::current_dir().unwrap().join("test/etc/image.sqsh");
+        assert!(e.image_source() == ImageSource::SquashFile(expected));
+        assert!(e.pull_policy.as_deref() == Some("never"));
+
+        let content = String::from("image = \"./test/etc/missing.sqsh\"\n");
+        let err = render_from_str_with_options(content, "socket", &opts).err().unwrap();
+        assert!(matches!(err.kind, SarusErrorKind::SquashfsStat { .. }));
+        let content = String::from("image = \"./test/etc/offset.sqsh\"\n");
+        let err = render_from_str_with_options(content, "socket", &opts).err().unwrap();
+        assert!(matches!(err.kind, SarusErrorKind::SquashfsMagic { .. }));
+    }
+
+    #[test]
+    fn image_archives() {
+        let s = image_source("docker-archive:/scratch/app.tar:app:v1");
+        assert!(s == ImageSource::DockerArchive(PathBuf::from("/scratch/app.tar"), Some(String::from("app:v1"))));
+        assert!(image_source("oci-archive:./app.tar") == ImageSource::OciArchive(PathBuf::from("./app.tar"), None));
+        assert!(image_source("dir:/scratch/app") == ImageSource::Unsupported(String::from("dir:/scratch/app")));
+        assert!(matches!(image_source("oci:5000/app"), ImageSource::Registry(r) if r.registry == "oci:5000"));
+
+        let opts = RenderOptions::new().skip_validation(true);
+        let content = String::from("image = \"oci-archive:./test/etc/plain.txt\"\n");
+        let e = render_from_str_with_options(content, "socket", &opts).unwrap();
+        let expected = std::env::current_dir().unwrap().join("test/etc/plain.txt");
+        assert!(e.image == format!("oci-archive:{}", expected.display()));
+        assert!(e.image_source().is_archive());
+
+        for image in ["docker-archive:/nonexistent/app.tar", "dir:/scratch/app", "docker-daemon:app:v1"] {
+            let content = format!("image = \"{image}\"\n");
+            let err = render_from_str_with_options(content, "socket", &opts).err().unwrap();
+            assert!(err.kind == SarusErrorKind::InvalidImage { image: String::from(image) });
+        }
+    }
+}
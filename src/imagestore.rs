@@ -1,8 +1,10 @@
 use std::path::Path;
 use std::fs::{self, File, FileTimes};
 use std::time::{Duration, SystemTime};
+use serde::Serialize;
 use walkdir::WalkDir;
 use crate::Config;
+use crate::error::{SarusError, SarusErrorKind, SarusResult};
 
 pub fn imagestore_keepalive(config: &Config) -> Result<Option<String>,String> {
     
@@ -47,3 +49,137 @@ pub fn imagestore_keepalive(config: &Config) -> Result<Option<String>,String> {
     output = Some(format!("Keep alive imagestore {}, refreshed {}/{} inodes", imagestore, upd_entries, num_entries));
     Ok(output)
 }
+
+// State of a parallax imagestore, see imagestore_status().
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ImagestoreInfo {
+    pub path: String,
+    pub exists: bool,
+    // Whether the calling user can create files in it.
+    pub writable: bool,
+    // Bytes available to unprivileged users, None when unknown.
+    pub free_bytes: Option<u64>,
+    // Images migrated to the imagestore.
+    pub image_count: usize,
+}
+
+impl ImagestoreInfo {
+    // Why parallax can't use the imagestore, None when it can.
+    pub fn problem(&self) -> Option<String> {
+        if self.path.is_empty() {
+            return Some(String::from("no imagestore is configured"));
+        }
+        if ! self.exists {
+            return Some(format!("imagestore {} doesn't exist", self.path));
+        }
+        if ! self.writable {
+            return Some(format!("imagestore {} isn't writable", self.path));
+        }
+        if self.free_bytes == Some(0) {
+            return Some(format!("imagestore {} has no space left", self.path));
+        }
+        None
+    }
+
+    pub fn is_usable(&self) -> bool {
+        self.problem().is_none()
+    }
+}
+
+pub fn imagestore_status(config: &Config) -> ImagestoreInfo {
+    imagestore_path_status(&config.parallax_imagestore)
+}
+
+pub fn imagestore_path_status(imagestore: &str) -> ImagestoreInfo {
+    let mut info = ImagestoreInfo {
+        path: String::from(imagestore),
+        ..Default::default()
+    };
+    let path = Path::new(imagestore);
+    if imagestore.is_empty() || ! path.is_dir() {
+        return info;
+    }
+    info.exists = true;
+    info.writable = is_writable_dir(path);
+    info.free_bytes = free_bytes(path);
+
+    // Images are stored like the ones of podman, one directory per image
+    info.image_count = match fs::read_dir(path.join("overlay-images")) {
+        Ok(entries) => entries.filter_map(Result::ok).filter(|e| e.path().is_dir()).count(),
+        Err(_) => 0,
+    };
+    info
+}
+
+#[cfg(unix)]
+fn is_writable_dir(path: &Path) -> bool {
+    use nix::unistd::{AccessFlags, access};
+    access(path, AccessFlags::W_OK | AccessFlags::X_OK).is_ok()
+}
+
+#[cfg(not(unix))]
+fn is_writable_dir(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|m| ! m.permissions().readonly())
+}
+
+#[cfg(unix)]
+fn free_bytes(path: &Path) -> Option<u64> {
+    let s = nix::sys::statvfs::statvfs(path).ok()?;
+    Some(s.blocks_available() as u64 * s.fragment_size() as u64)
+}
+
+#[cfg(not(unix))]
+fn free_bytes(_path: &Path) -> Option<u64> {
+    None
+}
+
+// Fail when parallax is enabled, the imagestore being set by the site or by
+// the EDF, and the imagestore is unusable.
+pub(crate) fn check_imagestore(imagestore: &str) -> SarusResult<()> {
+    if imagestore.is_empty() {
+        return Ok(());
+    }
+    let info = imagestore_path_status(imagestore);
+    match info.problem() {
+        None => Ok(()),
+        Some(p) => Err(SarusError {
+            kind: SarusErrorKind::ImagestoreUnusable { path: info.path },
+            file_path: None,
+            msg: format!("{p}, images can't be used with parallax"),
+            location: None,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RenderOptions, render_from_str_with_options};
+
+    #[test]
+    fn imagestore_info() {
+        let dir = std::env::temp_dir().join(format!("raster-imagestore-{}", std::process::id()));
+        fs::create_dir_all(dir.join("overlay-images/abc")).unwrap();
+        fs::write(dir.join("overlay-images/images.json"), "[]").unwrap();
+        let config = Config {
+            parallax_imagestore: dir.to_string_lossy().to_string(),
+            ..Default::default()
+        };
+        let info = imagestore_status(&config);
+        assert!(info.exists && info.writable && info.image_count == 1);
+        assert!(info.is_usable() && info.free_bytes.is_some());
+
+        let missing = imagestore_path_status("/nonexistent/imagestore");
+        assert!(! missing.exists && missing.problem().unwrap().contains("doesn't exist"));
+        assert!(! imagestore_path_status("").is_usable());
+
+        // The imagestore of the EDF overrides the one of the site
+        let opts = RenderOptions::new().skip_validation(true).check_imagestore(config.parallax_imagestore.clone());
+        let content = String::from("image = \"ubuntu\"\n");
+        assert!(render_from_str_with_options(content, "socket", &opts).is_ok());
+        let content = String::from("image = \"ubuntu\"\n[parallax]\nimagestore = \"/nonexistent/is\"\n");
+        let err = render_from_str_with_options(content, "socket", &opts).err().unwrap();
+        assert!(err.kind == SarusErrorKind::ImagestoreUnusable { path: String::from("/nonexistent/is") });
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
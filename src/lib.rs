@@ -1,61 +1,252 @@
 use derivative::Derivative;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
 use std::ffi::OsStr;
-use std::path::Path;
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
 use toml::Value;
 use toml::map::Map;
 
-use crate::common::{expand_vars_hashmap, expand_vars_vec};
-use crate::error::{SarusError, SarusResult};
-use crate::mount::{SarusMounts, sarus_mounts_from_strings};
-
+use crate::annotations::{SARUS_ANNOTATION_PREFIX, SARUS_SETTINGS};
+use crate::common::{
+    ErrorSink, dedup_entries, expand_vars_hashmap_with_options, expand_vars_string_with_options,
+    expand_vars_vec_with_options,
+};
+use crate::conditions::resolve_conditions;
+use crate::device::{Device, devices_from_strings};
+use crate::envfile::{env_file_path, env_file_read};
+use crate::error::{Location, SarusError, SarusErrorKind, SarusErrorList, SarusResult};
+use crate::mount::{MountEntry, SarusMounts, sarus_mounts_from_located_entries, verify_mount_sources};
+use crate::provenance::{list_item_locations, location_of};
+use crate::registry::check_image_digest;
+use crate::secrets::{glob_match, redact_env};
+use crate::validation::check_unknown_keys;
+use crate::validator::Schema;
+
+pub mod aliases;
+pub mod annotations;
+pub mod binaries;
+#[cfg(unix)]
+pub mod cache;
 pub mod common;
+mod conditions;
 pub mod config;
+pub mod device;
+pub mod diff;
+pub mod edit;
+pub mod engine;
+mod envfile;
 pub mod error;
+pub mod explain;
+#[cfg(feature = "arbitrary")]
+pub mod fuzz;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod git;
+pub mod gpu;
 pub mod hooks;
+pub mod image;
 pub mod imagestore;
+pub mod limits;
+pub mod lint;
+pub mod load;
+pub mod migrate;
 pub mod mount;
-
-pub use crate::common::expand_vars_string;
-pub use crate::config::{Config, VarExpand, load_config, load_config_path, update_config_by_user};
-pub use crate::hooks::{hook_run, ExecutedCommand};
-pub use crate::imagestore::{imagestore_keepalive};
+pub mod mpi;
+#[cfg(feature = "tokio")]
+pub mod nonblocking;
+pub mod oci;
+pub mod options;
+pub mod policy;
+pub mod provenance;
+pub mod registry;
+pub mod remote;
+pub mod sarif;
+pub mod search;
+pub mod secrets;
+pub mod skeleton;
+pub mod template;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod validation;
+pub mod validator;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+// Supported EDF file extensions, in lookup order.
+pub(crate) const EDF_EXTENSIONS: [&str; 3] = ["toml", "yaml", "yml"];
+
+pub use crate::aliases::{Deprecation, EDF_KEY_ALIASES, KeyAlias};
+pub use crate::annotations::{AnnotationProcessor, AnnotationProcessors, MalformedAnnotation, SarusAnnotations};
+pub use crate::binaries::{BinaryPaths, check_binaries};
+#[cfg(unix)]
+pub use crate::cache::RenderCache;
+pub use crate::common::{
+    ExpandOptions, ExpansionAudit, ExpansionBackend, ExpansionRecord, MergePolicy, VarSource, expand_tilde,
+    expand_vars_string, expand_vars_string_with_backend,
+};
+pub use crate::config::{
+    CONFIG_PATH, Config, ConfigOrigin, ConfigProvenance, ConfigSelector, ConfigSetting, OverrideRecord,
+    ParallaxSettings, PodmanSettings, VarExpand, load_config, load_config_path, load_config_path_lenient,
+    load_config_path_with_provenance, load_config_path_with_selector, load_config_with_provenance, load_site_config,
+    update_config_by_user, update_config_by_user_with_provenance, update_config_by_user_with_warnings,
+    user_config_dir, validate_config_dir,
+};
+pub use crate::diff::{EdfDiff, diff};
+pub use crate::edit::EdfDocument;
+pub use crate::engine::{Capabilities, Engine, engine_by_name, select_engine};
+pub use crate::explain::{RenderTrace, TraceStep, explain};
+pub use crate::git::GitRef;
+pub use crate::gpu::{GpuPaths, GpuProbe, GpuVendor};
+pub use crate::hooks::{hook_run, ExecutedCommand, HookDefinition, HookSpec};
+pub use crate::image::{ImageRef, ImageSource, image_source};
+pub use crate::imagestore::{ImagestoreInfo, imagestore_keepalive, imagestore_status};
+pub use crate::limits::{Limits, Ulimit};
+pub use crate::lint::{LintReport, Severity, lint, lint_with_options};
+pub use crate::load::{LoadLimits, SystemPathPolicy};
+pub use crate::migrate::{MigrateOptions, MigratedDocument, MigrationChange, migrate, migrate_str, migrate_with_options};
+pub use crate::mpi::MpiTemplate;
+#[cfg(feature = "tokio")]
+pub use crate::nonblocking::{load_async, render_async, render_file_async, render_from_str_async, validate_async};
+pub use crate::options::RenderOptions;
+pub use crate::policy::{Policy, load_site_policy};
+pub use crate::provenance::{Origin, Provenance};
+pub use crate::registry::RegistryRewrite;
+pub use crate::remote::{RemoteOptions, RemoteRef};
+pub use crate::sarif::{lint_to_sarif, validation_to_sarif};
+pub use crate::search::{EnvironmentInfo, SearchIndex, expand_search_path, list_environments};
+pub use crate::skeleton::SkeletonKind;
+pub use crate::template::{builtin_template_vars, expand_templates};
+pub use crate::validation::{Diagnostic, ValidationReport, validate_str_with_report, validate_with_report};
+pub use crate::validator::Validator;
 
 #[allow(dead_code)]
 #[derive(Derivative, Serialize, Deserialize, Clone, Default)]
 pub struct RawEDF {
-    annotations: Option<Annotations>,
-    base_environment: Option<BaseEnvironment>,
-    devices: Option<Vec<String>>,
-    entrypoint: Option<bool>,
-    env: Option<HashMap<String, String>>,
-    image: Option<String>,
-    mounts: Option<Vec<String>>,
-    workdir: Option<String>,
-    writable: Option<bool>,
+    pub annotations: Option<Annotations>,
+    // Entries of the base environments dropped by the file, see
+    // remove_entries().
+    pub annotations_remove: Option<Vec<String>>,
+    pub auth_file: Option<String>,
+    pub base_environment: Option<BaseEnvironment>,
+    pub description: Option<String>,
+    pub devices: Option<Vec<String>>,
+    pub devices_remove: Option<Vec<String>>,
+    pub edf_version: Option<u64>,
+    pub engine: Option<String>,
+    pub entrypoint: Option<bool>,
+    pub env: Option<HashMap<String, String>>,
+    pub env_file: Option<BaseEnvironment>,
+    pub env_passthrough: Option<Vec<String>>,
+    pub env_remove: Option<Vec<String>>,
+    pub gpu: Option<bool>,
+    pub group: Option<String>,
+    pub hooks: Option<HashMap<String, bool>>,
+    pub image: Option<String>,
+    pub include: Option<BaseEnvironment>,
+    pub labels: Option<HashMap<String, String>>,
+    pub limits: Option<Limits>,
+    pub maintainer: Option<String>,
+    pub mounts: Option<Vec<MountEntry>>,
+    pub mounts_remove: Option<Vec<String>>,
+    pub mpi: Option<String>,
+    pub network: Option<String>,
+    pub parallax: Option<ParallaxSettings>,
+    pub podman: Option<PodmanSettings>,
+    pub pull_policy: Option<String>,
+    pub tags: Option<Vec<String>>,
+    // Fields of the base environments reset to their default, and
+    // com.sarus.* settings dropped, e.g. ["workdir", "perfmon"].
+    pub unset: Option<Vec<String>>,
+    pub user: Option<String>,
+    pub userns: Option<String>,
+    pub workdir: Option<String>,
+    pub writable: Option<bool>,
+    pub writable_overlay_path: Option<String>,
+    pub writable_overlay_size: Option<String>,
+    // Where the mount entries were set, see list_item_locations().
+    #[serde(skip)]
+    pub mount_locations: Vec<(String, String, Location)>,
 }
 
 #[allow(dead_code)]
 #[derive(Derivative, Serialize, Deserialize, Clone)]
 pub struct EDF {
     #[serde(default = "get_default_annotations")]
-    pub annotations: HashMap<String, String>,
+    pub annotations: BTreeMap<String, String>,
+    // Credentials of the registry used to pull the image, the configured
+    // ones when None.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_file: Option<String>,
+    // Metadata documenting the environment, not passed to the engine.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
     #[serde(default = "get_default_devices")]
     pub devices: Vec<String>,
+    // Container engine to launch the EDF with, the configured one when None.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub engine: Option<String>,
     #[serde(default = "get_default_entrypoint")]
     pub entrypoint: bool,
     #[serde(default = "get_default_env")]
-    pub env: HashMap<String, String>,
+    pub env: BTreeMap<String, String>,
+    // Inject the GPUs found on the node.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub gpu: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    // OCI hooks requested by the EDF, resolved against the site catalog.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hooks: Vec<HookSpec>,
     pub image: String,
+    // Container labels, for accounting and bookkeeping.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Limits::is_empty")]
+    pub limits: Limits,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maintainer: Option<String>,
     #[serde(default = "get_default_mounts")]
     pub mounts: SarusMounts,
+    // Name of the MPI template of the configuration to expand.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mpi: Option<String>,
+    // Network mode of the container, the engine default when None.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<String>,
+    // When the engine pulls the image: "always", "missing" or "never", the
+    // engine default when None.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pull_policy: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    // User of the process of the container, as NAME|UID[:GROUP|GID].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    // User namespace mode, e.g. "keep-id" or "keep-id:uid=1000,gid=1000".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub userns: Option<String>,
     #[serde(default = "get_default_workdir")]
     pub workdir: String,
     #[serde(default = "get_default_writable")]
     pub writable: bool,
+    // Directory holding the overlay upper dir of a writable container.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub writable_overlay_path: Option<String>,
+    // Size limit of the overlay, e.g. "10G".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub writable_overlay_size: Option<String>,
+    // Patterns of the env variables whose values are redacted when the
+    // EDF is printed or serialized.
+    #[serde(skip)]
+    pub sensitive_env: Vec<String>,
+    // Problems found while rendering which didn't fail it, e.g. shadowed
+    // mounts.
+    #[serde(skip)]
+    pub warnings: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -75,6 +266,8 @@ pub enum Annotations {
 impl RawEDF {
     // Overwrite fields and tables with the other raw EDF.
     fn extend(&mut self, i: RawEDF) {
+        self.remove_entries(&i);
+
         if i.annotations.is_some() {
             let i_anno = i.annotations.unwrap();
 
@@ -106,6 +299,18 @@ impl RawEDF {
                 self.env = i.env;
             }
         }
+        if let Some(l) = i.labels {
+            self.labels.get_or_insert_default().extend(l);
+        }
+        if let Some(p) = i.env_passthrough {
+            let patterns = self.env_passthrough.get_or_insert_default();
+            for x in p {
+                if !patterns.contains(&x) {
+                    patterns.push(x);
+                }
+            }
+        }
+        self.mount_locations.extend(i.mount_locations);
         if i.mounts.is_some() {
             if self.mounts.is_some() {
                 let i_mounts = i.mounts.unwrap();
@@ -116,40 +321,352 @@ impl RawEDF {
             }
         }
 
+        if i.description.is_some() {
+            self.description = i.description;
+        }
+        if i.edf_version.is_some() {
+            self.edf_version = i.edf_version;
+        }
+        if i.engine.is_some() {
+            self.engine = i.engine;
+        }
         if i.entrypoint.is_some() {
             self.entrypoint = i.entrypoint;
         }
+        if i.gpu.is_some() {
+            self.gpu = i.gpu;
+        }
+        if i.group.is_some() {
+            self.group = i.group;
+        }
+        if let Some(h) = i.hooks {
+            self.hooks.get_or_insert_default().extend(h);
+        }
         if i.image.is_some() {
             self.image = i.image;
         }
+        if let Some(l) = i.limits {
+            self.limits.get_or_insert_default().extend(l);
+        }
+        if i.maintainer.is_some() {
+            self.maintainer = i.maintainer;
+        }
+        if i.mpi.is_some() {
+            self.mpi = i.mpi;
+        }
+        if i.network.is_some() {
+            self.network = i.network;
+        }
+        if i.auth_file.is_some() {
+            self.auth_file = i.auth_file;
+        }
+        if i.pull_policy.is_some() {
+            self.pull_policy = i.pull_policy;
+        }
+        if i.tags.is_some() {
+            self.tags = i.tags;
+        }
+        if i.user.is_some() {
+            self.user = i.user;
+        }
+        if i.userns.is_some() {
+            self.userns = i.userns;
+        }
         if i.workdir.is_some() {
             self.workdir = i.workdir;
         }
         if i.writable.is_some() {
             self.writable = i.writable;
         }
+        if i.writable_overlay_path.is_some() {
+            self.writable_overlay_path = i.writable_overlay_path;
+        }
+        if i.writable_overlay_size.is_some() {
+            self.writable_overlay_size = i.writable_overlay_size;
+        }
     }
 }
 
-impl EDF {
-    pub fn to_toml_string(&self) -> SarusResult<String> {
+type FieldReset = fn(&mut RawEDF);
+
+// Fields of an EDF a file can reset with unset, and how. Fields which
+// can't, see NOT_UNSETTABLE in the tests.
+const UNSET_FIELDS: [(&str, FieldReset); 26] = [
+    ("annotations", |r| r.annotations = None),
+    ("auth_file", |r| r.auth_file = None),
+    ("description", |r| r.description = None),
+    ("devices", |r| r.devices = None),
+    ("engine", |r| r.engine = None),
+    ("entrypoint", |r| r.entrypoint = None),
+    ("env", |r| r.env = None),
+    ("env_passthrough", |r| r.env_passthrough = None),
+    ("gpu", |r| r.gpu = None),
+    ("group", |r| r.group = None),
+    ("hooks", |r| r.hooks = None),
+    ("image", |r| r.image = None),
+    ("labels", |r| r.labels = None),
+    ("limits", |r| r.limits = None),
+    ("maintainer", |r| r.maintainer = None),
+    ("mounts", |r| {
+        r.mounts = None;
+        r.mount_locations.clear();
+    }),
+    ("mpi", |r| r.mpi = None),
+    ("network", |r| r.network = None),
+    ("pull_policy", |r| r.pull_policy = None),
+    ("tags", |r| r.tags = None),
+    ("user", |r| r.user = None),
+    ("userns", |r| r.userns = None),
+    ("workdir", |r| r.workdir = None),
+    ("writable", |r| r.writable = None),
+    ("writable_overlay_path", |r| r.writable_overlay_path = None),
+    ("writable_overlay_size", |r| r.writable_overlay_size = None),
+];
+
+// Names of unset are fields of UNSET_FIELDS or com.sarus.* settings.
+fn is_unsettable(name: &str) -> bool {
+    UNSET_FIELDS.iter().any(|(n, _)| *n == name) || SARUS_SETTINGS.contains(&name)
+}
 
-        let toml = match toml::to_string(&self) {
-            Ok(t) => t,
-            Err(e) => {
-                return Err(SarusError {
-                    code: 24,
-                    file_path: None,
-                    msg: String::from(format!("error serializing to toml - {}", e)),
-                });
+fn check_unset(r: &RawEDF, file_path: &str) -> SarusResult<()> {
+    let names = match &r.unset {
+        Some(u) => u,
+        None => return Ok(()),
+    };
+    match names.iter().find(|n| !is_unsettable(n)) {
+        Some(n) => Err(SarusError {
+            kind: SarusErrorKind::UnknownKey { key: n.clone() },
+            file_path: Some(String::from(file_path)),
+            msg: format!("cannot unset \"{n}\", expected a field of the EDF or a {SARUS_ANNOTATION_PREFIX}* setting"),
+            location: None,
+        }),
+        None => Ok(()),
+    }
+}
+
+impl RawEDF {
+    // Reset a field to its default, or drop the com.sarus.* annotation of a
+    // setting. Other names are refused by check_unset() when parsing.
+    fn unset_field(&mut self, name: &str) {
+        if let Some((_, reset)) = UNSET_FIELDS.iter().find(|(n, _)| *n == name) {
+            reset(self);
+        } else if SARUS_SETTINGS.contains(&name)
+            && let Some(a) = self.annotations.as_mut()
+        {
+            let mut h = annotations_as_hashmap(a.clone());
+            h.remove(&format!("{SARUS_ANNOTATION_PREFIX}{name}"));
+            *a = Annotations::TypeHashMap(h);
+        }
+    }
+
+    // Apply the unset and _remove lists of the other raw EDF, which is
+    // layered on top of this one: annotations and env are removed by key,
+    // devices as written and mounts as source:target or by target.
+    fn remove_entries(&mut self, i: &RawEDF) {
+        if let Some(u) = &i.unset {
+            for n in u.iter() {
+                self.unset_field(n);
             }
+        }
+        if let Some(r) = &i.annotations_remove
+            && let Some(a) = self.annotations.take()
+        {
+            let mut h = annotations_as_hashmap(a);
+            h.retain(|k, _| !r.contains(k));
+            self.annotations = Some(Annotations::TypeHashMap(h));
+        }
+        if let Some(r) = &i.devices_remove
+            && let Some(d) = self.devices.as_mut()
+        {
+            d.retain(|x| !r.contains(x));
+        }
+        if let Some(r) = &i.env_remove
+            && let Some(e) = self.env.as_mut()
+        {
+            e.retain(|k, _| !r.contains(k));
+        }
+        if let Some(r) = &i.mounts_remove
+            && let Some(m) = self.mounts.as_mut()
+        {
+            m.retain(|x| !r.iter().any(|s| x.removed_by(s)));
+        }
+    }
+
+    // Removals only apply to the bases of the file setting them.
+    fn clear_removals(&mut self) {
+        self.unset = None;
+        self.annotations_remove = None;
+        self.devices_remove = None;
+        self.env_remove = None;
+        self.mounts_remove = None;
+    }
+
+    // Layer other on top of this EDF, with the precedence of a file over its
+    // base environments. base_environment, include and env_file of other are
+    // not followed.
+    pub fn merge(&mut self, other: RawEDF) {
+        let mut other = other;
+        other.fold_engine_tables();
+        self.extend(other);
+    }
+
+    // Entries of the [parallax] and [podman] tables are the settings of the
+    // com.sarus.parallax_* and com.sarus.podman_* annotations, which they
+    // override within the same file.
+    fn fold_engine_tables(&mut self) {
+        let mut entries: Vec<(&str, Option<String>)> = vec![];
+        if let Some(p) = self.parallax.take() {
+            entries.extend([
+                ("parallax_imagestore", p.imagestore),
+                ("parallax_imagestore_keepalive", p.imagestore_keepalive.map(|b| b.to_string())),
+                ("parallax_mount_program", p.mount_program),
+                ("parallax_path", p.path),
+                ("parallax_mp_logfile", p.mp_logfile),
+                ("parallax_mp_squashfuse_path", p.mp_squashfuse_path),
+            ]);
+        }
+        if let Some(p) = self.podman.take() {
+            entries.extend([
+                ("podman_module", p.module),
+                ("podman_path", p.path),
+                ("podman_tmp_path", p.tmp_path),
+            ]);
+        }
+
+        let entries: Vec<(&str, String)> = entries.into_iter().filter_map(|(k, v)| Some((k, v?))).collect();
+        if entries.is_empty() {
+            return;
+        }
+        let mut h = match self.annotations.take() {
+            Some(a) => annotations_as_hashmap(a),
+            None => HashMap::new(),
         };
+        for (k, v) in entries {
+            h.insert(format!("com.sarus.{k}"), v);
+        }
+        self.annotations = Some(Annotations::TypeHashMap(h));
+    }
+
+    pub fn to_toml_string(&self) -> SarusResult<String> {
+        to_canonical_toml(self)
+    }
+}
+
+impl EDF {
+    pub fn to_toml_string(&self) -> SarusResult<String> {
+        to_canonical_toml(&self.redacted())
+    }
+
+    pub fn to_json(&self) -> SarusResult<String> {
+        to_canonical_json(&self.redacted())
+    }
+
+    // Copy of the EDF where the values of sensitive variables are replaced.
+    pub fn redacted(&self) -> EDF {
+        let mut e = self.clone();
+        e.env = redact_env(&self.env, &self.sensitive_env);
+        e
+    }
+
+    // Typed view of the com.sarus.* annotations.
+    pub fn sarus_annotations(&self) -> SarusAnnotations {
+        SarusAnnotations::parse(&self.annotations)
+    }
+
+    // Typed view of the devices, telling device paths and CDI names apart.
+    pub fn parsed_devices(&self) -> SarusResult<Vec<Device>> {
+        devices_from_strings(self.devices.clone())
+    }
+}
+
+impl std::fmt::Debug for EDF {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("EDF")
+            .field("annotations", &self.annotations)
+            .field("auth_file", &self.auth_file)
+            .field("description", &self.description)
+            .field("devices", &self.devices)
+            .field("engine", &self.engine)
+            .field("entrypoint", &self.entrypoint)
+            .field("env", &redact_env(&self.env, &self.sensitive_env))
+            .field("gpu", &self.gpu)
+            .field("group", &self.group)
+            .field("hooks", &self.hooks)
+            .field("image", &self.image)
+            .field("labels", &self.labels)
+            .field("limits", &self.limits)
+            .field("maintainer", &self.maintainer)
+            .field("mounts", &self.mounts)
+            .field("mpi", &self.mpi)
+            .field("network", &self.network)
+            .field("pull_policy", &self.pull_policy)
+            .field("tags", &self.tags)
+            .field("user", &self.user)
+            .field("userns", &self.userns)
+            .field("workdir", &self.workdir)
+            .field("writable", &self.writable)
+            .field("writable_overlay_path", &self.writable_overlay_path)
+            .field("writable_overlay_size", &self.writable_overlay_size)
+            .finish()
+    }
+}
+
+// Serialize through a toml::Value, whose tables keep keys sorted, so that
+// the output doesn't depend on HashMap iteration order.
+fn to_canonical_toml<T: Serialize>(v: &T) -> SarusResult<String> {
+    let value = match toml::Value::try_from(v) {
+        Ok(t) => t,
+        Err(e) => {
+            return Err(SarusError {
+                kind: SarusErrorKind::Serialization,
+                file_path: None,
+                msg: format!("error serializing to toml - {}", e),
+                location: None,
+            });
+        }
+    };
+
+    let toml = match toml::to_string(&value) {
+        Ok(t) => t,
+        Err(e) => {
+            return Err(SarusError {
+                kind: SarusErrorKind::Serialization,
+                file_path: None,
+                msg: format!("error serializing to toml - {}", e),
+                location: None,
+            });
+        }
+    };
+
+    Ok(toml)
+}
+
+// serde_json objects are sorted maps, the same canonical order as to_toml_string().
+pub(crate) fn to_canonical_json<T: Serialize>(v: &T) -> SarusResult<String> {
+    let value = match serde_json::to_value(v) {
+        Ok(t) => t,
+        Err(e) => {
+            return Err(SarusError {
+                kind: SarusErrorKind::Serialization,
+                file_path: None,
+                msg: format!("error serializing to json - {}", e),
+                location: None,
+            });
+        }
+    };
 
-        Ok(toml)
+    match serde_json::to_string_pretty(&value) {
+        Ok(j) => Ok(j),
+        Err(e) => Err(SarusError {
+            kind: SarusErrorKind::Serialization,
+            file_path: None,
+            msg: format!("error serializing to json - {}", e),
+            location: None,
+        }),
     }
 }
 
-fn annotations_as_hashmap(a: Annotations) -> HashMap<String, String> {
+pub(crate) fn annotations_as_hashmap(a: Annotations) -> HashMap<String, String> {
     let r = match a {
         Annotations::TypeHashMap(h) => h,
         Annotations::TypeMap(m) => map2hashmap(m),
@@ -180,8 +697,8 @@ fn map2hashmap(m: Map<String, Value>) -> HashMap<String, String> {
     r
 }
 
-fn get_default_annotations() -> HashMap<String, String> {
-    return HashMap::from([]);
+fn get_default_annotations() -> BTreeMap<String, String> {
+    return BTreeMap::new();
 }
 
 fn get_default_devices() -> Vec<String> {
@@ -192,8 +709,8 @@ fn get_default_entrypoint() -> bool {
     return true;
 }
 
-fn get_default_env() -> HashMap<String, String> {
-    return HashMap::from([]);
+fn get_default_env() -> BTreeMap<String, String> {
+    return BTreeMap::new();
 }
 
 fn get_default_mounts() -> SarusMounts {
@@ -207,38 +724,67 @@ fn get_default_writable() -> bool {
     return true;
 }
 
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
 fn edf_from_raw(r: RawEDF, uenv: &Option<HashMap<String, String>>) -> SarusResult<EDF> {
+    edf_from_raw_with_options(r, uenv, &ExpandOptions::default())
+}
+
+fn edf_from_raw_with_options(
+    r: RawEDF,
+    uenv: &Option<HashMap<String, String>>,
+    xopts: &ExpandOptions,
+) -> SarusResult<EDF> {
+    let (mounts, warnings) = match r.mounts {
+        Some(s) => sarus_mounts_from_located_entries(s, &r.mount_locations, uenv, xopts)?,
+        None => (get_default_mounts(), vec![]),
+    };
     Ok(EDF {
         annotations: match r.annotations {
-            Some(s) => annotations_as_hashmap(s),
+            Some(s) => annotations_as_hashmap(s).into_iter().collect(),
             None => get_default_annotations(),
         },
+        auth_file: r.auth_file,
+        description: r.description,
         devices: match r.devices {
             Some(s) => s,
             None => get_default_devices(),
         },
+        engine: r.engine,
         entrypoint: match r.entrypoint {
             Some(s) => s,
             None => get_default_entrypoint(),
         },
         env: match r.env {
-            Some(s) => s,
-            None => get_default_env(),
+            Some(s) => passthrough_env(&r.env_passthrough, uenv, xopts).into_iter().chain(s).collect(),
+            None => passthrough_env(&r.env_passthrough, uenv, xopts),
         },
+        gpu: r.gpu.unwrap_or_default(),
+        group: r.group,
+        hooks: vec![],
         image: match r.image {
             Some(s) => s,
             None => {
                 return Err(SarusError {
-                    code: 7,
+                    kind: SarusErrorKind::MissingImage,
                     file_path: None,
                     msg: String::from("missing image specification"),
+                    location: None,
                 });
             }
         },
-        mounts: match r.mounts {
-            Some(s) => sarus_mounts_from_strings(s, uenv)?,
-            None => get_default_mounts(),
-        },
+        labels: r.labels.unwrap_or_default().into_iter().collect(),
+        limits: r.limits.unwrap_or_default(),
+        maintainer: r.maintainer,
+        mounts,
+        mpi: r.mpi,
+        network: r.network,
+        pull_policy: r.pull_policy,
+        tags: r.tags.unwrap_or_default(),
+        user: r.user,
+        userns: r.userns,
         workdir: match r.workdir {
             Some(s) => s,
             None => get_default_workdir(),
@@ -247,10 +793,14 @@ fn edf_from_raw(r: RawEDF, uenv: &Option<HashMap<String, String>>) -> SarusResul
             Some(s) => s,
             None => get_default_writable(),
         },
+        writable_overlay_path: r.writable_overlay_path,
+        writable_overlay_size: r.writable_overlay_size,
+        sensitive_env: vec![],
+        warnings,
     })
 }
 
-fn load(file_path: &str) -> Result<String, Box<dyn Error>> {
+pub(crate) fn load(file_path: &str) -> Result<String, Box<dyn Error>> {
     // SD-67022 - prevent reading wrong file
     let fp = Path::new(file_path);
 
@@ -263,16 +813,17 @@ fn load(file_path: &str) -> Result<String, Box<dyn Error>> {
     Ok(outstr)
 }
 
-pub(crate) fn check_file_path_extension(file_path: &str, ext: &str) -> SarusResult<()> {
+pub(crate) fn check_file_path_extension(file_path: &str, exts: &[&str]) -> SarusResult<()> {
     let fp = Path::new(file_path);
 
     let fname = match fp.file_name().and_then(OsStr::to_str) {
         Some(name) => name,
         None => {
             return Err(SarusError {
-                code: 20,
+                kind: SarusErrorKind::FileName,
                 file_path: Some(file_path.to_string()),
                 msg: String::from("Cannot extract file name"),
+                location: None,
             });
         }
     };
@@ -281,80 +832,81 @@ pub(crate) fn check_file_path_extension(file_path: &str, ext: &str) -> SarusResu
         Some(x) => x,
         None => {
             return Err(SarusError {
-                code: 21,
+                kind: SarusErrorKind::FileExtension,
                 file_path: Some(file_path.to_string()),
                 msg: String::from("Cannot extract file extension"),
+                location: None,
             });
         }
     };
 
-    if cur_ext != ext {
+    if !exts.contains(&cur_ext) {
+        let expected = exts
+            .iter()
+            .map(|x| format!(".{x}"))
+            .collect::<Vec<_>>()
+            .join(" or ");
         return Err(SarusError {
-            code: 22,
+            kind: SarusErrorKind::WrongExtension { expected: expected.clone() },
             file_path: Some(file_path.to_string()),
-            msg: format!("File name {fname} doesn't end with .{ext}"),
+            msg: format!("File name {fname} doesn't end with {expected}"),
+            location: None,
         });
     }
     Ok(())
 }
 
-pub fn validate(path: String) -> SarusResult<()> {
-    // Embedding schema file
-    let schema_content = include_str!("schema/edf.json");
-
-    check_file_path_extension(&path, "toml")?;
-
-    validate_file(path, schema_content)
-}
-
-pub(crate) fn validate_file(path: String, schema_content: &str) -> SarusResult<()> {
-    let path_str = path.as_str();
+// Embedded EDF schemas, by edf_version.
+pub(crate) const EDF_SCHEMAS: [(u64, &str); 2] = [
+    (1, include_str!("schema/edf-v1.json")),
+    (2, include_str!("schema/edf-v2.json")),
+];
 
-    let schema: serde_json::Value = match serde_json::from_str(&schema_content) {
-        Ok(c) => c,
-        Err(_) => {
-            return Err(SarusError {
-                code: 0,
-                file_path: None,
-                msg: String::from("Failed to parse schema file"),
-            });
-        }
-    };
+// Version assumed for files without an edf_version field.
+const EDF_DEFAULT_VERSION: u64 = 1;
 
-    let validator = match jsonschema::options().build(&schema) {
-        Ok(v) => v,
-        Err(error) => {
-            return Err(SarusError {
-                code: 1,
-                file_path: None,
-                msg: String::from(format!("Schema is invalid.\n{error}")),
-            });
-        }
+// The edf_version declared by the document, checked to be supported.
+pub(crate) fn edf_version(doc: &serde_json::Value, file_path: &str) -> SarusResult<u64> {
+    let version = match doc.get("edf_version") {
+        Some(v) => v.clone(),
+        None => serde_json::Value::from(EDF_DEFAULT_VERSION),
     };
 
-    let mut has_errors = false;
-
-    let toml_in = toml_read(path_str)?;
-    let mut errors = validator.iter_errors(&toml_in);
-    let mut emsg = String::from("");
+    let supported = version
+        .as_u64()
+        .filter(|v| EDF_SCHEMAS.iter().any(|(n, _)| n == v));
 
-    if let Some(first) = errors.next() {
-        has_errors = true;
-        emsg = format!("Errors:\n1. {first}");
-        for (i, error) in errors.enumerate() {
-            emsg = String::from(format!("{emsg}\n{}. {}", (i + 2), error));
+    match supported {
+        Some(v) => Ok(v),
+        None => {
+            let supported: Vec<String> = EDF_SCHEMAS.iter().map(|(n, _)| n.to_string()).collect();
+            Err(SarusError {
+                kind: SarusErrorKind::UnsupportedEdfVersion { version: version.to_string() },
+                file_path: Some(String::from(file_path)),
+                msg: format!(
+                    "unsupported edf_version {version}, supported versions are {}",
+                    supported.join(", ")
+                ),
+                location: None,
+            })
         }
     }
+}
 
-    if has_errors {
-        return Err(SarusError {
-            code: 4,
-            file_path: Some(String::from(path_str)),
-            msg: String::from(format!("{}", emsg)),
-        });
-    } else {
-        return Ok(());
-    }
+// Pick the schema matching the edf_version declared by the document.
+pub(crate) fn edf_schema(doc: &serde_json::Value, file_path: &str) -> SarusResult<&'static str> {
+    let version = edf_version(doc, file_path)?;
+    let (_, s) = EDF_SCHEMAS.iter().find(|(n, _)| *n == version).unwrap();
+    Ok(s)
+}
+
+pub fn validate(path: String) -> SarusResult<()> {
+    Validator::get()?.validate(path)
+}
+
+pub(crate) fn validate_file(path: String, schema: &Schema) -> SarusResult<()> {
+    let toml_in = document_read(&path)?;
+    schema.validate(&path, &toml_in)
 }
 
 pub fn get_search_paths() -> Vec<String> {
@@ -370,21 +922,21 @@ pub fn get_search_paths() -> Vec<String> {
 }
 
 pub fn get_sys_search_paths() -> Vec<String> {
-    let mut search_paths = vec![];
+    match load_config() {
+        Ok(c) => sys_search_paths(&c),
+        Err(_) => vec![],
+    }
+}
 
-    let config = match load_config() {
-        Ok(c) => c,
-        Err(_) => {
-            return search_paths;
-        }
-    };
+fn sys_search_paths(config: &Config) -> Vec<String> {
+    let mut search_paths = vec![];
 
-    let sys_search_path = config.edf_system_search_path;
+    let sys_search_path = &config.edf_system_search_path;
 
     if sys_search_path != "" {
         let paths = sys_search_path.split(":");
         for p in paths {
-            search_paths.push(String::from(p));
+            search_paths.extend(expand_search_path(p));
         }
     }
 
@@ -416,28 +968,94 @@ pub fn get_user_search_paths() -> Vec<String> {
     search_paths
 }
 
-fn resolve_env_path(
+// Join a name to the search path it's looked up in, . and .. being resolved
+// lexically. None when the name leaves the search path, e.g.
+// "team/../../other/thing".
+pub(crate) fn join_search_path(s: &str, name: &str) -> Option<String> {
+    let mut parts = vec![];
+    for c in name.split('/') {
+        match c {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            c => parts.push(c),
+        }
+    }
+    Some(format!("{s}/{}", parts.join("/")))
+}
+
+// Names of environments looked up in the search paths, e.g. pytorch/24.01:
+// letters, digits, '-', '_' and '.', with '/' between subdirectories.
+fn check_env_name(name: &str) -> SarusResult<()> {
+    static RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[A-Za-z0-9._-]+(/[A-Za-z0-9._-]+)*$").unwrap());
+    if RE.is_match(name) {
+        return Ok(());
+    }
+    Err(SarusError {
+        kind: SarusErrorKind::InvalidEnvironmentName { name: String::from(name) },
+        file_path: None,
+        msg: format!(
+            "invalid environment name \"{name}\", expected letters, digits, '-', '_' and '.', with '/' between subdirectories, or a path starting with ./ or /"
+        ),
+        location: None,
+    })
+}
+
+// The file of an EDF name or path. Names leaving the search paths are
+// refused when contain is set.
+pub(crate) fn resolve_env_path(
     env: String,
     sp: &Vec<String>,
     uenv: &Option<HashMap<String, String>>,
+    xopts: &ExpandOptions,
+    limits: &LoadLimits,
 ) -> SarusResult<String> {
     let mut retopt = None;
     let mut file_path;
 
-    let ee = expand_vars_string(env, uenv)?;
+    let ee = expand_vars_string_with_options(env, uenv, xopts)?;
 
     // it doesn't look like a file_path
-    if ![".", "/"].iter().any(|s| ee.starts_with(*s)) && !ee.ends_with(".toml") {
-        for s in sp.iter() {
-            file_path = format!("{s}/{ee}.toml");
-            if std::path::Path::new(&file_path).is_file() {
-                match std::fs::File::open(&file_path) {
-                    Ok(_) => {
-                        retopt = Some(file_path.clone());
-                        break;
-                    }
-                    Err(_) => continue,
-                };
+    if ![".", "/"].iter().any(|s| ee.starts_with(*s))
+        && !EDF_EXTENSIONS.iter().any(|x| ee.ends_with(&format!(".{x}")))
+    {
+        check_env_name(&ee)?;
+        'search: for s in sp.iter() {
+            let joined = match join_search_path(s, &ee) {
+                Some(j) => j,
+                None if limits.contain_names => {
+                    return Err(SarusError {
+                        kind: SarusErrorKind::FileOutOfSearchPath {
+                            path: ee.clone(),
+                            search_path: s.clone(),
+                        },
+                        file_path: None,
+                        msg: format!("environment \"{ee}\" leaves the search path {s}"),
+                        location: None,
+                    });
+                }
+                None => format!("{s}/{ee}"),
+            };
+            for x in EDF_EXTENSIONS.iter() {
+                file_path = format!("{joined}.{x}");
+                if std::path::Path::new(&file_path).is_file() {
+                    match std::fs::File::open(&file_path) {
+                        Ok(_) => {
+                            retopt = Some(file_path.clone());
+                            break 'search;
+                        }
+                        Err(_) => continue,
+                    };
+                }
+            }
+            // Logical names of the index of the directory
+            if let Some(index) = SearchIndex::read(s, sp, limits)?
+                && let Some(f) = index.lookup(s, &ee)
+                && std::path::Path::new(&f).is_file()
+            {
+                retopt = Some(f);
+                break 'search;
             }
         }
     } else {
@@ -461,14 +1079,78 @@ fn resolve_env_path(
                 .collect::<Vec<_>>()
                 .join(",");
             return Err(SarusError {
-                code: 6,
+                kind: SarusErrorKind::EnvironmentNotFound { name: ee.clone(), search_paths: sp.clone() },
                 file_path: None,
                 msg: String::from(format!("environment \"{ee}\" not found at {paths}")),
+                location: None,
             });
         }
     }
 }
 
+pub(crate) fn is_yaml_path(s: &str) -> bool {
+    s.ends_with(".yaml") || s.ends_with(".yml")
+}
+
+// Read a TOML or YAML document, the format being detected by extension.
+pub(crate) fn document_read<T>(s: &str) -> SarusResult<T>
+where
+    T: for<'a> Deserialize<'a>,
+{
+    if is_yaml_path(s) {
+        yaml_read(s)
+    } else {
+        toml_read(s)
+    }
+}
+
+// Parse a TOML or YAML document, the format being detected by the
+// extension of the path it comes from.
+pub(crate) fn document_parse<T>(content: &str, s: &str) -> SarusResult<T>
+where
+    T: for<'a> Deserialize<'a>,
+{
+    if is_yaml_path(s) {
+        yaml_parse(content, s)
+    } else {
+        toml_parse(content, s)
+    }
+}
+
+pub(crate) fn yaml_read<T>(s: &str) -> SarusResult<T>
+where
+    T: for<'a> Deserialize<'a>,
+{
+    let yaml_content = match load(s) {
+        Ok(c) => c,
+        Err(e) => {
+            return Err(SarusError {
+                kind: SarusErrorKind::FileRead,
+                file_path: Some(String::from(s)),
+                msg: format!("{}", e),
+                location: None,
+            });
+        }
+    };
+
+    yaml_parse(&yaml_content, s)
+}
+
+fn yaml_parse<T>(yaml_content: &str, s: &str) -> SarusResult<T>
+where
+    T: for<'a> Deserialize<'a>,
+{
+    match serde_yaml::from_str(yaml_content) {
+        Ok(v) => Ok(v),
+        Err(e) => Err(SarusError {
+            kind: SarusErrorKind::YamlParse,
+            file_path: Some(String::from(s)),
+            msg: format!("{}", e),
+            location: None,
+        }),
+    }
+}
+
 pub(crate) fn toml_read<T>(s: &str) -> SarusResult<T>
 where
     T: for<'a> Deserialize<'a>,
@@ -477,20 +1159,29 @@ where
         Ok(c) => c,
         Err(e) => {
             return Err(SarusError {
-                code: 2,
+                kind: SarusErrorKind::FileRead,
                 file_path: Some(String::from(s)),
                 msg: String::from(format!("{}", e)),
+                location: None,
             });
         }
     };
 
-    let toml_value = match toml::from_str(toml_content.as_str()) {
+    toml_parse(&toml_content, s)
+}
+
+fn toml_parse<T>(toml_content: &str, s: &str) -> SarusResult<T>
+where
+    T: for<'a> Deserialize<'a>,
+{
+    let toml_value = match toml::from_str(toml_content) {
         Ok(v) => v,
         Err(e) => {
             return Err(SarusError {
-                code: 3,
+                kind: SarusErrorKind::TomlParse,
                 file_path: Some(String::from(s)),
                 msg: String::from(format!("{}", e)),
+                location: e.span().map(|r| location_of(toml_content, r.start)),
             });
         }
     };
@@ -498,93 +1189,804 @@ where
     Ok(toml_value)
 }
 
-fn render_inner_loop(
+pub(crate) fn raw_from_document(doc: serde_json::Value, file_path: &str) -> SarusResult<RawEDF> {
+    match serde_json::from_value(doc) {
+        Ok(r) => Ok(r),
+        Err(e) => Err(SarusError {
+            kind: match is_yaml_path(file_path) {
+                true => SarusErrorKind::YamlParse,
+                false => SarusErrorKind::TomlParse,
+            },
+            file_path: Some(String::from(file_path)),
+            msg: format!("{}", e),
+            location: None,
+        }),
+    }
+}
+
+pub(crate) fn canonical_path(file_path: &str) -> PathBuf {
+    match std::fs::canonicalize(file_path) {
+        Ok(p) => p,
+        Err(_) => PathBuf::from(file_path),
+    }
+}
+
+// State of a render walking the bases: the files being rendered and the
+// bases already rendered.
+#[derive(Default)]
+pub(crate) struct RenderWalk {
+    chain: Vec<String>,
+    // Bases by their canonical path or URL and fragment flag, with the
+    // depth they were rendered at and the provenance of their files.
+    memo: HashMap<(String, bool), (usize, RawEDF, Option<Provenance>)>,
+}
+
+impl RenderWalk {
+    // Files currently being rendered, a match means a circular reference
+    fn check_cycle(&self, origin: &str) -> SarusResult<()> {
+        let canonical = canonical_path(origin);
+        if !self.chain.iter().any(|p| canonical_path(p) == canonical) {
+            return Ok(());
+        }
+        let mut cycle = self.chain.clone();
+        cycle.push(String::from(origin));
+        Err(SarusError {
+            kind: SarusErrorKind::BaseEnvironmentCycle { chain: cycle.clone() },
+            file_path: Some(String::from(origin)),
+            msg: format!("base_environment cycle detected: {}", cycle.join(" -> ")),
+            location: None,
+        })
+    }
+
+    // Bases shared by several files, e.g. the site base of every team one,
+    // are read and validated once per render. A base is reused at the depth
+    // it was rendered at or above only, the levels below it being within
+    // max_levels there.
+    fn reuse(&self, origin: &str, fragment: bool, prov: &mut Option<Provenance>) -> Option<RawEDF> {
+        let key = (canonical_path(origin).display().to_string(), fragment);
+        let (depth, raw, base_prov) = self.memo.get(&key)?;
+        if self.chain.len() > *depth {
+            return None;
+        }
+        if let (Some(p), Some(b)) = (prov, base_prov) {
+            p.extend(b);
+        }
+        Some(raw.clone())
+    }
+}
+
+// Render a base and remember it, its provenance is recorded apart to be
+// replayed when it's reused.
+fn render_base(
+    origin: String,
+    content: &str,
+    sp: &Vec<String>,
+    walk: &mut RenderWalk,
+    prov: &mut Option<Provenance>,
+    opts: &RenderOptions,
+    fragment: bool,
+) -> SarusResult<RawEDF> {
+    let key = (canonical_path(&origin).display().to_string(), fragment);
+    let mut base_prov = prov.as_ref().map(|_| Provenance::default());
+    let raw = render_content(origin, content, sp, walk, &mut base_prov, opts, fragment)?;
+    if let (Some(p), Some(b)) = (prov.as_mut(), &base_prov) {
+        p.extend(b);
+    }
+    walk.memo.insert(key, (walk.chain.len(), raw.clone(), base_prov));
+    Ok(raw)
+}
+
+pub(crate) fn render_inner_loop(
     name: String,
     sp: &Vec<String>,
-    env: &Option<HashMap<String, String>>,
-    mut count: u64,
-    max: u64,
+    walk: &mut RenderWalk,
+    prov: &mut Option<Provenance>,
+    opts: &RenderOptions,
+    fragment: bool,
 ) -> SarusResult<RawEDF> {
-    count += 1;
+    let env = &opts.env;
+    let max = opts.max_levels;
+    let xopts = opts.expand_options();
+
+    let count = walk.chain.len() as u64 + 1;
     if count > max {
         return Err(SarusError {
-            code: 5,
+            kind: SarusErrorKind::MaxDepthExceeded { max },
             file_path: None,
             msg: String::from(format!(
                 "base_environment rendering has more than {max} levels"
             )),
+            location: None,
         });
     }
 
-    let edf_path = resolve_env_path(name.clone(), sp, env)?;
-    validate(edf_path.clone())?;
-
-    // Create current raw EDF
-    let path_str = edf_path.as_str();
-    let mut cur_redf: RawEDF = toml_read(path_str)?;
-
-    // Merge base EDFs
-    if cur_redf.base_environment.is_some() {
-        let mut base_redf = RawEDF::default();
-
-        let be = cur_redf.base_environment.clone().unwrap();
-        let ba = match be {
-            BaseEnvironment::TypeString(s) => vec![s],
-            BaseEnvironment::TypeVec(a) => a,
-        };
-
-        for b in ba.iter() {
-            let _base_redf = render_inner_loop(b.to_string(), &sp, env, count, max)?;
-            base_redf.extend(_base_redf);
+    // EDFs published on a web server
+    if remote::is_remote(&name) {
+        let r = RemoteRef::parse(&expand_vars_string_with_options(name, env, &xopts)?)?;
+        walk.check_cycle(&r.url)?;
+        if let Some(raw) = walk.reuse(&r.url, fragment, prov) {
+            return Ok(raw);
         }
-        cur_redf.base_environment = None;
-
-        base_redf.extend(cur_redf);
-        cur_redf = base_redf;
+        if !opts.skip_validation {
+            check_file_path_extension(&r.url, &EDF_EXTENSIONS)?;
+        }
+        let content = remote::fetch(&r, &opts.remote)?;
+        return render_base(r.url, &content, sp, walk, prov, opts, fragment);
     }
 
-    // Expand variables in the fields
-    if cur_redf.devices.is_some() {
-        cur_redf.devices = Some(expand_vars_vec(cur_redf.devices.unwrap(), env)?);
-
-        // Remove duplicates from devices
-        let dev = cur_redf.devices.clone().unwrap();
-        let dev_set: HashSet<_> = dev.into_iter().collect();
-        let dev_unique_vec: Vec<_> = dev_set.into_iter().collect();
-        cur_redf.devices = Some(dev_unique_vec);
+    // EDFs stored in git repositories
+    if git::is_git_ref(&name) {
+        let r = GitRef::parse(&expand_vars_string_with_options(name, env, &xopts)?)?;
+        let origin = r.origin();
+        walk.check_cycle(&origin)?;
+        if let Some(raw) = walk.reuse(&origin, fragment, prov) {
+            return Ok(raw);
+        }
+        if !opts.skip_validation {
+            check_file_path_extension(&r.path, &EDF_EXTENSIONS)?;
+        }
+        let cache_dir = match &opts.git_cache_dir {
+            Some(d) => d.clone(),
+            None => git::default_git_cache_dir(),
+        };
+        let content = git::fetch(&r, &cache_dir)?;
+        return render_base(origin, &content, sp, walk, prov, opts, fragment);
     }
-    if cur_redf.env.is_some() {
-        cur_redf.env = Some(expand_vars_hashmap(cur_redf.env.unwrap(), env)?);
+
+    let edf_path = resolve_env_path(name.clone(), sp, env, &xopts, &opts.load_limits)?;
+    if let Some(p) = prov {
+        p.lookups.push((name.clone(), edf_path.clone()));
     }
-    if cur_redf.annotations.is_some() {
-        let a = cur_redf.annotations.unwrap();
-        let mut h = annotations_as_hashmap(a);
-        h = expand_vars_hashmap(h, env)?;
-        cur_redf.annotations = Some(Annotations::TypeHashMap(h));
+
+    walk.check_cycle(&edf_path)?;
+    if let Some(raw) = walk.reuse(&edf_path, fragment, prov) {
+        return Ok(raw);
     }
-    if cur_redf.workdir.is_some() {
-        cur_redf.workdir = Some(expand_vars_string(cur_redf.workdir.unwrap(), env)?);
+
+    if !opts.skip_validation {
+        check_file_path_extension(&edf_path, &EDF_EXTENSIONS)?;
     }
 
-    return Ok(cur_redf);
-}
+    let content = load::load_edf(&edf_path, sp, &opts.load_limits)?;
 
-pub fn render_from_search_paths(
-    path: String,
-    search_paths: Vec<String>,
-    env: &Option<HashMap<String, String>>,
-) -> SarusResult<EDF> {
-    let sp = search_paths;
-    let max_levels = 10;
-    let loop_count = 0;
-    let raw = render_inner_loop(path, &sp, env, loop_count, max_levels)?;
-    let e = edf_from_raw(raw, env)?;
-    Ok(e)
+    render_base(edf_path, &content, sp, walk, prov, opts, fragment)
+}
+
+// Render the content of an EDF, origin being its file path or a name
+// standing for it. Bases are still looked up through the search paths.
+// Fragments are spliced into other EDFs and may lack an image.
+fn render_content(
+    origin: String,
+    content: &str,
+    sp: &Vec<String>,
+    walk: &mut RenderWalk,
+    prov: &mut Option<Provenance>,
+    opts: &RenderOptions,
+    fragment: bool,
+) -> SarusResult<RawEDF> {
+    let env = &opts.env;
+    let xopts = opts.expand_options();
+    let edf_path = origin;
+
+    let mut doc: serde_json::Value = document_parse(content, &edf_path)?;
+    aliases::resolve_aliases(&mut doc, EDF_KEY_ALIASES);
+    resolve_conditions(&mut doc, env, &xopts, &edf_path)?;
+    template::expand_document(&mut doc, &opts.template_vars, &edf_path)?;
+    if !opts.skip_validation {
+        Validator::get()?.validate_content(&edf_path, &doc, fragment, content)?;
+    }
+    if opts.strict {
+        check_unknown_keys(&doc, &edf_path, content)?;
+    }
+
+    // Create current raw EDF
+    let path_str = edf_path.as_str();
+    let mut cur_redf: RawEDF = raw_from_document(doc, path_str)?;
+    check_unset(&cur_redf, path_str)?;
+    cur_redf.fold_engine_tables();
+    if cur_redf.mounts.is_some() {
+        for (id, l) in list_item_locations(content, path_str, "mounts") {
+            cur_redf.mount_locations.push((id, edf_path.clone(), l));
+        }
+    }
+
+    // Load env files in order, entries of the env table win over them
+    if let Some(ef) = cur_redf.env_file.take() {
+        let fa = match ef {
+            BaseEnvironment::TypeString(s) => vec![s],
+            BaseEnvironment::TypeVec(a) => a,
+        };
+        let mut file_env = HashMap::new();
+        for f in fa.into_iter() {
+            let f = match xopts.expands("env_file") {
+                true => expand_vars_string_with_options(f, env, &xopts)?,
+                false => f,
+            };
+            let (p, root) = env_file_path(&f, path_str, sp)?;
+            file_env.extend(env_file_read(&p, &root, &opts.load_limits)?);
+            if let Some(pr) = prov {
+                pr.files.push(p);
+            }
+        }
+        if let Some(e) = cur_redf.env.take() {
+            file_env.extend(e);
+        }
+        cur_redf.env = Some(file_env);
+    }
+
+    // Merge base EDFs, then included fragments, below the current one
+    let mut layers = vec![];
+    if let Some(be) = cur_redf.base_environment.take() {
+        let ba = match be {
+            BaseEnvironment::TypeString(s) => vec![s],
+            BaseEnvironment::TypeVec(a) => a,
+        };
+        layers.extend(ba.into_iter().map(|b| (b, false)));
+    }
+    if let Some(inc) = cur_redf.include.take() {
+        let ia = match inc {
+            BaseEnvironment::TypeString(s) => vec![s],
+            BaseEnvironment::TypeVec(a) => a,
+        };
+        layers.extend(ia.into_iter().map(|i| (i, true)));
+    }
+
+    // Devices of the bases are expanded already
+    if cur_redf.devices_remove.is_some() && xopts.expands("devices") {
+        cur_redf.devices_remove = Some(expand_vars_vec_with_options(cur_redf.devices_remove.take().unwrap(), env, &xopts)?);
+    }
+
+    if !layers.is_empty() {
+        let mut base_redf = RawEDF::default();
+
+        walk.chain.push(edf_path.clone());
+        for (b, is_fragment) in layers.into_iter() {
+            let _base_redf = render_inner_loop(b, sp, walk, prov, opts, is_fragment)?;
+            base_redf.extend(_base_redf);
+        }
+        walk.chain.pop();
+
+        base_redf.extend(cur_redf);
+        cur_redf = base_redf;
+    }
+    cur_redf.clear_removals();
+
+    // Entries of the current file override the ones of its bases
+    if let Some(p) = prov {
+        p.record_content(path_str, content)?;
+        p.files.push(edf_path.clone());
+    }
+
+    expand_raw_fields(&mut cur_redf, env, &xopts)?;
+
+    return Ok(cur_redf);
+}
+
+// Variables of the render environment, the process one when uenv is None,
+// matching the env_passthrough patterns. Variables denied by the site are
+// left out.
+fn passthrough_env(
+    patterns: &Option<Vec<String>>,
+    uenv: &Option<HashMap<String, String>>,
+    xopts: &ExpandOptions,
+) -> BTreeMap<String, String> {
+    let patterns = match patterns {
+        Some(p) if !p.is_empty() => p,
+        _ => return get_default_env(),
+    };
+    let vars: Vec<(String, String)> = match uenv {
+        Some(e) => e.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+        None => std::env::vars().collect(),
+    };
+    vars.into_iter()
+        .filter(|(k, _)| patterns.iter().any(|p| glob_match(p, k)) && xopts.var_allowed(k))
+        .collect()
+}
+
+// Expand variables in the fields of a raw EDF.
+fn expand_raw_fields(
+    r: &mut RawEDF,
+    env: &Option<HashMap<String, String>>,
+    xopts: &ExpandOptions,
+) -> SarusResult<()> {
+    if r.devices.is_some() && xopts.expands("devices") {
+        r.devices = Some(expand_vars_vec_with_options(r.devices.take().unwrap(), env, xopts)?);
+    }
+    if let Some(d) = r.devices.as_mut() {
+        dedup_entries(d, xopts.merge_policy);
+    }
+    if r.env.is_some() && xopts.expands("env") {
+        r.env = Some(expand_vars_hashmap_with_options(r.env.take().unwrap(), env, xopts)?);
+    }
+    if r.annotations.is_some() && xopts.expands("annotations") {
+        let a = r.annotations.take().unwrap();
+        let mut h = annotations_as_hashmap(a);
+        h = expand_vars_hashmap_with_options(h, env, xopts)?;
+        r.annotations = Some(Annotations::TypeHashMap(h));
+    }
+    if r.labels.is_some() && xopts.expands("labels") {
+        r.labels = Some(expand_vars_hashmap_with_options(r.labels.take().unwrap(), env, xopts)?);
+    }
+    if r.workdir.is_some() && xopts.expands("workdir") {
+        let w = r.workdir.take().unwrap();
+        r.workdir = Some(xopts.collect(expand_vars_string_with_options(w.clone(), env, xopts), w)?);
+    }
+    if let Some(n) = r.network.take() {
+        r.network = match xopts.expands("network") {
+            true => Some(xopts.collect(expand_vars_string_with_options(n.clone(), env, xopts), n)?),
+            false => Some(n),
+        };
+    }
+    if let Some(p) = r.auth_file.take() {
+        r.auth_file = match xopts.expands("auth_file") {
+            true => Some(xopts.collect(expand_vars_string_with_options(p.clone(), env, xopts), p)?),
+            false => Some(p),
+        };
+    }
+    if let Some(p) = r.writable_overlay_path.take() {
+        r.writable_overlay_path = match xopts.expands("writable_overlay_path") {
+            true => Some(xopts.collect(expand_vars_string_with_options(p.clone(), env, xopts), p)?),
+            false => Some(p),
+        };
+    }
+    Ok(())
+}
+
+pub fn render_from_search_paths(
+    path: String,
+    search_paths: Vec<String>,
+    env: &Option<HashMap<String, String>>,
+) -> SarusResult<EDF> {
+    let mut opts = RenderOptions::new().search_paths(search_paths);
+    opts.env = env.clone();
+    render_with_options(path, &opts)
+}
+
+pub fn render_with_options(path: String, options: &RenderOptions) -> SarusResult<EDF> {
+    #[cfg(unix)]
+    if let Some(dir) = &options.cache_dir
+        && !options.provenance
+    {
+        return RenderCache::new(dir).render(path, options);
+    }
+    let (e, _) = render_with_options_provenance(path, options)?;
+    Ok(e)
+}
+
+// Render an EDF, then apply in-memory overrides on top of it, e.g. the
+// --image or --mount flags of a frontend. Variables of the overrides are
+// expanded like the ones of files.
+pub fn render_with_overrides(path: String, overrides: RawEDF, options: &RenderOptions) -> SarusResult<EDF> {
+    let sp = match &options.search_paths {
+        Some(s) => s.clone(),
+        None => get_search_paths(),
+    };
+    let mut raw = render_inner_loop(path, &sp, &mut RenderWalk::default(), &mut None, options, false)?;
+    let mut overrides = overrides;
+    expand_raw_fields(&mut overrides, &options.env, &options.expand_options())?;
+    raw.merge(overrides);
+    if let Some(d) = raw.devices.as_mut() {
+        dedup_entries(d, options.merge_policy);
+    }
+    let e = edf_from_raw_with_hooks(raw, options)?;
+    finish_edf(e, options)
+}
+
+fn render_panicked(path: &str, payload: Box<dyn std::any::Any + Send>) -> SarusError {
+    let cause = match payload.downcast_ref::<&str>() {
+        Some(s) => String::from(*s),
+        None => payload.downcast_ref::<String>().cloned().unwrap_or_default(),
+    };
+    SarusError {
+        kind: SarusErrorKind::RenderPanicked { path: String::from(path) },
+        file_path: Some(String::from(path)),
+        msg: format!("rendering {path} panicked: {cause}"),
+        location: None,
+    }
+}
+
+// Render a batch of EDFs concurrently, results are in the order of paths.
+// Search paths are resolved once and shared by every rendering. A panic
+// fails the rendering of its path only.
+pub fn render_many(paths: Vec<String>, options: &RenderOptions) -> Vec<SarusResult<EDF>> {
+    let mut opts = options.clone();
+    if opts.search_paths.is_none() {
+        opts.search_paths = Some(get_search_paths());
+    }
+
+    let workers = match std::thread::available_parallelism() {
+        Ok(n) => n.get(),
+        Err(_) => 1,
+    };
+    let chunk_size = paths.len().div_ceil(workers).max(1);
+
+    let opts = &opts;
+    std::thread::scope(|s| {
+        let handles: Vec<_> = paths
+            .chunks(chunk_size)
+            .map(|chunk| {
+                let handle = s.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|p| {
+                            let r = std::panic::catch_unwind(AssertUnwindSafe(|| render_with_options(p.clone(), opts)));
+                            r.unwrap_or_else(|e| Err(render_panicked(p, e)))
+                        })
+                        .collect::<Vec<_>>()
+                });
+                (chunk, handle)
+            })
+            .collect();
+
+        let mut res = vec![];
+        for (chunk, h) in handles {
+            match h.join() {
+                Ok(r) => res.extend(r),
+                Err(e) => {
+                    let cause = render_panicked(&chunk.join(", "), e);
+                    res.extend(chunk.iter().map(|_| Err(cause.clone())));
+                }
+            }
+        }
+        res
+    })
+}
+
+// Same as render_with_options(), provenance is returned when enabled.
+pub fn render_with_options_provenance(
+    path: String,
+    options: &RenderOptions,
+) -> SarusResult<(EDF, Option<Provenance>)> {
+    let sp = match &options.search_paths {
+        Some(s) => s.clone(),
+        None => get_search_paths(),
+    };
+    let mut prov = match options.provenance {
+        true => Some(Provenance::default()),
+        false => None,
+    };
+    let raw = render_inner_loop(path, &sp, &mut RenderWalk::default(), &mut prov, options, false)?;
+    let e = edf_from_raw_with_hooks(raw, options)?;
+    let e = finish_edf(e, options)?;
+    Ok((e, prov))
+}
+
+// Render an EDF, also returning the variables its expansions referenced
+// when expansion_audit is set. Values of sensitive variables are redacted
+// unless reveal_secrets is set.
+pub fn render_with_options_audit(
+    path: String,
+    options: &RenderOptions,
+) -> SarusResult<(EDF, Option<Vec<ExpansionRecord>>)> {
+    if !options.expansion_audit {
+        return Ok((render_with_options(path, options)?, None));
+    }
+
+    let mut opts = options.clone();
+    let audit = ExpansionAudit::default();
+    opts.audit = Some(audit.clone());
+    opts.cache_dir = None;
+    let e = render_with_options(path, &opts)?;
+
+    let mut records = audit.records();
+    if !options.reveal_secrets {
+        for r in records.iter_mut() {
+            if r.value.is_some() && secrets::is_sensitive(&r.var, &options.sensitive_env) {
+                r.value = Some(String::from(secrets::REDACTED));
+            }
+        }
+    }
+    Ok((e, Some(records)))
+}
+
+// Same as render_with_options(), reporting every failure of the entries of
+// the EDF (expansions, mounts, devices) instead of the first one. Failures
+// to read or merge the files still stop the render.
+pub fn render_with_options_errors(path: String, options: &RenderOptions) -> Result<EDF, SarusErrorList> {
+    let mut opts = options.clone();
+    let sink = ErrorSink::default();
+    opts.errors = Some(sink.clone());
+    opts.cache_dir = None;
+
+    let res = render_with_options(path, &opts);
+    let mut errors = sink.take();
+    let e = match res {
+        Ok(e) => e,
+        Err(x) => {
+            errors.push(x);
+            return Err(SarusErrorList { errors });
+        }
+    };
+    for d in e.devices.iter() {
+        if let Err(x) = Device::try_new(d.clone()) {
+            errors.push(x);
+        }
+    }
+    match errors.is_empty() {
+        true => Ok(e),
+        false => Err(SarusErrorList { errors }),
+    }
+}
+
+// Render EDF content received in memory. origin_hint names the content in
+// errors and selects the format: YAML for .yaml/.yml names, TOML otherwise.
+pub fn render_from_str(
+    content: String,
+    origin_hint: &str,
+    search_paths: Vec<String>,
+    env: &Option<HashMap<String, String>>,
+) -> SarusResult<EDF> {
+    let mut opts = RenderOptions::new().search_paths(search_paths);
+    opts.env = env.clone();
+    render_from_str_with_options(content, origin_hint, &opts)
+}
+
+pub fn render_from_str_with_options(
+    content: String,
+    origin_hint: &str,
+    options: &RenderOptions,
+) -> SarusResult<EDF> {
+    let sp = match &options.search_paths {
+        Some(s) => s.clone(),
+        None => get_search_paths(),
+    };
+    let mut prov = None;
+    let origin = String::from(origin_hint);
+    let raw = render_content(origin, &content, &sp, &mut RenderWalk::default(), &mut prov, options, false)?;
+    let e = edf_from_raw_with_hooks(raw, options)?;
+    finish_edf(e, options)
+}
+
+// Site adjustments and checks of a rendered EDF.
+// Convert a rendered RawEDF, resolving its [hooks] table against the hooks
+// catalog of the site.
+pub(crate) fn edf_from_raw_with_hooks(raw: RawEDF, options: &RenderOptions) -> SarusResult<EDF> {
+    let requested = raw.hooks.clone().unwrap_or_default();
+    let mut e = edf_from_raw_with_options(raw, &options.env, &options.expand_options())?;
+    e.hooks = hooks::resolve_hooks(&requested, &options.oci_hooks)?;
+    Ok(e)
+}
+
+pub(crate) fn finish_edf(mut e: EDF, options: &RenderOptions) -> SarusResult<EDF> {
+    e.image = options.registry.rewrite(&e.image);
+    image::apply_local_image(&mut e)?;
+    if e.writable {
+        if e.writable_overlay_path.is_none() {
+            e.writable_overlay_path = options.writable_overlay_path.clone();
+        }
+        if e.writable_overlay_size.is_none() {
+            e.writable_overlay_size = options.writable_overlay_size.clone();
+        }
+    }
+    check_writable_overlay(&e)?;
+    check_user_mapping(&e)?;
+    limits::check_limits(&e.limits)?;
+    check_network(&e)?;
+    check_auth_file(&e)?;
+    mpi::apply(&mut e, options)?;
+    if e.gpu {
+        gpu::inject(&mut e, options)?;
+    }
+    options.annotation_processors.process(&mut e.annotations)?;
+    if !options.reveal_secrets {
+        e.sensitive_env = options.sensitive_env.clone();
+    }
+    if options.require_image_digest {
+        check_image_digest(&e.image)?;
+    }
+    if let Some(p) = &options.policy {
+        p.enforce(&e)?;
+    }
+    if options.verify_mounts {
+        verify_mount_sources(&e.mounts)?;
+    }
+    if let Some(b) = &options.check_binaries {
+        let path_var = match &options.env {
+            Some(env) => env.get("PATH").cloned().unwrap_or_default(),
+            None => std::env::var("PATH").unwrap_or_default(),
+        };
+        binaries::check_binaries(b, &path_var)?;
+    }
+    if let Some(site) = &options.check_imagestore {
+        let imagestore = e.sarus_annotations().parallax_imagestore.unwrap_or(site.clone());
+        imagestore::check_imagestore(&imagestore)?;
+    }
+    Ok(e)
+}
+
+// The overlay of writable containers must be an absolute directory and its
+// size a number of bytes with an optional k, m, g or t suffix.
+fn check_writable_overlay(e: &EDF) -> SarusResult<()> {
+    let invalid = |field: &str, msg: String| SarusError {
+        kind: SarusErrorKind::InvalidWritableOverlay {
+            field: String::from(field),
+        },
+        file_path: None,
+        msg,
+        location: None,
+    };
+
+    let is_set = e.writable_overlay_path.is_some() || e.writable_overlay_size.is_some();
+    if is_set && !e.writable {
+        return Err(invalid(
+            "writable",
+            String::from("writable_overlay_path and writable_overlay_size require writable = true"),
+        ));
+    }
+    if let Some(p) = &e.writable_overlay_path
+        && !p.starts_with('/')
+    {
+        return Err(invalid("writable_overlay_path", format!("overlay path \"{p}\" is not absolute")));
+    }
+    static RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[0-9]+[kKmMgGtT]?$").unwrap());
+    if let Some(s) = &e.writable_overlay_size
+        && !RE.is_match(s)
+    {
+        return Err(invalid("writable_overlay_size", format!("invalid overlay size \"{s}\", expected e.g. 10G")));
+    }
+    Ok(())
+}
+
+// The user and group of the container are names or ids, the group being
+// set once, and userns one of the modes of podman --userns.
+fn check_user_mapping(e: &EDF) -> SarusResult<()> {
+    let invalid = |field: &str, msg: String| SarusError {
+        kind: SarusErrorKind::InvalidUserMapping {
+            field: String::from(field),
+        },
+        file_path: None,
+        msg,
+        location: None,
+    };
+
+    static USER_RE: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r"^([0-9]+|[A-Za-z_][A-Za-z0-9_.-]*)(:([0-9]+|[A-Za-z_][A-Za-z0-9_.-]*))?$").unwrap()
+    });
+    static GROUP_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^([0-9]+|[A-Za-z_][A-Za-z0-9_.-]*)$").unwrap());
+    if let Some(u) = &e.user
+        && !USER_RE.is_match(u)
+    {
+        return Err(invalid("user", format!("invalid user \"{u}\", expected NAME|UID[:GROUP|GID]")));
+    }
+    if let Some(g) = &e.group {
+        if !GROUP_RE.is_match(g) {
+            return Err(invalid("group", format!("invalid group \"{g}\", expected a name or a gid")));
+        }
+        match &e.user {
+            None => return Err(invalid("group", String::from("group requires user"))),
+            Some(u) if u.contains(':') => {
+                return Err(invalid("group", format!("group is set twice, by group and by user \"{u}\"")));
+            }
+            Some(_) => {}
+        }
+    }
+    static RE: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"^(keep-id(:(uid|gid)=[0-9]+(,(uid|gid)=[0-9]+)?)?|nomap|auto|host)$").unwrap());
+    if let Some(n) = &e.userns
+        && !RE.is_match(n)
+    {
+        return Err(invalid(
+            "userns",
+            format!("invalid userns \"{n}\", expected keep-id[:uid=UID,gid=GID], nomap, auto or host"),
+        ));
+    }
+    Ok(())
+}
+
+// Networks are host, none, slirp4netns with its options or the name of a
+// network created beforehand.
+fn check_network(e: &EDF) -> SarusResult<()> {
+    let n = match &e.network {
+        Some(n) => n,
+        None => return Ok(()),
+    };
+    static RE: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r"^(host|none|slirp4netns(:[A-Za-z0-9_.=,/-]+)?|[A-Za-z0-9][A-Za-z0-9_.-]*)$").unwrap()
+    });
+    if !RE.is_match(n) {
+        return Err(SarusError {
+            kind: SarusErrorKind::InvalidNetwork { network: n.clone() },
+            file_path: None,
+            msg: format!("invalid network \"{n}\", expected host, none, slirp4netns[:OPTIONS] or a network name"),
+            location: None,
+        });
+    }
+    Ok(())
+}
+
+// Registry credentials must be an absolute path to a file only its owner
+// can read, like podman login writes them.
+fn check_auth_file(e: &EDF) -> SarusResult<()> {
+    let p = match &e.auth_file {
+        Some(p) => p,
+        None => return Ok(()),
+    };
+    let invalid = |msg: String| SarusError {
+        kind: SarusErrorKind::InvalidAuthFile { path: p.clone() },
+        file_path: None,
+        msg,
+        location: None,
+    };
+    if !p.starts_with('/') {
+        return Err(invalid(format!("auth_file \"{p}\" is not absolute")));
+    }
+    let md = match std::fs::metadata(p) {
+        Ok(m) if m.is_file() => m,
+        Ok(_) => return Err(invalid(format!("auth_file {p} is not a regular file"))),
+        Err(err) => return Err(invalid(format!("cannot access auth_file {p}: {err}"))),
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = md.permissions().mode() & 0o777;
+        if mode & 0o077 != 0 {
+            return Err(invalid(format!("auth_file {p} has mode {mode:o}, expected 600")));
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = md;
+    Ok(())
+}
+
+// Options used by render(), built from the configuration of the site.
+pub fn get_site_render_options() -> SarusResult<RenderOptions> {
+    let mut opts = RenderOptions::new().search_paths(get_search_paths());
+    if let Some(config) = load_site_config()? {
+        let sys_paths = sys_search_paths(&config);
+        opts.registry = RegistryRewrite::from_config(&config);
+        opts.require_image_digest = config.require_image_digest;
+        opts.sensitive_env = config.sensitive_env_patterns;
+        opts.expansion_allowed_vars = config.expansion_allowed_vars;
+        opts.expansion_denied_vars = config.expansion_denied_vars;
+        opts.expansion_fields = config.expansion_fields;
+        opts.mpi_templates = config.mpi_templates;
+        opts.oci_hooks = config.oci_hooks;
+        opts.remote = RemoteOptions {
+            max_size: u64::from(config.remote_max_size),
+            timeout: u64::from(config.remote_timeout),
+        };
+        opts.load_limits = LoadLimits {
+            max_size: u64::from(config.edf_max_size),
+            reject_world_writable: config.edf_reject_world_writable,
+            contain_symlinks: config.edf_contain_symlinks,
+            contain_names: config.edf_contain_names,
+            system_paths: match config.edf_system_owner_check {
+                true => Some(SystemPathPolicy {
+                    search_paths: sys_paths,
+                    owner_uid: config.edf_system_owner_uid,
+                    allow_group_writable: config.edf_system_group_writable,
+                }),
+                false => None,
+            },
+        };
+        opts.template_vars = config.template_variables;
+        if !config.git_cache_dir.is_empty() {
+            opts.git_cache_dir = Some(config.git_cache_dir);
+        }
+        if !config.render_cache_dir.is_empty() {
+            opts.cache_dir = Some(config.render_cache_dir);
+        }
+        if !config.writable_overlay_path.is_empty() {
+            opts.writable_overlay_path = Some(config.writable_overlay_path);
+        }
+        if !config.writable_overlay_size.is_empty() {
+            opts.writable_overlay_size = Some(config.writable_overlay_size);
+        }
+    }
+    opts.policy = load_site_policy()?;
+    Ok(opts)
 }
 
 pub fn render(path: String) -> SarusResult<EDF> {
-    let sp = get_search_paths();
-    render_from_search_paths(path, sp, &None)
+    let opts = get_site_render_options()?;
+    render_with_options(path, &opts)
+}
+
+// Render an EDF and report which file last set each of its entries.
+pub fn render_with_provenance(path: String) -> SarusResult<(EDF, Provenance)> {
+    let opts = get_site_render_options()?.provenance(true);
+    let (e, prov) = render_with_options_provenance(path, &opts)?;
+    Ok((e, prov.unwrap_or_default()))
 }
 
 pub fn get_edf_from_string(content: String) -> SarusResult<EDF> {
@@ -593,9 +1995,10 @@ pub fn get_edf_from_string(content: String) -> SarusResult<EDF> {
         Ok(v) => v,
         Err(e) => {
             return Err(SarusError {
-                code: 25,
+                kind: SarusErrorKind::StringParse,
                 file_path: None,
                 msg: String::from(format!("{}", e)),
+                location: None,
             });
         }
     };
@@ -674,11 +2077,24 @@ mod tests {
         assert!(
             edf.mounts
                 .iter()
-                .any(|e| e.to_volume_string() == "/eee:./fff:ggg")
+                .any(|e| e.to_volume_string() == "/eee:./fff:ro")
         );
         assert!(edf.mounts.len() == 3);
     }
 
+    #[test]
+    #[serial]
+    fn render_devices_cdi() {
+        let edf = get_rendered_edf("devices-cdi.toml").unwrap();
+        let devices = edf.parsed_devices().unwrap();
+        assert!(devices.len() == 3);
+        assert!(devices.iter().filter(|d| d.is_cdi()).count() == 1);
+
+        let edf = get_rendered_edf("top-devices.toml").unwrap();
+        let err = edf.parsed_devices().err().unwrap();
+        assert!(err.code() == 35);
+    }
+
     #[test]
     #[serial]
     fn render_table_anno() {
@@ -697,6 +2113,12 @@ mod tests {
         assert!(edf.env.get("two_plus_two").unwrap() == "four");
         assert!(edf.env.get("minus_one").unwrap() == "three");
         assert!(edf.env.get("quick").unwrap() == "maths");
+
+        // Rendered collections come out in the same order on every run
+        let keys: Vec<&String> = edf.env.keys().collect();
+        assert!(keys == vec!["minus_one", "quick", "two_plus_two"]);
+        let again = get_rendered_edf("table-env.toml").unwrap();
+        assert!(edf.to_json().unwrap() == again.to_json().unwrap());
     }
 
     #[test]
@@ -756,7 +2178,7 @@ mod tests {
         assert!(
             edf.mounts
                 .iter()
-                .any(|e| e.to_volume_string() == "/eee:./fff:ggg")
+                .any(|e| e.to_volume_string() == "/eee:./fff:ro")
         );
         assert!(
             edf.mounts
@@ -777,6 +2199,134 @@ mod tests {
         assert!(get_rendered_edf("base-rec.toml").is_err());
     }
 
+    #[test]
+    #[serial]
+    fn render_base_cycle() {
+        let err = get_rendered_edf("cycle-a.toml").err().unwrap();
+        let chain = vec![
+            String::from("cycle-a.toml"),
+            String::from("./cycle-b.toml"),
+            String::from("./cycle-a.toml"),
+        ];
+        assert!(err.kind == SarusErrorKind::BaseEnvironmentCycle { chain });
+
+        let err = get_rendered_edf("base-cycle-self.toml").err().unwrap();
+        assert!(err.code() == 34);
+    }
+
+    #[test]
+    #[serial]
+    fn render_include() {
+        let edf = get_rendered_edf("include-top.toml").unwrap();
+        assert!(edf.image == "ubuntu:env");
+        assert!(edf.env.get("two_plus_two").unwrap() == "four");
+        assert!(edf.env.get("quick").unwrap() == "mpi");
+        assert!(edf.env.get("MPI").unwrap() == "mpich");
+        assert!(edf.env.get("GPU").unwrap() == "1");
+        assert!(edf.mounts.len() == 1);
+
+        // Fragments alone are not complete EDFs
+        let err = get_rendered_edf("frag-env-mpi.toml").err().unwrap();
+        assert!(err.code() == 4);
+    }
+
+    #[test]
+    #[serial]
+    fn render_env_file() {
+        let edf = get_rendered_edf("env-file.toml").unwrap();
+        assert!(edf.env.get("quick").unwrap() == "explicit");
+        assert!(edf.env.get("OMP_NUM_THREADS").unwrap() == "8");
+        assert!(edf.env.get("GREETING").unwrap() == "hello world");
+    }
+
+    #[test]
+    #[serial]
+    fn render_overrides() {
+        let uenv = HashMap::from([(String::from("SCRATCH"), String::from("/scratch"))]);
+        let opts = RenderOptions::new().env(uenv);
+        let overrides = RawEDF {
+            image: Some(String::from("ubuntu:override")),
+            mounts: Some(vec![MountEntry::Spec(String::from("$SCRATCH:/scratch"))]),
+            env: Some(HashMap::from([(String::from("DATA"), String::from("$SCRATCH/data"))])),
+            ..Default::default()
+        };
+
+        let edf = render_with_overrides(String::from("test/toml/top-simple-1.toml"), overrides, &opts).unwrap();
+        assert!(edf.image == "ubuntu:override");
+        assert!(edf.entrypoint);
+        assert!(edf.mounts[0].to_volume_string() == "/scratch:/scratch");
+        assert!(edf.env.get("DATA").unwrap() == "/scratch/data");
+
+        let mut raw = RawEDF {
+            workdir: Some(String::from("/a")),
+            ..Default::default()
+        };
+        raw.merge(RawEDF {
+            workdir: Some(String::from("/b")),
+            ..Default::default()
+        });
+        assert!(raw.workdir.as_deref() == Some("/b"));
+    }
+
+    #[test]
+    fn render_mount_tables() {
+        let mut uenv = HashMap::new();
+        uenv.insert(String::from("USER_NAME"), String::from("alice"));
+        let opts = RenderOptions::new().env(uenv);
+        let edf = render_with_options(String::from("test/toml/mount-tables.toml"), &opts).unwrap();
+
+        let mounts: Vec<String> = edf.mounts.iter().map(|m| m.to_volume_string()).collect();
+        assert!(mounts.len() == 4);
+        assert!(mounts[1] == "/capstor/store/run:2024:/data:ro,rbind");
+        assert!(matches!(edf.mounts[1].kind(), crate::mount::MountKind::Bind(o) if o.read_only));
+        assert!(matches!(edf.mounts[2].kind(), crate::mount::MountKind::Tmpfs(o) if o.size.as_deref() == Some("1g")));
+        assert!(edf.mounts[3].source() == "/users/alice");
+
+        // Written back as a table, the compact form would split the source
+        let out = edf.to_toml_string().unwrap();
+        let back = get_edf_from_string(out).unwrap();
+        assert!(back.mounts == edf.mounts);
+
+        let opts = RenderOptions::new().env(HashMap::new());
+        let edf = render_with_options(String::from("test/toml/mount-tables.toml"), &opts).unwrap();
+        assert!(edf.mounts.len() == 3);
+
+        let content = String::from("image = \"ubuntu\"\n[[mounts]]\nsource = \"/a\"\ntarget = \"/b\"\n");
+        let edf = render_from_str_with_options(content, "socket", &RenderOptions::new()).unwrap();
+        assert!(edf.mounts[0].to_volume_string() == "/a:/b");
+
+        let content = String::from("image = \"ubuntu\"\n[[mounts]]\ntarget = \"/data\"\n");
+        let err = render_from_str_with_options(content, "socket", &RenderOptions::new()).err().unwrap();
+        assert!(err.kind == SarusErrorKind::InvalidMount { mount: String::from("/data") });
+    }
+
+    #[test]
+    fn render_conditions() {
+        let mut uenv = HashMap::new();
+        uenv.insert(String::from("CLUSTER"), String::from("daint"));
+        let opts = RenderOptions::new().env(uenv.clone());
+        let path = String::from("test/toml/conditions.toml");
+
+        let edf = render_with_options(path.clone(), &opts).unwrap();
+        assert!(edf.env.get("COMMON").unwrap() == "1");
+        assert!(edf.env.get("PARTITION").unwrap() == "normal");
+        assert!(!edf.env.contains_key("when"));
+        let targets: Vec<&str> = edf.mounts.iter().map(|m| m.target()).collect();
+        assert!(targets == vec!["/scratch", "/capstor"]);
+        assert!(edf.devices.is_empty());
+
+        uenv.insert(String::from("CLUSTER"), String::from("eiger"));
+        uenv.insert(String::from("GPU"), String::from("1"));
+        let opts = RenderOptions::new().env(uenv);
+        let edf = render_with_options(path.clone(), &opts).unwrap();
+        assert!(edf.env.get("PARTITION").unwrap() == "debug");
+        let targets: Vec<&str> = edf.mounts.iter().map(|m| m.target()).collect();
+        assert!(targets == vec!["/scratch", "/users"]);
+        assert!(edf.devices == vec!["nvidia.com/gpu=all"]);
+
+        assert!(validate(path).is_ok());
+    }
+
     #[test]
     #[serial]
     fn render_base_nested() {
@@ -802,6 +2352,777 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    #[serial]
+    fn render_yaml() {
+        let edf = get_rendered_edf("yaml-anno.yaml").unwrap();
+        assert!(edf.image == "ubuntu:anno");
+        assert!(edf.annotations.get("two_plus_two").unwrap() == "four");
+
+        let edf = get_rendered_edf("yaml-base.yml").unwrap();
+        assert!(edf.image == "ubuntu:mounts");
+        assert!(edf.devices == vec!["dev1".to_string()]);
+        assert!(edf.env.get("quick").unwrap() == "yaml");
+        assert!(edf.mounts.len() == 3);
+    }
+
+    #[test]
+    #[serial]
+    fn render_provenance() {
+        let old_cwd = env::current_dir().unwrap();
+        env::set_current_dir(Path::new("test/toml")).unwrap();
+        let result = render_with_provenance(String::from("./base-multi-2.toml"));
+        env::set_current_dir(old_cwd).unwrap();
+
+        let (edf, prov) = result.unwrap();
+        assert!(edf.image == "ubuntu:multi-2");
+        let image = prov.get("image").unwrap();
+        assert!(image.file_path == "./base-multi-2.toml");
+        assert!(image.line == Some(2));
+        let quick = prov.get("annotations.quick").unwrap();
+        assert!(quick.file_path == "./base-multi-2.toml");
+        assert!(quick.line == Some(5));
+        let minus_one = prov.get("env.minus_one").unwrap();
+        assert!(minus_one.file_path == "./table-env.toml");
+        let entrypoint = prov.get("entrypoint").unwrap();
+        assert!(entrypoint.file_path == "./top-simple-1.toml");
+    }
+
+    #[test]
+    #[serial]
+    fn render_to_toml_string() {
+        let edf = get_rendered_edf("base-multi-2.toml").unwrap();
+        let out = edf.to_toml_string().unwrap();
+        assert!(out == edf.to_toml_string().unwrap());
+
+        let keys: Vec<&str> = out
+            .lines()
+            .skip_while(|l| *l != "[env]")
+            .skip(1)
+            .map(|l| l.split(" = ").next().unwrap())
+            .collect();
+        assert!(keys == vec!["minus_one", "quick", "two_plus_two"]);
+
+        let back = get_edf_from_string(out).unwrap();
+        assert!(back.image == "ubuntu:multi-2");
+        assert!(back.env.get("quick").unwrap() == "counting");
+        assert!(back.annotations.get("quick").unwrap() == "algebra");
+    }
+
+    #[test]
+    #[serial]
+    fn render_to_json() {
+        let edf = get_rendered_edf("base-multi-2.toml").unwrap();
+        let out = edf.to_json().unwrap();
+        assert!(out == edf.to_json().unwrap());
+
+        let v: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert!(v["image"] == "ubuntu:multi-2");
+        assert!(v["env"]["quick"] == "counting");
+
+        let err = get_rendered_edf("cycle-a.toml").err().unwrap();
+        let v: serde_json::Value = serde_json::from_str(&err.to_json().unwrap()).unwrap();
+        assert!(v["code"] == 34);
+        assert!(v["kind"] == "BaseEnvironmentCycle");
+        assert!(v["chain"].as_array().unwrap().len() == 3);
+        assert!(v["message"].as_str().unwrap().starts_with("base_environment cycle"));
+    }
+
+    #[test]
+    fn validate_report() {
+        let report = validate_with_report(String::from("test/toml/report-warnings.toml")).unwrap();
+        assert!(report.is_valid());
+        assert!(report.warnings.len() == 2);
+        assert!(report.warnings.iter().any(|w| w.id == "unknown-key" && w.key == Some("enviroment".to_string())));
+        assert!(report.warnings.iter().any(|w| w.id == "empty-list" && w.key == Some("mounts".to_string())));
+
+        let report = validate_with_report(String::from("test/toml/base-rec.toml")).unwrap();
+        assert!(report.is_valid());
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn validate_edf_version() {
+        assert!(validate(String::from("test/toml/top-simple-1.toml")).is_ok());
+        assert!(validate(String::from("test/toml/version-2.toml")).is_ok());
+
+        // v2 doesn't allow keys outside of the schema
+        assert!(validate(String::from("test/toml/unknown_entry.toml")).is_ok());
+        let err = validate(String::from("test/toml/version-2-unknown.toml")).err().unwrap();
+        assert!(err.code() == 4);
+
+        let err = validate(String::from("test/toml/version-7.toml")).err().unwrap();
+        assert!(err.kind == SarusErrorKind::UnsupportedEdfVersion { version: String::from("7") });
+        assert!(err.msg.contains("supported versions are 1, 2"));
+
+        let report = validate_with_report(String::from("test/toml/version-7.toml")).unwrap();
+        assert!(report.errors[0].id == "edf-version");
+
+        let edf = render(String::from("test/toml/version-2.toml")).unwrap();
+        assert!(edf.image == "ubuntu:v2");
+    }
+
+    #[test]
+    fn render_from_string() {
+        let sp = vec![String::from("test/toml")];
+        let content = String::from("base_environment = \"table-env\"\nworkdir = \"${HOME}\"\n");
+        let mut env = HashMap::new();
+        env.insert(String::from("HOME"), String::from("/home/user"));
+
+        let edf = render_from_str(content, "socket", sp.clone(), &Some(env)).unwrap();
+        assert!(edf.image == "ubuntu:env");
+        assert!(edf.env.get("quick").unwrap() == "maths");
+        assert!(edf.workdir == "/home/user");
+
+        // $$ keeps a variable for the container to expand
+        let content = String::from("image = \"ubuntu:24.04\"\n[env]\nPATH = \"$$HOME/bin:$${PATH}\"\n");
+        let edf = render_from_str(content, "socket", sp.clone(), &Some(HashMap::new())).unwrap();
+        assert!(edf.env.get("PATH").unwrap() == "$HOME/bin:${PATH}");
+
+        let content = String::from("image: ubuntu:yaml\nentrypoint: false\n");
+        let edf = render_from_str(content, "request.yaml", sp.clone(), &None).unwrap();
+        assert!(edf.image == "ubuntu:yaml");
+        assert!(!edf.entrypoint);
+
+        let content = String::from("workdir = \"/tmp\"\n");
+        let err = render_from_str(content, "socket", sp, &None).err().unwrap();
+        assert!(err.code() == 4);
+        assert!(err.file_path == Some(String::from("socket")));
+    }
+
+    #[test]
+    fn render_registry_rewrite() {
+        let mut registry = RegistryRewrite {
+            default_registry: Some(String::from("registry.example.org/dockerhub-mirror")),
+            ..Default::default()
+        };
+        registry.aliases.insert(String::from("quay.io"), String::from("mirror.example.org/quay"));
+        let opts = RenderOptions::new().registry(registry);
+
+        let edf = render_with_options(String::from("test/toml/top-simple-1.toml"), &opts).unwrap();
+        assert!(edf.image == "registry.example.org/dockerhub-mirror/ubuntu:simple-1");
+
+        let content = String::from("image = \"quay.io/org/app\"\n");
+        let edf = render_from_str_with_options(content, "socket", &opts).unwrap();
+        assert!(edf.image == "mirror.example.org/quay/org/app");
+    }
+
+    #[test]
+    fn render_expansion_fields() {
+        let content = String::from(
+            "image = \"ubuntu:24.04\"\nworkdir = \"$SCRATCH\"\nmounts = [\"/data/$SCRATCH:/scratch\"]\n\
+             [annotations]\n\"com.example.hook.args\" = \"--dir=$SCRATCH\"\n",
+        );
+        let env = HashMap::from([(String::from("SCRATCH"), String::from("scratch/u"))]);
+        let opts = RenderOptions::new().env(env).expand_field("annotations", false);
+        let edf = render_from_str_with_options(content.clone(), "socket", &opts).unwrap();
+        assert!(edf.annotations.get("com.example.hook.args").unwrap() == "--dir=$SCRATCH");
+        assert!(edf.workdir == "scratch/u");
+        assert!(edf.mounts.iter().any(|m| m.to_volume_string() == "/data/scratch/u:/scratch"));
+
+        let opts = opts.expand_field("annotations", true).expand_field("mounts", false);
+        let edf = render_from_str_with_options(content, "socket", &opts).unwrap();
+        assert!(edf.annotations.get("com.example.hook.args").unwrap() == "--dir=scratch/u");
+        assert!(edf.mounts.iter().any(|m| m.to_volume_string().starts_with("/data/$SCRATCH")));
+    }
+
+    #[test]
+    fn render_merge_policy() {
+        let opts = RenderOptions::new().search_paths(vec![String::from("test/toml")]);
+        let path = String::from("merge-top");
+        let volumes = |e: &EDF| e.mounts.iter().map(|m| m.to_volume_string()).collect::<Vec<_>>();
+
+        let edf = render_with_options(path.clone(), &opts).unwrap();
+        assert!(edf.devices == vec!["nvidia.com/gpu=0", "nvidia.com/gpu=1", "amd.com/gpu=0"]);
+        assert!(volumes(&edf) == vec!["/capstor/scratch:/scratch", "/users:/users", "/iopsstor/scratch:/scratch:ro"]);
+        assert!(edf.warnings == vec!["mount /iopsstor/scratch:/scratch:ro shadows /capstor/scratch:/scratch"]);
+
+        let edf = render_with_options(path.clone(), &opts.clone().merge_policy(MergePolicy::LastWinsByTarget)).unwrap();
+        assert!(edf.devices.len() == 3);
+        assert!(volumes(&edf) == vec!["/iopsstor/scratch:/scratch:ro", "/users:/users"]);
+
+        let edf = render_with_options(path, &opts.merge_policy(MergePolicy::KeepDuplicates)).unwrap();
+        assert!(edf.devices.len() == 4);
+        assert!(edf.mounts.len() == 4);
+    }
+
+    #[test]
+    fn render_error_list() {
+        let tmp = env::temp_dir().join(format!("raster-errors-{}", std::process::id()));
+        std::fs::create_dir_all(&tmp).unwrap();
+        let path = tmp.join("broken.toml").display().to_string();
+        std::fs::write(
+            &path,
+            "image = \"ubuntu:24.04\"\nworkdir = \"$NO_WORKDIR\"\ndevices = [\"dev1\", \"/dev/fuse\"]\n\
+             mounts = [\"/a:/b:bogus\", \"/c:/d\"]\n[env]\nA = \"$NO_A\"\nB = \"$NO_B\"\n",
+        )
+        .unwrap();
+        let opts = RenderOptions::new().search_paths(vec![]).env(HashMap::new());
+
+        // The plain render stops at the first failure
+        assert!(render_with_options(path.clone(), &opts).is_err());
+
+        let list = render_with_options_errors(path.clone(), &opts).err().unwrap();
+        let codes: Vec<u64> = list.errors.iter().map(|e| e.code()).collect();
+        assert!(codes.iter().filter(|c| **c == 17).count() == 3);
+        assert!(list.errors.len() == 5);
+        assert!(list.errors.iter().any(|e| e.kind == SarusErrorKind::InvalidDevice { device: String::from("dev1") }));
+        assert!(list.to_string().lines().count() == 5);
+
+        std::fs::write(&path, "image = \"ubuntu:24.04\"\n").unwrap();
+        assert!(render_with_options_errors(path, &opts).is_ok());
+        std::fs::remove_dir_all(tmp).unwrap();
+    }
+
+    #[test]
+    fn render_error_location() {
+        let opts = RenderOptions::new().search_paths(vec![]).env(HashMap::new());
+        let render = |content: &str, opts: &RenderOptions| {
+            render_from_str_with_options(String::from(content), "a.toml", opts).err().unwrap()
+        };
+        let at = |line, column| Some(Location { line, column });
+
+        let err = render("image = \"ubuntu:24.04\"\nmounts = [\n    \"/a:/a\",\n    \"/a:/b:bogus\",\n]\n", &opts);
+        assert!(err.code() == 31 && err.location == at(4, 5));
+        assert!(err.file_path.as_deref() == Some("a.toml"));
+        assert!(err.to_string().starts_with("Error 031 on a.toml:4:5: "));
+        let j: serde_json::Value = serde_json::from_str(&err.to_json().unwrap()).unwrap();
+        assert!(j["line"] == 4 && j["column"] == 5);
+
+        let err = render("image = \"ubuntu:24.04\"\n\n  entrypoint = \"yes\"\n", &opts);
+        assert!(err.code() == 4 && err.location == at(3, 3));
+
+        let err = render("image = \"ubuntu:24.04\"\nimagee = \"x\"\n", &opts.clone().strict(true));
+        assert!(err.code() == 33 && err.location == at(2, 1));
+
+        let err = render("image = \"ubuntu:24.04\"\nworkdir = /a\n", &opts);
+        assert!(err.code() == 3 && err.location.unwrap().line == 2);
+
+        // No position for YAML files
+        let err = render_from_str_with_options(String::from("image: ubuntu\nentrypoint: yes2\n"), "a.yaml", &opts)
+            .err()
+            .unwrap();
+        assert!(err.code() == 4 && err.location.is_none());
+    }
+
+    #[test]
+    fn render_require_image_digest() {
+        let opts = RenderOptions::new().require_image_digest(true);
+        let err = render_with_options(String::from("test/toml/top-simple-1.toml"), &opts)
+            .err()
+            .unwrap();
+        assert!(err.code() == 39);
+
+        let content = format!("image = \"ubuntu@sha256:{}\"\n", "0".repeat(64));
+        assert!(render_from_str_with_options(content, "socket", &opts).is_ok());
+    }
+
+    #[test]
+    fn render_many_edfs() {
+        let paths = vec![
+            String::from("test/toml/top-simple-1.toml"),
+            String::from("test/toml/no-image.toml"),
+            String::from("test/toml/devices-cdi.toml"),
+        ];
+        let opts = RenderOptions::new().search_paths(vec![String::from("test/toml")]);
+        let res = render_many(paths.clone(), &opts);
+        assert!(res.len() == 3);
+        for (p, r) in paths.into_iter().zip(res.iter()) {
+            let single = render_with_options(p, &opts);
+            assert!(r.is_ok() == single.is_ok());
+            if let (Ok(a), Ok(b)) = (r, single) {
+                assert!(a.image == b.image);
+            }
+        }
+        assert!(res[1].as_ref().err().unwrap().code() == 4);
+        assert!(render_many(vec![], &opts).is_empty());
+    }
+
+    struct Boom;
+
+    impl AnnotationProcessor for Boom {
+        fn name(&self) -> &'static str {
+            "boom"
+        }
+
+        fn process(&self, annotations: &mut BTreeMap<String, String>) -> SarusResult<()> {
+            assert!(!annotations.contains_key("boom"), "boom annotation");
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn render_many_panics() {
+        let tmp = env::temp_dir().join(format!("raster-many-{}", std::process::id()));
+        std::fs::create_dir_all(&tmp).unwrap();
+        std::fs::write(tmp.join("ok.toml"), "image = \"ubuntu\"\n").unwrap();
+        std::fs::write(tmp.join("boom.toml"), "image = \"ubuntu\"\n[annotations]\nboom = \"1\"\n").unwrap();
+        let mut processors = AnnotationProcessors::empty();
+        processors.register(Boom);
+        let opts = RenderOptions::new()
+            .search_paths(vec![tmp.display().to_string()])
+            .annotation_processors(processors);
+
+        let paths = ["ok", "boom", "ok"].map(|n| tmp.join(format!("{n}.toml")).display().to_string());
+        let res = render_many(paths.to_vec(), &opts);
+        assert!(res.len() == 3 && res[0].is_ok() && res[2].is_ok());
+        let err = res[1].as_ref().err().unwrap();
+        assert!(err.kind == SarusErrorKind::RenderPanicked { path: paths[1].clone() });
+        assert!(err.msg.contains("boom annotation"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn render_search_index() {
+        let sp = expand_search_path("test/search/*/");
+        let edf = render_from_search_paths(String::from("pytorch/24.01"), sp.clone(), &None).unwrap();
+        assert!(edf.image == "nvcr.io/nvidia/pytorch:24.01-py3");
+        assert!(edf.maintainer == Some(String::from("ML team <ml@example.org>")));
+        assert!(edf.tags == vec!["ml", "gpu"]);
+        assert!(edf.to_oci_spec().annotations.is_empty());
+        let edf = render_from_search_paths(String::from("analysis"), sp.clone(), &None).unwrap();
+        assert!(edf.workdir == "/work");
+        assert!(render_from_search_paths(String::from("tensorflow"), sp, &None).is_err());
+    }
+
+    #[test]
+    fn render_writable_overlay() {
+        let opts = RenderOptions::new()
+            .writable_overlay_path(String::from("/scratch/overlay"))
+            .writable_overlay_size(String::from("10G"));
+
+        let edf = render_from_str_with_options(String::from("image = \"ubuntu\""), "socket", &opts).unwrap();
+        assert!(edf.writable_overlay_path.as_deref() == Some("/scratch/overlay"));
+        assert!(edf.writable_overlay_size.as_deref() == Some("10G"));
+
+        let content = String::from("image = \"ubuntu\"\nwritable_overlay_path = \"/tmp/ov\"\n");
+        let edf = render_from_str_with_options(content, "socket", &opts).unwrap();
+        assert!(edf.writable_overlay_path.as_deref() == Some("/tmp/ov"));
+
+        let edf = render_from_str_with_options(String::from("image = \"ubuntu\"\nwritable = false\n"), "socket", &opts);
+        assert!(edf.unwrap().writable_overlay_path.is_none());
+
+        for (content, field) in [
+            ("writable = false\nwritable_overlay_size = \"1G\"", "writable"),
+            ("writable_overlay_path = \"overlay\"", "writable_overlay_path"),
+        ] {
+            let content = format!("image = \"ubuntu\"\n{content}\n");
+            let err = render_from_str_with_options(content, "socket", &RenderOptions::new()).err().unwrap();
+            assert!(err.kind == SarusErrorKind::InvalidWritableOverlay { field: String::from(field) });
+        }
+    }
+
+    #[test]
+    fn render_user_mapping() {
+        let content = String::from("image = \"ubuntu\"\nuser = \"alice:hpc\"\nuserns = \"keep-id\"\n");
+        let edf = render_from_str_with_options(content, "socket", &RenderOptions::new()).unwrap();
+        assert!(edf.user.as_deref() == Some("alice:hpc") && edf.userns.as_deref() == Some("keep-id"));
+
+        for content in ["group = \"100\"", "user = \"1000:100\"\ngroup = \"100\""] {
+            let content = format!("image = \"ubuntu\"\n{content}\n");
+            let err = render_from_str_with_options(content, "socket", &RenderOptions::new()).err().unwrap();
+            assert!(err.kind == SarusErrorKind::InvalidUserMapping { field: String::from("group") });
+        }
+        let content = String::from("image = \"ubuntu\"\nuserns = \"keep-id:uid=me\"\n");
+        let opts = RenderOptions::new().skip_validation(true);
+        let err = render_from_str_with_options(content, "socket", &opts).err().unwrap();
+        assert!(err.kind == SarusErrorKind::InvalidUserMapping { field: String::from("userns") });
+    }
+
+    #[test]
+    fn render_network() {
+        let mut opts = RenderOptions::new();
+        opts.env = Some(HashMap::from([(String::from("JOB_NET"), String::from("job-42"))]));
+        let content = String::from("image = \"ubuntu\"\nnetwork = \"$JOB_NET\"\n");
+        let edf = render_from_str_with_options(content, "socket", &opts).unwrap();
+        assert!(edf.network.as_deref() == Some("job-42"));
+        assert!(edf.to_podman_args(&Config::default()).contains(&String::from("--network=job-42")));
+        assert!(engine::Enroot.unsupported(&edf) == vec!["networks"]);
+
+        let content = String::from("image = \"ubuntu\"\nnetwork = \"host\"\n");
+        let edf = render_from_str_with_options(content, "socket", &opts).unwrap();
+        assert!(engine::Enroot.unsupported(&edf).is_empty());
+
+        let content = String::from("image = \"ubuntu\"\nnetwork = \"bridge:ip=10.0.0.2\"\n");
+        let err = render_from_str_with_options(content, "socket", &opts).err().unwrap();
+        assert!(err.code() == 58);
+    }
+
+    #[test]
+    fn render_auth_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("raster-auth-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let auth = dir.join("auth.json");
+        std::fs::write(&auth, "{}").unwrap();
+        std::fs::set_permissions(&auth, std::fs::Permissions::from_mode(0o600)).unwrap();
+
+        let mut opts = RenderOptions::new();
+        opts.env = Some(HashMap::from([(String::from("AUTH_DIR"), dir.display().to_string())]));
+        let content = String::from("image = \"ghcr.io/org/app\"\nauth_file = \"$AUTH_DIR/auth.json\"\n");
+        let edf = render_from_str_with_options(content.clone(), "socket", &opts).unwrap();
+        assert!(edf.auth_file.as_deref() == Some(auth.to_str().unwrap()));
+        assert!(edf.to_podman_args(&Config::default()).contains(&format!("--authfile={}", auth.display())));
+
+        let config = Config {
+            registry_auth_file: String::from("/etc/containers/auth.json"),
+            ..Default::default()
+        };
+        let plain = get_edf_from_string(String::from("image = \"ghcr.io/org/app\"\n")).unwrap();
+        assert!(plain.to_podman_args(&config).contains(&String::from("--authfile=/etc/containers/auth.json")));
+
+        std::fs::set_permissions(&auth, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let err = render_from_str_with_options(content, "socket", &opts).err().unwrap();
+        assert!(err.code() == 63 && err.msg.contains("mode 644"));
+        let content = String::from("image = \"ubuntu\"\nauth_file = \"/nonexistent/auth.json\"\n");
+        assert!(render_from_str_with_options(content, "socket", &opts).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn render_labels() {
+        let mut opts = RenderOptions::new();
+        opts.env = Some(HashMap::from([(String::from("SLURM_JOB_ACCOUNT"), String::from("g42"))]));
+        let content = String::from(
+            "image = \"ubuntu\"\n[labels]\nproject = \"$SLURM_JOB_ACCOUNT\"\nsite = \"cscs\"\n[annotations]\na = \"1\"\n",
+        );
+        let mut raw: RawEDF = toml::from_str(&content).unwrap();
+        raw.merge(toml::from_str("[labels]\nsite = \"eth\"\n").unwrap());
+        assert!(raw.labels.as_ref().unwrap().len() == 2 && raw.labels.as_ref().unwrap()["site"] == "eth");
+
+        let edf = render_from_str_with_options(content, "socket", &opts).unwrap();
+        assert!(edf.labels["project"] == "g42" && !edf.annotations.contains_key("project"));
+        let args = edf.to_podman_args(&Config::default());
+        assert!(args.contains(&String::from("--label=project=g42")));
+        assert!(args.contains(&String::from("--annotation=a=1")));
+    }
+
+    #[test]
+    fn render_env_passthrough() {
+        let mut opts = RenderOptions::new();
+        opts.env = Some(HashMap::from([
+            (String::from("SLURM_JOB_ID"), String::from("42")),
+            (String::from("SLURM_NTASKS"), String::from("8")),
+            (String::from("CUDA_VISIBLE_DEVICES"), String::from("0,1")),
+            (String::from("SLURM_SECRET"), String::from("x")),
+            (String::from("HOME"), String::from("/users/a")),
+        ]));
+        opts.expansion_denied_vars = vec![String::from("SLURM_SECRET")];
+        let content = String::from(
+            "image = \"ubuntu\"\nenv_passthrough = [\"SLURM_*\", \"CUDA_VISIBLE_DEVICES\"]\n[env]\nSLURM_NTASKS = \"1\"\n",
+        );
+        let edf = render_from_str_with_options(content, "socket", &opts).unwrap();
+        let keys: Vec<&str> = edf.env.keys().map(|k| k.as_str()).collect();
+        assert!(keys == vec!["CUDA_VISIBLE_DEVICES", "SLURM_JOB_ID", "SLURM_NTASKS"]);
+        assert!(edf.env["SLURM_NTASKS"] == "1" && edf.env["SLURM_JOB_ID"] == "42");
+    }
+
+    #[test]
+    fn render_template_vars() {
+        let vars = HashMap::from([(String::from("scratch"), String::from("/capstor/scratch"))]);
+        let opts = RenderOptions::new().template_vars(vars);
+
+        let content = String::from(
+            r#"
+            image = "ubuntu"
+            workdir = "%{scratch}/%{uid}"
+            mounts = ["%{scratch}:/scratch"]
+            [env]
+            HOST = "%{hostname}"
+            LITERAL = "%%{scratch}"
+            "#,
+        );
+        let edf = render_from_str_with_options(content, "socket", &opts).unwrap();
+        let builtins = builtin_template_vars();
+        assert!(edf.workdir == format!("/capstor/scratch/{}", builtins.get("uid").unwrap()));
+        assert!(edf.mounts[0].source() == "/capstor/scratch");
+        assert!(edf.env.get("HOST") == builtins.get("hostname"));
+        assert!(edf.env.get("LITERAL").unwrap() == "%{scratch}");
+
+        let content = String::from("image = \"ubuntu\"\nworkdir = \"%{jobid}\"\n");
+        let err = render_from_str_with_options(content, "socket", &opts).err().unwrap();
+        assert!(err.code() == 45);
+    }
+
+    #[test]
+    fn render_engine_tables() {
+        let content = String::from(
+            r#"
+            image = "ubuntu"
+            [annotations]
+            "com.sarus.parallax_path" = "/opt/parallax-flat"
+            "com.sarus.podman_module" = "hpc"
+            [parallax]
+            path = "/opt/parallax"
+            imagestore_keepalive = true
+            [podman]
+            tmp_path = "/dev/shm/podman"
+            "#,
+        );
+        let edf = render_from_str_with_options(content, "socket", &RenderOptions::new()).unwrap();
+        assert!(edf.annotations.get("com.sarus.parallax_path").unwrap() == "/opt/parallax");
+        assert!(edf.annotations.get("com.sarus.podman_module").unwrap() == "hpc");
+
+        let mut config = Config::default();
+        update_config_by_user(&mut config, edf).unwrap();
+        assert!(config.parallax_path == "/opt/parallax");
+        assert!(config.parallax_imagestore_keepalive);
+        assert!(config.podman_tmp_path == "/dev/shm/podman");
+
+        let content = String::from("image = \"ubuntu\"\n[parallax]\nmp_uid = 0\n");
+        assert!(render_from_str_with_options(content, "socket", &RenderOptions::new()).is_err());
+    }
+
+    #[test]
+    fn render_mpi() {
+        let template = MpiTemplate {
+            annotations: HashMap::from([(String::from("com.hooks.mpi.enabled"), String::from("true"))]),
+            env: HashMap::from([
+                (String::from("OMPI_MCA_pml"), String::from("ucx")),
+                (String::from("MPI_ROOT"), String::from("$SCRATCH/openmpi")),
+            ]),
+            mounts: vec![String::from("$SCRATCH/openmpi:/opt/openmpi:ro")],
+            devices: vec![],
+        };
+        let uenv = HashMap::from([(String::from("SCRATCH"), String::from("/scratch"))]);
+        let opts = RenderOptions::new()
+            .env(uenv)
+            .mpi_templates(HashMap::from([(String::from("openmpi"), template)]));
+
+        let content = String::from("image = \"ubuntu\"\nmpi = \"openmpi\"\n[env]\nOMPI_MCA_pml = \"ob1\"\n");
+        let edf = render_from_str_with_options(content, "socket", &opts).unwrap();
+        assert!(edf.annotations.get("com.hooks.mpi.enabled").unwrap() == "true");
+        assert!(edf.env.get("OMPI_MCA_pml").unwrap() == "ob1");
+        assert!(edf.env.get("MPI_ROOT").unwrap() == "/scratch/openmpi");
+        assert!(edf.mounts[0].to_volume_string() == "/scratch/openmpi:/opt/openmpi:ro");
+
+        let content = String::from("image = \"ubuntu\"\nmpi = \"mpich\"\n");
+        let err = render_from_str_with_options(content, "socket", &opts).err().unwrap();
+        assert!(err.kind == SarusErrorKind::UnknownMpi { name: String::from("mpich") });
+    }
+
+    #[test]
+    fn render_hooks() {
+        let ssh = HookDefinition {
+            path: String::from("/opt/sarus/hooks/ssh_hook"),
+            stage: String::from("createContainer"),
+            args: vec![String::from("ssh_hook"), String::from("start")],
+            ..Default::default()
+        };
+        let nccl = HookDefinition {
+            path: String::from("/opt/sarus/hooks/nccl_hook"),
+            stage: String::from("prestart"),
+            ..Default::default()
+        };
+        let opts = RenderOptions::new().oci_hooks(HashMap::from([
+            (String::from("ssh"), ssh),
+            (String::from("nccl"), nccl),
+        ]));
+
+        let content = String::from("image = \"ubuntu\"\n[hooks]\nssh = true\nnccl = true\n");
+        let edf = render_from_str_with_options(content, "socket", &opts).unwrap();
+        assert!(edf.hooks.len() == 2);
+        assert!(edf.hooks[0].name == "nccl" && edf.hooks[1].name == "ssh");
+        let spec = edf.to_oci_spec();
+        assert!(spec.hooks.get("createContainer").unwrap()[0].path == "/opt/sarus/hooks/ssh_hook");
+
+        let content = String::from("image = \"ubuntu\"\n[hooks]\nssh = false\n");
+        let edf = render_from_str_with_options(content, "socket", &opts).unwrap();
+        assert!(edf.hooks.is_empty());
+
+        let content = String::from("image = \"ubuntu\"\n[hooks]\ncxi = true\n");
+        let err = render_from_str_with_options(content, "socket", &opts).err().unwrap();
+        assert!(err.kind == SarusErrorKind::UnknownHook { name: String::from("cxi") });
+    }
+
+    #[test]
+    fn render_gpu() {
+        let mut uenv = HashMap::new();
+        uenv.insert(String::from("CUDA_VISIBLE_DEVICES"), String::from("0,1"));
+        let paths = GpuPaths {
+            dev_dir: String::from("test/gpu/dev-nvidia"),
+            cdi_dirs: vec![String::from("test/gpu/cdi")],
+        };
+        let opts = RenderOptions::new().env(uenv).gpu_paths(paths);
+
+        let content = String::from("image = \"ubuntu\"\ngpu = true\ndevices = [\"/dev/fuse\"]\n");
+        let edf = render_from_str_with_options(content, "socket", &opts).unwrap();
+        assert!(edf.devices == vec!["/dev/fuse", "nvidia.com/gpu=all"]);
+        assert!(edf.env.get("CUDA_VISIBLE_DEVICES").unwrap() == "0,1");
+        assert!(edf.annotations.get("com.hooks.gpu.vendor").unwrap() == "nvidia");
+
+        let edf = render_from_str_with_options(String::from("image = \"ubuntu\""), "socket", &opts).unwrap();
+        assert!(edf.devices.is_empty() && !edf.env.contains_key("CUDA_VISIBLE_DEVICES"));
+
+        let none = RenderOptions::new().gpu_paths(GpuPaths {
+            dev_dir: String::from("test/etc"),
+            cdi_dirs: vec![],
+        });
+        let content = String::from("image = \"ubuntu\"\ngpu = true\n");
+        let err = render_from_str_with_options(content, "socket", &none).err().unwrap();
+        assert!(err.kind == SarusErrorKind::GpuNotFound);
+    }
+
+    #[test]
+    fn render_redact_secrets() {
+        let content = String::from(
+            r#"
+            image = "ubuntu"
+            [env]
+            HF_TOKEN = "hf_abc"
+            HOME = "/home/u"
+            "#,
+        );
+        let opts = RenderOptions::new().sensitive_env(vec![String::from("*_TOKEN")]);
+        let edf = render_from_str_with_options(content.clone(), "socket", &opts).unwrap();
+        assert!(edf.env.get("HF_TOKEN").unwrap() == "hf_abc");
+        let json = edf.to_json().unwrap();
+        assert!(!json.contains("hf_abc") && json.contains(secrets::REDACTED));
+        assert!(!format!("{edf:?}").contains("hf_abc"));
+        assert!(edf.to_toml_string().unwrap().contains("/home/u"));
+
+        let opts = opts.reveal_secrets(true);
+        let edf = render_from_str_with_options(content, "socket", &opts).unwrap();
+        assert!(edf.to_json().unwrap().contains("hf_abc"));
+    }
+
+    #[test]
+    #[serial]
+    fn render_error_kind() {
+        let err = get_rendered_edf("base-rec.toml").err().unwrap();
+        assert!(matches!(
+            err.kind,
+            SarusErrorKind::EnvironmentNotFound { ref name, .. } if name == "./baseenv-rec.toml"
+        ));
+        assert!(err.code() == 6);
+    }
+
+    #[test]
+    fn render_removals() {
+        let tmp = env::temp_dir().join(format!("raster-remove-{}", std::process::id()));
+        std::fs::create_dir_all(&tmp).unwrap();
+        let files = [
+            (
+                "site",
+                "image = \"ubuntu\"\ndevices = [\"/dev/fuse\", \"/dev/kvm\"]\nmounts = [\"/a:/a\", \"/b:/b:ro\", \"/c:/c\"]\n\
+                 [env]\nA = \"1\"\nB = \"2\"\n[annotations]\nk = \"v\"\nl = \"w\"\n",
+            ),
+            (
+                "app",
+                "base_environment = \"site\"\ndevices = [\"/dev/dri\"]\ndevices_remove = [\"/dev/kvm\"]\n\
+                 mounts = [\"/d:/c\"]\nmounts_remove = [\"/b:/b\", \"/c\"]\nenv_remove = [\"A\", \"Z\"]\n\
+                 annotations_remove = [\"k\"]\n",
+            ),
+            ("tweak", "env_remove = [\"B\"]\n"),
+            ("top", "base_environment = \"app\"\ninclude = \"tweak\"\n"),
+        ];
+        for (name, content) in files {
+            std::fs::write(tmp.join(format!("{name}.toml")), content).unwrap();
+        }
+        let opts = RenderOptions::new()
+            .search_paths(vec![tmp.display().to_string()])
+            .env(HashMap::new());
+
+        let edf = render_with_options(String::from("app"), &opts).unwrap();
+        assert!(edf.devices == vec!["/dev/fuse", "/dev/dri"]);
+        let targets: Vec<String> = edf.mounts.iter().map(|m| m.to_volume_string()).collect();
+        assert!(targets == vec!["/a:/a", "/d:/c"]);
+        assert!(edf.env.keys().collect::<Vec<_>>() == vec!["B"]);
+        assert!(edf.annotations.get("k").is_none() && edf.annotations.get("l").is_some());
+
+        // Removals of a fragment without bases don't reach the other layers
+        let edf = render_with_options(String::from("top"), &opts).unwrap();
+        assert!(edf.env.get("B").is_some());
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn render_unset() {
+        let tmp = env::temp_dir().join(format!("raster-unset-{}", std::process::id()));
+        std::fs::create_dir_all(&tmp).unwrap();
+        let files = [
+            (
+                "site",
+                "image = \"ubuntu\"\nworkdir = \"/opt\"\nwritable = true\nuser = \"1000\"\n\
+                 [annotations]\n\"com.sarus.perfmon\" = \"true\"\nk = \"v\"\n",
+            ),
+            ("app", "base_environment = \"site\"\nunset = [\"workdir\", \"writable\", \"perfmon\", \"user\"]\nuser = \"2000\"\n"),
+            ("bad", "base_environment = \"site\"\nunset = [\"imagee\"]\n"),
+        ];
+        for (name, content) in files {
+            std::fs::write(tmp.join(format!("{name}.toml")), content).unwrap();
+        }
+        let opts = RenderOptions::new()
+            .search_paths(vec![tmp.display().to_string()])
+            .env(HashMap::new());
+
+        let edf = render_with_options(String::from("app"), &opts).unwrap();
+        assert!(edf.workdir == get_default_workdir() && edf.writable == get_default_writable());
+        assert!(edf.annotations.get("com.sarus.perfmon").is_none() && edf.annotations["k"] == "v");
+        // Fields of the file itself win over its unset list
+        assert!(edf.user.as_deref() == Some("2000"));
+
+        let err = render_with_options(String::from("bad"), &opts).err().unwrap();
+        assert!(err.kind == SarusErrorKind::UnknownKey { key: String::from("imagee") });
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    // Fields of RawEDF which unset can't reset, any other one must be in
+    // UNSET_FIELDS.
+    const NOT_UNSETTABLE: [&str; 11] = [
+        "annotations_remove",
+        "base_environment",
+        "devices_remove",
+        "edf_version",
+        "env_file",
+        "env_remove",
+        "include",
+        "mounts_remove",
+        "parallax",
+        "podman",
+        "unset",
+    ];
+
+    #[test]
+    fn render_unset_fields() {
+        let raw = serde_json::to_value(RawEDF::default()).unwrap();
+        for field in raw.as_object().unwrap().keys() {
+            let unsettable = UNSET_FIELDS.iter().any(|(n, _)| n == field);
+            assert!(unsettable != NOT_UNSETTABLE.contains(&field.as_str()), "{field}");
+        }
+        assert!(UNSET_FIELDS.iter().all(|(n, _)| raw.get(n).is_some()));
+
+        let mut r = RawEDF {
+            workdir: Some(String::from("/opt")),
+            annotations: Some(Annotations::TypeHashMap(HashMap::from([
+                (String::from("com.sarus.perfmon"), String::from("true")),
+                (String::from("com.sarus.other"), String::from("x")),
+            ]))),
+            ..Default::default()
+        };
+        for n in ["workdir", "perfmon", "other"] {
+            r.unset_field(n);
+        }
+        let a = annotations_as_hashmap(r.annotations.unwrap());
+        assert!(r.workdir.is_none() && a.len() == 1 && a.contains_key("com.sarus.other"));
+    }
+
+    #[test]
+    fn render_env_names() {
+        for name in ["pytorch/24.01", "merge-base", "team/../site", "my_env.v2"] {
+            assert!(check_env_name(name).is_ok(), "{name}");
+        }
+        let opts = RenderOptions::new().search_paths(vec![String::from("test/etc")]);
+        for name in ["", "a b", "team//site", "site/", "env;rm", "caf\u{e9}", "a\\b", "~user/env"] {
+            let err = render_with_options(String::from(name), &opts).err().unwrap();
+            assert!(err.kind == SarusErrorKind::InvalidEnvironmentName { name: String::from(name) }, "{name}");
+        }
+        let content = String::from("base_environment = \"base env\"\nimage = \"ubuntu\"\n");
+        let err = render_from_str_with_options(content, "socket", &opts).err().unwrap();
+        assert!(err.code() == 68 && err.msg.contains("\"base env\""));
+    }
+
     #[test]
     #[serial]
     fn render_not_a_toml_file() {
@@ -815,4 +3136,127 @@ mod tests {
         let result = render(String::from("test/toml/unknown_entry.toml"));
         assert!(result.is_ok());
     }
+
+    #[test]
+    #[serial]
+    fn render_unknown_entry_strict() {
+        let opts = RenderOptions::new().strict(true);
+        let err = render_with_options(String::from("test/toml/unknown_entry.toml"), &opts)
+            .err()
+            .unwrap();
+        assert!(err.kind == SarusErrorKind::UnknownKey { key: String::from("image2") });
+        assert!(err.file_path == Some(String::from("test/toml/unknown_entry.toml")));
+
+        let result = render_with_options(String::from("test/toml/top-mounts.toml"), &opts);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    #[serial]
+    fn render_options() {
+        let old_cwd = env::current_dir().unwrap();
+        env::set_current_dir(Path::new("test/toml")).unwrap();
+
+        let sp = vec![String::from(".")];
+        let opts = RenderOptions::new().search_paths(sp).max_levels(2);
+        let result = render_with_options(String::from("base-nested"), &opts);
+
+        let opts = opts.max_levels(3);
+        let nested = render_with_options(String::from("base-nested"), &opts);
+
+        let path = String::from("no-image.toml");
+        let invalid = render_with_options(path.clone(), &opts);
+
+        let mut uenv = HashMap::new();
+        uenv.insert(String::from("SCRATCH"), String::from("/scratch"));
+        let opts = RenderOptions::new()
+            .skip_validation(true)
+            .expansion_backend(ExpansionBackend::Bash)
+            .env(uenv);
+        let skipped = render_with_options(path, &opts);
+
+        let opts = opts.provenance(true);
+        let traced = render_with_options_provenance(String::from("base-single.toml"), &opts);
+
+        env::set_current_dir(old_cwd).unwrap();
+
+        let err = result.err().unwrap();
+        assert!(err.kind == SarusErrorKind::MaxDepthExceeded { max: 2 });
+        assert!(nested.unwrap().annotations.get("quick").unwrap() == "algebra");
+        assert!(invalid.err().unwrap().code() == 4);
+        assert!(skipped.err().unwrap().kind == SarusErrorKind::MissingImage);
+        let (edf, prov) = traced.unwrap();
+        assert!(edf.image == "ubuntu:anno");
+        assert!(prov.unwrap().get("annotations.quick").is_some());
+    }
+
+    #[test]
+    fn render_shared_bases() {
+        let tmp = env::temp_dir().join(format!("raster-shared-{}", std::process::id()));
+        std::fs::create_dir_all(&tmp).unwrap();
+        let files = [
+            ("site", "image = \"ubuntu:24.04\"\n[env]\nSITE = \"1\"\nX = \"site\"\n"),
+            ("c", "base_environment = \"site\"\n"),
+            ("b", "base_environment = \"c\"\n[env]\nB = \"1\"\n"),
+            ("app", "base_environment = [\"b\", \"a\"]\n"),
+        ];
+        for (name, content) in files {
+            std::fs::write(tmp.join(format!("{name}.toml")), content).unwrap();
+        }
+        let a = format!("base_environment = \"{}\"\n[env]\nA = \"1\"\nX = \"a\"\n", tmp.join("site.toml").display());
+        std::fs::write(tmp.join("a.toml"), a).unwrap();
+
+        // site is reached twice under two names, the second time one level
+        // above, its entries applying again over the ones of b and c
+        let opts = RenderOptions::new().search_paths(vec![tmp.display().to_string()]);
+        let traced = opts.clone().max_levels(4).provenance(true);
+        let (edf, prov) = render_with_options_provenance(String::from("app"), &traced).unwrap();
+        assert!(["SITE", "A", "B"].iter().all(|k| edf.env.contains_key(*k)));
+        assert!(edf.env.get("X").unwrap() == "a");
+        let prov = prov.unwrap();
+        assert!(prov.get("env.X").unwrap().file_path.ends_with("a.toml"));
+        assert!(prov.get("env.SITE").unwrap().file_path.ends_with("site.toml"));
+        assert!(prov.files.iter().filter(|f| f.ends_with("site.toml")).count() == 2);
+        let err = render_with_options(String::from("app"), &opts.max_levels(3)).err().unwrap();
+        assert!(err.kind == SarusErrorKind::MaxDepthExceeded { max: 3 });
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn render_expansion_audit() {
+        let tmp = env::temp_dir().join(format!("raster-audit-{}", std::process::id()));
+        std::fs::create_dir_all(&tmp).unwrap();
+        let path = tmp.join("audit.toml").display().to_string();
+        std::fs::write(
+            &path,
+            "image = \"ubuntu:24.04\"\nworkdir = \"${SCRATCH}/run\"\n\
+             [env]\nAPI_TOKEN = \"$API_TOKEN\"\nLITERAL = \"\\\\$NOT_A_VAR\"\n",
+        )
+        .unwrap();
+
+        let uenv = HashMap::from([
+            (String::from("SCRATCH"), String::from("/scratch")),
+            (String::from("API_TOKEN"), String::from("secret")),
+        ]);
+        let opts = RenderOptions::new()
+            .search_paths(vec![])
+            .env(uenv)
+            .sensitive_env(vec![String::from("*_TOKEN")]);
+        let (_, records) = render_with_options_audit(path.clone(), &opts).unwrap();
+        assert!(records.is_none());
+
+        let opts = opts.expansion_audit(true);
+        let (edf, records) = render_with_options_audit(path.clone(), &opts).unwrap();
+        assert!(edf.workdir == "/scratch/run");
+        let records = records.unwrap();
+        let vars: Vec<&str> = records.iter().map(|r| r.var.as_str()).collect();
+        assert!(vars == vec!["API_TOKEN", "SCRATCH"]);
+        assert!(records.iter().all(|r| r.source == VarSource::UserEnv));
+        assert!(records[0].value == Some(String::from(secrets::REDACTED)));
+        assert!(records[1].value == Some(String::from("/scratch")));
+
+        let (_, records) = render_with_options_audit(path, &opts.reveal_secrets(true)).unwrap();
+        assert!(records.unwrap()[0].value == Some(String::from("secret")));
+        std::fs::remove_dir_all(tmp).unwrap();
+    }
 }
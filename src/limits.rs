@@ -0,0 +1,149 @@
+use std::sync::LazyLock;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use regex::Regex;
+
+use crate::error::{SarusError, SarusErrorKind, SarusResult};
+
+// Resource limits of the container, the [limits] table of EDFs. Entries of
+// a file override the ones of its bases one by one.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Limits {
+    // Size of /dev/shm, e.g. "8G".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shm_size: Option<String>,
+    // Maximum number of processes, 0 for no limit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pids_limit: Option<u64>,
+    // Locked-in-memory bytes, "unlimited" being the usual value for RDMA.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memlock: Option<Ulimit>,
+    // Stack size in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stack: Option<Ulimit>,
+}
+
+// Value of a ulimit, the same for its soft and hard limits.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ulimit {
+    Bytes(u64),
+    Unlimited,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum UlimitRepr {
+    Bytes(u64),
+    Keyword(String),
+}
+
+impl Serialize for Ulimit {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Ulimit::Bytes(b) => UlimitRepr::Bytes(*b).serialize(s),
+            Ulimit::Unlimited => UlimitRepr::Keyword(String::from("unlimited")).serialize(s),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Ulimit {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        match UlimitRepr::deserialize(d)? {
+            UlimitRepr::Bytes(b) => Ok(Ulimit::Bytes(b)),
+            UlimitRepr::Keyword(k) if k == "unlimited" => Ok(Ulimit::Unlimited),
+            UlimitRepr::Keyword(k) => Err(serde::de::Error::custom(format!(
+                "invalid ulimit \"{k}\", expected a number of bytes or \"unlimited\""
+            ))),
+        }
+    }
+}
+
+impl std::fmt::Display for Ulimit {
+    // The form of podman --ulimit, -1 being no limit.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Ulimit::Bytes(b) => write!(f, "{b}"),
+            Ulimit::Unlimited => write!(f, "-1"),
+        }
+    }
+}
+
+impl Limits {
+    pub fn is_empty(&self) -> bool {
+        *self == Limits::default()
+    }
+
+    pub fn extend(&mut self, other: Limits) {
+        if other.shm_size.is_some() {
+            self.shm_size = other.shm_size;
+        }
+        if other.pids_limit.is_some() {
+            self.pids_limit = other.pids_limit;
+        }
+        if other.memlock.is_some() {
+            self.memlock = other.memlock;
+        }
+        if other.stack.is_some() {
+            self.stack = other.stack;
+        }
+    }
+
+    // The ulimits set, by their name.
+    pub fn ulimits(&self) -> Vec<(&'static str, Ulimit)> {
+        let mut res = vec![];
+        if let Some(u) = self.memlock {
+            res.push(("memlock", u));
+        }
+        if let Some(u) = self.stack {
+            res.push(("stack", u));
+        }
+        res
+    }
+}
+
+// The size of /dev/shm is a number of bytes with an optional k, m, g or t
+// suffix, like the one of overlays.
+pub(crate) fn check_limits(l: &Limits) -> SarusResult<()> {
+    static RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[0-9]+[kKmMgGtT]?$").unwrap());
+    if let Some(s) = &l.shm_size
+        && !RE.is_match(s)
+    {
+        return Err(SarusError {
+            kind: SarusErrorKind::InvalidLimit {
+                field: String::from("shm_size"),
+            },
+            file_path: None,
+            msg: format!("invalid shm_size \"{s}\", expected e.g. 8G"),
+            location: None,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RawEDF, RenderOptions, render_from_str_with_options};
+
+    #[test]
+    fn limits_merge() {
+        let mut base: RawEDF =
+            toml::from_str("image = \"ubuntu\"\n[limits]\nshm_size = \"1G\"\nmemlock = \"unlimited\"\n").unwrap();
+        let top: RawEDF = toml::from_str("[limits]\nshm_size = \"8G\"\npids_limit = 0\n").unwrap();
+        base.merge(top);
+        let l = base.limits.unwrap();
+        assert!(l.shm_size.as_deref() == Some("8G") && l.pids_limit == Some(0));
+        assert!(l.ulimits() == vec![("memlock", Ulimit::Unlimited)]);
+
+        let s = toml::to_string(&l).unwrap();
+        assert!(s.contains("memlock = \"unlimited\"") && toml::from_str::<Limits>(&s).unwrap() == l);
+        assert!(toml::from_str::<Limits>("stack = \"infinite\"\n").is_err());
+
+        let opts = RenderOptions::new().skip_validation(true);
+        let content = String::from("image = \"ubuntu\"\n[limits]\nshm_size = \"lots\"\n");
+        let err = render_from_str_with_options(content, "socket", &opts).err().unwrap();
+        assert!(err.kind == SarusErrorKind::InvalidLimit { field: String::from("shm_size") });
+    }
+}
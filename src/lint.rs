@@ -0,0 +1,253 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::aliases::{EDF_KEY_ALIASES, resolve_aliases};
+use crate::common::{expand_vars_string_with_options, expand_vars_vec_with_options};
+use crate::conditions::resolve_conditions;
+use crate::error::{Location, SarusError, SarusErrorKind, SarusResult};
+use crate::mount::SarusMount;
+use crate::options::RenderOptions;
+use crate::provenance::key_location;
+use crate::{
+    BaseEnvironment, EDF_EXTENSIONS, annotations_as_hashmap, check_file_path_extension, document_parse,
+    get_search_paths, load, raw_from_document, resolve_env_path,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+// Rules checked by lint(), with their severity.
+pub const LINT_RULES: [(&str, Severity, &str); 8] = [
+    ("unreachable-base", Severity::Error, "base environment or include not found in the search paths"),
+    ("invalid-mount", Severity::Error, "mount can't be parsed"),
+    ("duplicate-mount", Severity::Warning, "same source and target mounted with different flags"),
+    ("shadowed-mount", Severity::Warning, "mount hidden by a later one on the same target"),
+    ("empty-env", Severity::Warning, "env value which expands to an empty string"),
+    ("annotation-shadows-config", Severity::Warning, "annotation named after a configuration entry"),
+    ("duplicate-device", Severity::Info, "device listed more than once after expansion"),
+    ("deprecated-key", Severity::Warning, "key using a former name"),
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LintFinding {
+    pub rule: String,
+    pub severity: Severity,
+    pub key: Option<String>,
+    pub msg: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<Location>,
+}
+
+// Findings of the linter on one EDF file. Only the file itself is checked,
+// its base environments are linted on their own.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LintReport {
+    pub file_path: String,
+    pub findings: Vec<LintFinding>,
+}
+
+impl LintReport {
+    pub fn has_errors(&self) -> bool {
+        self.findings.iter().any(|f| f.severity == Severity::Error)
+    }
+
+    pub fn with_rule(&self, rule: &str) -> Vec<&LintFinding> {
+        self.findings.iter().filter(|f| f.rule == rule).collect()
+    }
+
+    fn push(&mut self, rule: &str, key: Option<&str>, msg: String) {
+        let severity = match LINT_RULES.iter().find(|(r, _, _)| *r == rule) {
+            Some((_, s, _)) => *s,
+            None => Severity::Warning,
+        };
+        self.findings.push(LintFinding {
+            rule: String::from(rule),
+            severity,
+            key: key.map(String::from),
+            msg,
+            location: None,
+        });
+    }
+
+    // Position the findings at their key in the file content.
+    fn locate(&mut self, content: &str) {
+        for f in self.findings.iter_mut() {
+            if let Some(k) = &f.key {
+                f.location = key_location(content, &self.file_path, k);
+            }
+        }
+    }
+}
+
+pub fn lint(path: String) -> SarusResult<LintReport> {
+    lint_with_options(path, &RenderOptions::new())
+}
+
+// Failures to read or parse the file are returned as Err, everything else
+// ends up in the report.
+pub fn lint_with_options(path: String, opts: &RenderOptions) -> SarusResult<LintReport> {
+    check_file_path_extension(&path, &EDF_EXTENSIONS)?;
+
+    let env = &opts.env;
+    let xopts = opts.expand_options();
+    let sp = match &opts.search_paths {
+        Some(s) => s.clone(),
+        None => get_search_paths(),
+    };
+
+    let content = match load(&path) {
+        Ok(c) => c,
+        Err(e) => {
+            return Err(SarusError {
+                kind: SarusErrorKind::FileRead,
+                file_path: Some(path),
+                msg: format!("{}", e),
+                location: None,
+            });
+        }
+    };
+    let mut doc: serde_json::Value = document_parse(&content, &path)?;
+    let deprecations = resolve_aliases(&mut doc, EDF_KEY_ALIASES);
+    resolve_conditions(&mut doc, env, &xopts, &path)?;
+    let r = raw_from_document(doc, &path)?;
+
+    let mut report = LintReport {
+        file_path: path.clone(),
+        ..Default::default()
+    };
+
+    for d in deprecations {
+        report.push("deprecated-key", Some(&d.old), d.msg());
+    }
+
+    for layer in [r.base_environment, r.include].into_iter().flatten() {
+        let names = match layer {
+            BaseEnvironment::TypeString(s) => vec![s],
+            BaseEnvironment::TypeVec(a) => a,
+        };
+        for n in names {
+            if let Err(e) = resolve_env_path(n.clone(), &sp, env, &xopts, &opts.load_limits) {
+                report.push("unreachable-base", Some("base_environment"), e.msg);
+            }
+        }
+    }
+
+    let mut mounts: Vec<SarusMount> = vec![];
+    for entry in r.mounts.unwrap_or_default() {
+        let m = match SarusMount::try_new_from_entry(entry, env, &xopts) {
+            Ok(m) => m,
+            Err(e) => {
+                report.push("invalid-mount", Some("mounts"), e.msg);
+                continue;
+            }
+        };
+        let clash = mounts
+            .iter()
+            .find(|o| o.source() == m.source() && o.target() == m.target() && o.flags() != m.flags());
+        if let Some(o) = clash {
+            report.push(
+                "duplicate-mount",
+                Some("mounts"),
+                format!("{} and {} differ only by their flags", o.to_volume_string(), m.to_volume_string()),
+            );
+        } else if let Some(o) = mounts.iter().find(|o| o.target() == m.target() && o.source() != m.source()) {
+            report.push(
+                "shadowed-mount",
+                Some("mounts"),
+                format!("{} shadows {}", m.to_volume_string(), o.to_volume_string()),
+            );
+        }
+        mounts.push(m);
+    }
+
+    let mut keys: Vec<(String, String)> = r.env.unwrap_or_default().into_iter().collect();
+    keys.sort();
+    for (k, v) in keys {
+        if v.is_empty() {
+            continue;
+        }
+        if let Ok(x) = expand_vars_string_with_options(v.clone(), env, &xopts)
+            && x.is_empty()
+        {
+            report.push("empty-env", Some(&format!("env.{k}")), format!("\"{v}\" expands to an empty string"));
+        }
+    }
+
+    let config_keys = config_keys();
+    let mut annotations: Vec<String> = match r.annotations {
+        Some(a) => annotations_as_hashmap(a).into_keys().collect(),
+        None => vec![],
+    };
+    annotations.sort();
+    for a in annotations {
+        let name = a.rsplit('.').next().unwrap_or(&a);
+        if config_keys.iter().any(|c| c == name) {
+            report.push(
+                "annotation-shadows-config",
+                Some(&format!("annotations.{a}")),
+                format!("annotation \"{a}\" shadows the configuration entry \"{name}\""),
+            );
+        }
+    }
+
+    let devices = expand_vars_vec_with_options(r.devices.unwrap_or_default(), env, &xopts)?;
+    let mut seen: HashMap<&String, usize> = HashMap::new();
+    for d in devices.iter() {
+        *seen.entry(d).or_default() += 1;
+        if seen[d] == 2 {
+            report.push("duplicate-device", Some("devices"), format!("device {d} is listed more than once"));
+        }
+    }
+
+    report.locate(&content);
+    Ok(report)
+}
+
+fn config_keys() -> Vec<String> {
+    let schema: serde_json::Value =
+        serde_json::from_str(include_str!("schema/config.json")).unwrap_or_default();
+    match schema.get("properties").and_then(|p| p.as_object()) {
+        Some(p) => p.keys().cloned().collect(),
+        None => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+    use std::env;
+
+    #[test]
+    #[serial]
+    fn lint_rules() {
+        let old_cwd = env::current_dir().unwrap();
+        env::set_current_dir("test/toml").unwrap();
+
+        let mut uenv = HashMap::new();
+        uenv.insert(String::from("DEV"), String::from("fuse"));
+        uenv.insert(String::from("LINT_BLANK"), String::new());
+        let opts = RenderOptions::new().env(uenv).search_paths(vec![String::from(".")]);
+        let report = lint_with_options(String::from("lint.toml"), &opts);
+        let clean = lint_with_options(String::from("top-simple-1.toml"), &opts);
+
+        env::set_current_dir(old_cwd).unwrap();
+
+        let report = report.unwrap();
+        assert!(report.has_errors());
+        assert!(report.with_rule("unreachable-base").len() == 1);
+        assert!(report.with_rule("duplicate-mount").len() == 1);
+        assert!(report.with_rule("shadowed-mount").len() == 1);
+        assert!(report.with_rule("empty-env")[0].key.as_deref() == Some("env.LINT_EMPTY"));
+        assert!(report.with_rule("empty-env")[0].location == Some(Location { line: 7, column: 1 }));
+        assert!(report.with_rule("annotation-shadows-config")[0].location.unwrap().line == 11);
+        assert!(report.with_rule("annotation-shadows-config").len() == 1);
+        assert!(report.with_rule("duplicate-device")[0].severity == Severity::Info);
+        assert!(clean.unwrap().findings.is_empty());
+    }
+}
@@ -0,0 +1,309 @@
+use std::io::Read;
+use std::path::Path;
+
+use crate::canonical_path;
+use crate::error::{SarusError, SarusErrorKind, SarusResult};
+
+// Limits of the loading of EDF files from the filesystem, renders possibly
+// running for a privileged launcher.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoadLimits {
+    // Largest accepted file, in bytes.
+    pub max_size: u64,
+    // Refuse files any user can write to.
+    pub reject_world_writable: bool,
+    // Refuse files of a search path whose real path is out of it, e.g.
+    // symlinks to /etc. Files given by their path aren't concerned.
+    pub contain_symlinks: bool,
+    // Refuse names looked up in the search paths leaving them through ..,
+    // e.g. base_environment = "team/../../other/thing".
+    pub contain_names: bool,
+    // Ownership required of the files of the system search paths.
+    pub system_paths: Option<SystemPathPolicy>,
+}
+
+impl Default for LoadLimits {
+    fn default() -> Self {
+        LoadLimits {
+            max_size: 1024 * 1024,
+            reject_world_writable: true,
+            contain_symlinks: false,
+            contain_names: true,
+            system_paths: None,
+        }
+    }
+}
+
+// EDFs of the system search paths are used by the jobs of every user, the
+// way sudo checks its sudoers files they must belong to a trusted owner
+// and be writable by it only: anyone else able to write one of them would
+// set the mounts of the jobs of other users.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SystemPathPolicy {
+    pub search_paths: Vec<String>,
+    // Uid owning the files, root by default.
+    pub owner_uid: u32,
+    pub allow_group_writable: bool,
+}
+
+impl SystemPathPolicy {
+    pub fn new(search_paths: Vec<String>) -> Self {
+        SystemPathPolicy {
+            search_paths,
+            owner_uid: 0,
+            allow_group_writable: false,
+        }
+    }
+}
+
+fn read_failed(file_path: &str, msg: String) -> SarusError {
+    SarusError {
+        kind: SarusErrorKind::FileRead,
+        file_path: Some(String::from(file_path)),
+        msg,
+        location: None,
+    }
+}
+
+// The search path holding a file found in it, as joined by
+// resolve_env_path().
+pub(crate) fn search_root<'a>(file_path: &str, sp: &'a [String]) -> Option<&'a String> {
+    sp.iter().find(|s| Path::new(file_path).starts_with(s))
+}
+
+fn check_containment(file_path: &str, sp: &[String]) -> SarusResult<()> {
+    let root = match search_root(file_path, sp) {
+        Some(r) => r,
+        None => return Ok(()),
+    };
+    let real = canonical_path(file_path);
+    if real.starts_with(canonical_path(root)) {
+        return Ok(());
+    }
+    Err(SarusError {
+        kind: SarusErrorKind::FileOutOfSearchPath {
+            path: String::from(file_path),
+            search_path: root.clone(),
+        },
+        file_path: Some(String::from(file_path)),
+        msg: format!("{file_path} resolves to {}, out of the search path {root}", real.display()),
+        location: None,
+    })
+}
+
+#[cfg(unix)]
+fn ownership_problem(m: &std::fs::Metadata, policy: &SystemPathPolicy) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+    if m.uid() != policy.owner_uid {
+        return Some(format!("is owned by uid {}, expected uid {}", m.uid(), policy.owner_uid));
+    }
+    if m.mode() & 0o002 != 0 {
+        return Some(String::from("is writable by any user"));
+    }
+    if m.mode() & 0o020 != 0 && !policy.allow_group_writable {
+        return Some(format!("is writable by its group (gid {})", m.gid()));
+    }
+    None
+}
+
+#[cfg(not(unix))]
+fn ownership_problem(_: &std::fs::Metadata, _: &SystemPathPolicy) -> Option<String> {
+    None
+}
+
+#[cfg(unix)]
+fn is_world_writable(m: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    m.permissions().mode() & 0o002 != 0
+}
+
+#[cfg(not(unix))]
+fn is_world_writable(_: &std::fs::Metadata) -> bool {
+    false
+}
+
+// Read an EDF file within the limits, sp being the search paths it may
+// have been found in. Size and mode are the ones of the opened file, and
+// no more than max_size bytes are read whatever it grows to.
+pub(crate) fn load_edf(file_path: &str, sp: &[String], limits: &LoadLimits) -> SarusResult<String> {
+    if limits.contain_symlinks {
+        check_containment(file_path, sp)?;
+    }
+
+    let file = match std::fs::File::open(file_path) {
+        Ok(f) => f,
+        Err(e) => return Err(read_failed(file_path, format!("{e}"))),
+    };
+    let metadata = match file.metadata() {
+        Ok(m) => m,
+        Err(e) => return Err(read_failed(file_path, format!("{e}"))),
+    };
+    if limits.reject_world_writable && is_world_writable(&metadata) {
+        return Err(SarusError {
+            kind: SarusErrorKind::FileWorldWritable { path: String::from(file_path) },
+            file_path: Some(String::from(file_path)),
+            msg: format!("{file_path} is writable by any user"),
+            location: None,
+        });
+    }
+    if let Some(policy) = &limits.system_paths
+        && let Some(root) = search_root(file_path, &policy.search_paths)
+        && let Some(problem) = ownership_problem(&metadata, policy)
+    {
+        return Err(SarusError {
+            kind: SarusErrorKind::UntrustedSystemFile { path: String::from(file_path) },
+            file_path: Some(String::from(file_path)),
+            msg: format!("{file_path} of the system search path {root} {problem}"),
+            location: None,
+        });
+    }
+
+    let too_large = |size: u64| SarusError {
+        kind: SarusErrorKind::FileTooLarge {
+            path: String::from(file_path),
+            max_size: limits.max_size,
+        },
+        file_path: Some(String::from(file_path)),
+        msg: format!("{file_path} is {size} bytes, more than the {} accepted", limits.max_size),
+        location: None,
+    };
+    if metadata.len() > limits.max_size {
+        return Err(too_large(metadata.len()));
+    }
+    let mut content = String::new();
+    if let Err(e) = file.take(limits.max_size + 1).read_to_string(&mut content) {
+        return Err(read_failed(file_path, format!("{e}")));
+    }
+    if content.len() as u64 > limits.max_size {
+        return Err(too_large(content.len() as u64));
+    }
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RenderOptions, render_with_options};
+
+    #[test]
+    fn load_limits() {
+        let tmp = std::env::temp_dir().join(format!("raster-load-{}", std::process::id()));
+        let sp = tmp.join("edf");
+        std::fs::create_dir_all(&sp).unwrap();
+        std::fs::write(tmp.join("outside.toml"), "image = \"ubuntu\"\n").unwrap();
+        std::fs::write(sp.join("big.toml"), format!("image = \"ubuntu\"\n#{}\n", "x".repeat(4096))).unwrap();
+        std::fs::write(sp.join("open.toml"), "image = \"ubuntu\"\n").unwrap();
+        let _ = std::fs::remove_file(sp.join("link.toml"));
+        std::os::unix::fs::symlink(tmp.join("outside.toml"), sp.join("link.toml")).unwrap();
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(sp.join("open.toml"), std::fs::Permissions::from_mode(0o666)).unwrap();
+
+        let render = |name: &str, limits: LoadLimits| {
+            let opts = RenderOptions::new()
+                .search_paths(vec![sp.display().to_string()])
+                .load_limits(limits);
+            render_with_options(String::from(name), &opts)
+        };
+        let small = LoadLimits {
+            max_size: 1024,
+            ..Default::default()
+        };
+        let err = render("big", small).err().unwrap();
+        assert!(matches!(err.kind, SarusErrorKind::FileTooLarge { max_size: 1024, .. }));
+        assert!(render("big", LoadLimits::default()).is_ok());
+
+        assert!(render("open", LoadLimits::default()).err().unwrap().code() == 65);
+        let lax = LoadLimits {
+            reject_world_writable: false,
+            ..Default::default()
+        };
+        assert!(render("open", lax).is_ok());
+
+        assert!(render("link", LoadLimits::default()).is_ok());
+        let contained = LoadLimits {
+            contain_symlinks: true,
+            ..Default::default()
+        };
+        let err = render("link", contained.clone()).err().unwrap();
+        assert!(matches!(err.kind, SarusErrorKind::FileOutOfSearchPath { .. }));
+        assert!(render("big", contained.clone()).is_ok());
+        // Files given by their path may be anywhere
+        let outside = tmp.join("outside.toml").display().to_string();
+        assert!(render(&outside, contained).is_ok());
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn load_system_paths() {
+        use std::os::unix::fs::PermissionsExt;
+        let tmp = std::env::temp_dir().join(format!("raster-system-{}", std::process::id()));
+        let (sys, user) = (tmp.join("sys"), tmp.join("user"));
+        for d in [&sys, &user] {
+            std::fs::create_dir_all(d).unwrap();
+        }
+        std::fs::write(sys.join("site.toml"), "image = \"ubuntu\"\n").unwrap();
+        std::fs::write(user.join("mine.toml"), "image = \"ubuntu\"\n").unwrap();
+        std::fs::set_permissions(sys.join("site.toml"), std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        let uid = nix::unistd::geteuid().as_raw();
+        let render = |name: &str, policy: SystemPathPolicy| {
+            let limits = LoadLimits {
+                system_paths: Some(policy),
+                ..Default::default()
+            };
+            let sp = vec![user.display().to_string(), sys.display().to_string()];
+            let opts = RenderOptions::new().search_paths(sp).load_limits(limits);
+            render_with_options(String::from(name), &opts)
+        };
+        let trusted = SystemPathPolicy {
+            owner_uid: uid,
+            ..SystemPathPolicy::new(vec![sys.display().to_string()])
+        };
+        let other = SystemPathPolicy {
+            owner_uid: uid + 1,
+            ..trusted.clone()
+        };
+        assert!(render("site", trusted.clone()).is_ok());
+        let err = render("site", other.clone()).err().unwrap();
+        assert!(err.code() == 67 && err.msg.contains(&format!("owned by uid {uid}")));
+        // Files of the user search paths may belong to anyone
+        assert!(render("mine", other).is_ok());
+
+        std::fs::set_permissions(sys.join("site.toml"), std::fs::Permissions::from_mode(0o664)).unwrap();
+        let err = render("site", trusted.clone()).err().unwrap();
+        assert!(err.kind == SarusErrorKind::UntrustedSystemFile { path: sys.join("site.toml").display().to_string() });
+        let group = SystemPathPolicy {
+            allow_group_writable: true,
+            ..trusted
+        };
+        assert!(render("site", group).is_ok());
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn load_contained_names() {
+        let tmp = std::env::temp_dir().join(format!("raster-names-{}", std::process::id()));
+        let sp = tmp.join("edf");
+        std::fs::create_dir_all(sp.join("team")).unwrap();
+        std::fs::write(sp.join("site.toml"), "image = \"ubuntu\"\n").unwrap();
+        std::fs::write(tmp.join("outside.toml"), "image = \"alpine\"\n").unwrap();
+        std::fs::write(sp.join("up.toml"), "base_environment = \"team/../../outside\"\n").unwrap();
+
+        let render = |name: &str, limits: LoadLimits| {
+            let opts = RenderOptions::new()
+                .search_paths(vec![sp.display().to_string()])
+                .load_limits(limits);
+            render_with_options(String::from(name), &opts)
+        };
+        assert!(render("team/../site", LoadLimits::default()).unwrap().image == "ubuntu");
+        let err = render("up", LoadLimits::default()).err().unwrap();
+        assert!(err.code() == 66 && err.msg.contains("leaves the search path"));
+        assert!(render("team/../../outside", LoadLimits::default()).is_err());
+        let lax = LoadLimits {
+            contain_names: false,
+            ..Default::default()
+        };
+        assert!(render("up", lax).unwrap().image == "alpine");
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+}
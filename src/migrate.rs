@@ -0,0 +1,278 @@
+use serde::Serialize;
+use toml_edit::{Array, InlineTable, Item, TableLike, Value};
+
+use crate::aliases::{EDF_KEY_ALIASES, KeyAlias};
+use crate::edit::EdfDocument;
+use crate::error::SarusResult;
+use crate::mount::is_volume_name;
+
+// Annotations superseded by the [parallax] and [podman] tables, as
+// (table, key, boolean value).
+const ENGINE_ANNOTATIONS: [(&str, &str, bool); 9] = [
+    ("parallax", "imagestore", false),
+    ("parallax", "imagestore_keepalive", true),
+    ("parallax", "mount_program", false),
+    ("parallax", "path", false),
+    ("parallax", "mp_logfile", false),
+    ("parallax", "mp_squashfuse_path", false),
+    ("podman", "module", false),
+    ("podman", "path", false),
+    ("podman", "tmp_path", false),
+];
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MigrateOptions {
+    // Write the SOURCE:TARGET[:FLAGS] mounts as tables.
+    pub table_mounts: bool,
+}
+
+// A change made by migrate(), with the key it touched.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MigrationChange {
+    pub id: String,
+    pub key: String,
+    pub msg: String,
+}
+
+// An EDF moved to the current layout, comments and formatting kept. Nothing
+// is written until the document is.
+#[derive(Clone, Debug)]
+pub struct MigratedDocument {
+    pub document: EdfDocument,
+    pub changes: Vec<MigrationChange>,
+}
+
+impl MigratedDocument {
+    pub fn is_changed(&self) -> bool {
+        !self.changes.is_empty()
+    }
+
+    fn change(&mut self, id: &str, key: &str, msg: String) {
+        self.changes.push(MigrationChange {
+            id: String::from(id),
+            key: String::from(key),
+            msg,
+        });
+    }
+}
+
+pub fn migrate(path: &str) -> SarusResult<MigratedDocument> {
+    migrate_with_options(path, &MigrateOptions::default())
+}
+
+pub fn migrate_with_options(path: &str, opts: &MigrateOptions) -> SarusResult<MigratedDocument> {
+    Ok(migrate_document(EdfDocument::read(path)?, EDF_KEY_ALIASES, opts))
+}
+
+pub fn migrate_str(content: &str, origin_hint: &str, opts: &MigrateOptions) -> SarusResult<MigratedDocument> {
+    Ok(migrate_document(EdfDocument::parse(content, origin_hint)?, EDF_KEY_ALIASES, opts))
+}
+
+pub(crate) fn migrate_document(document: EdfDocument, aliases: &[KeyAlias], opts: &MigrateOptions) -> MigratedDocument {
+    let mut m = MigratedDocument {
+        document,
+        changes: vec![],
+    };
+    rename_keys(&mut m, aliases);
+    move_engine_annotations(&mut m);
+    if opts.table_mounts {
+        convert_mounts(&mut m);
+    }
+    m
+}
+
+fn get_path<'a>(t: &'a dyn TableLike, path: &str) -> Option<&'a Item> {
+    match path.split_once('.') {
+        None => t.get(path),
+        Some((k, rest)) => get_path(t.get(k)?.as_table_like()?, rest),
+    }
+}
+
+fn take_path(t: &mut dyn TableLike, path: &str) -> Option<Item> {
+    match path.split_once('.') {
+        None => t.remove(path),
+        Some((k, rest)) => take_path(t.get_mut(k)?.as_table_like_mut()?, rest),
+    }
+}
+
+fn set_path(m: &mut MigratedDocument, path: &str, item: Item) {
+    let mut cur = m.document.doc.as_item_mut();
+    for k in path.split('.') {
+        cur = &mut cur[k];
+    }
+    *cur = item;
+}
+
+// Move the values of old keys to their new name, the new one winning when
+// both are set.
+fn rename_keys(m: &mut MigratedDocument, aliases: &[KeyAlias]) {
+    for a in aliases {
+        let item = match take_path(m.document.doc.as_table_mut(), a.old) {
+            Some(i) => i,
+            None => continue,
+        };
+        let msg = match get_path(m.document.doc.as_table(), a.new) {
+            Some(_) => format!("removed \"{}\", \"{}\" is already set", a.old, a.new),
+            None => {
+                set_path(m, a.new, item);
+                format!("renamed \"{}\" to \"{}\"", a.old, a.new)
+            }
+        };
+        m.change("renamed-key", a.old, msg);
+    }
+}
+
+// Move the com.sarus.parallax_* and com.sarus.podman_* annotations to the
+// [parallax] and [podman] tables, whether written as quoted or as dotted
+// keys.
+fn move_engine_annotations(m: &mut MigratedDocument) {
+    for (table, key, boolean) in ENGINE_ANNOTATIONS {
+        let name = format!("com.sarus.{table}_{key}");
+        let annotations = match m.document.doc.get_mut("annotations").and_then(|a| a.as_table_like_mut()) {
+            Some(a) => a,
+            None => return,
+        };
+        let found = match annotations.get(&name) {
+            Some(i) => i.as_str().map(String::from),
+            None => get_path(annotations, &name).and_then(|i| i.as_str()).map(String::from),
+        };
+        let s = match found {
+            Some(s) => s,
+            None => continue,
+        };
+        let v = match boolean {
+            true => match s.parse::<bool>() {
+                Ok(b) => Value::from(b),
+                Err(_) => continue,
+            },
+            false => Value::from(s),
+        };
+        if annotations.remove(&name).is_none() {
+            take_path(annotations, &name);
+        }
+
+        let new = format!("{table}.{key}");
+        let msg = match get_path(m.document.doc.as_table(), &new) {
+            Some(_) => format!("removed annotation \"{name}\", \"{new}\" is already set"),
+            None => {
+                set_path(m, &new, Item::Value(v));
+                format!("moved annotation \"{name}\" to \"{new}\"")
+            }
+        };
+        m.change("engine-annotation", &format!("annotations.{name}"), msg);
+    }
+
+    // Drop the tables left empty
+    for path in ["annotations.com.sarus", "annotations.com", "annotations"] {
+        let empty = get_path(m.document.doc.as_table(), path)
+            .and_then(|i| i.as_table_like())
+            .is_some_and(|t| t.is_empty());
+        if empty {
+            take_path(m.document.doc.as_table_mut(), path);
+        }
+    }
+}
+
+fn mount_table(spec: &str) -> Option<InlineTable> {
+    let parts: Vec<&str> = spec.split(':').collect();
+    let (source, target, flags) = match parts.as_slice() {
+        [s, t] => (*s, *t, ""),
+        [s, t, f] => (*s, *t, *f),
+        _ => return None,
+    };
+    let mut flags: Vec<&str> = flags.split(',').filter(|f| !f.is_empty()).collect();
+
+    let kind = match source {
+        "tmpfs" | "umount" => Some(source),
+        _ if flags.contains(&"sqsh") => {
+            flags.retain(|f| *f != "sqsh");
+            Some("sqsh")
+        }
+        _ if !source.starts_with(['/', '.', '$', '~']) && is_volume_name(source) => Some("volume"),
+        _ => None,
+    };
+
+    let mut t = InlineTable::new();
+    if kind != Some("tmpfs") && kind != Some("umount") {
+        t.insert("source", Value::from(source));
+    }
+    t.insert("target", Value::from(target));
+    if !flags.is_empty() {
+        t.insert("flags", Value::Array(flags.into_iter().collect::<Array>()));
+    }
+    if let Some(k) = kind {
+        t.insert("type", Value::from(k));
+    }
+    Some(t)
+}
+
+fn convert_mounts(m: &mut MigratedDocument) {
+    let mounts = match m.document.doc.get_mut("mounts").and_then(|i| i.as_array_mut()) {
+        Some(a) => a,
+        None => return,
+    };
+    let mut converted = vec![];
+    for v in mounts.iter_mut() {
+        let spec = match v.as_str() {
+            Some(s) => s.to_string(),
+            None => continue,
+        };
+        let t = match mount_table(&spec) {
+            Some(t) => t,
+            None => continue,
+        };
+        let decor = v.decor().clone();
+        *v = Value::InlineTable(t);
+        *v.decor_mut() = decor;
+        converted.push(spec);
+    }
+    for spec in converted {
+        m.change("table-mount", "mounts", format!("wrote mount \"{spec}\" as a table"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrate_layouts() {
+        let content = "# analysis\nimage = \"ubuntu:24.04\"\nworkingdir = \"/a\" # start here\n\
+                       mounts = [\n    \"/scratch:/scratch:ro\", # data\n    \"tmpfs:/tmp:size=1g\",\n    \
+                       \"/img.sqsh:/opt:sqsh\",\n]\n\n[annotations]\n\
+                       com.sarus.parallax_imagestore = \"/scratch/is\"\n\
+                       \"com.sarus.parallax_imagestore_keepalive\" = \"true\"\n\
+                       \"com.sarus.podman_path\" = \"/usr/bin/podman\"\ncom.example.keep = \"x\"\n";
+        let aliases = [KeyAlias {
+            old: "workingdir",
+            new: "workdir",
+            removed_in: "2.0",
+        }];
+        let doc = EdfDocument::parse(content, "a.toml").unwrap();
+        let m = migrate_document(doc, &aliases, &MigrateOptions { table_mounts: true });
+
+        let ids: Vec<&str> = m.changes.iter().map(|c| c.id.as_str()).collect();
+        assert!(ids[0] == "renamed-key" && ids.len() == 7);
+        assert!(ids.iter().filter(|i| **i == "engine-annotation").count() == 3);
+        assert!(ids.iter().filter(|i| **i == "table-mount").count() == 3);
+        let s = m.document.to_string();
+        assert!(s.starts_with("# analysis\nimage = \"ubuntu:24.04\"\n"));
+        assert!(s.contains("{ source = \"/scratch\", target = \"/scratch\", flags = [\"ro\"] }, # data\n"));
+
+        let r = m.document.raw().unwrap();
+        assert!(r.workdir.as_deref() == Some("/a"));
+        let p = r.parallax.unwrap();
+        assert!(p.imagestore.as_deref() == Some("/scratch/is") && p.imagestore_keepalive == Some(true));
+        assert!(r.podman.unwrap().path.as_deref() == Some("/usr/bin/podman"));
+        let annotations = crate::annotations_as_hashmap(r.annotations.unwrap());
+        assert!(annotations.len() == 1 && annotations["com.example.keep"] == "x");
+
+        let mounts = r.mounts.unwrap();
+        assert!(mounts.iter().all(|e| matches!(e, crate::mount::MountEntry::Table(_))));
+        assert!(crate::validate_str_with_report(&s, "a.toml").unwrap().is_valid());
+
+        // Already migrated
+        let m = migrate_str(&s, "a.toml", &MigrateOptions::default()).unwrap();
+        assert!(!m.is_changed() && m.document.to_string() == s);
+    }
+}
@@ -1,29 +1,405 @@
-use serde::{Deserialize, Serialize, Serializer};
-use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::sync::LazyLock;
 
-use crate::common::expand_vars_string;
-use crate::error::{SarusError, SarusResult};
+use regex::Regex;
+
+use crate::common::{ExpandOptions, MergePolicy, dedup_entries, expand_tilde, expand_vars_string_with_options};
+use crate::error::{Location, SarusError, SarusErrorKind, SarusResult};
 
 pub type SarusMounts = Vec<SarusMount>;
 
-#[derive(Deserialize, Clone, PartialEq)]
+// What to do with mounts sharing a target in the container, the later one
+// shadowing the others. A warning is issued unless it's an error.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MountCollision {
+    #[default]
+    Keep,      // Keep every mount.
+    Error,     // Fail the render.
+    LastWins,  // Keep the last mount on the target.
+    FirstWins, // Keep the first mount on the target.
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct SarusMount {
     source: String,
     target: String,
     flags: String,
+    kind: MountKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MountKind {
+    Bind(BindOptions),         // SOURCE:TARGET[:FLAGS] with a path as source.
+    Tmpfs(TmpfsOptions),       // tmpfs:TARGET[:FLAGS]
+    Detach,                    // umount:TARGET
+    Squashfs(SquashfsOptions), // SQSH_FILE:TARGET:sqsh[,OPTIONS]
+    Volume(VolumeOptions),     // VOLUME_NAME:TARGET[:FLAGS], a named volume
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BindOptions {
+    pub read_only: bool,
+    pub propagation: Option<String>,
+    pub flags: Vec<String>,
+}
+
+// Bind mount flags understood by podman, grouped by mutual exclusion.
+const BIND_FLAG_GROUPS: [&[&str]; 12] = [
+    &["ro", "rw"],
+    &["private", "rprivate", "shared", "rshared", "slave", "rslave", "unbindable", "runbindable"],
+    &["suid", "nosuid"],
+    &["dev", "nodev"],
+    &["exec", "noexec"],
+    &["bind", "rbind"],
+    &["z", "Z"],
+    &["atime", "noatime", "relatime", "norelatime", "strictatime", "nostrictatime"],
+    &["copy", "nocopy"],
+    &["U"],
+    &["O"],
+    &["idmap"],
+];
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TmpfsOptions {
+    pub size: Option<String>,
+    pub mode: Option<u32>,
+    pub flags: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SquashfsOptions {
+    pub image: PathBuf,
+    // Byte offset of the filesystem inside the image file.
+    pub offset: Option<u64>,
+    // Hex encoded dm-verity root hash of the filesystem.
+    pub verity: Option<String>,
+}
+
+// Magic number at the start of a squashfs superblock, little endian.
+const SQUASHFS_MAGIC: &[u8; 4] = b"hsqs";
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VolumeOptions {
+    pub name: String,
+    pub read_only: bool,
+    pub flags: Vec<String>,
+}
+
+// Named volume flags understood by podman, grouped by mutual exclusion.
+const VOLUME_FLAG_GROUPS: [&[&str]; 4] = [&["ro", "rw"], &["z", "Z"], &["nocopy"], &["U"]];
+
+// Mount written as a table, which spares escaping paths holding colons:
+// [[mounts]]
+// source = "/scratch"
+// target = "/data"
+// flags = ["ro", "rbind"]
+// type = "bind"
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct MountTable {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    pub target: String,
+    #[serde(default)]
+    pub flags: Vec<String>,
+    // One of bind (the default), tmpfs, umount, sqsh or volume.
+    #[serde(rename = "type", default)]
+    pub kind: Option<String>,
+}
+
+// Entry of the mounts list of an EDF.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum MountEntry {
+    Spec(String),
+    Table(MountTable),
 }
 
+// Mounts whose paths hold colons or escapes are written as tables, with
+// the paths as they are, the compact form can't be read back otherwise.
 impl Serialize for SarusMount {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        serializer.serialize_str(&self.to_volume_string())
+        match self.to_table() {
+            Some(t) => t.serialize(serializer),
+            None => serializer.serialize_str(&self.to_volume_string()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SarusMount {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let m = match MountEntry::deserialize(deserializer)? {
+            MountEntry::Spec(s) => SarusMount::from_string(s),
+            MountEntry::Table(t) => SarusMount::from_table(t).map(|mut m| {
+                m.source = escape_mount(m.source);
+                m.target = escape_mount(m.target);
+                m
+            }),
+        };
+        m.map_err(serde::de::Error::custom)
+    }
+}
+
+impl MountKind {
+    // Type of the kind in mount tables.
+    fn type_name(&self) -> &'static str {
+        match self {
+            MountKind::Bind(_) => "bind",
+            MountKind::Tmpfs(_) => "tmpfs",
+            MountKind::Detach => "umount",
+            MountKind::Squashfs(_) => "sqsh",
+            MountKind::Volume(_) => "volume",
+        }
+    }
+
+    fn detect(source: &str, flags: &str) -> MountKind {
+        if is_squashfs_flags(flags) {
+            return match SquashfsOptions::parse(source, flags) {
+                Ok(o) => MountKind::Squashfs(o),
+                Err(_) => MountKind::Squashfs(SquashfsOptions {
+                    image: PathBuf::from(source),
+                    ..Default::default()
+                }),
+            };
+        }
+
+        let flags: Vec<String> = flags
+            .split(',')
+            .filter(|f| !f.is_empty())
+            .map(String::from)
+            .collect();
+
+        match source {
+            "tmpfs" => match TmpfsOptions::parse(&flags.join(",")) {
+                Ok(o) => MountKind::Tmpfs(o),
+                Err(_) => MountKind::Tmpfs(TmpfsOptions {
+                    flags,
+                    ..Default::default()
+                }),
+            },
+            "umount" => MountKind::Detach,
+            s if is_volume_name(s) => match VolumeOptions::parse(s, &flags.join(",")) {
+                Ok(o) => MountKind::Volume(o),
+                Err(_) => MountKind::Volume(VolumeOptions {
+                    name: s.to_string(),
+                    flags,
+                    ..Default::default()
+                }),
+            },
+            _ => match BindOptions::parse(&flags.join(",")) {
+                Ok(o) => MountKind::Bind(o),
+                Err(_) => MountKind::Bind(BindOptions {
+                    flags,
+                    ..Default::default()
+                }),
+            },
+        }
+    }
+}
+
+impl BindOptions {
+    fn parse(flags: &str) -> Result<BindOptions, String> {
+        let mut opts = BindOptions::default();
+
+        for f in flags.split(',').filter(|f| !f.is_empty()) {
+            if opts.flags.iter().any(|x| x == f) {
+                continue;
+            }
+
+            // idmap takes the mappings, e.g. idmap=uids=0-1000-10
+            let name = match f.split_once('=') {
+                Some(("idmap", _)) => "idmap",
+                _ => f,
+            };
+            let group = match BIND_FLAG_GROUPS.iter().find(|g| g.contains(&name)) {
+                Some(g) => g,
+                None => return Err(format!("unknown bind mount flag \"{f}\"")),
+            };
+            if let Some(other) = opts.flags.iter().find(|x| group.contains(&x.as_str())) {
+                return Err(format!("bind mount flags \"{other}\" and \"{f}\" are mutually exclusive"));
+            }
+
+            if f == "ro" {
+                opts.read_only = true;
+            }
+            if group.contains(&"rprivate") {
+                opts.propagation = Some(f.to_string());
+            }
+            opts.flags.push(f.to_string());
+        }
+        Ok(opts)
+    }
+}
+
+impl VolumeOptions {
+    fn parse(name: &str, flags: &str) -> Result<VolumeOptions, String> {
+        let mut opts = VolumeOptions {
+            name: name.to_string(),
+            ..Default::default()
+        };
+
+        for f in flags.split(',').filter(|f| !f.is_empty()) {
+            if opts.flags.iter().any(|x| x == f) {
+                continue;
+            }
+
+            let group = match VOLUME_FLAG_GROUPS.iter().find(|g| g.contains(&f)) {
+                Some(g) => g,
+                None => return Err(format!("unknown volume flag \"{f}\"")),
+            };
+            if let Some(other) = opts.flags.iter().find(|x| group.contains(&x.as_str())) {
+                return Err(format!("volume flags \"{other}\" and \"{f}\" are mutually exclusive"));
+            }
+
+            if f == "ro" {
+                opts.read_only = true;
+            }
+            opts.flags.push(f.to_string());
+        }
+        Ok(opts)
+    }
+}
+
+impl SquashfsOptions {
+    // Parse squashfs options, e.g. "sqsh,offset=4096,ro". Squashfs is always
+    // mounted read-only, so "ro" is accepted and implied.
+    fn parse(image: &str, flags: &str) -> Result<SquashfsOptions, String> {
+        static RE_VERITY: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^([0-9a-fA-F]{2}){16,64}$").unwrap());
+        let mut opts = SquashfsOptions {
+            image: PathBuf::from(image),
+            ..Default::default()
+        };
+
+        for o in flags.split(',').filter(|o| !o.is_empty()) {
+            match o.split_once('=') {
+                Some(("offset", v)) => match v.parse::<u64>() {
+                    Ok(n) => opts.offset = Some(n),
+                    Err(_) => return Err(format!("invalid squashfs offset \"{v}\", expected a number of bytes")),
+                },
+                Some(("verity", v)) => {
+                    if !RE_VERITY.is_match(v) {
+                        return Err(format!("invalid squashfs verity \"{v}\", expected a hex encoded root hash"));
+                    }
+                    opts.verity = Some(v.to_string());
+                }
+                Some((k, _)) => return Err(format!("unknown squashfs option \"{k}\"")),
+                None if o == "sqsh" || o == "ro" => {}
+                None => return Err(format!("unknown squashfs flag \"{o}\"")),
+            }
+        }
+        Ok(opts)
+    }
+
+    // Flags of the rendered mount, empty unless options were given.
+    fn to_flags(&self) -> String {
+        let mut parts = vec![];
+        if let Some(o) = self.offset {
+            parts.push(format!("offset={o}"));
+        }
+        if let Some(v) = &self.verity {
+            parts.push(format!("verity={v}"));
+        }
+        match parts.is_empty() {
+            true => String::new(),
+            false => format!("sqsh,{}", parts.join(",")),
+        }
+    }
+
+    // Check that the image holds a squashfs superblock at the given offset.
+    pub(crate) fn check_magic(&self) -> Result<(), String> {
+        let mut f = match std::fs::File::open(&self.image) {
+            Ok(f) => f,
+            Err(e) => return Err(format!("could not open: {e}")),
+        };
+        let mut magic = [0u8; 4];
+        let read = f
+            .seek(SeekFrom::Start(self.offset.unwrap_or(0)))
+            .and_then(|_| f.read_exact(&mut magic));
+        match read {
+            Ok(_) if &magic == SQUASHFS_MAGIC => Ok(()),
+            _ => match self.offset {
+                Some(o) => Err(format!("no squashfs magic number at offset {o}")),
+                None => Err(String::from("no squashfs magic number")),
+            },
+        }
+    }
+}
+
+impl TmpfsOptions {
+    // Parse tmpfs options separated by '+' or ',', e.g. "size=16g+mode=1777".
+    fn parse(flags: &str) -> Result<TmpfsOptions, String> {
+        static RE_SIZE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[0-9]+[kmgKMG%]?$").unwrap());
+        static RE_MODE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[0-7]{3,4}$").unwrap());
+        let mut opts = TmpfsOptions::default();
+
+        for o in flags.split(['+', ',']).filter(|o| !o.is_empty()) {
+            match o.split_once('=') {
+                Some(("size", v)) => {
+                    if !RE_SIZE.is_match(v) {
+                        return Err(format!("invalid tmpfs size \"{v}\", expected a number optionally followed by k, m, g or %"));
+                    }
+                    opts.size = Some(v.to_string());
+                }
+                Some(("mode", v)) => {
+                    if !RE_MODE.is_match(v) {
+                        return Err(format!("invalid tmpfs mode \"{v}\", expected an octal permission like 1777"));
+                    }
+                    opts.mode = u32::from_str_radix(v, 8).ok();
+                }
+                Some((k, _)) => {
+                    return Err(format!("unknown tmpfs option \"{k}\""));
+                }
+                None => {
+                    if !opts.flags.contains(&o.to_string()) {
+                        opts.flags.push(o.to_string());
+                    }
+                }
+            }
+        }
+        Ok(opts)
+    }
+
+    fn to_flags(&self) -> String {
+        let mut parts = vec![];
+        if let Some(s) = &self.size {
+            parts.push(format!("size={s}"));
+        }
+        if let Some(m) = self.mode {
+            parts.push(format!("mode={m:o}"));
+        }
+        parts.extend(self.flags.iter().cloned());
+        parts.join(",")
     }
 }
 
 impl SarusMount {
 
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    pub fn flags(&self) -> &str {
+        &self.flags
+    }
+
+    pub fn kind(&self) -> &MountKind {
+        &self.kind
+    }
+
     pub fn to_volume_string(&self) -> String {
         if self.flags.is_empty() {
             format!("{}:{}", self.source, self.target)
@@ -32,30 +408,177 @@ impl SarusMount {
         }
     }
 
+    // Argument of podman mounting a bind mount or a volume, which takes the
+    // paths as they are and not escaped like the fields of an fstab. Paths
+    // holding colons go through --mount, --volume splits them.
+    pub fn to_podman_mount_arg(&self) -> String {
+        let (source, target) = (unescape_mount(&self.source), unescape_mount(&self.target));
+        if source.contains(':') || target.contains(':') {
+            let mut opts = vec![
+                format!("type={}", self.kind.type_name()),
+                format!("src={source}"),
+                format!("dst={target}"),
+            ];
+            for f in self.flags.split(',').filter(|f| !f.is_empty()) {
+                match f {
+                    "rbind" => {}
+                    "bind" => opts.push(String::from("bind-nonrecursive")),
+                    "z" => opts.push(String::from("relabel=shared")),
+                    "Z" => opts.push(String::from("relabel=private")),
+                    "nocopy" => opts.push(String::from("volume-nocopy")),
+                    f if BIND_FLAG_GROUPS[1].contains(&f) => opts.push(format!("bind-propagation={f}")),
+                    f => opts.push(f.to_string()),
+                }
+            }
+            // podman reads the options as a CSV record
+            let opts: Vec<String> = opts
+                .into_iter()
+                .map(|o| match o.contains([',', '"']) {
+                    true => format!("\"{}\"", o.replace('"', "\"\"")),
+                    false => o,
+                })
+                .collect();
+            return format!("--mount={}", opts.join(","));
+        }
+        if self.flags.is_empty() {
+            format!("--volume={source}:{target}")
+        } else {
+            format!("--volume={source}:{target}:{}", self.flags)
+        }
+    }
+
+    // The mount as a table, when the compact form would be ambiguous.
+    fn to_table(&self) -> Option<MountTable> {
+        let (source, target) = (unescape_mount(&self.source), unescape_mount(&self.target));
+        if !source.contains(':') && !target.contains(':') && source == self.source && target == self.target {
+            return None;
+        }
+        let kind = self.kind.type_name();
+        Some(MountTable {
+            source: match self.kind {
+                MountKind::Tmpfs(_) | MountKind::Detach => None,
+                _ => Some(source),
+            },
+            target,
+            flags: self
+                .flags
+                .split(',')
+                .filter(|f| !f.is_empty() && (kind != "sqsh" || *f != "sqsh"))
+                .map(String::from)
+                .collect(),
+            kind: Some(kind.to_string()),
+        })
+    }
+
     pub fn try_new(
         input: String,
         uenv: &Option<HashMap<String, String>>,
     ) -> SarusResult<SarusMount> {
+        Self::try_new_with_options(input, uenv, &ExpandOptions::default())
+    }
+
+    pub fn try_new_with_options(
+        input: String,
+        uenv: &Option<HashMap<String, String>>,
+        opts: &ExpandOptions,
+    ) -> SarusResult<SarusMount> {
 
         let mut m = Self::from_string(input)?;
-        m.render(uenv)?;
+        m.render(uenv, opts)?;
         m.validate()?;
 
         Ok(m)
     }
 
+    pub fn try_new_from_entry(
+        entry: MountEntry,
+        uenv: &Option<HashMap<String, String>>,
+        opts: &ExpandOptions,
+    ) -> SarusResult<SarusMount> {
+        let (mut m, kind) = match entry {
+            MountEntry::Spec(s) => (Self::from_string(s)?, None),
+            MountEntry::Table(t) => {
+                let kind = t.kind.clone().unwrap_or_else(|| String::from("bind"));
+                (Self::from_table(t)?, Some(kind))
+            }
+        };
+        m.render(uenv, opts)?;
+        m.validate()?;
+        // The expanded source may make it another kind of mount
+        if let Some(k) = kind {
+            m.check_type(&k)?;
+        }
+
+        Ok(m)
+    }
+
+    // Fails unless the mount is of the type of its table.
+    fn check_type(&self, kind: &str) -> SarusResult<()> {
+        if self.kind.type_name() == kind {
+            return Ok(());
+        }
+        Err(SarusError {
+            kind: SarusErrorKind::InvalidMount { mount: self.target.clone() },
+            file_path: None,
+            msg: format!(
+                "mount table with target {}: type {kind} but {} makes it a {} mount",
+                self.target,
+                self.source,
+                self.kind.type_name()
+            ),
+            location: None,
+        })
+    }
+
+    fn from_table(t: MountTable) -> SarusResult<SarusMount> {
+        let invalid = |msg: String| SarusError {
+            kind: SarusErrorKind::InvalidMount { mount: t.target.clone() },
+            file_path: None,
+            msg: format!("mount table with target {}: {msg}", t.target),
+            location: None,
+        };
+
+        let kind = t.kind.as_deref().unwrap_or("bind");
+        let source = match (kind, &t.source) {
+            ("tmpfs", None) => String::from("tmpfs"),
+            ("umount", None) => String::from("umount"),
+            ("tmpfs" | "umount", Some(_)) => return Err(invalid(format!("type {kind} takes no source"))),
+            ("bind" | "sqsh", Some(s)) => s.clone(),
+            ("volume", Some(s)) if is_volume_name(s) => s.clone(),
+            ("volume", Some(s)) => return Err(invalid(format!("invalid volume name \"{s}\""))),
+            ("bind" | "sqsh" | "volume", None) => return Err(invalid(format!("type {kind} needs a source"))),
+            _ => return Err(invalid(format!("unknown type \"{kind}\""))),
+        };
+
+        let flags = match kind {
+            "sqsh" if t.flags.is_empty() => String::from("sqsh"),
+            "sqsh" => format!("sqsh,{}", t.flags.join(",")),
+            _ => t.flags.join(","),
+        };
+
+        let m = SarusMount {
+            kind: MountKind::detect(&source, &flags),
+            source,
+            target: t.target.clone(),
+            flags,
+        };
+        m.check_type(kind)?;
+        Ok(m)
+    }
+
     fn from_string(input: String) -> SarusResult<SarusMount> {
         let mut a = input.split(":");
         let asize = a.clone().count();
 
         if asize < 2 || asize > 3 {
             return Err(SarusError {
-                code: 8,
+                kind: SarusErrorKind::InvalidMount { mount: input.clone() },
                 file_path: None,
                 msg: format!(
                     "{} contains {} number of fields, expected 2 or 3",
                     input, asize
                 ),
+                location: None,
             });
         };
 
@@ -70,6 +593,7 @@ impl SarusMount {
             source: String::from(s),
             target: String::from(t),
             flags: String::from(f),
+            kind: MountKind::detect(s, f),
         };
 
         Ok(m)
@@ -78,20 +602,26 @@ impl SarusMount {
     fn render(
         &mut self,
         uenv: &Option<HashMap<String, String>>,
+        opts: &ExpandOptions,
     ) -> SarusResult<()> {
 
         let mut i = self.clone();
-        i.translate_to_absolute()?;
 
-        let mut s = escape_mount(i.source);
-        let mut t = escape_mount(i.target);
-        s = expand_vars_string(s, uenv)?;
-        t = expand_vars_string(t, uenv)?;
+        // Expand every field against the same env first, the kind of the
+        // mount and its flags are the ones of the expanded values
+        if opts.expands("mounts") {
+            i.source = expand_vars_string_with_options(i.source, uenv, opts)?;
+            i.target = expand_vars_string_with_options(i.target, uenv, opts)?;
+            i.flags = expand_vars_string_with_options(i.flags, uenv, opts)?;
+            i.source = expand_tilde(&i.source, uenv)?;
+        }
+        i.translate_to_absolute()?;
 
-        i.source = s;
-        i.target = t;
-        i.flags = expand_vars_string(i.flags, uenv)?;
+        i.kind = MountKind::detect(&i.source, &i.flags);
         i.render_flags()?;
+
+        i.source = escape_mount(i.source);
+        i.target = escape_mount(i.target);
         *self = i;
 
         Ok(())
@@ -101,16 +631,17 @@ impl SarusMount {
 
         let mut i = self.clone();
 
-        if i.flags == "sqsh" {
+        if is_squashfs_flags(&i.flags) {
             let mut ps: std::path::PathBuf = std::path::Path::new(&i.source).into();
 
             if ps.starts_with(".") {
                 ps = match std::path::absolute(&ps) {
                     Err(_) => {
                         return Err(SarusError {
-                            code: 9,
+                            kind: SarusErrorKind::MountPathTranslation { path: ps.display().to_string() },
                             file_path: None,
                             msg: format!("cannot translate {} in an absolute path", ps.display()),
+                            location: None,
                         });
                     }
                     Ok(ok) => ok,
@@ -121,9 +652,10 @@ impl SarusMount {
                 Some(ok) => ok.to_string(),
                 None => {
                     return Err(SarusError {
-                        code: 11,
+                        kind: SarusErrorKind::MountPathEncoding { path: ps.display().to_string() },
                         file_path: None,
                         msg: format!("cannot translate {} into string", ps.display()),
+                        location: None,
                     });
                 }
             };
@@ -139,35 +671,105 @@ impl SarusMount {
 
         let mut i = self.clone();
 
-        if i.flags == "sqsh" {
-            let metadata = match std::fs::metadata(self.source.as_str()) {
+        if let MountKind::Squashfs(_) = i.kind {
+            let metadata = match std::fs::metadata(i.source.as_str()) {
                 Ok(m) => m,
                 Err(e) => {
                     return Err(SarusError {
-                        code: 14,
+                        kind: SarusErrorKind::SquashfsStat { source: i.source.clone() },
                         file_path: None,
                         msg: format!("could not stat source of squashfs mount ({}): {}", i.source, e),
+                        location: None,
                     });
                 }
             };
             if !metadata.is_file() {
                 return Err(SarusError {
-                    code: 16,
+                    kind: SarusErrorKind::SquashfsNotFile { source: i.source.clone() },
                     file_path: None,
                     msg: format!("source of squashfs mount ({}) must be a regular file", i.source),
+                    location: None,
                 });
             }
 
-            i.flags = String::from("");
+            let opts = match SquashfsOptions::parse(&i.source, &i.flags) {
+                Ok(o) => o,
+                Err(e) => {
+                    return Err(SarusError {
+                        kind: SarusErrorKind::InvalidMountOption { mount: i.to_volume_string() },
+                        file_path: None,
+                        msg: format!("mount {}: {e}", i.to_volume_string()),
+                        location: None,
+                    });
+                }
+            };
+            if let Err(e) = opts.check_magic() {
+                return Err(SarusError {
+                    kind: SarusErrorKind::SquashfsMagic { source: i.source.clone() },
+                    file_path: None,
+                    msg: format!("source of squashfs mount ({}) is not a squashfs image: {e}", i.source),
+                    location: None,
+                });
+            }
+
+            i.flags = opts.to_flags();
+            i.kind = MountKind::Squashfs(opts);
+
+        } else if let MountKind::Tmpfs(_) = i.kind {
+
+            let opts = match TmpfsOptions::parse(&i.flags) {
+                Ok(o) => o,
+                Err(e) => {
+                    return Err(SarusError {
+                        kind: SarusErrorKind::InvalidMountOption { mount: i.to_volume_string() },
+                        file_path: None,
+                        msg: format!("mount {}: {e}", i.to_volume_string()),
+                        location: None,
+                    });
+                }
+            };
+            i.flags = opts.to_flags();
+            i.kind = MountKind::Tmpfs(opts);
+
+        } else if let MountKind::Bind(_) = i.kind {
+
+            // Validate flags, removing duplicates
+            let opts = match BindOptions::parse(&i.flags) {
+                Ok(o) => o,
+                Err(e) => {
+                    return Err(SarusError {
+                        kind: SarusErrorKind::InvalidMountOption { mount: i.to_volume_string() },
+                        file_path: None,
+                        msg: format!("mount {}: {e}", i.to_volume_string()),
+                        location: None,
+                    });
+                }
+            };
+            i.flags = opts.flags.join(",");
+            i.kind = MountKind::Bind(opts);
+
+        } else if let MountKind::Volume(_) = i.kind {
+
+            let opts = match VolumeOptions::parse(&i.source, &i.flags) {
+                Ok(o) => o,
+                Err(e) => {
+                    return Err(SarusError {
+                        kind: SarusErrorKind::InvalidMountOption { mount: i.to_volume_string() },
+                        file_path: None,
+                        msg: format!("mount {}: {e}", i.to_volume_string()),
+                        location: None,
+                    });
+                }
+            };
+            i.flags = opts.flags.join(",");
+            i.kind = MountKind::Volume(opts);
 
         } else {
 
-            // Remove duplicate flags
-            let parts: Vec<_> = i.flags.split(',').collect();
-            let parts_set: HashSet<_> = parts.into_iter().collect();
-            let parts_unique_vec: Vec<_> = parts_set.into_iter().collect();
-            let f = parts_unique_vec.join(",");
-            i.flags = String::from(f);
+            // Remove duplicate flags, keeping their order
+            let mut parts: Vec<_> = i.flags.split(',').collect();
+            dedup_entries(&mut parts, MergePolicy::PreserveOrderDedupe);
+            i.flags = parts.join(",");
         }
         *self = i;
 
@@ -176,23 +778,28 @@ impl SarusMount {
 
     fn validate(&self) -> SarusResult<()> {
 
-        if ![".", "/"].iter().any(|s| self.source.starts_with(*s)) {
+        let is_path = [".", "/"].iter().any(|s| self.source.starts_with(*s));
+        let is_special = matches!(self.kind, MountKind::Tmpfs(_) | MountKind::Detach | MountKind::Volume(_));
+
+        if !is_path && !is_special {
             return Err(SarusError {
-                code: 12,
+                kind: SarusErrorKind::InvalidMountSource { source: self.source.clone() },
                 file_path: None,
                 msg: format!(
-                    "mount source {:#?} must be one among a relative path starting with . , an absolute path starting with / , \"tmpfs\" or \"umount\"", self.source
+                    "mount source {:#?} must be one among a relative path starting with . , an absolute path starting with / , \"tmpfs\", \"umount\" or a volume name", self.source
                 ),
+                location: None,
             });
         }
 
         if ![".", "/"].iter().any(|s| self.target.starts_with(*s)) {
             return Err(SarusError {
-                code: 13,
+                kind: SarusErrorKind::InvalidMountTarget { target: self.target.clone() },
                 file_path: None,
                 msg: format!(
                     "mount target {:#?} must be one among a relative path starting with . or an absolute path starting with /", self.target
                 ),
+                location: None,
             });
         }
 
@@ -204,16 +811,199 @@ pub fn sarus_mounts_from_strings(
     input: Vec<String>,
     uenv: &Option<HashMap<String, String>>,
 ) -> SarusResult<SarusMounts> {
-    let mut res = vec![];
+    sarus_mounts_from_strings_with_options(input, uenv, &ExpandOptions::default())
+}
+
+pub fn sarus_mounts_from_strings_with_options(
+    input: Vec<String>,
+    uenv: &Option<HashMap<String, String>>,
+    opts: &ExpandOptions,
+) -> SarusResult<SarusMounts> {
+    let entries = input.into_iter().map(MountEntry::Spec).collect();
+    sarus_mounts_from_entries(entries, uenv, opts)
+}
+
+pub fn sarus_mounts_from_entries(
+    input: Vec<MountEntry>,
+    uenv: &Option<HashMap<String, String>>,
+    opts: &ExpandOptions,
+) -> SarusResult<SarusMounts> {
+    let (res, _) = sarus_mounts_from_entries_with_warnings(input, uenv, opts)?;
+    Ok(res)
+}
+
+// Same as sarus_mounts_from_entries(), along with warnings about the mounts
+// sharing a target.
+pub fn sarus_mounts_from_entries_with_warnings(
+    input: Vec<MountEntry>,
+    uenv: &Option<HashMap<String, String>>,
+    opts: &ExpandOptions,
+) -> SarusResult<(SarusMounts, Vec<String>)> {
+    sarus_mounts_from_located_entries(input, &[], uenv, opts)
+}
+
+// Same, failures of the entries found in locations, (entry, file, position)
+// as given by list_item_locations(), being reported at their position.
+pub(crate) fn sarus_mounts_from_located_entries(
+    input: Vec<MountEntry>,
+    locations: &[(String, String, Location)],
+    uenv: &Option<HashMap<String, String>>,
+    opts: &ExpandOptions,
+) -> SarusResult<(SarusMounts, Vec<String>)> {
+    let mut res: SarusMounts = vec![];
 
     for i in input.iter() {
-        let m = SarusMount::try_new(i.clone(), uenv)?;
-        if !res.contains(&m) {
-            res.push(m.clone());
+        let r = SarusMount::try_new_from_entry(i.clone(), uenv, opts).map_err(|e| locate_entry(e, i, locations));
+        let m = match opts.collect(r.map(Some), None)? {
+            Some(m) => m,
+            None => continue,
+        };
+        if opts.merge_policy == MergePolicy::LastWinsByTarget {
+            res.retain(|x| x.target != m.target);
         }
+        res.push(m);
     }
+    dedup_entries(&mut res, opts.merge_policy);
 
-    Ok(res)
+    resolve_target_collisions(res, opts.mount_collision)
+}
+
+impl MountEntry {
+    // Whether an entry of mounts_remove designates this entry, either as
+    // source:target, e.g. "/aaa:/bbb", or as its target, e.g. "/bbb". Flags
+    // aren't compared.
+    pub(crate) fn removed_by(&self, r: &str) -> bool {
+        let (source, target) = match self {
+            MountEntry::Spec(s) => {
+                let mut f = s.splitn(3, ':');
+                (f.next().unwrap_or(""), f.next().unwrap_or(""))
+            }
+            MountEntry::Table(t) => (t.source.as_deref().unwrap_or(""), t.target.as_str()),
+        };
+        let mut f = r.splitn(3, ':');
+        match (f.next(), f.next()) {
+            (Some(s), Some(t)) => s == source && t == target,
+            _ => r == target,
+        }
+    }
+}
+
+fn locate_entry(e: SarusError, entry: &MountEntry, locations: &[(String, String, Location)]) -> SarusError {
+    let id = match entry {
+        MountEntry::Spec(s) => s,
+        MountEntry::Table(t) => &t.target,
+    };
+    match locations.iter().find(|(i, _, _)| i == id) {
+        Some((_, f, l)) if e.file_path.is_none() => SarusError {
+            file_path: Some(f.clone()),
+            ..e
+        }
+        .at(Some(*l)),
+        _ => e,
+    }
+}
+
+fn resolve_target_collisions(
+    mounts: SarusMounts,
+    policy: MountCollision,
+) -> SarusResult<(SarusMounts, Vec<String>)> {
+    let mut res: SarusMounts = vec![];
+    let mut warnings = vec![];
+
+    for m in mounts {
+        let other = match res.iter().position(|o| o.target == m.target) {
+            Some(i) => i,
+            None => {
+                res.push(m);
+                continue;
+            }
+        };
+        let (a, b) = (res[other].to_volume_string(), m.to_volume_string());
+        match policy {
+            MountCollision::Error => {
+                return Err(SarusError {
+                    kind: SarusErrorKind::MountTargetCollision { target: m.target.clone() },
+                    file_path: None,
+                    msg: format!("mounts {a} and {b} share the target {}", m.target),
+                    location: None,
+                });
+            }
+            MountCollision::Keep => {
+                warnings.push(format!("mount {b} shadows {a}"));
+                res.push(m);
+            }
+            MountCollision::LastWins => {
+                warnings.push(format!("mount {b} replaces {a}"));
+                res.remove(other);
+                res.push(m);
+            }
+            MountCollision::FirstWins => {
+                warnings.push(format!("mount {b} ignored, {a} has the same target"));
+            }
+        }
+    }
+
+    Ok((res, warnings))
+}
+
+// Check that the sources of bind and squashfs mounts exist and can be read
+// by the calling user, all the failing ones being reported at once.
+pub fn verify_mount_sources(mounts: &SarusMounts) -> SarusResult<()> {
+    let mut paths = vec![];
+    let mut problems = vec![];
+
+    for m in mounts.iter() {
+        let path = match &m.kind {
+            MountKind::Bind(_) => PathBuf::from(unescape_mount(&m.source)),
+            MountKind::Squashfs(o) => o.image.clone(),
+            _ => continue,
+        };
+        if let Err(e) = check_readable(&path) {
+            problems.push(format!("{} - {e}", path.display()));
+            paths.push(path.display().to_string());
+        }
+    }
+
+    if paths.is_empty() {
+        return Ok(());
+    }
+    Err(SarusError {
+        kind: SarusErrorKind::MountSourcesMissing { paths },
+        file_path: None,
+        msg: format!("mount sources not accessible: {}", problems.join(", ")),
+        location: None,
+    })
+}
+
+#[cfg(unix)]
+fn check_readable(path: &std::path::Path) -> Result<(), String> {
+    use nix::unistd::{AccessFlags, access};
+
+    let md = match std::fs::metadata(path) {
+        Ok(m) => m,
+        Err(e) => return Err(e.to_string()),
+    };
+    let mode = match md.is_dir() {
+        true => AccessFlags::R_OK | AccessFlags::X_OK,
+        false => AccessFlags::R_OK,
+    };
+    access(path, mode).map_err(|e| e.desc().to_string())
+}
+
+#[cfg(not(unix))]
+fn check_readable(path: &std::path::Path) -> Result<(), String> {
+    std::fs::metadata(path).map(|_| ()).map_err(|e| e.to_string())
+}
+
+// Names of podman volumes, e.g. vol-mydata
+pub(crate) fn is_volume_name(s: &str) -> bool {
+    static RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[A-Za-z0-9][A-Za-z0-9_.-]*$").unwrap());
+    RE.is_match(s)
+}
+
+// Squashfs mounts carry sqsh among their flags, e.g. "sqsh,offset=4096".
+fn is_squashfs_flags(flags: &str) -> bool {
+    flags.split(',').any(|f| f == "sqsh")
 }
 
 // From pyxis code (still needed ???)
@@ -241,3 +1031,267 @@ fn escape_mount(path: String) -> String {
     }
     epath
 }
+
+// Path of an escaped mount entry, see escape_mount().
+pub(crate) fn unescape_mount(path: &str) -> String {
+    let mut res = String::new();
+    let mut rest = path;
+    while let Some(i) = rest.find('\\') {
+        res.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let (c, n) = match rest.get(..4) {
+            Some("\\040") => (' ', 4),
+            Some("\\011") => ('\t', 4),
+            Some("\\012") => ('\n', 4),
+            Some("\\134") => ('\\', 4),
+            _ if rest.starts_with("\\\\") => ('\\', 2),
+            _ => ('\\', 1),
+        };
+        res.push(c);
+        rest = &rest[n..];
+    }
+    res.push_str(rest);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_mount(input: &str) -> SarusResult<SarusMount> {
+        SarusMount::try_new(input.to_string(), &Some(HashMap::new()))
+    }
+
+    #[test]
+    fn mount_kinds() {
+        let m = new_mount("/aaa:/bbb:ro").unwrap();
+        assert!(m.source() == "/aaa");
+        assert!(m.target() == "/bbb");
+        assert!(
+            *m.kind()
+                == MountKind::Bind(BindOptions {
+                    read_only: true,
+                    propagation: None,
+                    flags: vec!["ro".to_string()]
+                })
+        );
+
+        let m = new_mount("tmpfs:/scratch").unwrap();
+        assert!(*m.kind() == MountKind::Tmpfs(TmpfsOptions::default()));
+        assert!(m.to_volume_string() == "tmpfs:/scratch");
+
+        let m = new_mount("tmpfs:/scratch:size=16g+mode=1777").unwrap();
+        let opts = TmpfsOptions {
+            size: Some("16g".to_string()),
+            mode: Some(0o1777),
+            flags: vec![],
+        };
+        assert!(*m.kind() == MountKind::Tmpfs(opts));
+        assert!(m.to_volume_string() == "tmpfs:/scratch:size=16g,mode=1777");
+
+        let m = new_mount("umount:/scratch").unwrap();
+        assert!(*m.kind() == MountKind::Detach);
+
+        let m = new_mount("/test/etc/image.sqsh:/data:sqsh");
+        assert!(m.is_err());
+
+        let cwd = std::env::current_dir().unwrap();
+        let m = new_mount("./test/etc/image.sqsh:/data:sqsh").unwrap();
+        let image = cwd.join("test/etc/image.sqsh");
+        let opts = SquashfsOptions {
+            image,
+            ..Default::default()
+        };
+        assert!(*m.kind() == MountKind::Squashfs(opts));
+        assert!(m.flags() == "");
+    }
+
+    #[test]
+    fn mount_squashfs_options() {
+        let verity = "ab".repeat(32);
+        let m = new_mount(&format!("./test/etc/offset.sqsh:/data:sqsh,offset=4096,verity={verity},ro")).unwrap();
+        match m.kind() {
+            MountKind::Squashfs(o) => {
+                assert!(o.offset == Some(4096));
+                assert!(o.verity.as_deref() == Some(verity.as_str()));
+            }
+            _ => panic!("expected a squashfs mount"),
+        }
+        assert!(m.flags() == format!("sqsh,offset=4096,verity={verity}"));
+
+        // The magic number is looked up at the given offset
+        let err = new_mount("./test/etc/offset.sqsh:/data:sqsh").err().unwrap();
+        assert!(err.code() == 42);
+        assert!(new_mount("./test/etc/plain.txt:/data:sqsh").is_err());
+        assert!(new_mount("./test/etc/image.sqsh:/data:sqsh,offset=lots").is_err());
+        assert!(new_mount("./test/etc/image.sqsh:/data:sqsh,verity=xyz").is_err());
+        assert!(new_mount("./test/etc/image.sqsh:/data:sqsh,rw").is_err());
+    }
+
+    #[test]
+    fn mount_tmpfs_invalid_options() {
+        assert!(new_mount("tmpfs:/scratch:size=lots").is_err());
+        assert!(new_mount("tmpfs:/scratch:mode=999").is_err());
+        assert!(new_mount("tmpfs:/scratch:uid=0").is_err());
+    }
+
+    #[test]
+    fn mount_bind_flags() {
+        let m = new_mount("/aaa:/bbb:rslave,ro,nosuid,ro,nodev").unwrap();
+        assert!(m.flags() == "rslave,ro,nosuid,nodev");
+        match m.kind() {
+            MountKind::Bind(o) => {
+                assert!(o.read_only);
+                assert!(o.propagation == Some("rslave".to_string()));
+            }
+            _ => panic!("expected a bind mount"),
+        }
+
+        assert!(new_mount("/aaa:/bbb:ro,rw").is_err());
+        assert!(new_mount("/aaa:/bbb:rshared,rprivate").is_err());
+        assert!(new_mount("/aaa:/bbb:bogus").is_err());
+
+        let m = new_mount("/aaa:/bbb:noatime,U,O,nocopy,idmap=uids=0-1000-10").unwrap();
+        assert!(m.flags() == "noatime,U,O,nocopy,idmap=uids=0-1000-10");
+        assert!(new_mount("/aaa:/bbb:idmap").is_ok());
+        assert!(new_mount("/aaa:/bbb:noatime,relatime").is_err());
+        assert!(new_mount("/aaa:/bbb:ro=1").is_err());
+    }
+
+    #[test]
+    fn mount_expand_env() {
+        let mut uenv = HashMap::new();
+        uenv.insert(String::from("SCRATCH"), String::from("/capstor/scratch/u"));
+        uenv.insert(String::from("MODE"), String::from("ro"));
+        uenv.insert(String::from("IMAGE"), String::from("./test/etc/image.sqsh"));
+        uenv.insert(String::from("KIND"), String::from("sqsh"));
+        let uenv = Some(uenv);
+        let new = |input: &str| SarusMount::try_new(input.to_string(), &uenv);
+
+        let m = new("$SCRATCH:/scratch:$MODE").unwrap();
+        assert!(m.source() == "/capstor/scratch/u");
+        assert!(matches!(m.kind(), MountKind::Bind(o) if o.read_only));
+
+        let m = new("${SCRATCH}/my data:${SCRATCH}").unwrap();
+        assert!(m.source() == "/capstor/scratch/u/my\\040data");
+        assert!(m.target() == "/capstor/scratch/u");
+
+        // The kind is detected on the expanded flags
+        let m = new("$IMAGE:/data:$KIND").unwrap();
+        let image = std::env::current_dir().unwrap().join("test/etc/image.sqsh");
+        assert!(matches!(m.kind(), MountKind::Squashfs(o) if o.image == image));
+
+        assert!(new("$UNDEFINED:/scratch").is_err());
+        assert!(new("/scratch:/scratch:$UNDEFINED").is_err());
+    }
+
+    #[test]
+    fn mount_target_collisions() {
+        let input = vec![
+            MountEntry::Spec(String::from("/capstor/scratch:/scratch")),
+            MountEntry::Spec(String::from("/users:/users")),
+            MountEntry::Spec(String::from("/iopsstor/scratch:/scratch")),
+        ];
+        let with = |collision: MountCollision| {
+            let opts = ExpandOptions {
+                mount_collision: collision,
+                ..Default::default()
+            };
+            sarus_mounts_from_entries_with_warnings(input.clone(), &None, &opts)
+        };
+        let sources = |m: &SarusMounts| m.iter().map(|x| x.source().to_string()).collect::<Vec<_>>();
+
+        let (m, w) = with(MountCollision::Keep).unwrap();
+        assert!(m.len() == 3 && w.len() == 1);
+        let (m, w) = with(MountCollision::LastWins).unwrap();
+        assert!(sources(&m) == vec!["/users", "/iopsstor/scratch"]);
+        assert!(w[0].contains("replaces"));
+        let (m, _) = with(MountCollision::FirstWins).unwrap();
+        assert!(sources(&m) == vec!["/capstor/scratch", "/users"]);
+        let err = with(MountCollision::Error).err().unwrap();
+        assert!(err.kind == SarusErrorKind::MountTargetCollision { target: String::from("/scratch") });
+    }
+
+    #[test]
+    fn mount_verify_sources() {
+        let input = vec![
+            String::from("./test/etc:/etc/test:ro"),
+            String::from("/nonexistent/raster a:/a"),
+            String::from("tmpfs:/tmp"),
+            String::from("/nonexistent/raster-b:/b"),
+        ];
+        let m = sarus_mounts_from_strings(input, &None).unwrap();
+        let err = verify_mount_sources(&m).err().unwrap();
+        let paths = vec![String::from("/nonexistent/raster a"), String::from("/nonexistent/raster-b")];
+        assert!(err.kind == SarusErrorKind::MountSourcesMissing { paths });
+        assert!(verify_mount_sources(&m[..1].to_vec()).is_ok());
+        assert!(unescape_mount("/a\\040b\\\\c") == "/a b\\c");
+    }
+
+    #[test]
+    fn mount_expand_tilde() {
+        let uenv = Some(HashMap::from([(String::from("HOME"), String::from("/users/u/"))]));
+        let new = |input: &str| SarusMount::try_new(input.to_string(), &uenv);
+
+        let m = new("~/data:/data").unwrap();
+        assert!(m.source() == "/users/u/data");
+        assert!(matches!(m.kind(), MountKind::Bind(_)));
+        assert!(new("~:/home").unwrap().source() == "/users/u");
+        // Targets are paths of the container
+        assert!(new("/data:~/data").is_err());
+        assert!(new("~nosuchuser-raster/data:/data").err().unwrap().code() == 53);
+    }
+
+    #[test]
+    fn mount_volumes() {
+        let m = new_mount("vol-mydata:/data:ro,Z").unwrap();
+        let opts = VolumeOptions {
+            name: "vol-mydata".to_string(),
+            read_only: true,
+            flags: vec!["ro".to_string(), "Z".to_string()],
+        };
+        assert!(*m.kind() == MountKind::Volume(opts));
+        assert!(m.to_volume_string() == "vol-mydata:/data:ro,Z");
+
+        assert!(new_mount("vol-mydata:/data:ro,rw").is_err());
+        assert!(new_mount("vol-mydata:/data:rslave").is_err());
+    }
+
+    #[test]
+    fn mount_invalid_source() {
+        assert!(new_mount("-aaa:/bbb").is_err());
+        assert!(new_mount("a a:/bbb").is_err());
+        assert!(new_mount("/aaa:bbb").is_err());
+        assert!(new_mount("/aaa").is_err());
+    }
+
+    #[test]
+    fn mount_tables() {
+        let table = |toml: &str| {
+            let t: MountTable = toml::from_str(toml).unwrap();
+            SarusMount::try_new_from_entry(MountEntry::Table(t), &Some(HashMap::new()), &ExpandOptions::default())
+        };
+
+        // The type of the table is the kind of the mount
+        assert!(table("source = \"vol-a\"\ntarget = \"/a\"\ntype = \"volume\"").is_ok());
+        assert!(table("source = \"vol-a\"\ntarget = \"/a\"").err().unwrap().code() == 8);
+        assert!(table("source = \"tmpfs\"\ntarget = \"/a\"\ntype = \"bind\"").err().unwrap().code() == 8);
+
+        // Paths with colons or escapes round trip as tables
+        let m = table("source = \"/run:1/my data\"\ntarget = \"/data\"\nflags = [\"ro\", \"rslave\"]").unwrap();
+        let json = serde_json::to_string(&m).unwrap();
+        let v: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert!(v["source"] == "/run:1/my data" && v["type"] == "bind" && v["flags"][1] == "rslave");
+        assert!(serde_json::from_str::<SarusMount>(&json).unwrap() == m);
+        assert!(m.to_podman_mount_arg() == "--mount=type=bind,src=/run:1/my data,dst=/data,ro,bind-propagation=rslave");
+
+        let m = table("target = \"/a:b\"\nflags = [\"size=1g\"]\ntype = \"tmpfs\"").unwrap();
+        let json = serde_json::to_string(&m).unwrap();
+        assert!(json == r#"{"target":"/a:b","flags":["size=1g"],"type":"tmpfs"}"#);
+        assert!(serde_json::from_str::<SarusMount>(&json).unwrap() == m);
+
+        let m = new_mount("/aaa:/bbb:ro").unwrap();
+        assert!(serde_json::to_string(&m).unwrap() == "\"/aaa:/bbb:ro\"");
+        assert!(m.to_podman_mount_arg() == "--volume=/aaa:/bbb:ro");
+    }
+}
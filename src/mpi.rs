@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::EDF;
+use crate::common::{expand_vars_string_with_options, expand_vars_vec_with_options};
+use crate::error::{SarusError, SarusErrorKind, SarusResult};
+use crate::mount::sarus_mounts_from_strings_with_options;
+use crate::options::RenderOptions;
+
+// What the MPI hook of the site needs, declared once in the configuration
+// and pulled in by EDFs setting e.g. mpi = "openmpi":
+// [mpi_templates.openmpi]
+// mounts = ["/opt/openmpi:/opt/openmpi:ro"]
+// [mpi_templates.openmpi.annotations]
+// "com.hooks.mpi.enabled" = "true"
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct MpiTemplate {
+    #[serde(default)]
+    pub annotations: HashMap<String, String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    #[serde(default)]
+    pub mounts: Vec<String>,
+    #[serde(default)]
+    pub devices: Vec<String>,
+}
+
+// Expand the template named by the mpi field into the EDF. Annotations and
+// env set by the EDF itself win over the ones of the template.
+pub(crate) fn apply(e: &mut EDF, options: &RenderOptions) -> SarusResult<()> {
+    let name = match &e.mpi {
+        Some(n) => n.clone(),
+        None => return Ok(()),
+    };
+    let t = match options.mpi_templates.get(&name) {
+        Some(t) => t,
+        None => {
+            let mut known: Vec<&String> = options.mpi_templates.keys().collect();
+            known.sort();
+            return Err(SarusError {
+                kind: SarusErrorKind::UnknownMpi { name: name.clone() },
+                file_path: None,
+                msg: format!("mpi \"{name}\" has no template in the configuration, known ones are {known:?}"),
+                location: None,
+            });
+        }
+    };
+
+    let env = &options.env;
+    let xopts = options.expand_options();
+
+    for (k, v) in t.annotations.iter() {
+        if !e.annotations.contains_key(k) {
+            e.annotations.insert(k.clone(), v.clone());
+        }
+    }
+    for (k, v) in t.env.iter() {
+        if !e.env.contains_key(k) {
+            let v = expand_vars_string_with_options(v.clone(), env, &xopts)?;
+            e.env.insert(k.clone(), v);
+        }
+    }
+    for m in sarus_mounts_from_strings_with_options(t.mounts.clone(), env, &xopts)? {
+        if !e.mounts.contains(&m) {
+            e.mounts.push(m);
+        }
+    }
+    for d in expand_vars_vec_with_options(t.devices.clone(), env, &xopts)? {
+        if !e.devices.contains(&d) {
+            e.devices.push(d);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,81 @@
+// Async variants of the render API, for callers running on a tokio runtime.
+// Renders read files and may spawn bash, so they run on the blocking pool
+// instead of the executor threads.
+use crate::error::{SarusError, SarusErrorKind, SarusResult};
+use crate::options::RenderOptions;
+use crate::{EDF, render_from_str_with_options, render_with_options, validate};
+
+async fn blocking<T, F>(what: &str, f: F) -> SarusResult<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> SarusResult<T> + Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(r) => r,
+        Err(e) => Err(SarusError {
+            kind: SarusErrorKind::AsyncTask,
+            file_path: None,
+            msg: format!("{what} task failed - {e}"),
+            location: None,
+        }),
+    }
+}
+
+pub async fn render_async(path: String, options: RenderOptions) -> SarusResult<EDF> {
+    blocking("render", move || render_with_options(path, &options)).await
+}
+
+pub async fn render_from_str_async(content: String, origin_hint: String, options: RenderOptions) -> SarusResult<EDF> {
+    blocking("render", move || render_from_str_with_options(content, &origin_hint, &options)).await
+}
+
+pub async fn validate_async(path: String) -> SarusResult<()> {
+    blocking("validate", move || validate(path)).await
+}
+
+// Read a file without blocking the executor.
+pub async fn load_async(path: &str) -> SarusResult<String> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(s) => Ok(s),
+        Err(e) => Err(SarusError {
+            kind: SarusErrorKind::FileRead,
+            file_path: Some(String::from(path)),
+            msg: format!("{e}"),
+            location: None,
+        }),
+    }
+}
+
+// Render a file read without blocking the executor, its bases are read on
+// the blocking pool.
+pub async fn render_file_async(path: String, options: RenderOptions) -> SarusResult<EDF> {
+    let content = load_async(&path).await?;
+    render_from_str_async(content, path, options).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_async_api() {
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let opts = RenderOptions::new().search_paths(vec![String::from("test/toml")]);
+
+        rt.block_on(async {
+            let edf = render_async(String::from("test/toml/top-simple-1.toml"), opts.clone())
+                .await
+                .unwrap();
+            let sync = render_with_options(String::from("test/toml/top-simple-1.toml"), &opts).unwrap();
+            assert!(edf.image == sync.image);
+
+            let path = String::from("test/search/site/ml/pytorch-24.01.toml");
+            let edf = render_file_async(path, opts.clone()).await.unwrap();
+            assert!(edf.image == "nvcr.io/nvidia/pytorch:24.01-py3");
+
+            let err = load_async("test/toml/not_found.toml").await.err().unwrap();
+            assert!(err.kind == SarusErrorKind::FileRead);
+            assert!(validate_async(String::from("test/toml/top-simple-1.toml")).await.is_ok());
+        });
+    }
+}
@@ -0,0 +1,187 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::error::SarusResult;
+use crate::mount::{MountKind, unescape_mount};
+use crate::{EDF, to_canonical_json};
+
+// Subset of an OCI runtime spec (config.json) described by an EDF, to be
+// merged into the spec of the bundle by a runtime hook.
+#[derive(Clone, Debug, Serialize)]
+pub struct OciSpec {
+    pub annotations: BTreeMap<String, String>,
+    // Hooks by stage, e.g. "createContainer".
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub hooks: BTreeMap<String, Vec<OciHook>>,
+    pub mounts: Vec<OciMount>,
+    pub process: OciProcess,
+    pub root: OciRoot,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct OciMount {
+    pub destination: String,
+    #[serde(rename = "type")]
+    pub mount_type: String,
+    pub source: String,
+    pub options: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct OciHook {
+    pub path: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub env: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u32>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct OciProcess {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    pub env: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct OciRoot {
+    pub readonly: bool,
+}
+
+impl OciSpec {
+    pub fn to_json(&self) -> SarusResult<String> {
+        to_canonical_json(self)
+    }
+}
+
+impl EDF {
+    pub fn to_oci_spec(&self) -> OciSpec {
+        let mut mounts = vec![];
+        for m in self.mounts.iter() {
+            // The spec takes the paths as they are, not escaped
+            let target = unescape_mount(m.target());
+            let om = match m.kind() {
+                MountKind::Bind(o) => {
+                    let mut options = o.flags.clone();
+                    if !options.iter().any(|f| f == "bind" || f == "rbind") {
+                        options.insert(0, String::from("rbind"));
+                    }
+                    OciMount {
+                        destination: target,
+                        mount_type: String::from("bind"),
+                        source: unescape_mount(m.source()),
+                        options,
+                    }
+                }
+                MountKind::Tmpfs(_) => OciMount {
+                    destination: target,
+                    mount_type: String::from("tmpfs"),
+                    source: String::from("tmpfs"),
+                    options: m
+                        .flags()
+                        .split(',')
+                        .filter(|f| !f.is_empty())
+                        .map(String::from)
+                        .collect(),
+                },
+                MountKind::Squashfs(o) => {
+                    let mut options = vec![String::from("loop"), String::from("ro")];
+                    if let Some(offset) = o.offset {
+                        options.push(format!("offset={offset}"));
+                    }
+                    if let Some(v) = &o.verity {
+                        options.push(format!("verity.roothash={v}"));
+                    }
+                    OciMount {
+                        destination: target,
+                        mount_type: String::from("squashfs"),
+                        source: o.image.display().to_string(),
+                        options,
+                    }
+                }
+                // Nothing to add, the target is removed by the hooks.
+                MountKind::Detach => continue,
+                // Named volumes are managed by the container engine.
+                MountKind::Volume(_) => continue,
+            };
+            mounts.push(om);
+        }
+
+        let mut hooks: BTreeMap<String, Vec<OciHook>> = BTreeMap::new();
+        for h in self.hooks.iter() {
+            hooks.entry(h.stage.clone()).or_default().push(OciHook {
+                path: h.path.clone(),
+                args: h.args.clone(),
+                env: h.env.clone(),
+                timeout: h.timeout,
+            });
+        }
+
+        let env: BTreeMap<_, _> = self.env.iter().collect();
+        let cwd = match self.workdir.is_empty() {
+            true => None,
+            false => Some(self.workdir.clone()),
+        };
+
+        OciSpec {
+            annotations: self.annotations.clone().into_iter().collect(),
+            hooks,
+            mounts,
+            process: OciProcess {
+                cwd,
+                env: env.iter().map(|(k, v)| format!("{k}={v}")).collect(),
+            },
+            root: OciRoot {
+                readonly: !self.writable,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::get_edf_from_string;
+
+    #[test]
+    fn oci_spec() {
+        let edf = get_edf_from_string(String::from(
+            r#"
+            image = "ubuntu:24.04"
+            writable = false
+            workdir = "/work"
+            mounts = ["/scratch:/scratch:ro", "tmpfs:/tmp:size=1g", "umount:/opt"]
+            [env]
+            B = "2"
+            A = "1"
+            [annotations]
+            com.hooks.ssh.enabled = "true"
+            "#,
+        ))
+        .unwrap();
+
+        let spec = edf.to_oci_spec();
+        assert!(spec.root.readonly);
+        assert!(spec.process.cwd == Some(String::from("/work")));
+        assert!(spec.process.env == vec!["A=1", "B=2"]);
+        assert!(spec.annotations.get("com.hooks.ssh.enabled").unwrap() == "true");
+        assert!(spec.mounts.len() == 2);
+        assert!(spec.mounts[0].options == vec!["rbind", "ro"]);
+        assert!(spec.mounts[1].mount_type == "tmpfs");
+        assert!(spec.mounts[1].options == vec!["size=1g"]);
+
+        let v: serde_json::Value = serde_json::from_str(&spec.to_json().unwrap()).unwrap();
+        assert!(v["mounts"][0]["type"] == "bind");
+        assert!(v["process"]["cwd"] == "/work");
+        assert!(v["root"]["readonly"] == true);
+
+        let edf = get_edf_from_string(String::from(
+            "image = \"alpine\"\nmounts = [\"/scratch/my data:/data/my data\", \"tmpfs:/my tmp\"]\n",
+        ))
+        .unwrap();
+        let spec = edf.to_oci_spec();
+        assert!(spec.mounts[0].source == "/scratch/my data" && spec.mounts[0].destination == "/data/my data");
+        assert!(spec.mounts[1].destination == "/my tmp");
+    }
+}
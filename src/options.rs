@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+
+use crate::annotations::AnnotationProcessors;
+use crate::binaries::BinaryPaths;
+use crate::common::{ErrorSink, ExpandOptions, ExpansionAudit, ExpansionBackend, MergePolicy};
+use crate::gpu::GpuPaths;
+use crate::hooks::HookDefinition;
+use crate::load::LoadLimits;
+use crate::mount::MountCollision;
+use crate::mpi::MpiTemplate;
+use crate::policy::Policy;
+use crate::registry::RegistryRewrite;
+use crate::remote::RemoteOptions;
+
+fn get_default_max_levels() -> u64 {
+    10
+}
+
+// Options controlling how EDFs are rendered.
+//
+// Built with chained setters, e.g.
+// RenderOptions::new().strict(true).max_levels(4)
+#[derive(Clone, Debug)]
+pub struct RenderOptions {
+    // Maximum depth of base_environment nesting.
+    pub max_levels: u64,
+    // Reject EDF files containing keys unknown to the schema.
+    pub strict: bool,
+    // Backend used to expand variables in the EDF fields.
+    pub expansion_backend: ExpansionBackend,
+    // Skip schema validation of the EDF files.
+    pub skip_validation: bool,
+    // Track which file set each rendered entry.
+    pub provenance: bool,
+    // Variables used for expansion, the process environment when None.
+    pub env: Option<HashMap<String, String>>,
+    // EDF search paths, the configured ones when None.
+    pub search_paths: Option<Vec<String>>,
+    // Site policy the rendered EDF must comply with.
+    pub policy: Option<Policy>,
+    // Rewriting of the image towards the site registries.
+    pub registry: RegistryRewrite,
+    // Reject images which are not pinned to a digest.
+    pub require_image_digest: bool,
+    // Patterns of the env variables whose values are redacted in output.
+    pub sensitive_env: Vec<String>,
+    // Keep the values of sensitive variables in output.
+    pub reveal_secrets: bool,
+    // Overlay of writable containers, unless set by the EDF.
+    pub writable_overlay_path: Option<String>,
+    pub writable_overlay_size: Option<String>,
+    // Where GPUs are probed for EDFs with gpu = true.
+    pub gpu_paths: GpuPaths,
+    // MPI templates of the configuration, by name.
+    pub mpi_templates: HashMap<String, MpiTemplate>,
+    // Values of the %{name} placeholders, on top of the built-in ones.
+    pub template_vars: HashMap<String, String>,
+    // Site catalog of the OCI hooks EDFs can request, by name.
+    pub oci_hooks: HashMap<String, HookDefinition>,
+    // Limits of the fetching of https:// EDFs.
+    pub remote: RemoteOptions,
+    // Where git+ repositories are cloned, the user cache dir when None.
+    pub git_cache_dir: Option<String>,
+    // Directory of the cache of rendered EDFs, no caching when None.
+    pub cache_dir: Option<String>,
+    // Record the variables referenced by the expansions, see
+    // render_with_options_audit.
+    pub expansion_audit: bool,
+    pub(crate) audit: Option<ExpansionAudit>,
+    // Glob patterns of the variables EDFs may reference, any when empty,
+    // and of the ones they may not.
+    pub expansion_allowed_vars: Vec<String>,
+    pub expansion_denied_vars: Vec<String>,
+    // Whether the variables of an EDF field are expanded, by field name,
+    // e.g. "annotations". Fields not listed are.
+    pub expansion_fields: HashMap<String, bool>,
+    // How the devices and mounts of merged EDFs are deduplicated.
+    pub merge_policy: MergePolicy,
+    // What to do with mounts sharing a target in the container.
+    pub mount_collision: MountCollision,
+    // Check that the sources of the mounts exist and can be read.
+    pub verify_mounts: bool,
+    // Post-processing of the rendered annotations, the built-in processors
+    // by default.
+    pub annotation_processors: AnnotationProcessors,
+    // Check that the binaries of the site config are executable, searching
+    // the PATH of env for relative ones.
+    pub check_binaries: Option<BinaryPaths>,
+    // Check that the parallax imagestore is usable, this one being the
+    // imagestore of the site, unless the EDF sets its own. Parallax is off
+    // when both are empty.
+    pub check_imagestore: Option<String>,
+    // Limits of the EDF files read from the filesystem.
+    pub load_limits: LoadLimits,
+    pub(crate) errors: Option<ErrorSink>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            max_levels: get_default_max_levels(),
+            strict: false,
+            expansion_backend: ExpansionBackend::default(),
+            skip_validation: false,
+            provenance: false,
+            env: None,
+            search_paths: None,
+            policy: None,
+            registry: RegistryRewrite::default(),
+            require_image_digest: false,
+            sensitive_env: vec![],
+            reveal_secrets: false,
+            writable_overlay_path: None,
+            writable_overlay_size: None,
+            gpu_paths: GpuPaths::default(),
+            mpi_templates: HashMap::new(),
+            oci_hooks: HashMap::new(),
+            remote: RemoteOptions::default(),
+            git_cache_dir: None,
+            cache_dir: None,
+            expansion_audit: false,
+            audit: None,
+            expansion_allowed_vars: vec![],
+            expansion_denied_vars: vec![],
+            expansion_fields: HashMap::new(),
+            merge_policy: MergePolicy::default(),
+            mount_collision: MountCollision::default(),
+            verify_mounts: false,
+            annotation_processors: AnnotationProcessors::default(),
+            check_binaries: None,
+            check_imagestore: None,
+            load_limits: LoadLimits::default(),
+            errors: None,
+            template_vars: HashMap::new(),
+        }
+    }
+}
+
+impl RenderOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_levels(mut self, max_levels: u64) -> Self {
+        self.max_levels = max_levels;
+        self
+    }
+
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn expansion_backend(mut self, backend: ExpansionBackend) -> Self {
+        self.expansion_backend = backend;
+        self
+    }
+
+    pub fn skip_validation(mut self, skip: bool) -> Self {
+        self.skip_validation = skip;
+        self
+    }
+
+    pub fn provenance(mut self, provenance: bool) -> Self {
+        self.provenance = provenance;
+        self
+    }
+
+    pub fn env(mut self, env: HashMap<String, String>) -> Self {
+        self.env = Some(env);
+        self
+    }
+
+    pub fn search_paths(mut self, search_paths: Vec<String>) -> Self {
+        self.search_paths = Some(search_paths);
+        self
+    }
+
+    pub fn policy(mut self, policy: Policy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    pub fn registry(mut self, registry: RegistryRewrite) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    pub fn require_image_digest(mut self, require: bool) -> Self {
+        self.require_image_digest = require;
+        self
+    }
+
+    pub fn sensitive_env(mut self, patterns: Vec<String>) -> Self {
+        self.sensitive_env = patterns;
+        self
+    }
+
+    pub fn reveal_secrets(mut self, reveal: bool) -> Self {
+        self.reveal_secrets = reveal;
+        self
+    }
+
+    pub fn writable_overlay_path(mut self, path: String) -> Self {
+        self.writable_overlay_path = Some(path);
+        self
+    }
+
+    pub fn writable_overlay_size(mut self, size: String) -> Self {
+        self.writable_overlay_size = Some(size);
+        self
+    }
+
+    pub fn gpu_paths(mut self, paths: GpuPaths) -> Self {
+        self.gpu_paths = paths;
+        self
+    }
+
+    pub fn mpi_templates(mut self, templates: HashMap<String, MpiTemplate>) -> Self {
+        self.mpi_templates = templates;
+        self
+    }
+
+    pub fn template_vars(mut self, vars: HashMap<String, String>) -> Self {
+        self.template_vars = vars;
+        self
+    }
+
+    pub fn oci_hooks(mut self, catalog: HashMap<String, HookDefinition>) -> Self {
+        self.oci_hooks = catalog;
+        self
+    }
+
+    pub fn remote(mut self, remote: RemoteOptions) -> Self {
+        self.remote = remote;
+        self
+    }
+
+    pub fn git_cache_dir(mut self, dir: String) -> Self {
+        self.git_cache_dir = Some(dir);
+        self
+    }
+
+    pub fn cache_dir(mut self, dir: String) -> Self {
+        self.cache_dir = Some(dir);
+        self
+    }
+
+    pub fn expansion_audit(mut self, audit: bool) -> Self {
+        self.expansion_audit = audit;
+        self
+    }
+
+    pub fn expansion_allowed_vars(mut self, patterns: Vec<String>) -> Self {
+        self.expansion_allowed_vars = patterns;
+        self
+    }
+
+    pub fn expansion_denied_vars(mut self, patterns: Vec<String>) -> Self {
+        self.expansion_denied_vars = patterns;
+        self
+    }
+
+    pub fn expand_field(mut self, field: &str, expand: bool) -> Self {
+        self.expansion_fields.insert(String::from(field), expand);
+        self
+    }
+
+    pub fn merge_policy(mut self, policy: MergePolicy) -> Self {
+        self.merge_policy = policy;
+        self
+    }
+
+    pub fn mount_collision(mut self, collision: MountCollision) -> Self {
+        self.mount_collision = collision;
+        self
+    }
+
+    pub fn verify_mounts(mut self, verify: bool) -> Self {
+        self.verify_mounts = verify;
+        self
+    }
+
+    pub fn annotation_processors(mut self, processors: AnnotationProcessors) -> Self {
+        self.annotation_processors = processors;
+        self
+    }
+
+    pub fn check_binaries(mut self, binaries: BinaryPaths) -> Self {
+        self.check_binaries = Some(binaries);
+        self
+    }
+
+    pub fn check_imagestore(mut self, site_imagestore: String) -> Self {
+        self.check_imagestore = Some(site_imagestore);
+        self
+    }
+
+    pub fn load_limits(mut self, limits: LoadLimits) -> Self {
+        self.load_limits = limits;
+        self
+    }
+
+    pub(crate) fn expand_options(&self) -> ExpandOptions {
+        ExpandOptions {
+            backend: self.expansion_backend,
+            audit: self.audit.clone(),
+            allowed_vars: self.expansion_allowed_vars.clone(),
+            denied_vars: self.expansion_denied_vars.clone(),
+            fields: self.expansion_fields.clone(),
+            merge_policy: self.merge_policy,
+            mount_collision: self.mount_collision,
+            errors: self.errors.clone(),
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+
+use crate::error::{SarusError, SarusErrorKind, SarusResult};
+use crate::image::image_transport;
+use crate::mount::{MountKind, unescape_mount};
+use crate::registry::image_registry;
+use crate::{EDF, load_site_config, toml_read};
+
+// Constraints set by the site administrators on rendered EDFs, loaded from
+// the file pointed by the edf_policy_path config entry.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    // Mount sources which can't be mounted, nor anything below them.
+    #[serde(default)]
+    pub forbidden_mount_sources: Vec<String>,
+    // Mount sources which must be mounted with the "ro" flag.
+    #[serde(default)]
+    pub readonly_mount_sources: Vec<String>,
+    // Registries images can be pulled from, any when empty.
+    #[serde(default)]
+    pub allowed_registries: Vec<String>,
+    #[serde(default)]
+    pub max_devices: Option<usize>,
+    // Reject EDFs asking for a writable container filesystem.
+    #[serde(default)]
+    pub deny_writable: bool,
+}
+
+// Real path of a mount source, . and .. being resolved lexically when it
+// doesn't exist, e.g. /capstor/../etc/shadow is /etc/shadow.
+fn resolved_source(source: &Path) -> PathBuf {
+    if let Ok(p) = std::fs::canonicalize(source) {
+        return p;
+    }
+    let mut res = PathBuf::new();
+    for c in source.components() {
+        match c {
+            Component::ParentDir => {
+                res.pop();
+            }
+            Component::CurDir => {}
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+// A source below a path of the policy, or above it, e.g. / holds /etc.
+fn overlaps(source: &Path, p: &str) -> bool {
+    source.starts_with(p) || Path::new(p).starts_with(source)
+}
+
+impl Policy {
+    pub fn load(path: &str) -> SarusResult<Policy> {
+        toml_read(path)
+    }
+
+    // Every breach of the policy by the EDF, empty when it complies.
+    pub fn violations(&self, edf: &EDF) -> Vec<String> {
+        let mut v = vec![];
+
+        for m in edf.mounts.iter() {
+            let source = match m.kind() {
+                MountKind::Bind(_) => resolved_source(Path::new(&unescape_mount(m.source()))),
+                MountKind::Squashfs(o) => resolved_source(&o.image),
+                _ => continue,
+            };
+            if let Some(f) = self.forbidden_mount_sources.iter().find(|f| source.starts_with(f)) {
+                v.push(format!("mount {}: source below {f} is forbidden", m.to_volume_string()));
+            } else if let Some(f) = self.forbidden_mount_sources.iter().find(|f| overlaps(&source, f)) {
+                v.push(format!("mount {}: source holds {f}, which is forbidden", m.to_volume_string()));
+            }
+            let ro = match m.kind() {
+                MountKind::Bind(o) => o.read_only,
+                _ => true,
+            };
+            if let Some(r) = self.readonly_mount_sources.iter().find(|r| overlaps(&source, r))
+                && !ro
+            {
+                v.push(format!("mount {}: source below or holding {r} must be read-only", m.to_volume_string()));
+            }
+        }
+
+        // Archives come from no registry, they'd bypass the allowed ones
+        if !self.allowed_registries.is_empty() {
+            if let Some((t, _)) = image_transport(&edf.image) {
+                v.push(format!("image {}: {t} images are not allowed", edf.image));
+            } else if let Some(r) = image_registry(&edf.image)
+                && !self.allowed_registries.contains(&r)
+            {
+                v.push(format!("image {}: registry {r} is not allowed", edf.image));
+            }
+        }
+
+        if let Some(max) = self.max_devices
+            && edf.devices.len() > max
+        {
+            v.push(format!("{} devices requested, at most {max} are allowed", edf.devices.len()));
+        }
+
+        if self.deny_writable && edf.writable {
+            v.push(String::from("writable container filesystems are not allowed"));
+        }
+
+        v
+    }
+
+    pub fn enforce(&self, edf: &EDF) -> SarusResult<()> {
+        let violations = self.violations(edf);
+        if violations.is_empty() {
+            return Ok(());
+        }
+
+        let mut msg = String::from("EDF violates the site policy:");
+        for (i, e) in violations.iter().enumerate() {
+            msg = format!("{msg}\n{}. {e}", i + 1);
+        }
+        Err(SarusError {
+            kind: SarusErrorKind::PolicyViolation { violations },
+            file_path: None,
+            msg,
+            location: None,
+        })
+    }
+}
+
+// Policy configured for the site, if any. A missing configuration means
+// there's nothing to apply, a broken one fails the render.
+pub fn load_site_policy() -> SarusResult<Option<Policy>> {
+    let config = match load_site_config()? {
+        Some(c) => c,
+        None => return Ok(None),
+    };
+
+    match config.edf_policy_path.as_str() {
+        "" => Ok(None),
+        p => Ok(Some(Policy::load(p)?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_edf_from_string;
+
+    #[test]
+    fn policy_violations() {
+        let policy = Policy::load("test/policy/site.toml").unwrap();
+
+        let edf = get_edf_from_string(String::from(
+            "image = \"quay.io/org/app:1\"\nwritable = false\nmounts = [\"/capstor/scratch:/scratch:ro\"]\n",
+        ))
+        .unwrap();
+        assert!(policy.enforce(&edf).is_ok());
+
+        let edf = get_edf_from_string(String::from(
+            r#"
+            image = "ubuntu:24.04"
+            devices = ["/dev/fuse", "/dev/kvm", "/dev/net/tun"]
+            mounts = ["/etc/shadow:/etc/shadow", "/capstor/scratch:/scratch"]
+            "#,
+        ))
+        .unwrap();
+        let err = policy.enforce(&edf).err().unwrap();
+        let violations = match err.kind {
+            SarusErrorKind::PolicyViolation { violations } => violations,
+            _ => panic!("unexpected error kind"),
+        };
+        assert!(violations.len() == 5);
+        assert!(violations[0].contains("below /etc is forbidden"));
+        assert!(violations[1].contains("must be read-only"));
+        assert!(violations[2].contains("registry docker.io is not allowed"));
+
+        let edf = get_edf_from_string(String::from("image = \"docker-archive:/scratch/app.tar\"\nwritable = false\n")).unwrap();
+        let v = policy.violations(&edf);
+        assert!(v.len() == 1 && v[0].contains("docker-archive images are not allowed"));
+    }
+
+    #[test]
+    fn policy_normalized_sources() {
+        let policy = Policy {
+            forbidden_mount_sources: vec![String::from("/etc"), String::from("/root")],
+            readonly_mount_sources: vec![String::from("/capstor/apps")],
+            ..Default::default()
+        };
+        let check = |mounts: &str| {
+            let content = format!("image = \"ubuntu\"\nmounts = [{mounts}]\n");
+            policy.violations(&get_edf_from_string(content).unwrap())
+        };
+        let v = check("\"/capstor/../etc/shadow:/x:ro\"");
+        assert!(v.len() == 1 && v[0].contains("below /etc is forbidden"));
+        let v = check("\"/nonexistent/./../root/.ssh:/x:ro\"");
+        assert!(v.len() == 1 && v[0].contains("below /root is forbidden"));
+        let v = check("\"/:/host:ro\"");
+        assert!(v.len() == 1 && v[0].contains("holds /etc"));
+        let v = check("\"/capstor:/capstor\"");
+        assert!(v.len() == 1 && v[0].contains("must be read-only"));
+        assert!(check("\"/capstor:/capstor:ro\", \"/var/tmp:/tmp\"").is_empty());
+
+        // Sources are resolved once unescaped
+        let dir = std::env::temp_dir().join(format!("raster-policy-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let link = dir.join("my etc");
+        let _ = std::fs::remove_file(&link);
+        std::os::unix::fs::symlink("/etc", &link).unwrap();
+        let v = check(&format!("\"{}:/x:ro\"", link.display()));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(v.len() == 1 && v[0].contains("below /etc is forbidden"));
+    }
+
+    #[test]
+    fn policy_unknown_entry() {
+        assert!(toml::from_str::<Policy>("max_device = 1").is_err());
+    }
+}
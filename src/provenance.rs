@@ -0,0 +1,291 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use toml::de::{DeTable, DeValue};
+
+use crate::error::{Location, SarusError, SarusErrorKind, SarusResult};
+use crate::is_yaml_path;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Origin {
+    pub file_path: String,
+    pub line: Option<usize>,
+}
+
+// Map of rendered EDF entries to the file which last set them.
+// Keys are "image", "env.NAME", "annotations.NAME", "mounts[\"SPEC\"]", ...
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Provenance {
+    pub entries: BTreeMap<String, Origin>,
+    // Names looked up during the render, with the file they resolved to.
+    #[serde(skip)]
+    pub(crate) lookups: Vec<(String, String)>,
+    // Files read during the render, EDFs and env files.
+    #[serde(skip)]
+    pub(crate) files: Vec<String>,
+}
+
+impl Provenance {
+    pub fn get(&self, key: &str) -> Option<&Origin> {
+        self.entries.get(key)
+    }
+
+    // Record every entry of an EDF file, overriding previous layers.
+    pub(crate) fn record_content(&mut self, file_path: &str, content: &str) -> SarusResult<()> {
+        let entries = if is_yaml_path(file_path) {
+            yaml_entries(content, file_path)?
+        } else {
+            toml_entries(content, file_path)?
+        };
+
+        for (key, line) in entries {
+            let origin = Origin {
+                file_path: String::from(file_path),
+                line,
+            };
+            self.entries.insert(key, origin);
+        }
+        Ok(())
+    }
+
+    // Add the records of a render of later layers, e.g. of a base.
+    pub(crate) fn extend(&mut self, other: &Provenance) {
+        self.entries.extend(other.entries.clone());
+        self.lookups.extend(other.lookups.iter().cloned());
+        self.files.extend(other.files.iter().cloned());
+    }
+
+    pub fn report(&self) -> String {
+        let mut lines = vec![];
+        for (key, origin) in self.entries.iter() {
+            match origin.line {
+                Some(l) => lines.push(format!("{key}: {}:{l}", origin.file_path)),
+                None => lines.push(format!("{key}: {}", origin.file_path)),
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+impl std::fmt::Display for Provenance {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.report())
+    }
+}
+
+fn line_of(content: &str, offset: usize) -> usize {
+    content[..offset.min(content.len())].matches('\n').count() + 1
+}
+
+pub(crate) fn location_of(content: &str, offset: usize) -> Location {
+    let before = &content[..offset.min(content.len())];
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    Location {
+        line: before.matches('\n').count() as u32 + 1,
+        column: before[line_start..].chars().count() as u32 + 1,
+    }
+}
+
+// Position in an EDF file of the value at a JSON pointer of its document,
+// e.g. "/env/HOME": the one of its key for table entries, of the item for
+// array items. Only TOML files carry positions.
+pub(crate) fn document_location(content: &str, file_path: &str, pointer: &str) -> Option<Location> {
+    if is_yaml_path(file_path) {
+        return None;
+    }
+    let table = DeTable::parse(content).ok()?;
+
+    let mut segs = pointer.split('/').skip(1).map(|s| s.replace("~1", "/").replace("~0", "~"));
+    let (mut offset, mut cur) = find_key(table.get_ref(), &segs.next()?)?;
+    for seg in segs {
+        (offset, cur) = match cur {
+            DeValue::Table(t) => find_key(t, &seg)?,
+            DeValue::Array(a) => {
+                let item = a.get(seg.parse::<usize>().ok()?)?;
+                (item.span().start, item.get_ref())
+            }
+            _ => return None,
+        };
+    }
+    Some(location_of(content, offset))
+}
+
+// Position of an entry named like in the reports, e.g. "env.HOME", the
+// rest of the name being a single key or dotted ones, falling back to the
+// position of the top-level key.
+pub(crate) fn key_location(content: &str, file_path: &str, key: &str) -> Option<Location> {
+    let escape = |s: &str| s.replace('~', "~0").replace('/', "~1");
+    let (top, rest) = match key.split_once('.') {
+        Some((t, r)) => (escape(t), Some(r)),
+        None => (escape(key), None),
+    };
+    let mut pointers = vec![];
+    if let Some(r) = rest {
+        pointers.push(format!("/{top}/{}", escape(r)));
+        let dotted: Vec<String> = r.split('.').map(escape).collect();
+        pointers.push(format!("/{top}/{}", dotted.join("/")));
+    }
+    pointers.push(format!("/{top}"));
+    pointers.iter().find_map(|p| document_location(content, file_path, p))
+}
+
+fn find_key<'a, 'i>(t: &'a DeTable<'i>, key: &str) -> Option<(usize, &'a DeValue<'i>)> {
+    let (k, v) = t.iter().find(|(k, _)| k.get_ref() == key)?;
+    Some((k.span().start, v.get_ref()))
+}
+
+// Position of every item of a list field of an EDF file, by the string of
+// the item, or the spec or target of table items.
+pub(crate) fn list_item_locations(content: &str, file_path: &str, key: &str) -> Vec<(String, Location)> {
+    let mut res = vec![];
+    if is_yaml_path(file_path) {
+        return res;
+    }
+    let table = match DeTable::parse(content) {
+        Ok(t) => t,
+        Err(_) => return res,
+    };
+    let items = match find_key(table.get_ref(), key) {
+        Some((_, DeValue::Array(a))) => a,
+        _ => return res,
+    };
+
+    for item in items.iter() {
+        let id = match item.get_ref() {
+            DeValue::String(s) => Some(s.to_string()),
+            DeValue::Table(t) => find_key(t, "spec")
+                .or_else(|| find_key(t, "target"))
+                .and_then(|(_, v)| v.as_str().map(String::from)),
+            _ => None,
+        };
+        if let Some(id) = id {
+            res.push((id, location_of(content, item.span().start)));
+        }
+    }
+    res
+}
+
+fn is_list_field(key: &str) -> bool {
+    key == "mounts" || key == "devices"
+}
+
+fn toml_entries(content: &str, file_path: &str) -> SarusResult<Vec<(String, Option<usize>)>> {
+    let table = match DeTable::parse(content) {
+        Ok(t) => t,
+        Err(e) => {
+            return Err(SarusError {
+                kind: SarusErrorKind::TomlParse,
+                file_path: Some(String::from(file_path)),
+                msg: format!("{}", e),
+                location: None,
+            });
+        }
+    };
+
+    let mut entries = vec![];
+    for (k, v) in table.get_ref().iter() {
+        let key = k.get_ref().to_string();
+        if key == "base_environment" || key == "include" {
+            continue;
+        }
+        match v.get_ref() {
+            DeValue::Table(t) => toml_table_entries(content, &key, t, &mut entries),
+            DeValue::Array(a) if is_list_field(&key) => {
+                for item in a.iter() {
+                    let s = match item.get_ref().as_str() {
+                        Some(s) => s.to_string(),
+                        None => continue,
+                    };
+                    let line = line_of(content, item.span().start);
+                    entries.push((format!("{key}[{s:?}]"), Some(line)));
+                }
+            }
+            _ => entries.push((key, Some(line_of(content, k.span().start)))),
+        }
+    }
+    Ok(entries)
+}
+
+fn toml_table_entries(
+    content: &str,
+    prefix: &str,
+    table: &DeTable,
+    entries: &mut Vec<(String, Option<usize>)>,
+) {
+    for (k, v) in table.iter() {
+        let key = format!("{prefix}.{}", k.get_ref());
+        match v.get_ref() {
+            // Conditioned entries are attributed as if they were unconditioned
+            DeValue::Table(t) if k.get_ref() == "when" => {
+                for (_, c) in t.iter() {
+                    if let DeValue::Table(ct) = c.get_ref() {
+                        toml_table_entries(content, prefix, ct, entries);
+                    }
+                }
+            }
+            DeValue::Table(t) => toml_table_entries(content, &key, t, entries),
+            _ => entries.push((key, Some(line_of(content, k.span().start)))),
+        }
+    }
+}
+
+fn yaml_entries(content: &str, file_path: &str) -> SarusResult<Vec<(String, Option<usize>)>> {
+    let doc: serde_yaml::Value = match serde_yaml::from_str(content) {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(SarusError {
+                kind: SarusErrorKind::YamlParse,
+                file_path: Some(String::from(file_path)),
+                msg: format!("{}", e),
+                location: None,
+            });
+        }
+    };
+
+    // serde_yaml doesn't keep value locations, entries carry no line number.
+    let mut entries = vec![];
+    if let serde_yaml::Value::Mapping(m) = doc {
+        for (k, v) in m.iter() {
+            let key = match k.as_str() {
+                Some(s) => s.to_string(),
+                None => continue,
+            };
+            if key == "base_environment" || key == "include" {
+                continue;
+            }
+            match v {
+                serde_yaml::Value::Mapping(_) => yaml_table_entries(&key, v, &mut entries),
+                serde_yaml::Value::Sequence(a) if is_list_field(&key) => {
+                    for s in a.iter().filter_map(|i| i.as_str()) {
+                        entries.push((format!("{key}[{s:?}]"), None));
+                    }
+                }
+                _ => entries.push((key, None)),
+            }
+        }
+    }
+    Ok(entries)
+}
+
+fn yaml_table_entries(
+    prefix: &str,
+    value: &serde_yaml::Value,
+    entries: &mut Vec<(String, Option<usize>)>,
+) {
+    if let serde_yaml::Value::Mapping(m) = value {
+        for (k, v) in m.iter() {
+            let key = match k.as_str() {
+                Some("when") if v.is_mapping() => {
+                    for c in v.as_mapping().unwrap().values() {
+                        yaml_table_entries(prefix, c, entries);
+                    }
+                    continue;
+                }
+                Some(s) => format!("{prefix}.{s}"),
+                None => continue,
+            };
+            yaml_table_entries(&key, v, entries);
+        }
+    } else {
+        entries.push((prefix.to_string(), None));
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use crate::Config;
+use crate::error::{SarusError, SarusErrorKind, SarusResult};
+
+// Registry assumed by container engines for images without one.
+pub(crate) const IMPLICIT_REGISTRY: &str = "docker.io";
+
+// Rewriting of image references towards the registries of the site,
+// typically mirrors on air-gapped systems.
+#[derive(Clone, Debug, Default)]
+pub struct RegistryRewrite {
+    // Prefix for images without an explicit registry.
+    pub default_registry: Option<String>,
+    // Registry to registry prefix, e.g. "ghcr.io" to "mirror.example.org/ghcr".
+    pub aliases: HashMap<String, String>,
+}
+
+impl RegistryRewrite {
+    pub fn from_config(config: &Config) -> Self {
+        let default_registry = match config.default_registry.as_str() {
+            "" => None,
+            r => Some(r.trim_end_matches('/').to_string()),
+        };
+        RegistryRewrite {
+            default_registry,
+            aliases: config.registry_aliases.clone(),
+        }
+    }
+
+    // "ubuntu:24.04" becomes "<default_registry>/ubuntu:24.04", a registry
+    // with an alias is replaced by it.
+    pub fn rewrite(&self, image: &str) -> String {
+        if image_registry(image).is_none() {
+            return image.to_string();
+        }
+
+        let (registry, rest) = match has_explicit_registry(image) {
+            true => image.split_once('/').unwrap(),
+            false => match &self.default_registry {
+                Some(d) => (d.as_str(), image),
+                None => (IMPLICIT_REGISTRY, image),
+            },
+        };
+
+        match self.aliases.get(registry) {
+            Some(alias) => format!("{}/{rest}", alias.trim_end_matches('/')),
+            None if rest == image => match &self.default_registry {
+                Some(d) => format!("{d}/{image}"),
+                None => image.to_string(),
+            },
+            None => image.to_string(),
+        }
+    }
+}
+
+// Fail unless the image is pinned as NAME@sha256:DIGEST. Local squashfs
+// files are not pulled and aren't concerned, images read through a
+// transport such as docker-archive can't be pinned and always fail.
+pub(crate) fn check_image_digest(image: &str) -> SarusResult<()> {
+    if let Some((transport, _)) = crate::image::image_transport(image) {
+        return Err(SarusError {
+            kind: SarusErrorKind::MissingImageDigest { image: image.to_string() },
+            file_path: None,
+            msg: format!(
+                "image \"{image}\" is read through {transport}, it can't be pinned to a digest, which is required on this system"
+            ),
+            location: None,
+        });
+    }
+    if image_registry(image).is_none() {
+        return Ok(());
+    }
+
+    let pinned = match image.rsplit_once("@sha256:") {
+        Some((_, d)) => d.len() == 64 && d.chars().all(|c| c.is_ascii_hexdigit()),
+        None => false,
+    };
+    if pinned {
+        return Ok(());
+    }
+
+    let name = image.split('@').next().unwrap_or(image);
+    Err(SarusError {
+        kind: SarusErrorKind::MissingImageDigest { image: image.to_string() },
+        file_path: None,
+        msg: format!(
+            "image \"{image}\" is not pinned to a digest, which is required on this system. \
+Use a reference like \"{name}@sha256:<digest>\", the digest being shown by \
+\"podman image inspect --format '{{{{.Digest}}}}' {name}\""
+        ),
+        location: None,
+    })
+}
+
+pub(crate) fn has_explicit_registry(image: &str) -> bool {
+    match image.split_once('/') {
+        Some((first, _)) => first.contains(['.', ':']) || first == "localhost",
+        None => false,
+    }
+}
+
+// Registry of an image reference, None for local squashfs files.
+// "ubuntu:24.04" comes from docker.io, "quay.io/a/b" from quay.io.
+pub(crate) fn image_registry(image: &str) -> Option<String> {
+    if [".", "/"].iter().any(|s| image.starts_with(*s)) || image.ends_with(".sqsh") {
+        return None;
+    }
+    if crate::image::image_transport(image).is_some() {
+        return None;
+    }
+
+    match has_explicit_registry(image) {
+        true => image.split_once('/').map(|(r, _)| r.to_string()),
+        false => Some(String::from(IMPLICIT_REGISTRY)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_image_registry() {
+        assert!(image_registry("ubuntu") == Some(String::from("docker.io")));
+        assert!(image_registry("library/ubuntu:24.04") == Some(String::from("docker.io")));
+        assert!(image_registry("localhost/app") == Some(String::from("localhost")));
+        assert!(image_registry("host:5000/app") == Some(String::from("host:5000")));
+        assert!(image_registry("./image.sqsh").is_none() && image_registry("image.sqsh").is_none());
+    }
+
+    #[test]
+    fn registry_image_digest() {
+        let digest = "a".repeat(64);
+        assert!(check_image_digest(&format!("ubuntu@sha256:{digest}")).is_ok());
+        assert!(check_image_digest(&format!("quay.io/org/app:1@sha256:{digest}")).is_ok());
+        assert!(check_image_digest("./image.sqsh").is_ok());
+
+        let err = check_image_digest("ubuntu:24.04").err().unwrap();
+        assert!(err.kind == SarusErrorKind::MissingImageDigest { image: String::from("ubuntu:24.04") });
+        assert!(err.msg.contains("\"ubuntu:24.04@sha256:<digest>\""));
+        assert!(err.msg.contains("{{.Digest}}"));
+        assert!(check_image_digest("ubuntu@sha256:1234").is_err());
+        let err = check_image_digest("docker-archive:/scratch/app.tar").err().unwrap();
+        assert!(err.kind == SarusErrorKind::MissingImageDigest { image: String::from("docker-archive:/scratch/app.tar") });
+    }
+
+    #[test]
+    fn registry_rewrite() {
+        let mut config = Config {
+            default_registry: String::from("registry.example.org/dockerhub-mirror/"),
+            ..Default::default()
+        };
+        config.registry_aliases.insert(String::from("ghcr.io"), String::from("mirror.example.org/ghcr"));
+        let r = RegistryRewrite::from_config(&config);
+
+        assert!(r.rewrite("ubuntu:24.04") == "registry.example.org/dockerhub-mirror/ubuntu:24.04");
+        assert!(r.rewrite("ghcr.io/org/app:1") == "mirror.example.org/ghcr/org/app:1");
+        assert!(r.rewrite("quay.io/org/app") == "quay.io/org/app");
+        assert!(r.rewrite("/scratch/image.sqsh") == "/scratch/image.sqsh");
+
+        let mut r = RegistryRewrite::default();
+        assert!(r.rewrite("ubuntu:24.04") == "ubuntu:24.04");
+        r.aliases.insert(String::from("docker.io"), String::from("mirror.example.org/hub"));
+        assert!(r.rewrite("ubuntu:24.04") == "mirror.example.org/hub/ubuntu:24.04");
+        assert!(r.rewrite("docker.io/library/ubuntu") == "mirror.example.org/hub/library/ubuntu");
+    }
+}
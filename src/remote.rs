@@ -0,0 +1,167 @@
+use crate::error::{SarusError, SarusErrorKind, SarusResult};
+
+// Limits of the fetching of remote EDFs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RemoteOptions {
+    // Largest accepted file, in bytes.
+    pub max_size: u64,
+    // Timeout of the whole request, in seconds.
+    pub timeout: u64,
+}
+
+impl Default for RemoteOptions {
+    fn default() -> Self {
+        RemoteOptions {
+            max_size: 1024 * 1024,
+            timeout: 30,
+        }
+    }
+}
+
+// EDF published on a web server, optionally pinned to the sha256 checksum
+// of its content: https://edf.example.org/pytorch.toml#sha256=<hex>
+#[derive(Clone, Debug, PartialEq)]
+pub struct RemoteRef {
+    pub url: String,
+    pub sha256: Option<String>,
+}
+
+pub fn is_remote(name: &str) -> bool {
+    name.starts_with("https://")
+}
+
+impl RemoteRef {
+    pub fn parse(name: &str) -> SarusResult<RemoteRef> {
+        let (url, fragment) = match name.split_once('#') {
+            Some((u, f)) => (u, Some(f)),
+            None => (name, None),
+        };
+        let sha256 = match fragment {
+            None => None,
+            Some(f) => match f.strip_prefix("sha256=") {
+                Some(h) if h.len() == 64 && h.chars().all(|c| c.is_ascii_hexdigit()) => Some(h.to_lowercase()),
+                _ => {
+                    return Err(SarusError {
+                        kind: SarusErrorKind::RemoteFetch { url: String::from(url) },
+                        file_path: None,
+                        msg: format!("invalid fragment \"#{f}\" of {url}, expected #sha256=<64 hex digits>"),
+                        location: None,
+                    });
+                }
+            },
+        };
+        Ok(RemoteRef {
+            url: String::from(url),
+            sha256,
+        })
+    }
+}
+
+// Fetch a remote EDF, checking its checksum when pinned.
+pub(crate) fn fetch(r: &RemoteRef, opts: &RemoteOptions) -> SarusResult<String> {
+    let body = download(&r.url, opts)?;
+    if let Some(expected) = &r.sha256 {
+        check_sha256(&body, expected, &r.url)?;
+    }
+    match String::from_utf8(body) {
+        Ok(s) => Ok(s),
+        Err(_) => Err(SarusError {
+            kind: SarusErrorKind::RemoteFetch { url: r.url.clone() },
+            file_path: Some(r.url.clone()),
+            msg: format!("{} is not valid UTF-8", r.url),
+            location: None,
+        }),
+    }
+}
+
+#[cfg(feature = "remote")]
+fn download(url: &str, opts: &RemoteOptions) -> SarusResult<Vec<u8>> {
+    let failed = |msg: String| SarusError {
+        kind: SarusErrorKind::RemoteFetch { url: String::from(url) },
+        file_path: Some(String::from(url)),
+        msg,
+        location: None,
+    };
+
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(std::time::Duration::from_secs(opts.timeout)))
+        .https_only(true)
+        .build()
+        .into();
+    let mut resp = match agent.get(url).call() {
+        Ok(r) => r,
+        Err(e) => return Err(failed(format!("error fetching {url} - {e}"))),
+    };
+    match resp.body_mut().with_config().limit(opts.max_size).read_to_vec() {
+        Ok(b) => Ok(b),
+        Err(e) => Err(failed(format!("error reading {url}, limit is {} bytes - {e}", opts.max_size))),
+    }
+}
+
+#[cfg(not(feature = "remote"))]
+fn download(url: &str, _opts: &RemoteOptions) -> SarusResult<Vec<u8>> {
+    Err(SarusError {
+        kind: SarusErrorKind::RemoteFetch { url: String::from(url) },
+        file_path: Some(String::from(url)),
+        msg: format!("can't fetch {url}, remote EDFs need the \"remote\" feature"),
+        location: None,
+    })
+}
+
+#[cfg(feature = "remote")]
+fn check_sha256(body: &[u8], expected: &str, url: &str) -> SarusResult<()> {
+    use sha2::{Digest, Sha256};
+
+    let got: String = Sha256::digest(body).iter().map(|b| format!("{b:02x}")).collect();
+    if got != expected {
+        return Err(SarusError {
+            kind: SarusErrorKind::ChecksumMismatch { url: String::from(url) },
+            file_path: Some(String::from(url)),
+            msg: format!("sha256 of {url} is {got}, expected {expected}"),
+            location: None,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "remote"))]
+fn check_sha256(_body: &[u8], _expected: &str, _url: &str) -> SarusResult<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remote_ref_parse() {
+        let hex = "a".repeat(64);
+        let r = RemoteRef::parse(&format!("https://edf.example.org/env.toml#sha256={hex}")).unwrap();
+        assert!(r.url == "https://edf.example.org/env.toml");
+        assert!(r.sha256 == Some(hex));
+
+        let r = RemoteRef::parse("https://edf.example.org/env.toml").unwrap();
+        assert!(r.sha256.is_none());
+        assert!(RemoteRef::parse("https://edf.example.org/env.toml#md5=00").is_err());
+
+        assert!(is_remote("https://edf.example.org/env.toml"));
+        assert!(!is_remote("http://edf.example.org/env.toml") && !is_remote("./env.toml"));
+    }
+
+    #[cfg(feature = "remote")]
+    #[test]
+    fn remote_checksum() {
+        let sha = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+        assert!(check_sha256(b"hello", sha, "https://x/env.toml").is_ok());
+        let err = check_sha256(b"hello!", sha, "https://x/env.toml").err().unwrap();
+        assert!(err.code() == 49);
+    }
+
+    #[cfg(not(feature = "remote"))]
+    #[test]
+    fn remote_disabled() {
+        let r = RemoteRef::parse("https://edf.example.org/env.toml").unwrap();
+        let err = fetch(&r, &RemoteOptions::default()).err().unwrap();
+        assert!(err.code() == 48);
+    }
+}
@@ -0,0 +1,142 @@
+use serde_json::{Value, json};
+use std::collections::BTreeSet;
+
+use crate::error::{Location, SarusResult};
+use crate::lint::{LINT_RULES, LintReport, Severity};
+use crate::to_canonical_json;
+use crate::validation::ValidationReport;
+
+// SARIF 2.1.0 logs of the lint and validation reports, the format taken by
+// the code scanning of GitHub and GitLab to annotate the offending lines.
+const SARIF_VERSION: &str = "2.1.0";
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+fn level(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Info => "note",
+    }
+}
+
+fn sarif_result(rule: &str, level: &str, msg: &str, file_path: &str, location: &Option<Location>) -> Value {
+    let mut physical = json!({ "artifactLocation": { "uri": file_path } });
+    if let Some(l) = location {
+        physical["region"] = json!({ "startLine": l.line, "startColumn": l.column });
+    }
+    json!({
+        "ruleId": rule,
+        "level": level,
+        "message": { "text": msg },
+        "locations": [{ "physicalLocation": physical }],
+    })
+}
+
+fn sarif_log(rules: Vec<Value>, results: Vec<Value>) -> SarusResult<String> {
+    let log = json!({
+        "$schema": SARIF_SCHEMA,
+        "version": SARIF_VERSION,
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "raster",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                },
+            },
+            "results": results,
+        }],
+    });
+    to_canonical_json(&log)
+}
+
+// One run holding the findings of every report, every lint rule being
+// described whether it fired or not.
+pub fn lint_to_sarif(reports: &[LintReport]) -> SarusResult<String> {
+    let rules = LINT_RULES
+        .iter()
+        .map(|(id, severity, description)| {
+            json!({
+                "id": id,
+                "shortDescription": { "text": description },
+                "defaultConfiguration": { "level": level(*severity) },
+            })
+        })
+        .collect();
+
+    let mut results = vec![];
+    for r in reports {
+        for f in r.findings.iter() {
+            results.push(sarif_result(&f.rule, level(f.severity), &f.msg, &r.file_path, &f.location));
+        }
+    }
+    sarif_log(rules, results)
+}
+
+// One run holding the errors and warnings of every report, the rules being
+// the diagnostic ids found.
+pub fn validation_to_sarif(reports: &[ValidationReport]) -> SarusResult<String> {
+    let mut ids = BTreeSet::new();
+    let mut results = vec![];
+    for r in reports {
+        for (level, diagnostics) in [("error", &r.errors), ("warning", &r.warnings)] {
+            for d in diagnostics.iter() {
+                ids.insert(d.id.clone());
+                results.push(sarif_result(&d.id, level, &d.msg, &r.file_path, &d.location));
+            }
+        }
+    }
+
+    let rules = ids.into_iter().map(|id| json!({ "id": id })).collect();
+    sarif_log(rules, results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lint::LintFinding;
+    use crate::validate_with_report;
+
+    #[test]
+    fn sarif_lint() {
+        let report = LintReport {
+            file_path: String::from("envs/a.toml"),
+            findings: vec![LintFinding {
+                rule: String::from("empty-env"),
+                severity: Severity::Warning,
+                key: Some(String::from("env.A")),
+                msg: String::from("\"$A\" expands to an empty string"),
+                location: Some(Location { line: 3, column: 1 }),
+            }],
+        };
+        let log: Value = serde_json::from_str(&lint_to_sarif(&[report]).unwrap()).unwrap();
+        assert!(log["version"] == "2.1.0");
+
+        let run = &log["runs"][0];
+        assert!(run["tool"]["driver"]["rules"].as_array().unwrap().len() == LINT_RULES.len());
+        let result = &run["results"][0];
+        assert!(result["ruleId"] == "empty-env" && result["level"] == "warning");
+        let physical = &result["locations"][0]["physicalLocation"];
+        assert!(physical["artifactLocation"]["uri"] == "envs/a.toml");
+        assert!(physical["region"]["startLine"] == 3);
+    }
+
+    #[test]
+    fn sarif_validation() {
+        let report = validate_with_report(String::from("test/toml/report-warnings.toml")).unwrap();
+        let log: Value = serde_json::from_str(&validation_to_sarif(&[report]).unwrap()).unwrap();
+
+        let run = &log["runs"][0];
+        let rules: Vec<&str> = run["tool"]["driver"]["rules"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["id"].as_str().unwrap())
+            .collect();
+        assert!(rules == vec!["empty-list", "unknown-key"]);
+        let results = run["results"].as_array().unwrap();
+        assert!(results.len() == 2);
+        assert!(results.iter().all(|r| r["level"] == "warning"));
+        assert!(results.iter().all(|r| r["locations"][0]["physicalLocation"]["region"]["startLine"].is_u64()));
+    }
+}
@@ -0,0 +1,288 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use regex::Regex;
+use walkdir::WalkDir;
+
+use crate::error::{SarusError, SarusErrorKind, SarusResult};
+use crate::load::{LoadLimits, load_edf};
+use crate::{BaseEnvironment, EDF_EXTENSIONS, RawEDF, annotations_as_hashmap, document_read, join_search_path, toml_parse};
+
+// Name of the optional index of a search directory.
+pub const SEARCH_INDEX_FILE: &str = "index.toml";
+
+// Annotation holding the one line description of an environment, for the
+// files without a description field.
+pub const DESCRIPTION_ANNOTATION: &str = "org.opencontainers.image.description";
+
+// An environment found in the search paths, with what a shallow parse of
+// its file tells, i.e. without rendering its base environments.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct EnvironmentInfo {
+    // Name to render it by.
+    pub name: String,
+    pub file_path: String,
+    pub search_path: String,
+    pub image: Option<String>,
+    pub description: Option<String>,
+    pub maintainer: Option<String>,
+    pub tags: Vec<String>,
+    pub base_environment: Vec<String>,
+    // Why the file couldn't be parsed, the other fields are empty then.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// Index of a search directory, mapping logical environment names to files
+// relative to the directory:
+// [environments]
+// "pytorch/24.01" = "ml/pytorch-24.01.toml"
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SearchIndex {
+    #[serde(default)]
+    pub environments: HashMap<String, String>,
+}
+
+impl SearchIndex {
+    // The index of a directory, None when it has no index file. It's read
+    // within the limits of EDFs and its files must stay in the directory.
+    pub fn read(dir: &str, sp: &[String], limits: &LoadLimits) -> SarusResult<Option<SearchIndex>> {
+        let path = format!("{dir}/{SEARCH_INDEX_FILE}");
+        if !Path::new(&path).is_file() {
+            return Ok(None);
+        }
+        let index: SearchIndex = toml_parse(&load_edf(&path, sp, limits)?, &path)?;
+        for (name, f) in index.environments.iter() {
+            if f.starts_with('/') || f.split('/').any(|c| c == "..") {
+                return Err(SarusError {
+                    kind: SarusErrorKind::FileOutOfSearchPath {
+                        path: f.clone(),
+                        search_path: String::from(dir),
+                    },
+                    file_path: Some(path.clone()),
+                    msg: format!("file {f} of environment \"{name}\" leaves the search path {dir}"),
+                    location: None,
+                });
+            }
+        }
+        Ok(Some(index))
+    }
+
+    // Path of the file of an environment, resolved from the directory of
+    // the index.
+    pub fn lookup(&self, dir: &str, name: &str) -> Option<String> {
+        join_search_path(dir, self.environments.get(name)?)
+    }
+}
+
+fn is_glob(s: &str) -> bool {
+    s.contains(['*', '?', '['])
+}
+
+fn glob_regex(component: &str) -> Regex {
+    let mut re = String::from("^");
+    for c in component.chars() {
+        match c {
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            '[' | ']' => re.push(c),
+            _ => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    re.push('$');
+    Regex::new(&re).unwrap_or_else(|_| Regex::new("$^").unwrap())
+}
+
+// Expand a search path entry holding glob patterns, e.g. "/etc/edf/*/", to
+// the matching directories, sorted. Entries without patterns are kept as is.
+pub fn expand_search_path(entry: &str) -> Vec<String> {
+    if !is_glob(entry) {
+        return vec![String::from(entry)];
+    }
+
+    let absolute = entry.starts_with('/');
+    let mut found = vec![String::new()];
+    for component in entry.split('/').filter(|c| !c.is_empty()) {
+        let mut next = vec![];
+        for base in found.iter() {
+            let join = |c: &str| match (base.is_empty(), absolute) {
+                (true, true) => format!("/{c}"),
+                (true, false) => String::from(c),
+                (false, _) => format!("{base}/{c}"),
+            };
+            if !is_glob(component) {
+                next.push(join(component));
+                continue;
+            }
+            let dir = match base.is_empty() {
+                true if absolute => String::from("/"),
+                true => String::from("."),
+                false => base.clone(),
+            };
+            let re = glob_regex(component);
+            let mut names: Vec<String> = match std::fs::read_dir(&dir) {
+                Ok(d) => d
+                    .flatten()
+                    .map(|e| e.file_name().to_string_lossy().to_string())
+                    .filter(|n| !n.starts_with('.') && re.is_match(n))
+                    .collect(),
+                Err(_) => continue,
+            };
+            names.sort();
+            next.extend(names.iter().map(|n| join(n)));
+        }
+        found = next;
+    }
+
+    found.retain(|p| Path::new(p).is_dir());
+    found
+}
+
+fn environment_info(name: String, file_path: String, search_path: &str) -> EnvironmentInfo {
+    let mut info = EnvironmentInfo {
+        name,
+        file_path,
+        search_path: String::from(search_path),
+        image: None,
+        description: None,
+        maintainer: None,
+        tags: vec![],
+        base_environment: vec![],
+        error: None,
+    };
+    let r: RawEDF = match document_read(&info.file_path) {
+        Ok(r) => r,
+        Err(e) => {
+            info.error = Some(e.msg);
+            return info;
+        }
+    };
+    info.image = r.image;
+    info.description = match (r.description, r.annotations) {
+        (Some(d), _) => Some(d),
+        (None, Some(a)) => annotations_as_hashmap(a).remove(DESCRIPTION_ANNOTATION),
+        (None, None) => None,
+    };
+    info.maintainer = r.maintainer;
+    info.tags = r.tags.unwrap_or_default();
+    info.base_environment = match r.base_environment {
+        Some(BaseEnvironment::TypeString(s)) => vec![s],
+        Some(BaseEnvironment::TypeVec(v)) => v,
+        None => vec![],
+    };
+    info
+}
+
+// The environments of the search paths, sorted by name. A name found in
+// several paths is listed once, from the path it resolves to.
+pub fn list_environments(search_paths: &[String]) -> Vec<EnvironmentInfo> {
+    let mut res = vec![];
+    let mut seen = HashSet::new();
+
+    for sp in search_paths {
+        let mut found = vec![];
+        let walker = WalkDir::new(sp)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|e| e.depth() == 0 || !e.file_name().to_string_lossy().starts_with('.'));
+        for entry in walker.flatten() {
+            if !entry.file_type().is_file() || (entry.depth() == 1 && entry.file_name() == SEARCH_INDEX_FILE) {
+                continue;
+            }
+            let rel = match entry.path().strip_prefix(sp) {
+                Ok(r) => r.to_string_lossy().to_string(),
+                Err(_) => continue,
+            };
+            let name = match EDF_EXTENSIONS.iter().find_map(|x| rel.strip_suffix(&format!(".{x}"))) {
+                Some(n) => String::from(n),
+                None => continue,
+            };
+            found.push((name, entry.path().display().to_string()));
+        }
+        if let Ok(Some(index)) = SearchIndex::read(sp, std::slice::from_ref(sp), &LoadLimits::default()) {
+            let mut names: Vec<&String> = index.environments.keys().collect();
+            names.sort();
+            for n in names {
+                if let Some(f) = index.lookup(sp, n) {
+                    found.push((n.clone(), f));
+                }
+            }
+        }
+
+        for (name, file_path) in found {
+            if seen.insert(name.clone()) {
+                res.push(environment_info(name, file_path, sp));
+            }
+        }
+    }
+
+    res.sort_by(|a, b| a.name.cmp(&b.name));
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search_path_glob() {
+        let sp = expand_search_path("test/search/*/");
+        assert!(sp == vec!["test/search/site", "test/search/team"]);
+        assert!(expand_search_path("test/search/s*") == vec!["test/search/site"]);
+        assert!(expand_search_path("test/search/none-*").is_empty());
+        assert!(expand_search_path("/etc/edf") == vec!["/etc/edf"]);
+
+        let limits = LoadLimits::default();
+        let index = SearchIndex::read("test/search/site", &sp, &limits).unwrap().unwrap();
+        let f = index.lookup("test/search/site", "pytorch/24.01").unwrap();
+        assert!(f == "test/search/site/ml/pytorch-24.01.toml");
+        assert!(index.lookup("test/search/site", "tensorflow").is_none());
+        assert!(SearchIndex::read("test/search/team", &sp, &limits).unwrap().is_none());
+    }
+
+    #[test]
+    fn search_index_limits() {
+        let dir = std::env::temp_dir().join(format!("raster-index-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let d = dir.display().to_string();
+        let sp = vec![d.clone()];
+        let read = |content: &str, limits: &LoadLimits| {
+            std::fs::write(dir.join(SEARCH_INDEX_FILE), content).unwrap();
+            SearchIndex::read(&d, &sp, limits)
+        };
+
+        let limits = LoadLimits::default();
+        let index = read("[environments]\n\"a\" = \"team/./a.toml\"\n", &limits).unwrap().unwrap();
+        assert!(index.lookup(&d, "a").unwrap() == format!("{d}/team/a.toml"));
+        // Entries leaving the directory
+        assert!(read("[environments]\n\"a\" = \"/etc/a.toml\"\n", &limits).err().unwrap().code() == 66);
+        assert!(read("[environments]\n\"a\" = \"team/../../a.toml\"\n", &limits).err().unwrap().code() == 66);
+        // Indexes are read like EDFs
+        let small = LoadLimits {
+            max_size: 8,
+            ..Default::default()
+        };
+        assert!(read("[environments]\n", &small).err().unwrap().code() == 64);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn search_list_environments() {
+        let sp = expand_search_path("test/search/*/");
+        let envs = list_environments(&sp);
+        let names: Vec<&str> = envs.iter().map(|e| e.name.as_str()).collect();
+        assert!(names == vec!["analysis", "ml/pytorch-24.01", "pytorch/24.01"]);
+
+        let analysis = &envs[0];
+        assert!(analysis.image == Some(String::from("ubuntu:24.04")));
+        assert!(analysis.description == Some(String::from("Data analysis tools")));
+        assert!(analysis.base_environment == vec!["pytorch/24.01"]);
+        assert!(analysis.search_path == "test/search/team");
+        assert!(envs[2].file_path == "test/search/site/ml/pytorch-24.01.toml");
+        assert!(envs[2].description == Some(String::from("PyTorch 24.01 from NGC")));
+        assert!(envs[2].tags == vec!["ml", "gpu"]);
+    }
+}
@@ -0,0 +1,67 @@
+use std::collections::BTreeMap;
+
+// Shown in place of the value of sensitive variables.
+pub const REDACTED: &str = "<redacted>";
+
+// Whether the variable matches one of the patterns, where '*' stands for
+// any sequence of characters, e.g. "*_TOKEN".
+pub fn is_sensitive(key: &str, patterns: &[String]) -> bool {
+    patterns.iter().any(|p| glob_match(p, key))
+}
+
+pub fn redact_env(env: &BTreeMap<String, String>, patterns: &[String]) -> BTreeMap<String, String> {
+    let mut res = env.clone();
+    for (k, v) in res.iter_mut() {
+        if is_sensitive(k, patterns) {
+            *v = String::from(REDACTED);
+        }
+    }
+    res
+}
+
+pub(crate) fn glob_match(pattern: &str, s: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == s;
+    }
+
+    let first = parts[0];
+    let last = parts[parts.len() - 1];
+    if !s.starts_with(first) || s.len() < first.len() + last.len() {
+        return false;
+    }
+    let mut rest = &s[first.len()..s.len() - last.len()];
+    if !s.ends_with(last) {
+        return false;
+    }
+    for p in parts[1..parts.len() - 1].iter() {
+        match rest.find(p) {
+            Some(i) => rest = &rest[i + p.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sensitive_patterns() {
+        let patterns = vec![String::from("*_TOKEN"), String::from("DB_*_PASS*")];
+        assert!(is_sensitive("HF_TOKEN", &patterns));
+        assert!(is_sensitive("_TOKEN", &patterns));
+        assert!(is_sensitive("DB_MAIN_PASSWORD", &patterns));
+        assert!(!is_sensitive("TOKENS", &patterns));
+        assert!(!is_sensitive("DB_PASS", &patterns));
+        assert!(!is_sensitive("HOME", &[]));
+
+        let mut env = BTreeMap::new();
+        env.insert(String::from("HF_TOKEN"), String::from("hf_abc"));
+        env.insert(String::from("HOME"), String::from("/home/u"));
+        let r = redact_env(&env, &patterns);
+        assert!(r.get("HF_TOKEN").unwrap() == REDACTED);
+        assert!(r.get("HOME").unwrap() == "/home/u");
+    }
+}
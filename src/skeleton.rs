@@ -0,0 +1,155 @@
+use serde_json::Value;
+
+use crate::{EDF, EDF_SCHEMAS};
+
+// Starter EDFs printed by `raster init`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SkeletonKind {
+    // An image from a registry, nothing else.
+    Plain,
+    // GPUs of the node and the MPI template of the site.
+    GpuMpi,
+    // A local squashfs image with squashfs data.
+    Squashfs,
+    // A writable container keeping its changes in an overlay.
+    DevContainer,
+}
+
+impl SkeletonKind {
+    pub const ALL: [SkeletonKind; 4] = [
+        SkeletonKind::Plain,
+        SkeletonKind::GpuMpi,
+        SkeletonKind::Squashfs,
+        SkeletonKind::DevContainer,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SkeletonKind::Plain => "plain",
+            SkeletonKind::GpuMpi => "gpu-mpi",
+            SkeletonKind::Squashfs => "squashfs",
+            SkeletonKind::DevContainer => "dev",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<SkeletonKind> {
+        SkeletonKind::ALL.into_iter().find(|k| k.name() == name)
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            SkeletonKind::Plain => "Container from a registry image",
+            SkeletonKind::GpuMpi => "GPU and MPI application",
+            SkeletonKind::Squashfs => "Container from a local squashfs image",
+            SkeletonKind::DevContainer => "Development container with a writable overlay",
+        }
+    }
+
+    // Fields of the skeleton with their value, the ones without a value
+    // being left commented out with their default or an example.
+    fn fields(&self) -> Vec<(&'static str, Option<&'static str>)> {
+        let mut fields = match self {
+            SkeletonKind::Plain => vec![("image", Some("\"ubuntu:24.04\""))],
+            SkeletonKind::GpuMpi => vec![
+                ("image", Some("\"nvcr.io/nvidia/pytorch:24.01-py3\"")),
+                ("gpu", Some("true")),
+                ("mpi", Some("\"openmpi\"")),
+            ],
+            SkeletonKind::Squashfs => vec![
+                ("image", Some("\"/path/to/image.sqsh\"")),
+                ("mounts", Some("[\"/path/to/data.sqsh:/data:sqsh\"]")),
+            ],
+            SkeletonKind::DevContainer => vec![
+                ("image", Some("\"ubuntu:24.04\"")),
+                ("writable", Some("true")),
+                ("writable_overlay_path", Some("\"$SCRATCH/overlay\"")),
+                ("writable_overlay_size", Some("\"10G\"")),
+                ("mounts", Some("[\"$HOME:$HOME\"]")),
+                ("workdir", Some("\"$HOME\"")),
+            ],
+        };
+        for f in ["description", "mounts", "workdir", "entrypoint", "writable"] {
+            if !fields.iter().any(|(k, _)| *k == f) {
+                fields.push((f, None));
+            }
+        }
+        fields
+    }
+}
+
+fn example(field: &str, schema: &Value) -> String {
+    match schema.get("default") {
+        Some(d) => d.to_string(),
+        None => match field {
+            "description" => String::from("\"One line description\""),
+            "mounts" => String::from("[\"$SCRATCH:$SCRATCH\"]"),
+            "workdir" => String::from("\"$SCRATCH\""),
+            _ => String::from("\"\""),
+        },
+    }
+}
+
+fn comment(text: &str, width: usize) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::from("#");
+    for word in text.split_whitespace() {
+        if line.len() + word.len() + 1 > width && line != "#" {
+            lines.push(line);
+            line = String::from("#");
+        }
+        line.push(' ');
+        line.push_str(word);
+    }
+    lines.push(line);
+    lines
+}
+
+impl EDF {
+    // A commented starter EDF of the latest edf_version, the comments being
+    // the descriptions of its schema.
+    pub fn skeleton(kind: SkeletonKind) -> String {
+        let (version, content) = EDF_SCHEMAS.iter().max_by_key(|(v, _)| *v).unwrap();
+        let schema: Value = serde_json::from_str(content).unwrap_or_default();
+        let properties = schema.get("properties").cloned().unwrap_or_default();
+
+        let mut lines = comment(&format!("{}, generated by raster init.", kind.title()), 78);
+        lines.push(String::new());
+        lines.push(format!("edf_version = {version}"));
+        for (field, value) in kind.fields() {
+            let p = properties.get(field).cloned().unwrap_or_default();
+            lines.push(String::new());
+            if let Some(d) = p.get("description").and_then(|d| d.as_str()) {
+                lines.extend(comment(d, 78));
+            }
+            match value {
+                Some(v) => lines.push(format!("{field} = {v}")),
+                None => lines.push(format!("# {field} = {}", example(field, &p))),
+            }
+        }
+        lines.push(String::new());
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validate_str_with_report;
+
+    #[test]
+    fn skeleton_valid() {
+        for kind in SkeletonKind::ALL {
+            let s = EDF::skeleton(kind);
+            let report = validate_str_with_report(&s, "skeleton.toml").unwrap();
+            assert!(report.is_valid() && report.warnings.is_empty());
+            assert!(SkeletonKind::from_name(kind.name()) == Some(kind));
+            assert!(s.lines().all(|l| l.len() <= 78 || !l.starts_with('#')));
+        }
+
+        let s = EDF::skeleton(SkeletonKind::Plain);
+        assert!(s.contains("\nimage = \"ubuntu:24.04\"\n"));
+        assert!(s.contains("\n# entrypoint = false\n"));
+        assert!(s.contains("\n# writable = true\n"));
+        assert!(SkeletonKind::from_name("docker").is_none());
+    }
+}
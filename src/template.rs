@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+
+use regex::{Captures, Regex};
+use serde_json::Value;
+
+use crate::error::{SarusError, SarusErrorKind, SarusResult};
+
+// Built-in values of the %{name} placeholders, describing the user running
+// the render and the node it runs on.
+pub fn builtin_template_vars() -> HashMap<String, String> {
+    let mut vars = HashMap::new();
+    #[cfg(unix)]
+    {
+        let uid = nix::unistd::geteuid();
+        vars.insert(String::from("uid"), uid.to_string());
+        vars.insert(String::from("gid"), nix::unistd::getegid().to_string());
+        if let Ok(Some(u)) = nix::unistd::User::from_uid(uid) {
+            vars.insert(String::from("user"), u.name.clone());
+            vars.insert(String::from("username"), u.name);
+        }
+    }
+    if let Ok(h) = std::fs::read_to_string("/proc/sys/kernel/hostname") {
+        vars.insert(String::from("hostname"), h.trim().to_string());
+    }
+    vars
+}
+
+// Replace %{name} placeholders with their value, %%{name} is kept as the
+// literal %{name}. Unlike $VAR, values never come from the environment.
+pub fn expand_templates(input: &str, vars: &HashMap<String, String>) -> SarusResult<String> {
+    let re = Regex::new(r"%(%?)\{([A-Za-z_][A-Za-z0-9_.-]*)\}").unwrap();
+
+    let mut undefined = None;
+    let out = re.replace_all(input, |c: &Captures| {
+        if !c[1].is_empty() {
+            return format!("%{{{}}}", &c[2]);
+        }
+        match vars.get(&c[2]) {
+            Some(v) => v.clone(),
+            None => {
+                undefined.get_or_insert(c[2].to_string());
+                String::new()
+            }
+        }
+    });
+
+    match undefined {
+        Some(var) => Err(SarusError {
+            kind: SarusErrorKind::UndefinedTemplateVariable { var: var.clone() },
+            file_path: None,
+            msg: format!("template variable %{{{var}}} is not defined"),
+            location: None,
+        }),
+        None => Ok(out.into_owned()),
+    }
+}
+
+// Expand the placeholders in the string values of a parsed EDF. Built-ins
+// are only looked up when the document holds a placeholder.
+pub(crate) fn expand_document(doc: &mut Value, site: &HashMap<String, String>, path: &str) -> SarusResult<()> {
+    if !doc.to_string().contains("%{") {
+        return Ok(());
+    }
+    let mut vars = builtin_template_vars();
+    vars.extend(site.iter().map(|(k, v)| (k.clone(), v.clone())));
+
+    let mut stack = vec![doc];
+    while let Some(v) = stack.pop() {
+        match v {
+            Value::String(s) => {
+                *s = match expand_templates(s, &vars) {
+                    Ok(x) => x,
+                    Err(mut e) => {
+                        e.file_path = Some(String::from(path));
+                        return Err(e);
+                    }
+                };
+            }
+            Value::Array(a) => stack.extend(a.iter_mut()),
+            Value::Object(o) => stack.extend(o.values_mut()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn template_expand() {
+        let vars = HashMap::from([
+            (String::from("scratch"), String::from("/capstor/scratch/cscs")),
+            (String::from("jobid"), String::from("42")),
+        ]);
+
+        let s = expand_templates("%{scratch}/run-%{jobid}:/work", &vars).unwrap();
+        assert!(s == "/capstor/scratch/cscs/run-42:/work");
+        assert!(expand_templates("100%%{jobid} $HOME", &vars).unwrap() == "100%{jobid} $HOME");
+        assert!(expand_templates("%{jobid", &vars).unwrap() == "%{jobid");
+
+        let err = expand_templates("%{partition}", &vars).err().unwrap();
+        assert!(err.kind == SarusErrorKind::UndefinedTemplateVariable { var: String::from("partition") });
+
+        let builtins = builtin_template_vars();
+        assert!(builtins.get("uid").unwrap() == &nix::unistd::geteuid().to_string());
+    }
+}
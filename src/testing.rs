@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::config::{Config, VarExpand, load_config_path};
+use crate::error::SarusResult;
+use crate::options::RenderOptions;
+use crate::{EDF, render_with_options};
+
+// Helpers for the tests of crates using raster, e.g. the Slurm plugin or
+// the CLI: EDFs and configuration files written to a temporary tree, and
+// rendered from it without changing the working directory.
+//
+// let tree = EdfTree::new();
+// tree.write_edf("base.toml", "image = \"ubuntu\"\n");
+// tree.write_edf("app.toml", "base_environment = \"base\"\n");
+// let edf = tree.render("app").unwrap();
+// assert_env(&edf, "A", "1");
+
+static TREES: AtomicUsize = AtomicUsize::new(0);
+
+// A temporary directory holding an EDF search path and a config path,
+// removed when dropped.
+#[derive(Debug)]
+pub struct EdfTree {
+    root: PathBuf,
+    // Variables used for expansion, none of the process environment.
+    pub env: HashMap<String, String>,
+}
+
+impl Default for EdfTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EdfTree {
+    pub fn new() -> Self {
+        let n = TREES.fetch_add(1, Ordering::SeqCst);
+        let root = std::env::temp_dir().join(format!("raster-testing-{}-{n}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        for d in ["edf", "config"] {
+            std::fs::create_dir_all(root.join(d)).expect("cannot create the EDF tree");
+        }
+        EdfTree {
+            root,
+            env: HashMap::new(),
+        }
+    }
+
+    pub fn env(mut self, name: &str, value: &str) -> Self {
+        self.env.insert(String::from(name), String::from(value));
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    // The directory of the EDFs, the only search path of renders.
+    pub fn search_path(&self) -> PathBuf {
+        self.root.join("edf")
+    }
+
+    pub fn config_path(&self) -> PathBuf {
+        self.root.join("config")
+    }
+
+    fn write(&self, path: PathBuf, content: &str) -> PathBuf {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).expect("cannot create the fixture directory");
+        }
+        std::fs::write(&path, content).expect("cannot write the fixture");
+        path
+    }
+
+    // Write an EDF of the search path, name being relative to it, e.g.
+    // "team/base.toml".
+    pub fn write_edf(&self, name: &str, content: &str) -> PathBuf {
+        self.write(self.search_path().join(name), content)
+    }
+
+    // Write a file of the config path, e.g. "00-base.conf" or
+    // "conf.d/10-site.conf".
+    pub fn write_config(&self, name: &str, content: &str) -> PathBuf {
+        self.write(self.config_path().join(name), content)
+    }
+
+    // Options rendering against the tree and its env only.
+    pub fn render_options(&self) -> RenderOptions {
+        RenderOptions::new()
+            .search_paths(vec![self.search_path().display().to_string()])
+            .env(self.env.clone())
+    }
+
+    pub fn render(&self, name: &str) -> SarusResult<EDF> {
+        self.render_with_options(name, &self.render_options())
+    }
+
+    pub fn render_with_options(&self, name: &str, options: &RenderOptions) -> SarusResult<EDF> {
+        render_with_options(String::from(name), options)
+    }
+
+    pub fn load_config(&self) -> SarusResult<Config> {
+        load_config_path(Some(self.config_path()), VarExpand::Try, &Some(self.env.clone()))
+    }
+}
+
+impl Drop for EdfTree {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+pub fn assert_image(edf: &EDF, image: &str) {
+    assert!(edf.image == image, "image is \"{}\", expected \"{image}\"", edf.image);
+}
+
+pub fn assert_env(edf: &EDF, name: &str, value: &str) {
+    match edf.env.get(name) {
+        Some(v) => assert!(v == value, "env {name} is \"{v}\", expected \"{value}\""),
+        None => panic!("env {name} is not set, expected \"{value}\""),
+    }
+}
+
+pub fn assert_annotation(edf: &EDF, key: &str, value: &str) {
+    match edf.annotations.get(key) {
+        Some(v) => assert!(v == value, "annotation {key} is \"{v}\", expected \"{value}\""),
+        None => panic!("annotation {key} is not set, expected \"{value}\""),
+    }
+}
+
+// A mount targets the path in the container.
+pub fn assert_mount(edf: &EDF, target: &str) {
+    let targets: Vec<&str> = edf.mounts.iter().map(|m| m.target()).collect();
+    assert!(
+        targets.contains(&target),
+        "no mount targets {target}, the mounts target {targets:?}"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn testing_tree() {
+        let tree = EdfTree::new().env("SCRATCH", "/scratch/u");
+        tree.write_edf("team/base.toml", "image = \"ubuntu:24.04\"\n[env]\nA = \"1\"\n");
+        tree.write_edf(
+            "app.toml",
+            "base_environment = \"team/base\"\nmounts = [\"$SCRATCH:/scratch\"]\n[annotations]\nk = \"v\"\n",
+        );
+        let edf = tree.render("app").unwrap();
+        assert_image(&edf, "ubuntu:24.04");
+        assert_env(&edf, "A", "1");
+        assert_annotation(&edf, "k", "v");
+        assert_mount(&edf, "/scratch");
+        assert!(tree.render("missing").is_err());
+
+        tree.write_config("00-base.conf", "podman_path = \"/usr/bin/podman\"\n");
+        assert!(tree.load_config().unwrap().podman_path == "/usr/bin/podman");
+
+        let root = tree.root().to_path_buf();
+        drop(tree);
+        assert!(!root.exists());
+    }
+
+    #[test]
+    #[should_panic(expected = "env B is not set")]
+    fn testing_assert_env() {
+        let tree = EdfTree::new();
+        tree.write_edf("a.toml", "image = \"ubuntu\"\n");
+        assert_env(&tree.render("a").unwrap(), "B", "2");
+    }
+}
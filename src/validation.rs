@@ -0,0 +1,173 @@
+use serde::Serialize;
+
+use crate::aliases::{EDF_KEY_ALIASES, resolve_aliases};
+use crate::error::{Location, SarusError, SarusErrorKind, SarusResult};
+use crate::provenance::{document_location, key_location};
+use crate::validator::Validator;
+use crate::{EDF_EXTENSIONS, check_file_path_extension, document_parse, edf_schema, load};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub id: String,
+    pub key: Option<String>,
+    pub msg: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<Location>,
+}
+
+// Outcome of validating one EDF file. Errors make the file unusable,
+// warnings are advisory and don't prevent rendering.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ValidationReport {
+    pub file_path: String,
+    pub errors: Vec<Diagnostic>,
+    pub warnings: Vec<Diagnostic>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub(crate) fn error(&mut self, id: &str, key: Option<&str>, msg: String) {
+        self.errors.push(Diagnostic {
+            id: String::from(id),
+            key: key.map(String::from),
+            msg,
+            location: None,
+        });
+    }
+
+    // Position the diagnostics about a key at the key in the file content.
+    pub(crate) fn locate(&mut self, content: &str) {
+        for d in self.errors.iter_mut().chain(self.warnings.iter_mut()) {
+            if d.location.is_none()
+                && let Some(k) = &d.key
+            {
+                d.location = key_location(content, &self.file_path, k);
+            }
+        }
+    }
+
+    pub(crate) fn warning(&mut self, id: &str, key: Option<&str>, msg: String) {
+        self.warnings.push(Diagnostic {
+            id: String::from(id),
+            key: key.map(String::from),
+            msg,
+            location: None,
+        });
+    }
+}
+
+// Validate an EDF file against the schema and collect advisory warnings.
+// Only failures to read or parse the file are returned as Err.
+pub fn validate_with_report(path: String) -> SarusResult<ValidationReport> {
+    check_file_path_extension(&path, &EDF_EXTENSIONS)?;
+
+    let content = match load(&path) {
+        Ok(c) => c,
+        Err(e) => {
+            return Err(SarusError {
+                kind: SarusErrorKind::FileRead,
+                file_path: Some(path),
+                msg: format!("{}", e),
+                location: None,
+            });
+        }
+    };
+    validate_str_with_report(&content, &path)
+}
+
+// Validate EDF content received in memory, origin_hint names it in the
+// report and selects the format like for render_from_str.
+pub fn validate_str_with_report(content: &str, origin_hint: &str) -> SarusResult<ValidationReport> {
+    let doc: serde_json::Value = document_parse(content, origin_hint)?;
+    report_document(doc, String::from(origin_hint), content)
+}
+
+fn report_document(mut doc: serde_json::Value, path: String, content: &str) -> SarusResult<ValidationReport> {
+    let mut report = ValidationReport {
+        file_path: path.clone(),
+        ..Default::default()
+    };
+
+    for d in resolve_aliases(&mut doc, EDF_KEY_ALIASES) {
+        report.warning("deprecated-key", Some(&d.old), d.msg());
+    }
+
+    let schema_content = match edf_schema(&doc, &path) {
+        Ok(s) => s,
+        Err(e) => {
+            report.error("edf-version", Some("edf_version"), e.msg);
+            report.locate(content);
+            return Ok(report);
+        }
+    };
+
+    for (pointer, e) in Validator::get()?.errors_with_paths(&path, &doc)? {
+        report.errors.push(Diagnostic {
+            id: String::from("schema"),
+            key: None,
+            msg: e,
+            location: document_location(content, &path, &pointer),
+        });
+    }
+
+    let schema: serde_json::Value = serde_json::from_str(schema_content).unwrap_or_default();
+    collect_warnings(&doc, &schema, &mut report);
+
+    report.locate(content);
+    Ok(report)
+}
+
+// Fail on the first top-level key not declared in the EDF schema, content
+// being the one the document was parsed from.
+pub(crate) fn check_unknown_keys(doc: &serde_json::Value, path: &str, content: &str) -> SarusResult<()> {
+    let schema: serde_json::Value = serde_json::from_str(edf_schema(doc, path)?).unwrap_or_default();
+
+    if let Some(key) = unknown_keys(doc, &schema).first() {
+        return Err(SarusError {
+            kind: SarusErrorKind::UnknownKey { key: key.clone() },
+            file_path: Some(String::from(path)),
+            msg: format!("unknown key \"{key}\""),
+            location: key_location(content, path, key),
+        });
+    }
+    Ok(())
+}
+
+fn unknown_keys(doc: &serde_json::Value, schema: &serde_json::Value) -> Vec<String> {
+    let known = match schema.get("properties").and_then(|p| p.as_object()) {
+        Some(k) => k,
+        None => return vec![],
+    };
+
+    match doc.as_object() {
+        Some(t) => t.keys().filter(|k| !known.contains_key(*k)).cloned().collect(),
+        None => vec![],
+    }
+}
+
+fn collect_warnings(doc: &serde_json::Value, schema: &serde_json::Value, report: &mut ValidationReport) {
+    let table = match doc.as_object() {
+        Some(t) => t,
+        None => return,
+    };
+
+    for key in unknown_keys(doc, schema) {
+        report.warning("unknown-key", Some(&key), format!("unknown key \"{key}\" is ignored"));
+    }
+
+    for (key, value) in table.iter() {
+        let is_empty_array = value.as_array().is_some_and(|a| a.is_empty());
+        if (key == "mounts" || key == "devices") && is_empty_array {
+            report.warning("empty-list", Some(key), format!("\"{key}\" is an empty list"));
+        }
+    }
+
+    if let Some(image) = table.get("image").and_then(|i| i.as_str())
+        && image.is_empty()
+    {
+        report.warning("empty-image", Some("image"), String::from("\"image\" is an empty string"));
+    }
+}
@@ -0,0 +1,166 @@
+use serde_json::Value;
+use std::sync::OnceLock;
+
+use crate::aliases::{EDF_KEY_ALIASES, resolve_aliases};
+use crate::error::{SarusError, SarusErrorKind, SarusResult};
+use crate::provenance::document_location;
+use crate::{EDF_EXTENSIONS, EDF_SCHEMAS, check_file_path_extension, document_read, edf_version};
+
+// A compiled JSON schema.
+pub(crate) struct Schema {
+    validator: jsonschema::Validator,
+}
+
+impl Schema {
+    pub(crate) fn compile(schema_content: &str) -> SarusResult<Schema> {
+        let schema: Value = match serde_json::from_str(schema_content) {
+            Ok(c) => c,
+            Err(_) => {
+                return Err(SarusError {
+                    kind: SarusErrorKind::SchemaParse,
+                    file_path: None,
+                    msg: String::from("Failed to parse schema file"),
+                    location: None,
+                });
+            }
+        };
+
+        match jsonschema::options().build(&schema) {
+            Ok(validator) => Ok(Schema { validator }),
+            Err(error) => Err(SarusError {
+                kind: SarusErrorKind::SchemaInvalid,
+                file_path: None,
+                msg: format!("Schema is invalid.\n{error}"),
+                location: None,
+            }),
+        }
+    }
+
+    pub(crate) fn errors(&self, doc: &Value) -> Vec<String> {
+        self.validator.iter_errors(doc).map(|e| e.to_string()).collect()
+    }
+
+    // Errors with the JSON pointer of the failing value, e.g. "/mounts/0".
+    pub(crate) fn errors_with_paths(&self, doc: &Value) -> Vec<(String, String)> {
+        self.validator.iter_errors(doc).map(|e| (e.instance_path.to_string(), e.to_string())).collect()
+    }
+
+    pub(crate) fn validate(&self, path_str: &str, doc: &Value) -> SarusResult<()> {
+        let errors = self.errors(doc);
+        if errors.is_empty() {
+            return Ok(());
+        }
+
+        let mut emsg = String::from("Errors:");
+        for (i, error) in errors.iter().enumerate() {
+            emsg = format!("{emsg}\n{}. {}", (i + 1), error);
+        }
+        Err(SarusError {
+            kind: SarusErrorKind::SchemaViolation { errors },
+            file_path: Some(String::from(path_str)),
+            msg: emsg,
+            location: None,
+        })
+    }
+}
+
+// Compiled schemas of every supported edf_version, for complete EDFs and
+// for fragments. Compiling is costly, so it's done once per process and
+// Validator::get() hands out the shared handle.
+pub struct Validator {
+    schemas: Vec<(u64, Schema, Schema)>,
+}
+
+static VALIDATOR: OnceLock<SarusResult<Validator>> = OnceLock::new();
+
+impl Validator {
+    pub fn get() -> SarusResult<&'static Validator> {
+        match VALIDATOR.get_or_init(Validator::build) {
+            Ok(v) => Ok(v),
+            Err(e) => Err(e.clone()),
+        }
+    }
+
+    fn build() -> SarusResult<Validator> {
+        let mut schemas = vec![];
+        for (version, content) in EDF_SCHEMAS.iter() {
+            let full = Schema::compile(content)?;
+            let fragment = Schema::compile(&fragment_schema(content))?;
+            schemas.push((*version, full, fragment));
+        }
+        Ok(Validator { schemas })
+    }
+
+    fn schema(&self, doc: &Value, path: &str, fragment: bool) -> SarusResult<&Schema> {
+        let version = edf_version(doc, path)?;
+        // edf_version() only returns versions with an embedded schema
+        let (_, full, frag) = self.schemas.iter().find(|(v, _, _)| *v == version).unwrap();
+        match fragment {
+            true => Ok(frag),
+            false => Ok(full),
+        }
+    }
+
+    // Validate an EDF file against the schema of its edf_version, keys
+    // with a former name are validated under their new one.
+    pub fn validate(&self, path: String) -> SarusResult<()> {
+        check_file_path_extension(&path, &EDF_EXTENSIONS)?;
+
+        let mut doc: Value = document_read(&path)?;
+        resolve_aliases(&mut doc, EDF_KEY_ALIASES);
+        self.validate_document(&path, &doc, false)
+    }
+
+    // Validate a parsed EDF, fragments are not required to set an image.
+    pub fn validate_document(&self, path: &str, doc: &Value, fragment: bool) -> SarusResult<()> {
+        self.schema(doc, path, fragment)?.validate(path, doc)
+    }
+
+    // Same, the error being reported at the position in the file content
+    // of the first failing value.
+    pub(crate) fn validate_content(&self, path: &str, doc: &Value, fragment: bool, content: &str) -> SarusResult<()> {
+        let schema = self.schema(doc, path, fragment)?;
+        schema.validate(path, doc).map_err(|e| {
+            let first = schema.errors_with_paths(doc).into_iter().next();
+            let location = first.and_then(|(p, _)| document_location(content, path, &p));
+            e.at(location)
+        })
+    }
+
+    pub fn errors(&self, path: &str, doc: &Value) -> SarusResult<Vec<String>> {
+        Ok(self.schema(doc, path, false)?.errors(doc))
+    }
+
+    pub(crate) fn errors_with_paths(&self, path: &str, doc: &Value) -> SarusResult<Vec<(String, String)>> {
+        Ok(self.schema(doc, path, false)?.errors_with_paths(doc))
+    }
+}
+
+// Fragments only carry some of the fields, drop the requirement of an image.
+fn fragment_schema(schema_content: &str) -> String {
+    let mut schema: Value = serde_json::from_str(schema_content).unwrap_or_default();
+    if let Some(s) = schema.as_object_mut() {
+        s.remove("anyOf");
+    }
+    schema.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validator_shared() {
+        let a = Validator::get().unwrap();
+        let b = Validator::get().unwrap();
+        assert!(std::ptr::eq(a, b));
+
+        assert!(a.validate(String::from("test/toml/top-simple-1.toml")).is_ok());
+        let err = a.validate(String::from("test/toml/no-image.toml")).err().unwrap();
+        assert!(err.code() == 4);
+
+        let doc: Value = serde_json::from_str(r#"{"env": {"A": "1"}}"#).unwrap();
+        assert!(a.validate_document("frag.toml", &doc, true).is_ok());
+        assert!(a.errors("frag.toml", &doc).unwrap().len() == 1);
+    }
+}
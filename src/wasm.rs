@@ -0,0 +1,70 @@
+// Entry points of the wasm32-unknown-unknown build, behind the wasm
+// feature, for validating EDFs in a browser. Nothing is read from the
+// filesystem: bases can't be resolved and variables only come from the
+// env passed in. Results are JSON, {"edf": ...} or {"report": ...} on
+// success and {"error": ...} on failure.
+use std::collections::HashMap;
+
+use serde_json::{Value, json};
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::common::ExpansionBackend;
+use crate::error::{SarusError, SarusResult};
+use crate::options::RenderOptions;
+use crate::{render_from_str_with_options, validate_str_with_report};
+
+fn error_json(e: &SarusError) -> String {
+    let err: Value = match e.to_json() {
+        Ok(j) => serde_json::from_str(&j).unwrap_or_default(),
+        Err(_) => Value::String(e.msg.clone()),
+    };
+    json!({ "error": err }).to_string()
+}
+
+fn result_json<T: serde::Serialize>(field: &str, r: SarusResult<T>) -> String {
+    match r {
+        Ok(v) => json!({ field: v }).to_string(),
+        Err(e) => error_json(&e),
+    }
+}
+
+// Validate EDF content, name selects the format and names it in the report.
+#[wasm_bindgen]
+pub fn validate_edf(content: &str, name: &str) -> String {
+    result_json("report", validate_str_with_report(content, name))
+}
+
+// Render EDF content without bases. env is a JSON object of the variables
+// used for expansion, e.g. {"HOME": "/users/alice"}.
+#[wasm_bindgen]
+pub fn render_edf(content: &str, name: &str, env: &str) -> String {
+    let env: HashMap<String, String> = match env.is_empty() {
+        true => HashMap::new(),
+        false => serde_json::from_str(env).unwrap_or_default(),
+    };
+    let opts = RenderOptions {
+        env: Some(env),
+        search_paths: Some(vec![]),
+        expansion_backend: ExpansionBackend::Native,
+        ..RenderOptions::new()
+    };
+    result_json("edf", render_from_str_with_options(String::from(content), name, &opts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wasm_entry_points() {
+        let v: Value = serde_json::from_str(&validate_edf("image = \"ubuntu\"\nworkdir = 3\n", "edf.toml")).unwrap();
+        assert!(!v["report"]["errors"].as_array().unwrap().is_empty());
+
+        let content = "image = \"ubuntu\"\nworkdir = \"$HOME\"\n";
+        let v: Value = serde_json::from_str(&render_edf(content, "edf.toml", r#"{"HOME": "/users/a"}"#)).unwrap();
+        assert!(v["edf"]["workdir"] == "/users/a");
+
+        let v: Value = serde_json::from_str(&render_edf("base_environment = \"x\"\n", "edf.toml", "")).unwrap();
+        assert!(v["error"]["code"] == 6);
+    }
+}